@@ -6,7 +6,7 @@ fn main() -> Result<(), Box<dyn Error>> {
         .out_dir("src/proto")
         .file_descriptor_set_path("src/proto/reflection-descriptor.bin")
         .compile(
-            &["protos/tinykvpb.proto"], 
+            &["protos/tinykvpb.proto", "protos/schedulerpb.proto"], 
             &["protos/"]
         )?;
     Ok(())
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use tinykv::{kv::storage::disk::DiskStorage, proto::{self, schedulerpb::scheduler_server::SchedulerServer}, scheduler::{config::Config, server::SchedulerService}};
+use tonic::transport::Server;
+
+
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    println!("Welcome to the scheduler");
+
+    env_logger::init();
+
+
+    let mut config = Config::default();
+    if let Some(addr) = std::env::args().nth(1) {
+        config.addr = addr;
+    }
+    std::fs::create_dir_all(&config.data_dir)?;
+    let storage = DiskStorage::new(&config.data_dir).map_err(|err| anyhow!("{:?}", err))?;
+    let service = SchedulerService::new(&config, Arc::new(storage)).map_err(|err| anyhow!("{:?}", err))?;
+    let addr = config.addr.parse()?;
+    service.spawn_coordinator();
+
+    let reflection_service = tonic_reflection::server::Builder::configure()
+        .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
+        .build()?;
+
+    Server::builder()
+        .add_service(reflection_service)
+        .add_service(SchedulerServer::new(service))
+        .serve(addr)
+        .await?;
+
+
+    Ok(())
+}
@@ -4,6 +4,7 @@ use std::time::Duration;
 pub enum DiskSize {
     KiB(u64),
     MiB(u64),
+    GiB(u64),
 }
 
 impl DiskSize {
@@ -11,12 +12,16 @@ impl DiskSize {
         match self {
             DiskSize::KiB(n) => n * 1024,
             DiskSize::MiB(n) => n * 1024 * 1024,
+            DiskSize::GiB(n) => n * 1024 * 1024 * 1024,
         }
     }
 }
 
 pub struct Config {
     pub store_addr: String,
+    // (key, value) labels locating the store, such as its zone, rack and
+    // host. The scheduler spreads the replicas of a region across them.
+    pub labels: Vec<(String, String)>,
     pub is_raft: bool,
     pub scheduler_addr: String,
 
@@ -39,31 +44,74 @@ pub struct Config {
     // delay time before deleting a stale peer
     pub scheduler_heartbeat_tick_interval: Duration,
     pub scheduler_store_heartbeat_tick_interval: Duration,
+    // capacity of the store reported to the scheduler, the
+    // space available is what the data in db_path leaves of it.
+    pub capacity: DiskSize,
 
     // when region [a, e) size reaches region_max_size, it will be split into
     // several regions [a, b), [b,c), [c, d), [d, e), and the size [a, b), [b,c),
     // [c, d) will be region_split_size (maybe a little larger).
     pub region_max_size: DiskSize,
     pub region_split_size: DiskSize,
+
+    // interval (ms) to check whether a region can be merged with its neighbour.
+    pub merge_check_tick_interval: Duration,
+    // a region smaller than region_merge_max_size is merged into an adjacent
+    // region, as long as the merged region stays below region_merge_max_merged_size.
+    pub region_merge_max_size: DiskSize,
+    pub region_merge_max_merged_size: DiskSize,
+
+    // raft messages headed to the same store are sent in batches of at most
+    // raft_msg_max_batch_size messages, waiting raft_msg_flush_interval (ms)
+    // for more messages before flushing a batch.
+    pub raft_msg_max_batch_size: usize,
+    pub raft_msg_flush_interval: Duration,
+    // delay (ms) before reconnecting to an unreachable store, doubled
+    // on each failed attempt up to raft_client_max_backoff.
+    pub raft_client_backoff: Duration,
+    pub raft_client_max_backoff: Duration,
+
+    // number of threads applying committed raft entries, each one queuing
+    // at most apply_queue_size batches, the others wait in the raft store.
+    pub apply_pool_size: usize,
+    pub apply_queue_size: usize,
+
+    // snapshots are sent in chunks of snap_chunk_size, all the snapshots
+    // sent by a store share a bandwidth of snap_max_bytes_per_sec.
+    pub snap_chunk_size: DiskSize,
+    pub snap_max_bytes_per_sec: DiskSize,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self { 
             store_addr: "127.0.0.1:20160".to_string(), 
+            labels: vec![],
             is_raft: true, 
             scheduler_addr: "127.0.0.1:2379".to_string(), 
             db_path: "/tmp/data".to_string(), 
             raft_base_tick_interval: Duration::from_secs(1), 
             raft_heart_beat_ticks: Duration::from_secs(2), 
-            raft_election_timeout_ticks: Duration::from_millis(10), 
+            raft_election_timeout_ticks: Duration::from_secs(10), 
             raft_log_gc_tick_interval: Duration::from_secs(10), 
             raft_log_gc_count_limit: 128_000, // assume the average size of entries is 1k.
             split_region_check_tick_interval: Duration::from_secs(10), 
             scheduler_heartbeat_tick_interval: Duration::from_secs(10),  
             scheduler_store_heartbeat_tick_interval: Duration::from_secs(10),
+            capacity: DiskSize::GiB(100),
             region_max_size: DiskSize::MiB(144), 
             region_split_size: DiskSize::MiB(96), 
+            merge_check_tick_interval: Duration::from_secs(10),
+            region_merge_max_size: DiskSize::MiB(20),
+            region_merge_max_merged_size: DiskSize::MiB(96),
+            raft_msg_max_batch_size: 256,
+            raft_msg_flush_interval: Duration::from_millis(2),
+            raft_client_backoff: Duration::from_millis(100),
+            raft_client_max_backoff: Duration::from_secs(10),
+            apply_pool_size: 2,
+            apply_queue_size: 1024,
+            snap_chunk_size: DiskSize::MiB(1),
+            snap_max_bytes_per_sec: DiskSize::MiB(100),
         }
     }
 }
@@ -90,6 +138,26 @@ impl Config {
         if self.raft_election_timeout_ticks <= self.raft_heart_beat_ticks {
             bail!("election tick must be greater than heartbeat tick.")
         }
+
+        if self.region_merge_max_merged_size.num_bytes() >= self.region_max_size.num_bytes() {
+            bail!("merged region size must be less than region max size, otherwise it would be split again.")
+        }
+
+        if self.raft_msg_max_batch_size == 0 {
+            bail!("raft message batch size must be greater than 0.")
+        }
+
+        if self.snap_chunk_size.num_bytes() == 0 {
+            bail!("snapshot chunk size must be greater than 0.")
+        }
+
+        if self.labels.iter().any(|(key, value)| key.is_empty() || value.is_empty()) {
+            bail!("store labels must have a key and a value.")
+        }
+
+        if self.apply_pool_size == 0 || self.apply_queue_size == 0 {
+            bail!("apply pool and queue sizes must be greater than 0.")
+        }
         Ok(())
     }
 
@@ -97,6 +165,7 @@ impl Config {
     pub fn for_test() -> Self {
         Self {
             store_addr: "127.0.0.1:20160".to_string(), 
+            labels: vec![],
             is_raft: true, 
             scheduler_addr: "127.0.0.1:2379".to_string(), 
             db_path: "/tmp/data".to_string(), 
@@ -108,8 +177,20 @@ impl Config {
             split_region_check_tick_interval: Duration::from_millis(100), 
             scheduler_heartbeat_tick_interval: Duration::from_millis(100),  
             scheduler_store_heartbeat_tick_interval: Duration::from_millis(500),
+            capacity: DiskSize::GiB(1),
             region_max_size: DiskSize::MiB(144), 
             region_split_size: DiskSize::MiB(96), 
+            merge_check_tick_interval: Duration::from_millis(100),
+            region_merge_max_size: DiskSize::MiB(20),
+            region_merge_max_merged_size: DiskSize::MiB(96),
+            raft_msg_max_batch_size: 256,
+            raft_msg_flush_interval: Duration::from_millis(2),
+            raft_client_backoff: Duration::from_millis(50),
+            raft_client_max_backoff: Duration::from_millis(500),
+            apply_pool_size: 2,
+            apply_queue_size: 1024,
+            snap_chunk_size: DiskSize::KiB(4),
+            snap_max_bytes_per_sec: DiskSize::MiB(100),
         }
     }
 }
@@ -3,6 +3,12 @@
 #[derive(Debug)]
 pub struct TkvError(String);
 
+impl TkvError {
+    pub fn new(err: String) -> Self {
+        Self(err)
+    }
+}
+
 impl<T: std::error::Error> From<T> for TkvError {
     fn from(source: T) -> Self {
         Self(format!("TkvError({})", source.to_string()))
@@ -3,10 +3,13 @@ pub mod storage;
 pub mod util;
 pub mod error;
 pub mod server;
+pub mod raftstore;
+pub mod transaction;
 
 
 use std::ops::Bound;
 
+use serde::{Deserialize, Serialize};
 use strum::EnumString;
 
 use self::{error::TkvResult, storage::mutation::Mutation};
@@ -16,12 +19,14 @@ pub trait KvIterator: Iterator<Item = TkvResult<(Vec<u8>, Vec<u8>)>> {}
 
 impl<I: Iterator<Item = TkvResult<(Vec<u8>, Vec<u8>)>>> KvIterator for I{}
 
-#[derive(Debug, Copy, Clone, PartialEq, EnumString)]
+#[derive(Debug, Copy, Clone, PartialEq, EnumString, Serialize, Deserialize)]
 #[strum(serialize_all = "snake_case")]
 pub enum ColumnFamily {
     Default,
     Write,
     Lock,
+    // raft logs and states of the regions, see raftstore::engine.
+    Raft,
 }
 
 impl ColumnFamily {
@@ -30,16 +35,31 @@ impl ColumnFamily {
             ColumnFamily::Default => b"default_".to_vec(),
             ColumnFamily::Write => b"write_".to_vec(),
             ColumnFamily::Lock => b"lock_".to_vec(),
+            ColumnFamily::Raft => b"raft_".to_vec(),
         };
         real_key.extend(key);
         real_key
     }
 
-    pub fn add_bound_prefix(&self, bound: Bound<Vec<u8>>) -> Bound<Vec<u8>> {
+    // An unbounded start is the first key of the column family.
+    pub fn add_start_bound_prefix(&self, bound: Bound<Vec<u8>>) -> Bound<Vec<u8>> {
         match  bound {
             Bound::Included(v) => Bound::Included(self.add_prefix(&v)),
             Bound::Excluded(v) => Bound::Excluded(self.add_prefix(&v)),
-            Bound::Unbounded => Bound::Unbounded,
+            Bound::Unbounded => Bound::Included(self.add_prefix(&[])),
+        }
+    }
+
+    // An unbounded end stops before the keys of the next column family.
+    pub fn add_end_bound_prefix(&self, bound: Bound<Vec<u8>>) -> Bound<Vec<u8>> {
+        match  bound {
+            Bound::Included(v) => Bound::Included(self.add_prefix(&v)),
+            Bound::Excluded(v) => Bound::Excluded(self.add_prefix(&v)),
+            Bound::Unbounded => {
+                let mut end = self.add_prefix(&[]);
+                *end.last_mut().unwrap() += 1;
+                Bound::Excluded(end)
+            },
         }
     }
 
@@ -48,6 +68,7 @@ impl ColumnFamily {
             ColumnFamily::Default => key.strip_prefix(b"default_"),
             ColumnFamily::Write => key.strip_prefix(b"write_"),
             ColumnFamily::Lock => key.strip_prefix(b"lock_"),
+            ColumnFamily::Raft => key.strip_prefix(b"raft_"),
         };
         real_key.unwrap().to_vec()
     }
@@ -177,6 +198,12 @@ mod tests {
             fn scan() -> TkvResult<()> {
                 let (storage, cf) = $setup;
 
+                // keys of the other column families are never scanned.
+                for other in [ColumnFamily::Default, ColumnFamily::Lock, ColumnFamily::Raft, ColumnFamily::Write] {
+                    if other != cf {
+                        storage.put(other, b"b", vec![0])?;
+                    }
+                }
                 storage.put(cf, b"a", vec![1])?;
                 storage.put(cf, b"b", vec![2])?;
                 storage.put(cf, b"ba", vec![2, 1])?;
@@ -214,7 +241,23 @@ mod tests {
 
                 Ok(())
             }
-            
+
+            // several scans may be open at once, and keys read meanwhile.
+            #[test]
+            fn concurrent_scans() -> TkvResult<()> {
+                let (storage, cf) = $setup;
+                storage.put(cf, b"a", vec![1])?;
+                storage.put(cf, b"b", vec![2])?;
+
+                let first = storage.scan(cf, Bound::Unbounded, Bound::Unbounded)?;
+                let mut first = first.iter();
+                assert_eq!(first.next().transpose()?, Some((b"a".to_vec(), vec![1])));
+                let second = storage.scan(cf, Bound::Included(b"b".to_vec()), Bound::Unbounded)?;
+                assert_iter(second.iter(), vec![(b"b".to_vec(), vec![2])])?;
+                assert_eq!(storage.get(cf, b"a")?, Some(vec![1]));
+                assert_iter(first, vec![(b"b".to_vec(), vec![2])])?;
+                Ok(())
+            }
 
         };
     }
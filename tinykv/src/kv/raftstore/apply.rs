@@ -0,0 +1,600 @@
+use std::{collections::{HashMap, VecDeque}, path::PathBuf, sync::Arc, thread};
+
+use tokio::sync::{mpsc::{self, error::TrySendError}, oneshot};
+
+use crate::{kv::{error::{TkvError, TkvResult}, storage::mutation::Mutation, Storage}, proto::{metapb::{self, Region}, raft_serverpb::SnapshotMeta}};
+
+use super::{cmd::{AdminCmd, ChangePeerType, CmdRequest, RaftCmd}, engine::{ApplyState, PeerState, RaftWriteBatch, RegionLocalState}, peer::{Entry, MergeState, Peer}, region::{change_peer, merge_regions, region_epoch, split_region}, snap::{clear_region_data, SnapManager}, store::{Callback, StoreMsg, StoreSender}};
+
+
+// The outcome of applying a committed entry.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApplyResult {
+    None,
+    // the region was split, the first region is the updated
+    // version of the current one, others are new.
+    Split { regions: Vec<Region> },
+    // the source region stopped serving and waits to be merged into target.
+    PrepareMerge { region: Region, target: Region, commit: u64 },
+    // the source region was merged into this one, region is the merged region.
+    CommitMerge { region: Region, source: Region },
+    // the target region could not take over the source region.
+    CommitMergeFailed { source_id: u64, commit: u64 },
+    RollbackMerge { region: Region },
+    // the data of the region was replaced by a snapshot.
+    Snapshot { region: Region },
+    // the membership of the region changed, region is the new one.
+    ChangePeer { region: Region, change_type: ChangePeerType, peer: metapb::Peer },
+}
+
+// Apply progress of a region, sent back to the raft store
+// after each batch of entries is applied.
+#[derive(Debug)]
+pub struct ApplyRes {
+    pub region_id: u64,
+    pub applied_index: u64,
+    pub region: Region,
+    pub merge_state: Option<MergeState>,
+    // the results other than ApplyResult::None, in log order.
+    pub results: Vec<ApplyResult>,
+}
+
+#[derive(Debug)]
+pub enum ApplyTask {
+    // starts applying the entries of a region.
+    Register(ApplyDelegate),
+    // applies committed entries, the callbacks are keyed by entry index.
+    Apply { region_id: u64, entries: Vec<Entry>, callbacks: HashMap<u64, Callback> },
+    Destroy { region_id: u64 },
+    // drops a peer removed from its region, along with its data.
+    Remove { region: Region },
+    // generates a snapshot of the region as of its last applied entry.
+    GenSnapshot { region_id: u64, term: u64, callback: oneshot::Sender<TkvResult<(SnapshotMeta, PathBuf)>> },
+    // applies a snapshot received from the leader, the file is removed once applied.
+    ApplySnapshot { meta: SnapshotMeta, path: PathBuf },
+}
+
+impl ApplyTask {
+    fn region_id(&self) -> u64 {
+        match self {
+            ApplyTask::Register(delegate) => delegate.region.id,
+            ApplyTask::Apply { region_id, .. } => *region_id,
+            ApplyTask::Destroy { region_id } => *region_id,
+            ApplyTask::Remove { region } => region.id,
+            ApplyTask::GenSnapshot { region_id, .. } => *region_id,
+            ApplyTask::ApplySnapshot { meta, .. } => meta.region.as_ref().map(|region| region.id).unwrap_or_default(),
+        }
+    }
+}
+
+
+/// ApplyDelegate is the apply side of a peer. It owns the region as seen
+/// by the applied entries, the peer catches up through ApplyRes.
+#[derive(Debug)]
+pub struct ApplyDelegate {
+    region: Region,
+    merge_state: Option<MergeState>,
+    applied_index: u64,
+}
+
+impl ApplyDelegate {
+    pub fn new(peer: &Peer) -> Self {
+        Self {
+            region: peer.region.clone(),
+            merge_state: peer.merge_state().cloned(),
+            applied_index: peer.applied_index(),
+        }
+    }
+
+    // Applies a committed entry. Its writes, the applied index and the
+    // region changes it makes are persisted within a single write. A
+    // rejected command is applied as a no-op and returned as the inner
+    // error, the outer one leaves the entry unapplied.
+    pub fn apply(&mut self, storage: &dyn Storage, entry: &Entry) -> TkvResult<TkvResult<ApplyResult>> {
+        let cmd = RaftCmd::decode(&entry.data)?;
+        let mut wb = RaftWriteBatch::default();
+        wb.set_apply_state(self.region.id, &ApplyState { applied_index: entry.index })?;
+
+        let (region, merge_state) = (self.region.clone(), self.merge_state.clone());
+        let mut data = vec![];
+        let result = self.exec(entry.index, cmd, &mut data);
+        match &result {
+            Ok(ApplyResult::None) | Ok(ApplyResult::CommitMergeFailed { .. }) | Ok(ApplyResult::Snapshot { .. }) => (),
+            Ok(ApplyResult::Split { regions }) => {
+                for region in regions {
+                    wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+                }
+            },
+            Ok(ApplyResult::PrepareMerge { region, target, commit }) => {
+                let state = PeerState::Merging(MergeState { target: target.clone(), commit: *commit });
+                wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state })?;
+            },
+            Ok(ApplyResult::CommitMerge { region, source }) => {
+                wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+                wb.set_region_state(source.id, &RegionLocalState { region: source.clone(), state: PeerState::Tombstone })?;
+            },
+            Ok(ApplyResult::RollbackMerge { region }) | Ok(ApplyResult::ChangePeer { region, .. }) => {
+                wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+            },
+            // a rejected command only moves the applied index.
+            Err(_) => data.clear(),
+        }
+        data.extend(wb.into_mutations());
+        if let Err(err) = storage.write(data) {
+            (self.region, self.merge_state) = (region, merge_state);
+            return Err(err);
+        }
+        self.applied_index = entry.index;
+        Ok(result)
+    }
+
+    fn exec(&mut self, index: u64, cmd: RaftCmd, data: &mut Vec<Mutation>) -> TkvResult<ApplyResult> {
+        let is_stale = self.is_stale(&cmd);
+
+        match cmd.request {
+            // a failed commit is reported so that the source can be rolled back.
+            CmdRequest::Admin(AdminCmd::CommitMerge { source, commit, .. }) => {
+                let merged = if is_stale {
+                    Err(TkvError::new(format!("stale merge command for region {}", self.region.id)))
+                } else {
+                    merge_regions(&source, &self.region)
+                };
+                match merged {
+                    Ok(region) => {
+                        self.region = region.clone();
+                        Ok(ApplyResult::CommitMerge { region, source })
+                    },
+                    Err(err) => {
+                        log::warn!("failed to merge region {} into {}: {:?}", source.id, self.region.id, err);
+                        Ok(ApplyResult::CommitMergeFailed { source_id: source.id, commit })
+                    },
+                }
+            },
+            // the region changed since the command was proposed.
+            _ if is_stale => {
+                Err(TkvError::new(format!("stale command for region {} at index {}", self.region.id, index)))
+            },
+            CmdRequest::Write(batch) => {
+                data.extend(batch);
+                Ok(ApplyResult::None)
+            },
+            CmdRequest::Admin(AdminCmd::Split { split_keys, new_regions }) => {
+                let regions = split_region(&self.region, &split_keys, &new_regions)?;
+                self.region = regions[0].clone();
+                Ok(ApplyResult::Split { regions })
+            },
+            CmdRequest::Admin(AdminCmd::PrepareMerge { target }) => {
+                if self.merge_state.is_some() {
+                    return Err(TkvError::new(format!("region {} is already merging", self.region.id)));
+                }
+                // both versions are bumped so that no in-flight
+                // command can be applied to the source any more.
+                let mut epoch = region_epoch(&self.region);
+                epoch.version += 1;
+                epoch.config_version += 1;
+                self.region.region_epoch = Some(epoch);
+                self.merge_state = Some(MergeState { target: target.clone(), commit: index });
+                Ok(ApplyResult::PrepareMerge { region: self.region.clone(), target, commit: index })
+            },
+            CmdRequest::Admin(AdminCmd::RollbackMerge { commit }) => {
+                if self.merge_state.as_ref().map(|state| state.commit) != Some(commit) {
+                    return Ok(ApplyResult::None);
+                }
+                self.merge_state = None;
+                let mut epoch = region_epoch(&self.region);
+                epoch.version += 1;
+                self.region.region_epoch = Some(epoch);
+                Ok(ApplyResult::RollbackMerge { region: self.region.clone() })
+            },
+            CmdRequest::Admin(AdminCmd::ChangePeer { change_type, peer }) => {
+                self.region = change_peer(&self.region, change_type, &peer)?;
+                Ok(ApplyResult::ChangePeer { region: self.region.clone(), change_type, peer })
+            },
+        }
+    }
+
+    fn is_stale(&self, cmd: &RaftCmd) -> bool {
+        let epoch = region_epoch(&self.region);
+        cmd.version != epoch.version || (cmd.is_admin() && cmd.conf_ver != epoch.config_version)
+    }
+
+    fn apply_res(&self, results: Vec<ApplyResult>) -> ApplyRes {
+        ApplyRes {
+            region_id: self.region.id,
+            applied_index: self.applied_index,
+            region: self.region.clone(),
+            merge_state: self.merge_state.clone(),
+            results,
+        }
+    }
+}
+
+
+/// ApplyPool applies committed entries on a pool of worker threads so that
+/// large writes do not stall the raft store. A region is always handled by
+/// the same worker, which keeps its entries applied in log order. The tasks
+/// of a region whose worker is lagging behind are kept until it has room.
+#[derive(Debug)]
+pub struct ApplyPool {
+    senders: Vec<mpsc::Sender<ApplyTask>>,
+    // region_id -> tasks waiting for room on the worker of the region
+    pending: HashMap<u64, VecDeque<ApplyTask>>,
+}
+
+impl ApplyPool {
+    pub fn new(
+        pool_size: usize,
+        queue_size: usize,
+        storage: Arc<dyn Storage>,
+        snap_manager: Arc<SnapManager>,
+        store_tx: StoreSender,
+    ) -> TkvResult<Self> {
+        let mut senders = vec![];
+        for id in 0..pool_size.max(1) {
+            let (sender, receiver) = mpsc::channel(queue_size);
+            let worker = ApplyWorker {
+                storage: storage.clone(),
+                snap_manager: snap_manager.clone(),
+                store_tx: store_tx.clone(),
+                delegates: HashMap::new(),
+            };
+            thread::Builder::new()
+                .name(format!("apply-{}", id))
+                .spawn(move || worker.run(receiver))?;
+            senders.push(sender);
+        }
+        Ok(Self { senders, pending: HashMap::new() })
+    }
+
+    // Queues a task on the worker of its region, or behind the tasks
+    // of the region still waiting for room. It never blocks the store.
+    pub fn schedule(&mut self, task: ApplyTask) -> TkvResult<()> {
+        let region_id = task.region_id();
+        self.pending.entry(region_id).or_default().push_back(task);
+        self.flush_region(region_id)
+    }
+
+    // Hands the pending tasks over to the workers that have room.
+    pub fn flush(&mut self) -> TkvResult<()> {
+        let regions: Vec<u64> = self.pending.keys().copied().collect();
+        for region_id in regions {
+            self.flush_region(region_id)?;
+        }
+        Ok(())
+    }
+
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    fn flush_region(&mut self, region_id: u64) -> TkvResult<()> {
+        let worker = (region_id % self.senders.len() as u64) as usize;
+        let Some(tasks) = self.pending.get_mut(&region_id) else {
+            return Ok(());
+        };
+        while let Some(task) = tasks.pop_front() {
+            match self.senders[worker].try_send(task) {
+                Ok(()) => (),
+                Err(TrySendError::Full(task)) => {
+                    tasks.push_front(task);
+                    return Ok(());
+                },
+                Err(TrySendError::Closed(_)) => return Err(TkvError::new(format!("apply worker {} is stopped", worker))),
+            }
+        }
+        self.pending.remove(&region_id);
+        Ok(())
+    }
+}
+
+struct ApplyWorker {
+    storage: Arc<dyn Storage>,
+    snap_manager: Arc<SnapManager>,
+    store_tx: StoreSender,
+    delegates: HashMap<u64, ApplyDelegate>,
+}
+
+impl ApplyWorker {
+    // Runs until the pool is dropped.
+    fn run(mut self, mut receiver: mpsc::Receiver<ApplyTask>) {
+        while let Some(task) = receiver.blocking_recv() {
+            match task {
+                ApplyTask::Register(delegate) => {
+                    self.delegates.insert(delegate.region.id, delegate);
+                },
+                ApplyTask::Destroy { region_id } => {
+                    self.delegates.remove(&region_id);
+                },
+                ApplyTask::Remove { region } => {
+                    self.delegates.remove(&region.id);
+                    let cleared = clear_region_data(self.storage.as_ref(), &region)
+                        .and_then(|batch| self.storage.write(batch));
+                    if let Err(err) = cleared {
+                        log::error!("failed to clear the data of region {}: {:?}", region.id, err);
+                    }
+                },
+                ApplyTask::Apply { region_id, entries, callbacks } => {
+                    self.handle_apply(region_id, entries, callbacks);
+                },
+                ApplyTask::GenSnapshot { region_id, term, callback } => {
+                    let _ = callback.send(self.handle_gen_snapshot(region_id, term));
+                },
+                ApplyTask::ApplySnapshot { meta, path } => {
+                    if let Err(err) = self.handle_apply_snapshot(&meta, &path) {
+                        log::error!("failed to apply snapshot {:?}: {:?}", path, err);
+                    }
+                    let _ = std::fs::remove_file(&path);
+                },
+            }
+        }
+    }
+
+    // Applies the entries of a region in order. The region stops applying
+    // at the first entry that can't be written, the store is told so.
+    fn handle_apply(&mut self, region_id: u64, entries: Vec<Entry>, mut callbacks: HashMap<u64, Callback>) {
+        let Some(delegate) = self.delegates.get_mut(&region_id) else {
+            log::warn!("drop {} entries of unregistered region {}", entries.len(), region_id);
+            for (_, callback) in callbacks {
+                let _ = callback.send(Err(TkvError::new(format!("region {} is not applying entries", region_id))));
+            }
+            return;
+        };
+        let mut results = vec![];
+        let mut failed = None;
+        for entry in entries {
+            if entry.index <= delegate.applied_index {
+                continue;
+            }
+            let result = match delegate.apply(self.storage.as_ref(), &entry) {
+                Ok(result) => result,
+                Err(err) => {
+                    failed = Some(TkvError::new(format!("failed to apply entry {} of region {}: {:?}", entry.index, region_id, err)));
+                    break;
+                },
+            };
+            if let Some(callback) = callbacks.remove(&entry.index) {
+                let _ = callback.send(result.as_ref().map(|_| ()).map_err(|err| TkvError::new(format!("{:?}", err))));
+            }
+            match result {
+                Ok(ApplyResult::None) => (),
+                Ok(result) => results.push(result),
+                Err(err) => log::warn!("rejected entry {} of region {}: {:?}", entry.index, region_id, err),
+            }
+        }
+        let _ = self.store_tx.send(StoreMsg::ApplyRes(delegate.apply_res(results)));
+        if let Some(error) = failed {
+            self.delegates.remove(&region_id);
+            for (_, callback) in callbacks {
+                let _ = callback.send(Err(TkvError::new(format!("{:?}", error))));
+            }
+            let _ = self.store_tx.send(StoreMsg::ApplyFailed { region_id, error });
+        }
+    }
+
+    fn handle_gen_snapshot(&mut self, region_id: u64, term: u64) -> TkvResult<(SnapshotMeta, PathBuf)> {
+        let delegate = self.delegates.get(&region_id)
+            .ok_or(TkvError::new(format!("region {} is not registered", region_id)))?;
+        self.snap_manager.generate(self.storage.as_ref(), &delegate.region, term, delegate.applied_index)
+    }
+
+    fn handle_apply_snapshot(&mut self, meta: &SnapshotMeta, path: &std::path::Path) -> TkvResult<()> {
+        let region = meta.region.clone().unwrap_or_default();
+        let Some(delegate) = self.delegates.get_mut(&region.id) else {
+            return Err(TkvError::new(format!("region {} is not registered", region.id)));
+        };
+        if meta.index <= delegate.applied_index {
+            return Ok(());
+        }
+        self.snap_manager.apply(self.storage.as_ref(), meta, path)?;
+        delegate.region = region.clone();
+        delegate.merge_state = None;
+        delegate.applied_index = meta.index;
+        let _ = self.store_tx.send(StoreMsg::ApplyRes(delegate.apply_res(vec![ApplyResult::Snapshot { region }])));
+        Ok(())
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{ops::Bound, sync::{atomic::{AtomicBool, Ordering}, Arc}, time::Duration};
+
+    use tempfile::tempdir;
+    use tokio::sync::{mpsc::unbounded_channel, oneshot};
+
+    use crate::kv::config::Config;
+    use crate::kv::error::{TkvError, TkvResult};
+    use crate::kv::raftstore::cmd::{AdminCmd, CmdRequest, RaftCmd, SplitIds};
+    use crate::kv::raftstore::peer::Peer;
+    use crate::kv::raftstore::region::region_epoch;
+    use crate::kv::raftstore::snap::SnapManager;
+    use crate::kv::raftstore::store::StoreMsg;
+    use crate::kv::storage::{memory::MemoryStorage, mutation::Mutation};
+    use crate::kv::{ColumnFamily, Storage, StorageScanner};
+    use crate::proto::metapb::{Peer as PeerMeta, Region, RegionEpoch};
+
+    use super::{ApplyDelegate, ApplyPool, ApplyResult, ApplyTask};
+
+    fn new_region(id: u64) -> Region {
+        Region {
+            id,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: vec![PeerMeta { id, store_id: 1, ..Default::default() }],
+        }
+    }
+
+    #[test]
+    fn propose_and_apply() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let mut peer = Peer::new(1, new_region(1), storage.clone())?;
+        let mut delegate = ApplyDelegate::new(&peer);
+
+        let put = Mutation::Put { key: b"k".to_vec(), value: vec![1], cf: ColumnFamily::Default };
+        peer.propose(&RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put])))?;
+        let split = AdminCmd::Split {
+            split_keys: vec![b"m".to_vec()],
+            new_regions: vec![SplitIds { region_id: 2, peer_ids: vec![2] }],
+        };
+        peer.propose(&RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Admin(split.clone())))?;
+
+        let entries = peer.take_committed_entries();
+        assert_eq!(entries.len(), 2);
+        assert!(peer.take_committed_entries().is_empty());
+        assert_eq!(delegate.apply(storage.as_ref(), &entries[0])??, ApplyResult::None);
+        assert_eq!(storage.get(ColumnFamily::Default, b"k")?, Some(vec![1]));
+        match delegate.apply(storage.as_ref(), &entries[1])?? {
+            ApplyResult::Split { regions } => assert_eq!(regions.len(), 2),
+            res => panic!("unexpected apply result {:?}", res),
+        }
+        peer.on_apply_res(&delegate.apply_res(vec![]))?;
+        assert_eq!(peer.region.end_key, b"m".to_vec());
+        assert_eq!(peer.applied_index(), 2);
+
+        // the same split proposed with the old epoch is rejected.
+        peer.propose(&RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Admin(split)))?;
+        let entries = peer.take_committed_entries();
+        assert!(delegate.apply(storage.as_ref(), &entries[0])?.is_err());
+        assert_eq!(delegate.region.region_epoch.as_ref().unwrap().version, 2);
+        Ok(())
+    }
+
+    #[test]
+    fn prepare_and_rollback_merge() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let mut region = new_region(1);
+        region.end_key = b"k".to_vec();
+        let mut target = region.clone();
+        (target.id, target.start_key, target.end_key) = (2, b"k".to_vec(), vec![]);
+        let mut peer = Peer::new(1, region, storage.clone())?;
+        let mut delegate = ApplyDelegate::new(&peer);
+
+        let epoch = region_epoch(&peer.region);
+        peer.propose(&RaftCmd::new(1, &epoch, CmdRequest::Admin(AdminCmd::PrepareMerge { target })))?;
+        let entries = peer.take_committed_entries();
+        assert!(matches!(delegate.apply(storage.as_ref(), &entries[0])??, ApplyResult::PrepareMerge { commit: 1, .. }));
+        peer.on_apply_res(&delegate.apply_res(vec![]))?;
+        assert_eq!(peer.merge_state().map(|state| state.commit), Some(1));
+        assert_eq!(region_epoch(&peer.region), RegionEpoch { config_version: 2, version: 2 });
+
+        // writes are rejected while merging.
+        let epoch = region_epoch(&peer.region);
+        assert!(peer.propose(&RaftCmd::new(1, &epoch, CmdRequest::Write(vec![]))).is_err());
+
+        peer.propose(&RaftCmd::new(1, &epoch, CmdRequest::Admin(AdminCmd::RollbackMerge { commit: 1 })))?;
+        let entries = peer.take_committed_entries();
+        assert!(matches!(delegate.apply(storage.as_ref(), &entries[0])??, ApplyResult::RollbackMerge { .. }));
+        peer.on_apply_res(&delegate.apply_res(vec![]))?;
+        assert_eq!(peer.merge_state(), None);
+        assert_eq!(region_epoch(&peer.region), RegionEpoch { config_version: 2, version: 3 });
+        assert!(peer.propose(&RaftCmd::new(1, &region_epoch(&peer.region), CmdRequest::Write(vec![]))).is_ok());
+        Ok(())
+    }
+
+    // Fails the writes while fail is set.
+    #[derive(Debug)]
+    struct FailingStorage {
+        inner: MemoryStorage,
+        fail: AtomicBool,
+    }
+
+    impl Storage for FailingStorage {
+        fn start(&self) -> TkvResult<()> {
+            Ok(())
+        }
+
+        fn stop(self) -> TkvResult<()> {
+            Ok(())
+        }
+
+        fn write(&self, batch: Vec<Mutation>) -> TkvResult<()> {
+            if self.fail.load(Ordering::SeqCst) {
+                return Err(TkvError::new("disk is full".to_string()));
+            }
+            self.inner.write(batch)
+        }
+
+        fn get(&self, cf: ColumnFamily, key: &[u8]) -> TkvResult<Option<Vec<u8>>> {
+            self.inner.get(cf, key)
+        }
+
+        fn scan(&self, cf: ColumnFamily, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> TkvResult<Box<dyn StorageScanner<'_> + '_>> {
+            self.inner.scan(cf, start, end)
+        }
+    }
+
+    #[test]
+    fn failed_write() -> TkvResult<()> {
+        let storage = Arc::new(FailingStorage { inner: MemoryStorage::new(), fail: AtomicBool::new(false) });
+        let mut peer = Peer::new(1, new_region(1), storage.clone())?;
+        let mut delegate = ApplyDelegate::new(&peer);
+        let split = AdminCmd::Split {
+            split_keys: vec![b"m".to_vec()],
+            new_regions: vec![SplitIds { region_id: 2, peer_ids: vec![2] }],
+        };
+        peer.propose(&RaftCmd::new(1, &region_epoch(&peer.region), CmdRequest::Admin(split)))?;
+        let entries = peer.take_committed_entries();
+
+        // nothing is applied until the entry is written.
+        storage.fail.store(true, Ordering::SeqCst);
+        assert!(delegate.apply(storage.as_ref(), &entries[0]).is_err());
+        assert_eq!((delegate.applied_index, delegate.region.end_key.clone()), (0, vec![]));
+        storage.fail.store(false, Ordering::SeqCst);
+        assert!(matches!(delegate.apply(storage.as_ref(), &entries[0])??, ApplyResult::Split { .. }));
+        assert_eq!((delegate.applied_index, delegate.region.end_key.clone()), (1, b"m".to_vec()));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn apply_in_order() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let (store_tx, mut store_rx) = unbounded_channel();
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &Config::for_test())?);
+        let mut pool = ApplyPool::new(2, 4, storage.clone(), snap_manager, store_tx)?;
+
+        let mut peers = vec![];
+        for region_id in 1..=3 {
+            let peer = Peer::new(1, new_region(region_id), storage.clone())?;
+            pool.schedule(ApplyTask::Register(ApplyDelegate::new(&peer)))?;
+            peers.push(peer);
+        }
+
+        // every region overwrites the same key, the last write must win.
+        let mut applied = vec![];
+        for round in 0..20u8 {
+            for peer in peers.iter_mut() {
+                let region_id = peer.region_id();
+                let put = Mutation::Put { key: region_id.to_be_bytes().to_vec(), value: vec![round], cf: ColumnFamily::Default };
+                peer.propose(&RaftCmd::new(region_id, &region_epoch(&peer.region), CmdRequest::Write(vec![put])))?;
+                let entries = peer.take_committed_entries();
+                let (callback, result) = oneshot::channel();
+                let callbacks = [(entries[0].index, callback)].into_iter().collect();
+                pool.schedule(ApplyTask::Apply { region_id, entries, callbacks })?;
+                applied.push(result);
+            }
+        }
+        // the workers have room for a few tasks only, the others wait.
+        while pool.has_pending() {
+            pool.flush()?;
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        for result in applied {
+            result.await??;
+        }
+
+        let mut applied_index = [0u64; 3];
+        while let Ok(Some(StoreMsg::ApplyRes(res))) = tokio::time::timeout(Duration::from_millis(100), store_rx.recv()).await {
+            let last = &mut applied_index[res.region_id as usize - 1];
+            assert_eq!(res.applied_index, *last + 1);
+            *last = res.applied_index;
+        }
+        assert_eq!(applied_index, [20; 3]);
+        for region_id in 1..=3u64 {
+            assert_eq!(storage.get(ColumnFamily::Default, &region_id.to_be_bytes())?, Some(vec![19]));
+        }
+        Ok(())
+    }
+}
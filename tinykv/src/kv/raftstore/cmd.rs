@@ -0,0 +1,112 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{kv::{error::{TkvError, TkvResult}, storage::mutation::Mutation}, proto::metapb::{self, Region, RegionEpoch}};
+
+use super::peer::Entry;
+
+
+// Ids for a region created by a split, one peer id
+// per peer of the region being split (in the same order).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SplitIds {
+    pub region_id: u64,
+    pub peer_ids: Vec<u64>,
+}
+
+// Admin commands change the region metadata rather than the user data.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum AdminCmd {
+    Split {
+        split_keys: Vec<Vec<u8>>,
+        new_regions: Vec<SplitIds>,
+    },
+    // Proposed to the source region, it stops serving writes
+    // until the merge is either committed or rolled back.
+    PrepareMerge {
+        #[serde(with = "pb_message")]
+        target: Region,
+    },
+    // Proposed to the target region, extends its range over the source.
+    // commit is the log index of the source's PrepareMerge, entries are
+    // the source entries up to there that a follower may still miss.
+    CommitMerge {
+        #[serde(with = "pb_message")]
+        source: Region,
+        commit: u64,
+        entries: Vec<Entry>,
+    },
+    // Proposed to the source region when the merge could not be committed.
+    RollbackMerge {
+        commit: u64,
+    },
+    // Changes the membership of the region, one peer at a time.
+    ChangePeer {
+        change_type: ChangePeerType,
+        #[serde(with = "pb_message")]
+        peer: metapb::Peer,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ChangePeerType {
+    AddLearner,
+    // turns a learner into a voter.
+    Promote,
+    Remove,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum CmdRequest {
+    Write(Vec<Mutation>),
+    Admin(AdminCmd),
+}
+
+// RaftCmd is what gets proposed to and replicated by a region's raft group.
+// The epoch is the one seen at proposal time, a command whose epoch
+// no longer matches the region's is rejected when applied.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RaftCmd {
+    pub region_id: u64,
+    pub conf_ver: u64,
+    pub version: u64,
+    pub request: CmdRequest,
+}
+
+impl RaftCmd {
+    pub fn new(region_id: u64, epoch: &RegionEpoch, request: CmdRequest) -> Self {
+        Self {
+            region_id,
+            conf_ver: epoch.config_version,
+            version: epoch.version,
+            request,
+        }
+    }
+
+    pub fn encode(&self) -> TkvResult<Vec<u8>> {
+        bincode::serialize(self).map_err(TkvError::from)
+    }
+
+    pub fn decode(data: &[u8]) -> TkvResult<Self> {
+        bincode::deserialize(data).map_err(TkvError::from)
+    }
+
+    pub fn is_admin(&self) -> bool {
+        matches!(self.request, CmdRequest::Admin(_))
+    }
+}
+
+
+// (De)serializes protobuf messages embedded in commands as their encoded bytes.
+pub(crate) mod pb_message {
+    use prost::Message;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<M: Message, S: Serializer>(msg: &M, serializer: S) -> Result<S::Ok, S::Error> {
+        msg.encode_to_vec().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, M: Message + Default, D: Deserializer<'de>>(deserializer: D) -> Result<M, D::Error> {
+        let data = Vec::<u8>::deserialize(deserializer)?;
+        M::decode(data.as_slice()).map_err(D::Error::custom)
+    }
+}
@@ -0,0 +1,280 @@
+use std::{ops::Bound, sync::Arc};
+
+use byteorder::{BigEndian, ByteOrder};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{kv::{error::{TkvError, TkvResult}, storage::mutation::Mutation, ColumnFamily, Storage}, proto::metapb::Region};
+
+use super::{cmd::pb_message, peer::{Entry, MergeState}};
+
+
+// Keys of the raft column family:
+//   store id:     STORE_IDENT_KEY
+//   log entry:    LOG_PREFIX   | region_id | index
+//   region state: STATE_PREFIX | region_id | state suffix
+// integers are big endian so that the keys of a region sort by index.
+const STORE_IDENT_KEY: u8 = 0x00;
+const LOG_PREFIX: u8 = 0x01;
+const STATE_PREFIX: u8 = 0x02;
+
+const HARD_STATE_SUFFIX: u8 = 0x01;
+const APPLY_STATE_SUFFIX: u8 = 0x02;
+const REGION_STATE_SUFFIX: u8 = 0x03;
+const TRUNCATED_STATE_SUFFIX: u8 = 0x04;
+
+fn log_key(region_id: u64, index: u64) -> Vec<u8> {
+    let mut key = vec![0u8; 17];
+    key[0] = LOG_PREFIX;
+    BigEndian::write_u64(&mut key[1..9], region_id);
+    BigEndian::write_u64(&mut key[9..], index);
+    key
+}
+
+fn state_key(region_id: u64, suffix: u8) -> Vec<u8> {
+    let mut key = vec![0u8; 10];
+    key[0] = STATE_PREFIX;
+    BigEndian::write_u64(&mut key[1..9], region_id);
+    key[9] = suffix;
+    key
+}
+
+// The raft state that must survive a restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HardState {
+    pub term: u64,
+    pub vote: u64,
+    pub commit: u64,
+}
+
+// Index of the last entry applied to the storage, written
+// in the same batch as the data of that entry.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ApplyState {
+    pub applied_index: u64,
+}
+
+// The last entry dropped from the log, by a compaction or a snapshot.
+// Its term is checked when the entries following it are appended.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TruncatedState {
+    pub index: u64,
+    pub term: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PeerState {
+    Normal,
+    Merging(MergeState),
+    // the region was merged away, its peer must not be recreated.
+    Tombstone,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegionLocalState {
+    #[serde(with = "pb_message")]
+    pub region: Region,
+    pub state: PeerState,
+}
+
+
+/// RaftWriteBatch collects the raft writes of one or more regions so that
+/// they can be persisted within a single storage write.
+#[derive(Debug, Default)]
+pub struct RaftWriteBatch {
+    mutations: Vec<Mutation>,
+}
+
+impl RaftWriteBatch {
+    pub fn append(&mut self, region_id: u64, entries: &[Entry]) -> TkvResult<()> {
+        for entry in entries {
+            self.put(log_key(region_id, entry.index), entry)?;
+        }
+        Ok(())
+    }
+
+    pub fn delete_entry(&mut self, region_id: u64, index: u64) {
+        self.mutations.push(Mutation::Delete { key: log_key(region_id, index), cf: ColumnFamily::Raft });
+    }
+
+    pub fn set_hard_state(&mut self, region_id: u64, state: &HardState) -> TkvResult<()> {
+        self.put(state_key(region_id, HARD_STATE_SUFFIX), state)
+    }
+
+    pub fn set_apply_state(&mut self, region_id: u64, state: &ApplyState) -> TkvResult<()> {
+        self.put(state_key(region_id, APPLY_STATE_SUFFIX), state)
+    }
+
+    pub fn set_region_state(&mut self, region_id: u64, state: &RegionLocalState) -> TkvResult<()> {
+        self.put(state_key(region_id, REGION_STATE_SUFFIX), state)
+    }
+
+    pub fn set_truncated_state(&mut self, region_id: u64, state: &TruncatedState) -> TkvResult<()> {
+        self.put(state_key(region_id, TRUNCATED_STATE_SUFFIX), state)
+    }
+
+    // Removes the raft log and states of a region, but its region state.
+    pub fn clear_raft_state(&mut self, region_id: u64, entries: &[Entry]) {
+        for entry in entries {
+            self.delete_entry(region_id, entry.index);
+        }
+        for suffix in [HARD_STATE_SUFFIX, APPLY_STATE_SUFFIX, TRUNCATED_STATE_SUFFIX] {
+            self.mutations.push(Mutation::Delete { key: state_key(region_id, suffix), cf: ColumnFamily::Raft });
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mutations.is_empty()
+    }
+
+    pub fn into_mutations(self) -> Vec<Mutation> {
+        self.mutations
+    }
+
+    fn put<T: Serialize>(&mut self, key: Vec<u8>, value: &T) -> TkvResult<()> {
+        let value = bincode::serialize(value)?;
+        self.mutations.push(Mutation::Put { key, value, cf: ColumnFamily::Raft });
+        Ok(())
+    }
+}
+
+/// RaftEngine persists the raft logs and states of all the regions of
+/// a store in the raft column family of the store's storage.
+#[derive(Debug, Clone)]
+pub struct RaftEngine {
+    storage: Arc<dyn Storage>,
+}
+
+impl RaftEngine {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    pub fn write(&self, batch: RaftWriteBatch) -> TkvResult<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        self.storage.write(batch.into_mutations())
+    }
+
+    // Returns the entries of the region in [low, high).
+    pub fn entries(&self, region_id: u64, low: u64, high: u64) -> TkvResult<Vec<Entry>> {
+        let scanner = self.storage.scan(
+            ColumnFamily::Raft,
+            Bound::Included(log_key(region_id, low)),
+            Bound::Excluded(log_key(region_id, high)),
+        )?;
+        let mut entries = vec![];
+        for item in scanner.iter() {
+            let (_, value) = item?;
+            entries.push(bincode::deserialize(&value)?);
+        }
+        Ok(entries)
+    }
+
+    // The id the store was given when it first joined the cluster.
+    pub fn store_id(&self) -> TkvResult<Option<u64>> {
+        self.get(vec![STORE_IDENT_KEY])
+    }
+
+    pub fn set_store_id(&self, store_id: u64) -> TkvResult<()> {
+        self.storage.put(ColumnFamily::Raft, &[STORE_IDENT_KEY], bincode::serialize(&store_id)?)
+    }
+
+    pub fn hard_state(&self, region_id: u64) -> TkvResult<Option<HardState>> {
+        self.get(state_key(region_id, HARD_STATE_SUFFIX))
+    }
+
+    pub fn apply_state(&self, region_id: u64) -> TkvResult<Option<ApplyState>> {
+        self.get(state_key(region_id, APPLY_STATE_SUFFIX))
+    }
+
+    pub fn region_state(&self, region_id: u64) -> TkvResult<Option<RegionLocalState>> {
+        self.get(state_key(region_id, REGION_STATE_SUFFIX))
+    }
+
+    pub fn truncated_state(&self, region_id: u64) -> TkvResult<Option<TruncatedState>> {
+        self.get(state_key(region_id, TRUNCATED_STATE_SUFFIX))
+    }
+
+    // Returns the states of all the regions known by the store.
+    pub fn region_states(&self) -> TkvResult<Vec<RegionLocalState>> {
+        let scanner = self.storage.scan(
+            ColumnFamily::Raft,
+            Bound::Included(vec![STATE_PREFIX]),
+            Bound::Excluded(vec![STATE_PREFIX + 1]),
+        )?;
+        let mut states = vec![];
+        for item in scanner.iter() {
+            let (key, value) = item?;
+            if key.last() == Some(&REGION_STATE_SUFFIX) {
+                states.push(bincode::deserialize(&value)?);
+            }
+        }
+        Ok(states)
+    }
+
+    fn get<T: DeserializeOwned>(&self, key: Vec<u8>) -> TkvResult<Option<T>> {
+        match self.storage.get(ColumnFamily::Raft, &key)? {
+            Some(value) => bincode::deserialize(&value).map(Some).map_err(TkvError::from),
+            None => Ok(None),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tempfile::tempdir;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::peer::Entry;
+    use crate::kv::storage::disk::DiskStorage;
+    use crate::proto::metapb::Region;
+
+    use super::{HardState, PeerState, RaftEngine, RaftWriteBatch, RegionLocalState, TruncatedState};
+
+    fn new_entries(low: u64, high: u64) -> Vec<Entry> {
+        (low..high).map(|index| Entry { term: 1, index, data: vec![index as u8] }).collect()
+    }
+
+    #[test]
+    fn persist_raft_state() -> TkvResult<()> {
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("data");
+        std::fs::create_dir_all(&db_path)?;
+        let region = Region { id: 7, ..Default::default() };
+
+        {
+            let engine = RaftEngine::new(Arc::new(DiskStorage::new(&db_path)?));
+            assert_eq!(engine.store_id()?, None);
+            engine.set_store_id(3)?;
+            let mut batch = RaftWriteBatch::default();
+            batch.append(7, &new_entries(1, 300))?;
+            batch.append(8, &new_entries(1, 5))?;
+            batch.set_hard_state(7, &HardState { term: 2, vote: 1, commit: 250 })?;
+            batch.set_region_state(7, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+            engine.write(batch)?;
+
+            let mut batch = RaftWriteBatch::default();
+            for index in 1..100 {
+                batch.delete_entry(7, index);
+            }
+            batch.set_truncated_state(7, &TruncatedState { index: 99, term: 1 })?;
+            engine.write(batch)?;
+        }
+
+        // reopen the storage
+        let engine = RaftEngine::new(Arc::new(DiskStorage::new(&db_path)?));
+        assert_eq!(engine.entries(7, 0, u64::MAX)?, new_entries(100, 300));
+        assert_eq!(engine.entries(7, 256, 258)?, new_entries(256, 258));
+        assert_eq!(engine.entries(8, 0, u64::MAX)?, new_entries(1, 5));
+        assert_eq!(engine.hard_state(7)?, Some(HardState { term: 2, vote: 1, commit: 250 }));
+        assert_eq!(engine.hard_state(8)?, None);
+        assert_eq!(engine.truncated_state(7)?, Some(TruncatedState { index: 99, term: 1 }));
+        assert_eq!(engine.store_id()?, Some(3));
+        assert_eq!(engine.region_states()?, vec![RegionLocalState { region, state: PeerState::Normal }]);
+        Ok(())
+    }
+}
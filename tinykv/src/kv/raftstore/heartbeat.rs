@@ -0,0 +1,153 @@
+use std::{path::{Path, PathBuf}, sync::Arc};
+
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::{kv::{config::Config, transaction::concurrency::ConcurrencyManager}, proto::{metapb::{Peer, Region, Store, StoreLabel}, schedulerpb::{ConfChangeType, RegionHeartbeatResponse, StoreStats}}, scheduler::{client::SchedulerClient, hot_region::RegionFlow}};
+
+use super::{cmd::{AdminCmd, ChangePeerType, CmdRequest, RaftCmd}, store::{StoreMsg, StoreSender}};
+
+
+#[derive(Debug)]
+pub enum HeartbeatTask {
+    // the stats known by the raft store, completed with the disk usage.
+    Store(StoreStats),
+    Region { region: Region, leader: Peer, flow: RegionFlow },
+    // a peer of the store leads the region in term, the versions read
+    // by the previous leader may be above the max_ts of the store.
+    UpdateMaxTs { region_id: u64, term: u64 },
+}
+
+/// HeartbeatWorker reports the store and the regions it leads to the
+/// scheduler, off the raft store's event loop. The operators returned
+/// by the scheduler are handed back to the store. It also raises max_ts
+/// to a timestamp of the scheduler for the regions the store starts to lead.
+#[derive(Debug)]
+pub struct HeartbeatWorker {
+    client: SchedulerClient,
+    store: Store,
+    db_path: PathBuf,
+    capacity: u64,
+    // whether the scheduler knows about the store.
+    registered: bool,
+    concurrency_manager: Option<Arc<ConcurrencyManager>>,
+}
+
+impl HeartbeatWorker {
+    pub fn new(store_id: u64, config: &Config, client: SchedulerClient) -> Self {
+        Self {
+            client,
+            store: Store {
+                id: store_id,
+                address: config.store_addr.clone(),
+                labels: config.labels.iter()
+                    .map(|(key, value)| StoreLabel { key: key.clone(), value: value.clone() })
+                    .collect(),
+                ..Default::default()
+            },
+            db_path: PathBuf::from(&config.db_path),
+            capacity: config.capacity.num_bytes(),
+            registered: false,
+            concurrency_manager: None,
+        }
+    }
+
+    // The max_ts of the transactions served by the store.
+    pub fn with_concurrency_manager(mut self, concurrency_manager: Arc<ConcurrencyManager>) -> Self {
+        self.concurrency_manager = Some(concurrency_manager);
+        self
+    }
+
+    pub fn spawn(self, store_tx: StoreSender) -> UnboundedSender<HeartbeatTask> {
+        let (tx, rx) = unbounded_channel();
+        tokio::spawn(self.run(rx, store_tx));
+        tx
+    }
+
+    async fn run(mut self, mut rx: UnboundedReceiver<HeartbeatTask>, store_tx: StoreSender) {
+        while let Some(task) = rx.recv().await {
+            match task {
+                HeartbeatTask::Store(stats) => self.on_store_heartbeat(stats).await,
+                HeartbeatTask::Region { region, leader, flow } => {
+                    let region_id = region.id;
+                    match self.client.region_heartbeat(region, leader, &flow).await {
+                        Ok(response) => {
+                            if let Some(msg) = schedule_msg(response) {
+                                let _ = store_tx.send(msg);
+                            }
+                        },
+                        Err(err) => log::warn!("failed to send heartbeat of region {}: {:?}", region_id, err),
+                    }
+                },
+                HeartbeatTask::UpdateMaxTs { region_id, term } => {
+                    let Some(concurrency_manager) = &self.concurrency_manager else {
+                        continue;
+                    };
+                    // any version read before the leader changed is below a fresh timestamp.
+                    match self.client.get_ts().await {
+                        Ok(ts) => {
+                            concurrency_manager.update_max_ts(ts);
+                            let _ = store_tx.send(StoreMsg::MaxTsSynced { region_id, term });
+                        },
+                        Err(err) => log::warn!("failed to update max_ts for region {}: {:?}", region_id, err),
+                    }
+                },
+            }
+        }
+    }
+
+    // The store registers itself on its first heartbeat, and
+    // again after a failure in case the scheduler lost it.
+    async fn on_store_heartbeat(&mut self, mut stats: StoreStats) {
+        if !self.registered {
+            if let Err(err) = self.client.put_store(self.store.clone()).await {
+                log::warn!("failed to register store {}: {:?}", self.store.id, err);
+                return;
+            }
+            self.registered = true;
+        }
+
+        let db_path = self.db_path.clone();
+        let used_size = tokio::task::spawn_blocking(move || dir_size(&db_path)).await.unwrap_or_default();
+        stats.capacity = self.capacity;
+        stats.used_size = used_size;
+        stats.available = self.capacity.saturating_sub(used_size);
+        if let Err(err) = self.client.store_heartbeat(stats).await {
+            log::warn!("failed to send heartbeat of store {}: {:?}", self.store.id, err);
+            self.registered = false;
+        }
+    }
+}
+
+// The command running the operator step returned for a region, if any.
+fn schedule_msg(response: RegionHeartbeatResponse) -> Option<StoreMsg> {
+    let region_epoch = response.region_epoch.unwrap_or_default();
+    if let Some(change_peer) = response.change_peer {
+        let change_type = match change_peer.change_type() {
+            ConfChangeType::AddLearnerNode => ChangePeerType::AddLearner,
+            ConfChangeType::AddNode => ChangePeerType::Promote,
+            ConfChangeType::RemoveNode => ChangePeerType::Remove,
+        };
+        let request = CmdRequest::Admin(AdminCmd::ChangePeer { change_type, peer: change_peer.peer.unwrap_or_default() });
+        let cmd = RaftCmd::new(response.region_id, &region_epoch, request);
+        return Some(StoreMsg::RaftCmd { cmd, callback: None });
+    }
+    if response.split_region.is_some() {
+        return Some(StoreMsg::HalfSplitRegion { region_id: response.region_id, region_epoch });
+    }
+    let peer = response.transfer_leader?.peer?;
+    Some(StoreMsg::TransferLeader { region_id: response.region_id, region_epoch, peer })
+}
+
+// Size of the files under path, a missing directory is empty.
+fn dir_size(path: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return 0;
+    };
+    entries.flatten()
+        .map(|entry| match entry.metadata() {
+            Ok(metadata) if metadata.is_dir() => dir_size(&entry.path()),
+            Ok(metadata) => metadata.len(),
+            Err(_) => 0,
+        })
+        .sum()
+}
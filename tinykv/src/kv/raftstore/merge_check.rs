@@ -0,0 +1,71 @@
+use crate::{kv::{config::Config, error::TkvResult, Storage}, proto::metapb::Region};
+
+use super::{region::{is_adjacent, is_same_stores}, split_check::approximate_size};
+
+
+/// MergeChecker decides whether a small region should be merged into
+/// one of its neighbours. The merged region must stay small enough
+/// not to be split again right away.
+#[derive(Debug, Clone)]
+pub struct MergeChecker {
+    max_size: u64,
+    max_merged_size: u64,
+}
+
+impl MergeChecker {
+    pub fn new(max_size: u64, max_merged_size: u64) -> Self {
+        Self { max_size, max_merged_size }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.region_merge_max_size.num_bytes(), config.region_merge_max_merged_size.num_bytes())
+    }
+
+    // Returns true when source should be merged into target.
+    pub fn check(&self, storage: &dyn Storage, source: &Region, target: &Region) -> TkvResult<bool> {
+        if !(is_adjacent(source, target) || is_adjacent(target, source)) || !is_same_stores(source, target) {
+            return Ok(false);
+        }
+
+        let source_size = approximate_size(storage, source)?;
+        if source_size >= self.max_size {
+            return Ok(false);
+        }
+        let target_size = approximate_size(storage, target)?;
+        Ok(source_size + target_size <= self.max_merged_size)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::proto::metapb::{Peer, Region};
+
+    use super::MergeChecker;
+
+    #[test]
+    fn merge_check() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        storage.put(ColumnFamily::Default, b"a", vec![0; 9])?;
+        storage.put(ColumnFamily::Default, b"m", vec![0; 49])?;
+
+        let peers = vec![Peer { id: 1, store_id: 1, ..Default::default() }];
+        let left = Region { id: 1, start_key: vec![], end_key: b"k".to_vec(), peers: peers.clone(), ..Default::default() };
+        let right = Region { id: 2, start_key: b"k".to_vec(), end_key: vec![], peers, ..Default::default() };
+
+        assert!(MergeChecker::new(20, 100).check(&storage, &left, &right)?);
+        // the source is too large.
+        assert!(!MergeChecker::new(20, 100).check(&storage, &right, &left)?);
+        // the merged region would be too large.
+        assert!(!MergeChecker::new(20, 50).check(&storage, &left, &right)?);
+
+        // peers must be on the same stores.
+        let mut other = right.clone();
+        other.peers[0].store_id = 2;
+        assert!(!MergeChecker::new(20, 100).check(&storage, &left, &other)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,240 @@
+pub mod apply;
+pub mod cmd;
+pub mod engine;
+pub mod heartbeat;
+pub mod merge_check;
+pub mod peer;
+pub mod region;
+pub mod snap;
+pub mod split_check;
+pub mod store;
+pub mod transport;
+
+use std::{collections::{BTreeMap, HashMap, VecDeque}, ops::{Bound, Range}, sync::{atomic::{AtomicU64, Ordering}, Arc}};
+
+use parking_lot::Mutex;
+
+use crate::{proto::metapb::Region, scheduler::{client::SchedulerClient, hot_region::RegionFlow}};
+
+use self::region::check_key_in_region;
+
+use super::error::{TkvError, TkvResult};
+
+
+/// IdAllocator hands out cluster wide unique ids for regions and peers.
+pub trait IdAllocator: std::fmt::Debug + Send + Sync {
+    fn alloc_id(&self) -> TkvResult<u64>;
+}
+
+// Allocates ids from a local counter, only suitable for a single store.
+#[derive(Debug)]
+pub struct LocalIdAllocator {
+    next_id: AtomicU64,
+}
+
+impl LocalIdAllocator {
+    pub fn new(start: u64) -> Self {
+        Self { next_id: AtomicU64::new(start) }
+    }
+}
+
+impl IdAllocator for LocalIdAllocator {
+    fn alloc_id(&self) -> TkvResult<u64> {
+        Ok(self.next_id.fetch_add(1, Ordering::SeqCst))
+    }
+}
+
+const ID_BATCH_SIZE: u32 = 100;
+
+#[derive(Debug, Default)]
+struct IdBatches {
+    ranges: VecDeque<Range<u64>>,
+    fetching: bool,
+}
+
+/// SchedulerIdAllocator hands out ids allocated by the scheduler. They
+/// are fetched in batches, the next one in the background once half of
+/// the ids left are used. Allocating fails while no id is left.
+#[derive(Debug, Clone)]
+pub struct SchedulerIdAllocator {
+    client: SchedulerClient,
+    batches: Arc<Mutex<IdBatches>>,
+}
+
+impl SchedulerIdAllocator {
+    // Fetches the first batch.
+    pub async fn new(client: SchedulerClient) -> TkvResult<Self> {
+        let first = client.alloc_ids(ID_BATCH_SIZE).await?;
+        let mut ranges = VecDeque::new();
+        ranges.push_back(first..first + ID_BATCH_SIZE as u64);
+        let batches = IdBatches { ranges, fetching: false };
+        Ok(Self { client, batches: Arc::new(Mutex::new(batches)) })
+    }
+
+    fn fetch(&self) {
+        let allocator = self.clone();
+        tokio::spawn(async move {
+            let fetched = allocator.client.alloc_ids(ID_BATCH_SIZE).await;
+            let mut batches = allocator.batches.lock();
+            batches.fetching = false;
+            match fetched {
+                Ok(first) => batches.ranges.push_back(first..first + ID_BATCH_SIZE as u64),
+                Err(err) => log::warn!("failed to allocate ids: {:?}", err),
+            }
+        });
+    }
+}
+
+impl IdAllocator for SchedulerIdAllocator {
+    fn alloc_id(&self) -> TkvResult<u64> {
+        let mut batches = self.batches.lock();
+        let id = batches.ranges.front_mut().and_then(|range| range.next());
+        if batches.ranges.front().is_some_and(|range| range.is_empty()) {
+            batches.ranges.pop_front();
+        }
+        let left: u64 = batches.ranges.iter().map(|range| range.end - range.start).sum();
+        if left < ID_BATCH_SIZE as u64 / 2 && !batches.fetching {
+            batches.fetching = true;
+            self.fetch();
+        }
+        id.ok_or(TkvError::new("no id left, waiting for the scheduler".to_string()))
+    }
+}
+
+
+/// StoreMeta is the view of the regions hosted on a store,
+/// shared between the raft store and the services reading it.
+#[derive(Debug, Default)]
+pub struct StoreMeta {
+    // region_id -> region
+    pub regions: HashMap<u64, Region>,
+    // region end key -> region_id, an unbounded end key sorts last.
+    region_ranges: BTreeMap<(bool, Vec<u8>), u64>,
+    // traffic of the regions since their last heartbeat, region_id -> flow
+    flows: HashMap<u64, RegionFlow>,
+    // leaders known by the peers of the regions, region_id -> (leader peer id, term)
+    leaders: HashMap<u64, (u64, u64)>,
+    // terms in which max_ts was raised after the store became the leader,
+    // region_id -> term
+    max_ts_synced: HashMap<u64, u64>,
+}
+
+impl StoreMeta {
+    pub fn set_region(&mut self, region: Region) {
+        if let Some(old) = self.regions.get(&region.id) {
+            self.region_ranges.remove(&range_key(&old.end_key));
+        }
+        self.region_ranges.insert(range_key(&region.end_key), region.id);
+        self.regions.insert(region.id, region);
+    }
+
+    pub fn remove_region(&mut self, region_id: u64) -> Option<Region> {
+        let region = self.regions.remove(&region_id)?;
+        self.flows.remove(&region_id);
+        self.leaders.remove(&region_id);
+        self.max_ts_synced.remove(&region_id);
+        if self.region_ranges.get(&range_key(&region.end_key)) == Some(&region_id) {
+            self.region_ranges.remove(&range_key(&region.end_key));
+        }
+        Some(region)
+    }
+
+    // Finds the region holding key.
+    pub fn search_region(&self, key: &[u8]) -> Option<&Region> {
+        let (_, region_id) = self.region_ranges
+            .range((Bound::Excluded((false, key.to_vec())), Bound::Unbounded))
+            .next()?;
+        self.regions.get(region_id).filter(|region| check_key_in_region(key, region))
+    }
+
+    pub fn set_leader(&mut self, region_id: u64, leader_id: u64, term: u64) {
+        self.leaders.insert(region_id, (leader_id, term));
+    }
+
+    // The leader of a region and the term it leads in, as known locally.
+    pub fn leader(&self, region_id: u64) -> Option<(u64, u64)> {
+        self.leaders.get(&region_id).copied()
+    }
+
+    pub fn set_max_ts_synced(&mut self, region_id: u64, term: u64) {
+        self.max_ts_synced.insert(region_id, term);
+    }
+
+    // Whether max_ts is above the versions read by the previous leaders of
+    // the region, async commit and 1PC transactions rely on it.
+    pub fn is_max_ts_synced(&self, region_id: u64) -> bool {
+        self.leaders.get(&region_id).is_some_and(|(_, term)| self.max_ts_synced.get(&region_id) == Some(term))
+    }
+
+    pub fn record_read(&mut self, region_id: u64, keys: u64, bytes: u64) {
+        let flow = self.flows.entry(region_id).or_default();
+        flow.read_keys += keys;
+        flow.read_bytes += bytes;
+    }
+
+    pub fn record_write(&mut self, region_id: u64, keys: u64, bytes: u64) {
+        let flow = self.flows.entry(region_id).or_default();
+        flow.written_keys += keys;
+        flow.written_bytes += bytes;
+    }
+
+    // Takes the traffic recorded since the previous call.
+    pub fn take_flow(&mut self, region_id: u64) -> RegionFlow {
+        self.flows.remove(&region_id).unwrap_or_default()
+    }
+}
+
+fn range_key(end_key: &[u8]) -> (bool, Vec<u8>) {
+    (end_key.is_empty(), end_key.to_vec())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::schedulerpb::scheduler_server::SchedulerServer;
+    use crate::scheduler::{client::SchedulerClient, config::Config, server::SchedulerService};
+
+    use super::{IdAllocator, SchedulerIdAllocator, ID_BATCH_SIZE};
+
+    #[tokio::test]
+    async fn ids_from_scheduler() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let service = SchedulerService::new(&Config::default(), Arc::new(MemoryStorage::new()))?;
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let client = SchedulerClient::connect(&addr.to_string())?;
+        let allocators = [SchedulerIdAllocator::new(client.clone()).await?, SchedulerIdAllocator::new(client).await?];
+        let mut ids = vec![];
+        for _ in 0..ID_BATCH_SIZE * 2 {
+            for allocator in &allocators {
+                // give the background fetch a chance to run.
+                let id = match allocator.alloc_id() {
+                    Ok(id) => id,
+                    Err(_) => {
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        allocator.alloc_id()?
+                    },
+                };
+                ids.push(id);
+            }
+            tokio::task::yield_now().await;
+        }
+        let count = ids.len();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+
+        server.abort();
+        Ok(())
+    }
+}
@@ -0,0 +1,933 @@
+use std::{collections::{HashMap, HashSet}, sync::Arc};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{kv::{error::{TkvError, TkvResult}, Storage}, proto::{metapb::{self, Region}, raft_serverpb::{RaftMessage, SnapshotMeta}}};
+
+use super::{apply::ApplyRes, cmd::{pb_message, AdminCmd, ChangePeerType, CmdRequest, RaftCmd}, engine::{HardState, PeerState, RaftEngine, RaftWriteBatch, RegionLocalState, TruncatedState}, region::{find_peer, is_learner, region_epoch}};
+
+
+// Maximum number of entries sent in a single append message.
+const MAX_APPEND_ENTRIES: usize = 64;
+// Base ticks without hearing from a leader before a voter campaigns.
+const DEFAULT_ELECTION_TIMEOUT: u64 = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub term: u64,
+    pub index: u64,
+    pub data: Vec<u8>,
+}
+
+// Messages exchanged between the peers of a region, carried
+// by the transport as the payload of a RaftMessage.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PeerMessage {
+    // sent by the leader to replicate its log, an append without entries
+    // is a heartbeat that still propagates the commit index. The entries
+    // follow the one at prev_index, which the follower must hold at prev_term.
+    Append { term: u64, prev_index: u64, prev_term: u64, entries: Vec<Entry>, commit: u64 },
+    // index is the last index of the follower's log matching the leader's,
+    // on a reject the leader resends its log from there.
+    AppendResponse { term: u64, index: u64, reject: bool },
+    // sent by the leader to the voter it hands the leadership over to.
+    TimeoutNow { term: u64 },
+    // sent by a candidate to the other voters, last_index and last_term
+    // describe its log, a vote is only granted to a log as up to date.
+    RequestVote { term: u64, last_index: u64, last_term: u64 },
+    RequestVoteResponse { term: u64, reject: bool },
+    // sent by the leader to a peer removed from the region.
+    Tombstone,
+}
+
+// State of a source region between PrepareMerge and CommitMerge
+// or RollbackMerge. commit is the log index of the PrepareMerge.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergeState {
+    #[serde(with = "pb_message")]
+    pub target: Region,
+    pub commit: u64,
+}
+
+/// Peer is the replica of a region hosted on this store. It keeps the
+/// region's raft log, committed entries are applied by the apply pool.
+#[derive(Debug)]
+pub struct Peer {
+    pub meta: metapb::Peer,
+    pub region: Region,
+    pub leader_id: u64,
+    engine: RaftEngine,
+    // log changes not persisted yet, see persist.
+    raft_wb: RaftWriteBatch,
+    // last hard state written to the engine.
+    hard_state: HardState,
+
+    term: u64,
+    // the peer voted for in the current term, 0 if none.
+    vote: u64,
+    // the voters that granted their vote to this candidate.
+    votes: HashSet<u64>,
+    // base ticks since the leader was last heard of, or the campaign started.
+    election_elapsed: u64,
+    election_timeout: u64,
+    // drawn in [election_timeout, 2 * election_timeout) for each election,
+    // so that the voters rarely campaign at the same time.
+    randomized_election_timeout: u64,
+    entries: Vec<Entry>,
+    // the last entry dropped from the log.
+    truncated: TruncatedState,
+    // index of highest log entry known to be committed.
+    commit_index: u64,
+    // index of highest log entry applied to the storage.
+    applied_index: u64,
+    // index of highest log entry handed to the apply pool.
+    apply_sent_index: u64,
+    // index of highest log entry known to be replicated on each peer.
+    match_index: HashMap<u64, u64>,
+    // index of the next log entry to send to each peer.
+    next_index: HashMap<u64, u64>,
+    // messages waiting to be sent by the transport.
+    msgs: Vec<RaftMessage>,
+    // followers behind the leader's log, waiting for a snapshot to be sent.
+    snapshot_requests: Vec<metapb::Peer>,
+    // followers with a snapshot in flight.
+    pending_snapshots: HashSet<u64>,
+    merge_state: Option<MergeState>,
+    // the voter to hand the leadership over to, once it has the whole log.
+    lead_transferee: Option<metapb::Peer>,
+    // the peer was removed from the region and must be destroyed.
+    removed: bool,
+}
+
+impl Peer {
+    // Creates the peer of region living on store_id, or recovers it from
+    // the raft engine when the region was persisted before. The first peer
+    // of a new region leads it in the first term, a recovered peer follows
+    // the leader it hears from or campaigns.
+    pub fn new(store_id: u64, region: Region, storage: Arc<dyn Storage>) -> TkvResult<Self> {
+        let engine = RaftEngine::new(storage.clone());
+        let mut raft_wb = RaftWriteBatch::default();
+        let (region, merge_state) = match engine.region_state(region.id)? {
+            // a removed peer may only come back with a newer membership.
+            Some(RegionLocalState { region: old, state: PeerState::Tombstone })
+                if region_epoch(&region).config_version <= region_epoch(&old).config_version => {
+                return Err(TkvError::new(format!("region {} is tombstone", region.id)));
+            },
+            Some(RegionLocalState { region, state: PeerState::Merging(state) }) => (region, Some(state)),
+            Some(RegionLocalState { region, state: PeerState::Normal }) => (region, None),
+            _ => {
+                raft_wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+                (region, None)
+            },
+        };
+        let meta = find_peer(&region, store_id)
+            .cloned()
+            .ok_or(TkvError::new(format!("region {} has no peer on store {}", region.id, store_id)))?;
+
+        let hard_state = engine.hard_state(region.id)?.unwrap_or_default();
+        let (term, vote, leader_id) = if hard_state.term > 0 {
+            (hard_state.term, hard_state.vote, 0)
+        } else {
+            let first = region.peers.first().map(|peer| peer.id).unwrap_or_default();
+            (1, if first == meta.id { first } else { 0 }, first)
+        };
+        let applied_index = engine.apply_state(region.id)?.unwrap_or_default().applied_index;
+        let truncated = engine.truncated_state(region.id)?.unwrap_or_default();
+        let mut entries = engine.entries(region.id, 0, u64::MAX)?;
+        let mut commit_index = hard_state.commit.max(applied_index);
+        // the log was reset by a snapshot that did not get applied,
+        // the leader sends it again.
+        if entries.first().is_some_and(|entry| entry.index > applied_index + 1) {
+            for entry in entries.drain(..) {
+                raft_wb.delete_entry(region.id, entry.index);
+            }
+            commit_index = applied_index;
+        }
+        let mut peer = Self {
+            meta,
+            region,
+            leader_id,
+            engine,
+            raft_wb,
+            term,
+            vote,
+            votes: HashSet::new(),
+            election_elapsed: 0,
+            election_timeout: DEFAULT_ELECTION_TIMEOUT,
+            randomized_election_timeout: DEFAULT_ELECTION_TIMEOUT,
+            entries,
+            truncated,
+            commit_index,
+            applied_index,
+            apply_sent_index: applied_index,
+            hard_state,
+            match_index: HashMap::new(),
+            next_index: HashMap::new(),
+            msgs: vec![],
+            snapshot_requests: vec![],
+            pending_snapshots: HashSet::new(),
+            merge_state,
+            lead_transferee: None,
+            removed: false,
+        };
+        peer.reset_election_timeout();
+        if peer.is_leader() {
+            peer.match_index.insert(peer.meta.id, peer.last_index());
+        } else if peer.leader_id == 0 && peer.voters().len() == 1 && !is_learner(&peer.meta) {
+            // the only voter needs no one else's vote.
+            peer.campaign()?;
+        }
+        Ok(peer)
+    }
+
+    // The number of base ticks a voter waits for the leader before it
+    // campaigns, randomized up to twice as many.
+    pub fn with_election_timeout(mut self, ticks: u64) -> Self {
+        self.election_timeout = ticks.max(1);
+        self.reset_election_timeout();
+        self
+    }
+
+    pub fn region_id(&self) -> u64 {
+        self.region.id
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader_id == self.meta.id
+    }
+
+    pub fn last_index(&self) -> u64 {
+        self.entries.last().map(|entry| entry.index).unwrap_or(self.applied_index.max(self.apply_sent_index))
+    }
+
+    pub fn term(&self) -> u64 {
+        self.term
+    }
+
+    // The term of the entry at index, if it is still known. Index 0 is
+    // before the first entry, until the log is truncated.
+    fn term_of(&self, index: u64) -> Option<u64> {
+        if index == self.truncated.index {
+            return Some(self.truncated.term);
+        }
+        self.entries.iter().find(|entry| entry.index == index).map(|entry| entry.term)
+    }
+
+    fn last_term(&self) -> u64 {
+        self.entries.last().map_or(self.truncated.term, |entry| entry.term)
+    }
+
+    // Whether the log holds the entry at index with term. An entry dropped
+    // from the log does not match, the leader sends the log after it.
+    fn matches(&self, index: u64, term: u64) -> bool {
+        self.term_of(index) == Some(term)
+    }
+
+    pub fn applied_index(&self) -> u64 {
+        self.applied_index
+    }
+
+    pub fn merge_state(&self) -> Option<&MergeState> {
+        self.merge_state.as_ref()
+    }
+
+    pub fn is_removed(&self) -> bool {
+        self.removed
+    }
+
+    // Appends a command to the leader's log and returns its index.
+    pub fn propose(&mut self, cmd: &RaftCmd) -> TkvResult<u64> {
+        if !self.is_leader() {
+            return Err(TkvError::new(format!("peer {} is not leader of region {}", self.meta.id, self.region.id)));
+        }
+        // a merging region only accepts to be rolled back.
+        if self.merge_state.is_some() && !matches!(cmd.request, CmdRequest::Admin(AdminCmd::RollbackMerge { .. })) {
+            return Err(TkvError::new(format!("region {} is merging", self.region.id)));
+        }
+        // the leadership has to be transferred first.
+        if let CmdRequest::Admin(AdminCmd::ChangePeer { change_type: ChangePeerType::Remove, peer }) = &cmd.request {
+            if peer.id == self.meta.id {
+                return Err(TkvError::new(format!("cannot remove the leader of region {}", self.region.id)));
+            }
+        }
+        let index = self.last_index() + 1;
+        let entry = Entry { term: self.term, index, data: cmd.encode()? };
+        self.raft_wb.append(self.region.id, std::slice::from_ref(&entry))?;
+        self.entries.push(entry);
+        self.match_index.insert(self.meta.id, index);
+        self.update_commit_index();
+        Ok(index)
+    }
+
+    // Records that peer_id has replicated the log up to index.
+    pub fn on_append_response(&mut self, peer_id: u64, index: u64) {
+        let matched = self.match_index.entry(peer_id).or_default();
+        *matched = index.max(*matched);
+        self.update_commit_index();
+    }
+
+    // The commit index is the highest index replicated on a quorum of voters.
+    fn update_commit_index(&mut self) {
+        let mut matched: Vec<u64> = self.region.peers.iter()
+            .filter(|peer| !is_learner(peer))
+            .map(|peer| self.match_index.get(&peer.id).copied().unwrap_or_default())
+            .collect();
+        if matched.is_empty() {
+            return;
+        }
+        matched.sort_unstable_by(|a, b| b.cmp(a));
+        let quorum_index = matched[matched.len() / 2];
+        // the entries of the previous terms are committed along with one of this term.
+        if self.term_of(quorum_index) == Some(self.term) {
+            self.commit_index = self.commit_index.max(quorum_index);
+        }
+    }
+
+    // The entries from index on are handed to the apply pool again later.
+    pub fn defer_entries(&mut self, index: u64) {
+        self.apply_sent_index = self.apply_sent_index.min(index - 1);
+    }
+
+    // The entries up to commit that a follower may miss, they are
+    // carried by the CommitMerge once this peer is merged away.
+    pub fn merge_entries(&self, commit: u64) -> Vec<Entry> {
+        let low = self.region.peers.iter()
+            .map(|peer| self.match_index.get(&peer.id).copied().unwrap_or_default())
+            .min()
+            .unwrap_or_default();
+        self.entries.iter()
+            .filter(|entry| entry.index > low && entry.index <= commit)
+            .cloned()
+            .collect()
+    }
+
+    // Catches up with the source log carried by a CommitMerge, the
+    // entries up to commit are committed by the source's leader.
+    pub fn append_merge_entries(&mut self, commit: u64, entries: Vec<Entry>) -> TkvResult<()> {
+        if entries.first().is_some_and(|entry| entry.index > self.last_index() + 1) {
+            return Err(TkvError::new(format!("region {} misses the entries before {}", self.region.id, entries[0].index)));
+        }
+        self.append_entries(entries)?;
+        self.commit_index = self.commit_index.max(commit.min(self.last_index()));
+        Ok(())
+    }
+
+    // Appends the entries sent by the leader, replacing the conflicting ones.
+    fn append_entries(&mut self, entries: Vec<Entry>) -> TkvResult<()> {
+        for entry in entries {
+            if entry.index <= self.applied_index {
+                continue;
+            }
+            match self.entries.iter().position(|e| e.index == entry.index) {
+                Some(pos) if self.entries[pos].term == entry.term => continue,
+                Some(pos) => {
+                    for conflict in self.entries.drain(pos..) {
+                        self.raft_wb.delete_entry(self.region.id, conflict.index);
+                    }
+                },
+                None => (),
+            }
+            if entry.index == self.last_index() + 1 {
+                self.raft_wb.append(self.region.id, std::slice::from_ref(&entry))?;
+                self.entries.push(entry);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the entries committed but not handed to the apply pool yet.
+    pub fn take_committed_entries(&mut self) -> Vec<Entry> {
+        let from = self.apply_sent_index.max(self.applied_index);
+        let entries: Vec<Entry> = self.entries.iter()
+            .filter(|entry| entry.index > from && entry.index <= self.commit_index)
+            .cloned()
+            .collect();
+        if let Some(last) = entries.last() {
+            self.apply_sent_index = last.index;
+        }
+        entries
+    }
+
+    // Catches up with the entries applied by the apply pool.
+    pub fn on_apply_res(&mut self, res: &ApplyRes) -> TkvResult<()> {
+        self.applied_index = self.applied_index.max(res.applied_index);
+        if self.is_leader() {
+            let added: Vec<u64> = res.region.peers.iter()
+                .filter(|peer| find_peer(&self.region, peer.store_id).is_none())
+                .map(|peer| peer.id)
+                .collect();
+            // a new peer has no log yet, it starts from a snapshot.
+            for peer_id in added {
+                self.next_index.insert(peer_id, 0);
+            }
+            let removed: Vec<metapb::Peer> = self.region.peers.iter()
+                .filter(|peer| !res.region.peers.iter().any(|p| p.id == peer.id))
+                .cloned()
+                .collect();
+            for peer in removed {
+                self.send(peer, PeerMessage::Tombstone)?;
+            }
+        }
+        self.region = res.region.clone();
+        if let Some(meta) = find_peer(&self.region, self.meta.store_id) {
+            self.meta = meta.clone();
+        }
+        self.merge_state = res.merge_state.clone();
+        Ok(())
+    }
+
+    // Hands the leadership over to a voter of the region, as soon as it
+    // has replicated the whole log.
+    pub fn transfer_leader(&mut self, to: &metapb::Peer) -> TkvResult<()> {
+        if !self.is_leader() || to.id == self.meta.id {
+            return Ok(());
+        }
+        if !self.region.peers.iter().any(|peer| peer.id == to.id && !is_learner(peer)) {
+            return Err(TkvError::new(format!("peer {} is not a voter of region {}", to.id, self.region.id)));
+        }
+        self.lead_transferee = Some(to.clone());
+        self.try_transfer_leader()
+    }
+
+    fn try_transfer_leader(&mut self) -> TkvResult<()> {
+        let Some(to) = self.lead_transferee.clone() else {
+            return Ok(());
+        };
+        if self.match_index.get(&to.id).copied().unwrap_or_default() < self.last_index() {
+            return Ok(());
+        }
+        // step down right away, no more entries can be proposed here.
+        self.lead_transferee = None;
+        self.leader_id = to.id;
+        self.send(to, PeerMessage::TimeoutNow { term: self.term })
+    }
+
+    // Leaders send heartbeats that also retransmit the missing entries,
+    // the other voters campaign when they do not hear from a leader.
+    pub fn tick(&mut self) -> TkvResult<()> {
+        if self.is_leader() {
+            return self.broadcast_append();
+        }
+        if is_learner(&self.meta) {
+            return Ok(());
+        }
+        self.election_elapsed += 1;
+        if self.election_elapsed >= self.randomized_election_timeout {
+            self.campaign()?;
+        }
+        Ok(())
+    }
+
+    fn reset_election_timeout(&mut self) {
+        self.election_elapsed = 0;
+        self.randomized_election_timeout = rand::thread_rng().gen_range(self.election_timeout..2 * self.election_timeout);
+    }
+
+    fn voters(&self) -> Vec<metapb::Peer> {
+        self.region.peers.iter().filter(|peer| !is_learner(peer)).cloned().collect()
+    }
+
+    // Starts an election in the next term, voting for itself.
+    fn campaign(&mut self) -> TkvResult<()> {
+        self.term += 1;
+        self.vote = self.meta.id;
+        self.leader_id = 0;
+        self.lead_transferee = None;
+        self.votes = HashSet::from([self.meta.id]);
+        self.reset_election_timeout();
+        log::info!("peer {} of region {} campaigns in term {}", self.meta.id, self.region.id, self.term);
+        self.on_vote()?;
+        let (last_index, last_term) = (self.last_index(), self.last_term());
+        for to in self.voters() {
+            if to.id != self.meta.id {
+                self.send(to, PeerMessage::RequestVote { term: self.term, last_index, last_term })?;
+            }
+        }
+        Ok(())
+    }
+
+    // Becomes leader once a quorum of voters granted their vote.
+    fn on_vote(&mut self) -> TkvResult<()> {
+        let voters = self.voters();
+        let granted = voters.iter().filter(|peer| self.votes.contains(&peer.id)).count();
+        if granted > voters.len() / 2 {
+            self.become_leader()?;
+        }
+        Ok(())
+    }
+
+    fn become_leader(&mut self) -> TkvResult<()> {
+        self.leader_id = self.meta.id;
+        self.votes.clear();
+        self.match_index.clear();
+        self.next_index.clear();
+        self.pending_snapshots.clear();
+        // an empty write of the new term commits the entries left by the
+        // previous leaders, it bypasses the checks of propose.
+        let cmd = RaftCmd::new(self.region.id, &region_epoch(&self.region), CmdRequest::Write(vec![]));
+        let entry = Entry { term: self.term, index: self.last_index() + 1, data: cmd.encode()? };
+        self.raft_wb.append(self.region.id, std::slice::from_ref(&entry))?;
+        self.entries.push(entry);
+        self.match_index.insert(self.meta.id, self.last_index());
+        self.update_commit_index();
+        self.broadcast_append()
+    }
+
+    // Follows leader_id, unknown when 0, in term or the current one if later.
+    fn become_follower(&mut self, term: u64, leader_id: u64) {
+        if term > self.term {
+            self.term = term;
+            self.vote = 0;
+        }
+        self.leader_id = leader_id;
+        self.lead_transferee = None;
+        self.votes.clear();
+    }
+
+    // Drops the log entries that are applied and, on the leader, known
+    // to be replicated on every peer. Once the log holds more than
+    // gc_count_limit entries, lagging peers get a snapshot instead.
+    pub fn compact(&mut self, gc_count_limit: u64) -> TkvResult<()> {
+        let mut index = self.applied_index;
+        if self.is_leader() && self.entries.len() as u64 <= gc_count_limit {
+            for peer in &self.region.peers {
+                index = index.min(self.match_index.get(&peer.id).copied().unwrap_or_default());
+            }
+        }
+        let region_id = self.region.id;
+        let mut truncated = self.truncated;
+        self.entries.retain(|entry| {
+            if entry.index > index {
+                return true;
+            }
+            self.raft_wb.delete_entry(region_id, entry.index);
+            truncated = TruncatedState { index: entry.index, term: entry.term };
+            false
+        });
+        self.set_truncated(truncated)
+    }
+
+    fn set_truncated(&mut self, truncated: TruncatedState) -> TkvResult<()> {
+        if truncated != self.truncated {
+            self.truncated = truncated;
+            self.raft_wb.set_truncated_state(self.region.id, &truncated)?;
+        }
+        Ok(())
+    }
+
+    // Writes the log changes and the hard state within a single write,
+    // it must be called before the new entries are sent or applied.
+    pub fn persist(&mut self) -> TkvResult<()> {
+        let hard_state = HardState { term: self.term, vote: self.vote, commit: self.commit_index };
+        if hard_state != self.hard_state {
+            self.raft_wb.set_hard_state(self.region.id, &hard_state)?;
+        }
+        self.engine.write(std::mem::take(&mut self.raft_wb))?;
+        self.hard_state = hard_state;
+        Ok(())
+    }
+
+    // Removes the raft log and states of a peer that was merged away or
+    // removed from its region, its tombstone is kept so that it is never
+    // recreated with the same membership.
+    pub fn destroy(self) -> TkvResult<()> {
+        let mut wb = RaftWriteBatch::default();
+        wb.clear_raft_state(self.region.id, &self.entries);
+        wb.set_region_state(self.region.id, &RegionLocalState { region: self.region, state: PeerState::Tombstone })?;
+        self.engine.write(wb)
+    }
+
+    pub fn take_messages(&mut self) -> Vec<RaftMessage> {
+        std::mem::take(&mut self.msgs)
+    }
+
+    pub fn take_snapshot_requests(&mut self) -> Vec<metapb::Peer> {
+        std::mem::take(&mut self.snapshot_requests)
+    }
+
+    // The snapshot could not be sent, it is retried on the next append.
+    pub fn report_snapshot_failure(&mut self, peer_id: u64) {
+        self.pending_snapshots.remove(&peer_id);
+    }
+
+    // Resets the log of a follower to the snapshot sent by the leader,
+    // returns whether the snapshot has to be applied.
+    pub fn on_snapshot(&mut self, meta: &SnapshotMeta) -> TkvResult<bool> {
+        let from = meta.from_peer.clone().unwrap_or_default();
+        let is_stale = meta.index <= self.last_index().min(self.commit_index);
+        if !is_stale {
+            for entry in self.entries.drain(..) {
+                self.raft_wb.delete_entry(self.region.id, entry.index);
+            }
+            self.become_follower(meta.term, from.id);
+            self.election_elapsed = 0;
+            self.commit_index = self.commit_index.max(meta.index);
+            self.apply_sent_index = meta.index;
+            self.set_truncated(TruncatedState { index: meta.index, term: meta.term })?;
+        }
+        let index = self.last_index();
+        self.send(from, PeerMessage::AppendResponse { term: self.term, index, reject: false })?;
+        Ok(!is_stale)
+    }
+
+    // Sends the leader's log to every follower, starting from the
+    // next entry each of them is expected to miss.
+    pub fn broadcast_append(&mut self) -> TkvResult<()> {
+        if !self.is_leader() {
+            return Ok(());
+        }
+        let followers: Vec<metapb::Peer> = self.region.peers.iter()
+            .filter(|peer| peer.id != self.meta.id)
+            .cloned()
+            .collect();
+        for to in followers {
+            let next = self.next_index.get(&to.id).copied().unwrap_or(self.last_index() + 1);
+            // the entries the follower misses are compacted.
+            let prev_term = next.checked_sub(1).and_then(|prev_index| self.term_of(prev_index));
+            let Some(prev_term) = prev_term.filter(|_| !self.pending_snapshots.contains(&to.id)) else {
+                if self.pending_snapshots.insert(to.id) {
+                    self.snapshot_requests.push(to);
+                }
+                continue;
+            };
+            let entries: Vec<Entry> = self.entries.iter()
+                .skip_while(|entry| entry.index < next)
+                .take(MAX_APPEND_ENTRIES)
+                .cloned()
+                .collect();
+            if let Some(last) = entries.last() {
+                self.next_index.insert(to.id, last.index + 1);
+            }
+            let msg = PeerMessage::Append { term: self.term, prev_index: next - 1, prev_term, entries, commit: self.commit_index };
+            self.send(to, msg)?;
+        }
+        Ok(())
+    }
+
+    // Handles a message from another peer of the region.
+    pub fn step(&mut self, msg: RaftMessage) -> TkvResult<()> {
+        let from = msg.from_peer.unwrap_or_default();
+        if msg.to_peer.map(|peer| peer.id) != Some(self.meta.id) {
+            return Ok(());
+        }
+        match bincode::deserialize(&msg.message)? {
+            PeerMessage::Append { term, prev_index, prev_term, entries, commit } => {
+                // sent by a leader that stepped down since, it learns the current term.
+                if term < self.term {
+                    let index = self.last_index();
+                    return self.send(from, PeerMessage::AppendResponse { term: self.term, index, reject: true });
+                }
+                self.become_follower(term, from.id);
+                self.election_elapsed = 0;
+                if !self.matches(prev_index, prev_term) {
+                    // back off past the entries of the conflicting term at once.
+                    let index = match self.term_of(prev_index) {
+                        Some(conflict) => self.entries.iter().find(|entry| entry.term == conflict).map_or(prev_index, |entry| entry.index) - 1,
+                        None => self.last_index(),
+                    };
+                    let index = index.max(self.commit_index);
+                    return self.send(from, PeerMessage::AppendResponse { term: self.term, index, reject: true });
+                }
+                // the log is only known to match the leader's up to there.
+                let last_index = entries.last().map_or(prev_index, |entry| entry.index);
+                self.append_entries(entries)?;
+                self.commit_index = self.commit_index.max(commit.min(last_index));
+                self.send(from, PeerMessage::AppendResponse { term: self.term, index: last_index, reject: false })
+            },
+            PeerMessage::AppendResponse { term, index, reject } => {
+                // another peer leads in a later term.
+                if term > self.term {
+                    self.become_follower(term, 0);
+                    return Ok(());
+                }
+                if !self.is_leader() {
+                    return Ok(());
+                }
+                if reject {
+                    self.next_index.insert(from.id, index + 1);
+                } else {
+                    self.pending_snapshots.remove(&from.id);
+                    self.on_append_response(from.id, index);
+                    let next = self.next_index.entry(from.id).or_default();
+                    *next = (*next).max(index + 1);
+                }
+                self.try_transfer_leader()
+            },
+            PeerMessage::TimeoutNow { term } => {
+                // learners cannot lead.
+                if is_learner(&self.meta) || term < self.term {
+                    return Ok(());
+                }
+                self.campaign()
+            },
+            PeerMessage::RequestVote { term, last_index, last_term } => {
+                if term > self.term {
+                    self.become_follower(term, 0);
+                }
+                let up_to_date = (last_term, last_index) >= (self.last_term(), self.last_index());
+                let granted = term == self.term && (self.vote == 0 || self.vote == from.id) && up_to_date;
+                if granted {
+                    self.vote = from.id;
+                    self.election_elapsed = 0;
+                }
+                self.send(from, PeerMessage::RequestVoteResponse { term: self.term, reject: !granted })
+            },
+            PeerMessage::RequestVoteResponse { term, reject } => {
+                if term > self.term {
+                    self.become_follower(term, 0);
+                    return Ok(());
+                }
+                // only a candidate of this term counts the votes.
+                if term < self.term || reject || self.leader_id != 0 || self.vote != self.meta.id {
+                    return Ok(());
+                }
+                self.votes.insert(from.id);
+                self.on_vote()
+            },
+            PeerMessage::Tombstone => {
+                self.removed = true;
+                Ok(())
+            },
+        }
+    }
+
+    fn send(&mut self, to: metapb::Peer, msg: PeerMessage) -> TkvResult<()> {
+        self.msgs.push(RaftMessage {
+            region_id: self.region.id,
+            from_peer: Some(self.meta.clone()),
+            to_peer: Some(to),
+            region_epoch: self.region.region_epoch.clone(),
+            message: bincode::serialize(&msg)?,
+        });
+        Ok(())
+    }
+
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::apply::ApplyDelegate;
+    use crate::kv::raftstore::cmd::{CmdRequest, RaftCmd};
+    use crate::kv::storage::{memory::MemoryStorage, mutation::Mutation};
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::proto::metapb::{Peer as PeerMeta, Region, RegionEpoch};
+    use crate::proto::raft_serverpb::RaftMessage;
+
+    use super::{Peer, PeerMessage};
+
+    fn new_region(peers: Vec<PeerMeta>) -> Region {
+        Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers,
+        }
+    }
+
+    #[test]
+    fn commit_needs_quorum() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let peers = vec![
+            PeerMeta { id: 1, store_id: 1, ..Default::default() },
+            PeerMeta { id: 2, store_id: 2, ..Default::default() },
+            PeerMeta { id: 3, store_id: 3, ..Default::default() },
+        ];
+        let mut peer = Peer::new(1, new_region(peers), storage)?;
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        peer.propose(&RaftCmd::new(1, &epoch, CmdRequest::Write(vec![])))?;
+        assert!(peer.take_committed_entries().is_empty());
+
+        peer.on_append_response(3, 1);
+        assert_eq!(peer.take_committed_entries().len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn replicate() -> TkvResult<()> {
+        let peers = vec![PeerMeta { id: 1, store_id: 1, ..Default::default() }, PeerMeta { id: 2, store_id: 2, ..Default::default() }];
+        let region = new_region(peers);
+        let leader_storage = Arc::new(MemoryStorage::new());
+        let follower_storage = Arc::new(MemoryStorage::new());
+        let mut leader = Peer::new(1, region.clone(), leader_storage.clone())?;
+        let mut follower = Peer::new(2, region, follower_storage.clone())?;
+        let mut leader_delegate = ApplyDelegate::new(&leader);
+        let mut follower_delegate = ApplyDelegate::new(&follower);
+
+        // the first append is rejected as the follower's log is behind.
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        let put = Mutation::Put { key: b"k".to_vec(), value: vec![1], cf: ColumnFamily::Default };
+        leader.propose(&RaftCmd::new(1, &epoch, CmdRequest::Write(vec![put])))?;
+        leader.broadcast_append()?;
+        for _ in 0..3 {
+            for msg in leader.take_messages() {
+                follower.step(msg)?;
+            }
+            for msg in follower.take_messages() {
+                leader.step(msg)?;
+            }
+            leader.broadcast_append()?;
+        }
+
+        let entries = leader.take_committed_entries();
+        assert_eq!(entries.len(), 1);
+        leader_delegate.apply(leader_storage.as_ref(), &entries[0])??;
+
+        // the follower learns about the commit on the next append.
+        for msg in leader.take_messages() {
+            follower.step(msg)?;
+        }
+        let entries = follower.take_committed_entries();
+        assert_eq!(entries.len(), 1);
+        follower_delegate.apply(follower_storage.as_ref(), &entries[0])??;
+        assert_eq!(follower_storage.get(ColumnFamily::Default, b"k")?, Some(vec![1]));
+        Ok(())
+    }
+
+    #[test]
+    fn match_terms() -> TkvResult<()> {
+        let peers = vec![PeerMeta { id: 1, store_id: 1, ..Default::default() }, PeerMeta { id: 2, store_id: 2, ..Default::default() }];
+        let region = new_region(peers);
+        let mut leader = Peer::new(1, region.clone(), Arc::new(MemoryStorage::new()))?;
+        let mut follower = Peer::new(2, region, Arc::new(MemoryStorage::new()))?;
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        leader.propose(&RaftCmd::new(1, &epoch, CmdRequest::Write(vec![])))?;
+        exchange(&mut leader, &mut follower)?;
+        assert_eq!(follower.take_committed_entries().len(), 1);
+
+        let mut append = |term, prev_term| -> TkvResult<PeerMessage> {
+            let msg = PeerMessage::Append { term, prev_index: 1, prev_term, entries: vec![], commit: 1 };
+            let message = bincode::serialize(&msg)?;
+            follower.step(RaftMessage { region_id: 1, from_peer: Some(leader.meta.clone()), to_peer: Some(follower.meta.clone()), message, ..Default::default() })?;
+            Ok(bincode::deserialize(&follower.take_messages()[0].message)?)
+        };
+        // a committed entry matches only with its own term.
+        assert_eq!(append(3, 2)?, PeerMessage::AppendResponse { term: 3, index: 1, reject: true });
+        assert_eq!(append(3, 1)?, PeerMessage::AppendResponse { term: 3, index: 1, reject: false });
+        // an append of an older term is rejected with the current one.
+        assert_eq!(append(2, 1)?, PeerMessage::AppendResponse { term: 3, index: 1, reject: true });
+        Ok(())
+    }
+
+    // Runs a few rounds of appends from the leader to the follower.
+    fn exchange(leader: &mut Peer, follower: &mut Peer) -> TkvResult<()> {
+        for _ in 0..3 {
+            leader.broadcast_append()?;
+            for msg in leader.take_messages() {
+                follower.step(msg)?;
+            }
+            for msg in follower.take_messages() {
+                leader.step(msg)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn overwrite_divergent_log() -> TkvResult<()> {
+        let peers: Vec<PeerMeta> = (1..=3).map(|id| PeerMeta { id, store_id: id, ..Default::default() }).collect();
+        let region = new_region(peers);
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        let cmd = |value| {
+            let put = Mutation::Put { key: b"k".to_vec(), value: vec![value], cf: ColumnFamily::Default };
+            RaftCmd::new(1, &epoch, CmdRequest::Write(vec![put]))
+        };
+        let mut p1 = Peer::new(1, region.clone(), Arc::new(MemoryStorage::new()))?;
+        let mut p2 = Peer::new(2, region.clone(), Arc::new(MemoryStorage::new()))?;
+        let mut p3 = Peer::new(3, region, Arc::new(MemoryStorage::new()))?;
+
+        // 1 is committed on 1 and 2, 2 and 3 only reach the log of 1.
+        p1.propose(&cmd(1))?;
+        exchange(&mut p1, &mut p2)?;
+        p1.propose(&cmd(2))?;
+        p1.propose(&cmd(3))?;
+        p1.take_messages();
+
+        // 2 takes over with the vote of 3, it commits its empty entry and
+        // another one at 2 and 3 with 3.
+        let timeout = bincode::serialize(&PeerMessage::TimeoutNow { term: 1 })?;
+        p2.step(RaftMessage { region_id: 1, from_peer: Some(p1.meta.clone()), to_peer: Some(p2.meta.clone()), message: timeout, ..Default::default() })?;
+        deliver(&mut p2, &mut p3)?;
+        deliver(&mut p3, &mut p2)?;
+        assert_eq!((p2.is_leader(), p2.term()), (true, 2));
+        p2.take_messages();
+        p2.propose(&cmd(4))?;
+        exchange(&mut p2, &mut p3)?;
+        assert_eq!(p2.take_committed_entries().len(), 3);
+
+        // 1 learns the new term from the peers it still sends its log to.
+        exchange(&mut p1, &mut p3)?;
+        assert_eq!((p1.is_leader(), p1.term()), (false, 2));
+
+        // the entries of 1 after the committed ones are replaced, never committed.
+        exchange(&mut p2, &mut p1)?;
+        let entries = p1.take_committed_entries();
+        assert_eq!(entries.iter().map(|entry| (entry.index, entry.term)).collect::<Vec<_>>(), vec![(1, 1), (2, 2), (3, 2)]);
+        assert_eq!(p1.last_index(), 3);
+        Ok(())
+    }
+
+    // Steps the messages of from, those to other peers are dropped.
+    fn deliver(from: &mut Peer, to: &mut Peer) -> TkvResult<()> {
+        for msg in from.take_messages() {
+            to.step(msg)?;
+        }
+        Ok(())
+    }
+
+    // Ticks the peer until it campaigns.
+    fn campaign(peer: &mut Peer) -> TkvResult<()> {
+        let term = peer.term();
+        while peer.term() == term {
+            peer.tick()?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn elect_leader() -> TkvResult<()> {
+        let peers: Vec<PeerMeta> = (1..=3).map(|id| PeerMeta { id, store_id: id, ..Default::default() }).collect();
+        let region = new_region(peers);
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        let mut p1 = Peer::new(1, region.clone(), Arc::new(MemoryStorage::new()))?;
+        let mut p2 = Peer::new(2, region.clone(), Arc::new(MemoryStorage::new()))?.with_election_timeout(2);
+        let mut p3 = Peer::new(3, region, Arc::new(MemoryStorage::new()))?.with_election_timeout(2);
+        assert!(p1.is_leader() && !p2.is_leader());
+
+        // the leader hears from 2 only, then goes down.
+        p1.propose(&RaftCmd::new(1, &epoch, CmdRequest::Write(vec![])))?;
+        exchange(&mut p1, &mut p2)?;
+        p1.take_messages();
+
+        // 3 misses the committed entry, 2 does not vote for it.
+        campaign(&mut p3)?;
+        deliver(&mut p3, &mut p2)?;
+        deliver(&mut p2, &mut p3)?;
+        assert_eq!((p3.is_leader(), p3.term()), (false, 2));
+
+        // 2 is elected by 3 in the next term.
+        campaign(&mut p2)?;
+        assert_eq!((p2.is_leader(), p2.term()), (false, 3));
+        deliver(&mut p2, &mut p3)?;
+        deliver(&mut p3, &mut p2)?;
+        assert!(p2.is_leader());
+        assert_eq!(p3.leader_id, 0);
+
+        // its empty entry commits the one of the previous leader.
+        exchange(&mut p2, &mut p3)?;
+        assert_eq!(p3.leader_id, 2);
+        let entries = p2.take_committed_entries();
+        assert_eq!(entries.iter().map(|entry| (entry.index, entry.term)).collect::<Vec<_>>(), vec![(1, 1), (2, 3)]);
+
+        // the old leader steps down on the vote request of a later term.
+        p2.campaign()?;
+        deliver(&mut p2, &mut p1)?;
+        assert_eq!((p1.is_leader(), p1.term()), (false, 4));
+        Ok(())
+    }
+}
@@ -0,0 +1,264 @@
+use std::ops::Bound;
+
+use crate::{kv::error::{TkvError, TkvResult}, proto::metapb::{Peer, PeerRole, Region, RegionEpoch}};
+
+use super::cmd::{ChangePeerType, SplitIds};
+
+
+// Returns true if the key falls in the region's range [start_key, end_key).
+// An empty end_key means the region is unbounded on the right.
+pub fn check_key_in_region(key: &[u8], region: &Region) -> bool {
+    key >= region.start_key.as_slice() && (region.end_key.is_empty() || key < region.end_key.as_slice())
+}
+
+pub fn region_epoch(region: &Region) -> RegionEpoch {
+    region.region_epoch.clone().unwrap_or_default()
+}
+
+// The [start, end) bounds of a region, suitable for a storage scan.
+pub fn region_bounds(region: &Region) -> (Bound<Vec<u8>>, Bound<Vec<u8>>) {
+    let end = if region.end_key.is_empty() {
+        Bound::Unbounded
+    } else {
+        Bound::Excluded(region.end_key.clone())
+    };
+    (Bound::Included(region.start_key.clone()), end)
+}
+
+pub fn find_peer(region: &Region, store_id: u64) -> Option<&Peer> {
+    region.peers.iter().find(|peer| peer.store_id == store_id)
+}
+
+// Splits region at the given keys. The original region keeps its id and the
+// left-most range, every other range becomes a new region using the ids in
+// new_regions. All the resulting regions get their epoch version bumped.
+pub fn split_region(region: &Region, split_keys: &[Vec<u8>], new_regions: &[SplitIds]) -> TkvResult<Vec<Region>> {
+    if split_keys.is_empty() {
+        return Err(TkvError::new("missing split keys".to_string()));
+    }
+    if split_keys.len() != new_regions.len() {
+        return Err(TkvError::new(format!(
+            "expected {} new region ids, got {}", split_keys.len(), new_regions.len()
+        )));
+    }
+
+    let mut previous_key = region.start_key.as_slice();
+    for key in split_keys {
+        if key.as_slice() <= previous_key || !check_key_in_region(key, region) {
+            return Err(TkvError::new(format!("invalid split key {:?} for region {}", key, region.id)));
+        }
+        previous_key = key;
+    }
+    for ids in new_regions {
+        if ids.peer_ids.len() != region.peers.len() {
+            return Err(TkvError::new(format!(
+                "expected {} new peer ids, got {}", region.peers.len(), ids.peer_ids.len()
+            )));
+        }
+    }
+
+    let mut epoch = region_epoch(region);
+    epoch.version += 1;
+
+    let mut derived = region.clone();
+    derived.end_key = split_keys[0].clone();
+    derived.region_epoch = Some(epoch.clone());
+    let mut regions = vec![derived];
+
+    for (idx, ids) in new_regions.iter().enumerate() {
+        let end_key = split_keys.get(idx + 1).cloned().unwrap_or_else(|| region.end_key.clone());
+        let peers = region.peers.iter()
+            .zip(ids.peer_ids.iter())
+            .map(|(peer, id)| Peer { id: *id, store_id: peer.store_id, role: peer.role })
+            .collect();
+        regions.push(Region {
+            id: ids.region_id,
+            start_key: split_keys[idx].clone(),
+            end_key,
+            region_epoch: Some(epoch.clone()),
+            peers,
+        });
+    }
+
+    Ok(regions)
+}
+
+// Two regions are adjacent when one ends where the other starts.
+pub fn is_adjacent(left: &Region, right: &Region) -> bool {
+    !left.end_key.is_empty() && left.end_key == right.start_key
+}
+
+// Returns true if both regions have their peers on the same stores.
+pub fn is_same_stores(a: &Region, b: &Region) -> bool {
+    let mut a_stores: Vec<_> = a.peers.iter().map(|peer| peer.store_id).collect();
+    let mut b_stores: Vec<_> = b.peers.iter().map(|peer| peer.store_id).collect();
+    a_stores.sort_unstable();
+    b_stores.sort_unstable();
+    a_stores == b_stores
+}
+
+// Merges source into target. The target keeps its id and peers, covers both
+// ranges and gets an epoch version greater than the versions of both regions.
+pub fn merge_regions(source: &Region, target: &Region) -> TkvResult<Region> {
+    if !is_same_stores(source, target) {
+        return Err(TkvError::new(format!(
+            "regions {} and {} do not have their peers on the same stores", source.id, target.id
+        )));
+    }
+
+    let mut merged = target.clone();
+    if is_adjacent(source, target) {
+        merged.start_key = source.start_key.clone();
+    } else if is_adjacent(target, source) {
+        merged.end_key = source.end_key.clone();
+    } else {
+        return Err(TkvError::new(format!("regions {} and {} are not adjacent", source.id, target.id)));
+    }
+
+    let mut epoch = region_epoch(target);
+    epoch.version = epoch.version.max(region_epoch(source).version) + 1;
+    merged.region_epoch = Some(epoch);
+    Ok(merged)
+}
+
+pub fn is_learner(peer: &Peer) -> bool {
+    peer.role() == PeerRole::Learner
+}
+
+// Applies a membership change to region, bumping its epoch config version.
+// A store hosts at most one peer of a region, and only a learner is promoted.
+pub fn change_peer(region: &Region, change_type: ChangePeerType, peer: &Peer) -> TkvResult<Region> {
+    let mut changed = region.clone();
+    let existing = changed.peers.iter().position(|p| p.store_id == peer.store_id);
+    match (change_type, existing) {
+        (ChangePeerType::AddLearner, None) => {
+            let mut learner = peer.clone();
+            learner.set_role(PeerRole::Learner);
+            changed.peers.push(learner);
+        },
+        (ChangePeerType::Promote, Some(pos)) if changed.peers[pos].id == peer.id && is_learner(&changed.peers[pos]) => {
+            changed.peers[pos].set_role(PeerRole::Voter);
+        },
+        (ChangePeerType::Remove, Some(pos)) if changed.peers[pos].id == peer.id => {
+            changed.peers.remove(pos);
+        },
+        _ => {
+            return Err(TkvError::new(format!(
+                "cannot {:?} peer {:?} of region {} with peers {:?}", change_type, peer, region.id, region.peers
+            )));
+        },
+    }
+    let mut epoch = region_epoch(region);
+    epoch.config_version += 1;
+    changed.region_epoch = Some(epoch);
+    Ok(changed)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::cmd::{ChangePeerType, SplitIds};
+    use crate::proto::metapb::{Peer, PeerRole, Region, RegionEpoch};
+
+    use super::{change_peer, check_key_in_region, merge_regions, split_region};
+
+    fn new_region() -> Region {
+        Region {
+            id: 1,
+            start_key: b"a".to_vec(),
+            end_key: b"z".to_vec(),
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: vec![Peer { id: 10, store_id: 1, ..Default::default() }, Peer { id: 11, store_id: 2, ..Default::default() }],
+        }
+    }
+
+    #[test]
+    fn key_in_region() {
+        let mut region = new_region();
+        assert!(check_key_in_region(b"a", &region));
+        assert!(check_key_in_region(b"m", &region));
+        assert!(!check_key_in_region(b"z", &region));
+        assert!(!check_key_in_region(b"", &region));
+
+        region.end_key = vec![];
+        assert!(check_key_in_region(b"zzz", &region));
+    }
+
+    #[test]
+    fn split() -> TkvResult<()> {
+        let region = new_region();
+        let ids = vec![
+            SplitIds { region_id: 2, peer_ids: vec![20, 21] },
+            SplitIds { region_id: 3, peer_ids: vec![30, 31] },
+        ];
+
+        let regions = split_region(&region, &[b"g".to_vec(), b"p".to_vec()], &ids)?;
+        assert_eq!(regions.len(), 3);
+        let ranges: Vec<_> = regions.iter()
+            .map(|r| (r.id, r.start_key.clone(), r.end_key.clone()))
+            .collect();
+        assert_eq!(ranges, vec![
+            (1, b"a".to_vec(), b"g".to_vec()),
+            (2, b"g".to_vec(), b"p".to_vec()),
+            (3, b"p".to_vec(), b"z".to_vec()),
+        ]);
+        for r in &regions {
+            assert_eq!(r.region_epoch, Some(RegionEpoch { config_version: 1, version: 2 }));
+        }
+        assert_eq!(regions[2].peers, vec![Peer { id: 30, store_id: 1, ..Default::default() }, Peer { id: 31, store_id: 2, ..Default::default() }]);
+
+        // keys out of range or not sorted are rejected.
+        assert!(split_region(&region, &[b"a".to_vec()], &ids[..1]).is_err());
+        assert!(split_region(&region, &[b"zz".to_vec()], &ids[..1]).is_err());
+        assert!(split_region(&region, &[b"p".to_vec(), b"g".to_vec()], &ids).is_err());
+        assert!(split_region(&region, &[b"g".to_vec()], &ids).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn merge() -> TkvResult<()> {
+        let region = new_region();
+        let ids = vec![SplitIds { region_id: 2, peer_ids: vec![20, 21] }];
+        let regions = split_region(&region, &[b"g".to_vec()], &ids)?;
+        let (left, mut right) = (regions[0].clone(), regions[1].clone());
+        right.region_epoch.as_mut().unwrap().version = 5;
+
+        let merged = merge_regions(&left, &right)?;
+        assert_eq!(merged.id, 2);
+        assert_eq!((merged.start_key.as_slice(), merged.end_key.as_slice()), (b"a".as_slice(), b"z".as_slice()));
+        assert_eq!(merged.region_epoch.as_ref().unwrap().version, 6);
+
+        let merged = merge_regions(&right, &left)?;
+        assert_eq!(merged.id, 1);
+        assert_eq!((merged.start_key.as_slice(), merged.end_key.as_slice()), (b"a".as_slice(), b"z".as_slice()));
+
+        // regions must be adjacent and share the same stores.
+        assert!(merge_regions(&left, &left).is_err());
+        right.peers[1].store_id = 3;
+        assert!(merge_regions(&left, &right).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn change_peers() -> TkvResult<()> {
+        let region = new_region();
+        let peer = Peer { id: 12, store_id: 3, ..Default::default() };
+        let added = change_peer(&region, ChangePeerType::AddLearner, &peer)?;
+        assert_eq!(added.peers[2], Peer { id: 12, store_id: 3, role: PeerRole::Learner as i32 });
+        assert_eq!(added.region_epoch.as_ref().unwrap().config_version, 2);
+        // a store hosts a single peer of the region.
+        let other = Peer { id: 13, store_id: 3, ..Default::default() };
+        assert!(change_peer(&added, ChangePeerType::AddLearner, &other).is_err());
+        assert!(change_peer(&added, ChangePeerType::Promote, &other).is_err());
+
+        let promoted = change_peer(&added, ChangePeerType::Promote, &peer)?;
+        assert_eq!(promoted.peers[2].role(), PeerRole::Voter);
+        assert!(change_peer(&promoted, ChangePeerType::Promote, &peer).is_err());
+
+        let removed = change_peer(&promoted, ChangePeerType::Remove, &region.peers[0])?;
+        assert_eq!(removed.peers.iter().map(|peer| peer.id).collect::<Vec<_>>(), vec![11, 12]);
+        assert_eq!(removed.region_epoch.as_ref().unwrap().config_version, 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,357 @@
+use std::{fs::{self, File}, io::{BufReader, BufWriter, Read, Write}, path::{Path, PathBuf}, time::{Duration, Instant}};
+
+use futures::{Stream, StreamExt};
+use parking_lot::Mutex;
+use tokio::{io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt}, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::transport::Channel;
+
+use crate::{kv::{config::Config, error::{TkvError, TkvResult}, storage::mutation::Mutation, ColumnFamily, Storage}, proto::{metapb::Region, raft_serverpb::{SnapshotChunk, SnapshotMeta}, tinykv::tiny_kv_client::TinyKvClient}};
+
+use super::{engine::{ApplyState, PeerState, RaftWriteBatch, RegionLocalState}, region::region_bounds};
+
+
+// Column families held by a snapshot, the raft column family is local to each store.
+const SNAPSHOT_CFS: [ColumnFamily; 3] = [ColumnFamily::Default, ColumnFamily::Lock, ColumnFamily::Write];
+
+// Attempts to send a snapshot before giving up, each
+// attempt resumes from what the receiver already holds.
+const MAX_SEND_ATTEMPTS: usize = 5;
+
+// A record of a snapshot file, the file ends with None.
+type SnapshotRecord = Option<(ColumnFamily, Vec<u8>, Vec<u8>)>;
+
+/// SnapManager owns the snapshot files of a store. It generates, sends,
+/// receives and applies the snapshots of the regions hosted on the store.
+#[derive(Debug)]
+pub struct SnapManager {
+    dir: PathBuf,
+    chunk_size: usize,
+    // bytes sent per second by all the snapshots of the store.
+    max_bytes_per_sec: u64,
+    // instant at which the rate limiter lets the next bytes out.
+    available_at: Mutex<Instant>,
+}
+
+impl SnapManager {
+    pub fn new(dir: impl AsRef<Path>, config: &Config) -> TkvResult<Self> {
+        fs::create_dir_all(dir.as_ref())?;
+        Ok(Self {
+            dir: dir.as_ref().to_path_buf(),
+            chunk_size: config.snap_chunk_size.num_bytes() as usize,
+            max_bytes_per_sec: config.snap_max_bytes_per_sec.num_bytes(),
+            available_at: Mutex::new(Instant::now()),
+        })
+    }
+
+    fn gen_path(&self, region_id: u64, term: u64, index: u64) -> PathBuf {
+        self.dir.join(format!("gen_{}_{}_{}.snap", region_id, term, index))
+    }
+
+    fn recv_path(&self, meta: &SnapshotMeta) -> PathBuf {
+        let region_id = meta.region.as_ref().map(|region| region.id).unwrap_or_default();
+        let peer_id = meta.to_peer.as_ref().map(|peer| peer.id).unwrap_or_default();
+        self.dir.join(format!("rcv_{}_{}_{}_{}.snap", region_id, peer_id, meta.term, meta.index))
+    }
+
+    // Writes the data of the region to a snapshot file, the caller makes
+    // sure that index is the last entry applied to the storage.
+    pub fn generate(&self, storage: &dyn Storage, region: &Region, term: u64, index: u64) -> TkvResult<(SnapshotMeta, PathBuf)> {
+        let path = self.gen_path(region.id, term, index);
+        let mut writer = ChecksumWriter::new(BufWriter::new(File::create(&path)?));
+        for cf in SNAPSHOT_CFS {
+            let (start, end) = region_bounds(region);
+            let scanner = storage.scan(cf, start, end)?;
+            for item in scanner.iter() {
+                let (key, value) = item?;
+                let record: SnapshotRecord = Some((cf, key, value));
+                bincode::serialize_into(&mut writer, &record)?;
+            }
+        }
+        bincode::serialize_into(&mut writer, &SnapshotRecord::None)?;
+        let (size, checksum) = writer.finish()?;
+
+        let meta = SnapshotMeta {
+            region: Some(region.clone()),
+            term,
+            index,
+            size,
+            checksum,
+            ..Default::default()
+        };
+        Ok((meta, path))
+    }
+
+    // Replaces the data of the region with the snapshot. The data, the
+    // applied index and the region are written within a single write.
+    pub fn apply(&self, storage: &dyn Storage, meta: &SnapshotMeta, path: &Path) -> TkvResult<()> {
+        let region = meta.region.clone().unwrap_or_default();
+        let mut batch = clear_region_data(storage, &region)?;
+
+        let mut reader = BufReader::new(File::open(path)?);
+        while let Some((cf, key, value)) = bincode::deserialize_from::<_, SnapshotRecord>(&mut reader)? {
+            batch.push(Mutation::Put { key, value, cf });
+        }
+
+        let mut wb = RaftWriteBatch::default();
+        wb.set_apply_state(region.id, &ApplyState { applied_index: meta.index })?;
+        wb.set_region_state(region.id, &RegionLocalState { region: region.clone(), state: PeerState::Normal })?;
+        batch.extend(wb.into_mutations());
+        storage.write(batch)
+    }
+
+    // Sends a snapshot file to a store, resuming from the bytes
+    // the store already received when an attempt fails.
+    pub async fn send(&self, client: &mut TinyKvClient<Channel>, meta: &SnapshotMeta, path: &Path) -> TkvResult<()> {
+        let mut offset = 0;
+        let mut attempts = 0;
+        while attempts < MAX_SEND_ATTEMPTS {
+            match self.send_from(client, meta, path, offset).await {
+                Ok(received) if received >= meta.size => return Ok(()),
+                Ok(received) => {
+                    // the receiver lost what it had, or made no progress.
+                    if received <= offset {
+                        attempts += 1;
+                    }
+                    offset = received;
+                },
+                Err(err) => {
+                    attempts += 1;
+                    log::warn!("failed to send snapshot {:?}, attempt {}: {:?}", path, attempts, err);
+                    tokio::time::sleep(Duration::from_millis(100) * attempts as u32).await;
+                },
+            }
+        }
+        Err(TkvError::new(format!("failed to send snapshot {:?}", path)))
+    }
+
+    // Streams the file from offset and returns the bytes held by the receiver.
+    async fn send_from(&self, client: &mut TinyKvClient<Channel>, meta: &SnapshotMeta, path: &Path, offset: u64) -> TkvResult<u64> {
+        let mut file = tokio::fs::File::open(path).await?;
+        file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+        let (chunk_tx, chunk_rx) = mpsc::channel(4);
+        let call = client.snapshot(ReceiverStream::new(chunk_rx));
+        let produce = async {
+            let mut offset = offset;
+            // an empty chunk tells the receiver where the sender starts.
+            let mut data = vec![];
+            loop {
+                let chunk = SnapshotChunk { meta: Some(meta.clone()), offset, data: std::mem::take(&mut data) };
+                offset += chunk.data.len() as u64;
+                if chunk_tx.send(chunk).await.is_err() {
+                    break; // the receiver stopped early.
+                }
+                if offset >= meta.size {
+                    break;
+                }
+                data = vec![0; self.chunk_size.min((meta.size - offset) as usize)];
+                file.read_exact(&mut data).await?;
+                tokio::time::sleep(self.reserve(data.len() as u64)).await;
+            }
+            drop(chunk_tx);
+            Ok::<_, TkvError>(())
+        };
+
+        let (response, produced) = tokio::join!(call, produce);
+        produced?;
+        Ok(response?.into_inner().received)
+    }
+
+    // Reserves the bandwidth to send n bytes and returns the delay before sending them.
+    fn reserve(&self, n: u64) -> Duration {
+        if self.max_bytes_per_sec == 0 {
+            return Duration::ZERO;
+        }
+        let now = Instant::now();
+        let mut available_at = self.available_at.lock();
+        let start = (*available_at).max(now);
+        *available_at = start + Duration::from_secs_f64(n as f64 / self.max_bytes_per_sec as f64);
+        start - now
+    }
+
+    // Receives a snapshot into a partial file kept across attempts. Returns the
+    // bytes held for the snapshot, along with the snapshot once it is complete.
+    pub async fn recv<S>(&self, mut chunks: S) -> TkvResult<(u64, Option<(SnapshotMeta, PathBuf)>)>
+    where
+        S: Stream<Item = Result<SnapshotChunk, tonic::Status>> + Unpin,
+    {
+        let Some(first) = chunks.next().await.transpose()? else {
+            return Ok((0, None));
+        };
+        let meta = first.meta.clone().ok_or(TkvError::new("snapshot chunk without meta".to_string()))?;
+        let path = self.recv_path(&meta);
+        let tmp_path = path.with_extension("tmp");
+        let mut file = tokio::fs::OpenOptions::new().create(true).append(true).open(&tmp_path).await?;
+        let mut received = file.metadata().await?.len();
+
+        let mut next = Some(first);
+        while let Some(chunk) = next {
+            if chunk.offset != received || received + chunk.data.len() as u64 > meta.size {
+                // the sender has to resume from what is already here.
+                file.flush().await?;
+                return Ok((received, None));
+            }
+            file.write_all(&chunk.data).await?;
+            received += chunk.data.len() as u64;
+            next = chunks.next().await.transpose()?;
+        }
+        file.flush().await?;
+        if received < meta.size {
+            return Ok((received, None));
+        }
+
+        if file_checksum(&tmp_path)? != meta.checksum {
+            fs::remove_file(&tmp_path)?;
+            return Err(TkvError::new(format!("checksum mismatch for snapshot {:?}", path)));
+        }
+        fs::rename(&tmp_path, &path)?;
+        Ok((received, Some((meta, path))))
+    }
+}
+
+
+// Computes the crc32 of the bytes written through it.
+struct ChecksumWriter<W: Write> {
+    inner: W,
+    hasher: crc32fast::Hasher,
+    size: u64,
+}
+
+impl<W: Write> ChecksumWriter<W> {
+    fn new(inner: W) -> Self {
+        Self { inner, hasher: crc32fast::Hasher::new(), size: 0 }
+    }
+
+    // Flushes the file and returns its size and checksum.
+    fn finish(mut self) -> TkvResult<(u64, u32)> {
+        self.inner.flush()?;
+        Ok((self.size, self.hasher.finalize()))
+    }
+}
+
+impl<W: Write> Write for ChecksumWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn file_checksum(path: &Path) -> TkvResult<u32> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = reader.read(&mut buf)?;
+        if n == 0 {
+            return Ok(hasher.finalize());
+        }
+        hasher.update(&buf[..n]);
+    }
+}
+
+
+// The deletes of all the data held in the range of region.
+pub fn clear_region_data(storage: &dyn Storage, region: &Region) -> TkvResult<Vec<Mutation>> {
+    let mut batch = vec![];
+    for cf in SNAPSHOT_CFS {
+        let (start, end) = region_bounds(region);
+        let scanner = storage.scan(cf, start, end)?;
+        for item in scanner.iter() {
+            let (key, _) = item?;
+            batch.push(Mutation::Delete { key, cf });
+        }
+    }
+    Ok(batch)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, sync::Arc, time::Duration};
+
+    use tempfile::tempdir;
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::config::{Config, DiskSize};
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::engine::RaftEngine;
+    use crate::kv::raftstore::store::StoreMsg;
+    use crate::kv::server::TinyKvService;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::proto::metapb::{Peer, Region};
+    use crate::proto::tinykv::{tiny_kv_client::TinyKvClient, tiny_kv_server::TinyKvServer};
+
+    use super::{SnapManager, SNAPSHOT_CFS};
+
+    #[tokio::test]
+    async fn send_and_apply() -> TkvResult<()> {
+        let mut config = Config::for_test();
+        config.snap_chunk_size = DiskSize::KiB(1);
+        let region = Region { id: 1, start_key: b"b".to_vec(), end_key: b"y".to_vec(), ..Default::default() };
+
+        let source = MemoryStorage::new();
+        for cf in SNAPSHOT_CFS {
+            for i in 0..100u32 {
+                source.put(cf, format!("k{:03}", i).as_bytes(), vec![1; 64])?;
+            }
+            source.put(cf, b"a", vec![1])?; // outside of the region.
+        }
+        let source_dir = tempdir()?;
+        let sender = SnapManager::new(source_dir.path(), &config)?;
+        let (mut meta, path) = sender.generate(&source, &region, 2, 10)?;
+        meta.to_peer = Some(Peer { id: 3, store_id: 3, ..Default::default() });
+
+        // the receiver kept half of the snapshot from an interrupted transfer.
+        let target_dir = tempdir()?;
+        let receiver = Arc::new(SnapManager::new(target_dir.path(), &config)?);
+        let content = std::fs::read(&path)?;
+        std::fs::File::create(receiver.recv_path(&meta).with_extension("tmp"))?.write_all(&content[..content.len() / 2])?;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let (router, mut store_rx) = unbounded_channel();
+        let service = TinyKvService::new(Arc::new(MemoryStorage::new()))
+            .with_raft_router(router)
+            .with_snap_manager(receiver.clone());
+        let server = tokio::spawn(Server::builder()
+            .add_service(TinyKvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let mut client = TinyKvClient::connect(format!("http://{}", addr)).await?;
+        sender.send(&mut client, &meta, &path).await?;
+        let Ok(Some(StoreMsg::Snapshot { meta, path })) = tokio::time::timeout(Duration::from_secs(1), store_rx.recv()).await else {
+            panic!("snapshot not received");
+        };
+        assert_eq!(std::fs::read(&path)?, content);
+
+        // the region data is replaced, the data around it is kept.
+        let target = MemoryStorage::new();
+        for cf in SNAPSHOT_CFS {
+            target.put(cf, b"c", vec![2])?;
+            target.put(cf, b"z", vec![2])?;
+        }
+        receiver.apply(&target, &meta, &path)?;
+        for cf in SNAPSHOT_CFS {
+            assert_eq!(target.get(cf, b"k042")?, Some(vec![1; 64]));
+            assert_eq!(target.get(cf, b"c")?, None);
+            assert_eq!(target.get(cf, b"z")?, Some(vec![2]));
+            assert_eq!(target.get(cf, b"a")?, None);
+        }
+        assert_eq!(target.get(ColumnFamily::Default, b"k099")?, Some(vec![1; 64]));
+        let target = Arc::new(target);
+        assert_eq!(RaftEngine::new(target).apply_state(1)?.map(|state| state.applied_index), Some(10));
+
+        server.abort();
+        Ok(())
+    }
+}
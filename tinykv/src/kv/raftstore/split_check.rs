@@ -0,0 +1,191 @@
+use crate::{kv::{config::Config, error::TkvResult, transaction::mvcc::decode_key, ColumnFamily, Storage}, proto::metapb::Region};
+
+use super::region::region_bounds;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SplitCheckResult {
+    // approximate size of the region across all column families.
+    pub size: u64,
+    // keys to split the region at, empty when no split is needed.
+    pub split_keys: Vec<Vec<u8>>,
+}
+
+/// SplitChecker estimates the size of a region and, when it exceeds
+/// region_max_size, picks the keys to split it at so that every
+/// resulting region is about region_split_size.
+#[derive(Debug, Clone)]
+pub struct SplitChecker {
+    max_size: u64,
+    split_size: u64,
+}
+
+impl SplitChecker {
+    pub fn new(max_size: u64, split_size: u64) -> Self {
+        Self { max_size, split_size }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.region_max_size.num_bytes(), config.region_split_size.num_bytes())
+    }
+
+    pub fn check(&self, storage: &dyn Storage, region: &Region) -> TkvResult<SplitCheckResult> {
+        let (start, end) = region_bounds(region);
+
+        // split keys are picked on the default cf, where the values live.
+        let mut size = 0u64;
+        let mut chunk_size = 0u64;
+        let mut split_keys: Vec<Vec<u8>> = vec![];
+        {
+            let scanner = storage.scan(ColumnFamily::Default, start.clone(), end.clone())?;
+            for item in scanner.iter() {
+                let (key, value) = item?;
+                if chunk_size >= self.split_size {
+                    let split_key = split_key(&key);
+                    if split_key > *split_keys.last().unwrap_or(&region.start_key) {
+                        split_keys.push(split_key);
+                        chunk_size = 0;
+                    }
+                }
+                let entry_size = (key.len() + value.len()) as u64;
+                chunk_size += entry_size;
+                size += entry_size;
+            }
+        }
+
+        for cf in [ColumnFamily::Write, ColumnFamily::Lock] {
+            size += cf_size(storage, cf, region)?;
+        }
+
+        if size <= self.max_size {
+            split_keys.clear();
+        }
+        Ok(SplitCheckResult { size, split_keys })
+    }
+
+    // The key splitting the region in two halves of about the same size,
+    // None when the region holds a single key.
+    pub fn half_split_key(&self, storage: &dyn Storage, region: &Region) -> TkvResult<Option<Vec<u8>>> {
+        let size = cf_size(storage, ColumnFamily::Default, region)?;
+        let (start, end) = region_bounds(region);
+        let scanner = storage.scan(ColumnFamily::Default, start, end)?;
+        let mut chunk_size = 0;
+        for item in scanner.iter() {
+            let (key, value) = item?;
+            if chunk_size > 0 && chunk_size * 2 >= size && split_key(&key) > region.start_key {
+                return Ok(Some(split_key(&key)));
+            }
+            chunk_size += (key.len() + value.len()) as u64;
+        }
+        Ok(None)
+    }
+}
+
+// The versions of a key stay in the same region, along with its lock, a
+// versioned key is cut to the encoded user key they all start with.
+fn split_key(key: &[u8]) -> Vec<u8> {
+    match decode_key(key) {
+        Ok(_) => key[..key.len() - 8].to_vec(),
+        Err(_) => key.to_vec(),
+    }
+}
+
+// Approximate size of the region's keys and values across all column families.
+pub fn approximate_size(storage: &dyn Storage, region: &Region) -> TkvResult<u64> {
+    let mut size = 0;
+    for cf in [ColumnFamily::Default, ColumnFamily::Write, ColumnFamily::Lock] {
+        size += cf_size(storage, cf, region)?;
+    }
+    Ok(size)
+}
+
+fn cf_size(storage: &dyn Storage, cf: ColumnFamily, region: &Region) -> TkvResult<u64> {
+    let (start, end) = region_bounds(region);
+    let scanner = storage.scan(cf, start, end)?;
+    let mut size = 0;
+    for item in scanner.iter() {
+        let (key, value) = item?;
+        size += (key.len() + value.len()) as u64;
+    }
+    Ok(size)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::transaction::mvcc::{decode_bytes, encode_bytes, encode_key, lock::Lock, write::{Write, WriteKind}, MvccTxn};
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::proto::metapb::Region;
+
+    use super::SplitChecker;
+
+    #[test]
+    fn split_check() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        // 10 entries of 10 bytes each
+        for i in 0..10u8 {
+            storage.put(ColumnFamily::Default, &[b'k', i], vec![0; 8])?;
+        }
+        storage.put(ColumnFamily::Write, &[b'k', 0], vec![0; 8])?;
+        // outside of the region
+        storage.put(ColumnFamily::Default, b"z", vec![0; 100])?;
+
+        let region = Region { id: 1, start_key: b"k".to_vec(), end_key: b"l".to_vec(), ..Default::default() };
+
+        let result = SplitChecker::new(200, 30).check(&storage, &region)?;
+        assert_eq!(result.size, 110);
+        assert!(result.split_keys.is_empty());
+
+        let result = SplitChecker::new(100, 30).check(&storage, &region)?;
+        assert_eq!(result.size, 110);
+        assert_eq!(result.split_keys, vec![vec![b'k', 3], vec![b'k', 6], vec![b'k', 9]]);
+
+        let checker = SplitChecker::new(200, 30);
+        assert_eq!(checker.half_split_key(&storage, &region)?, Some(vec![b'k', 5]));
+        let region = Region { id: 1, start_key: vec![b'k', 9], end_key: b"l".to_vec(), ..Default::default() };
+        assert_eq!(checker.half_split_key(&storage, &region)?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn split_between_keys() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        // 4 versions of 10 keys of 12 bytes, the last ones are locked.
+        let keys: Vec<Vec<u8>> = (0..10).map(|i| format!("user_key_{:03}", i).into_bytes()).collect();
+        for key in &keys {
+            for ts in 1..=4 {
+                let mut txn = MvccTxn::new(&storage, ts * 10);
+                txn.put_value(key, vec![0; 16]);
+                txn.put_write(key, ts * 10 + 1, &Write::new(ts * 10, WriteKind::Put))?;
+                storage.write(txn.into_mutations())?;
+            }
+            let mut txn = MvccTxn::new(&storage, 50);
+            txn.put_lock(key, &Lock::new(key.clone(), 50, 3000, WriteKind::Put))?;
+            storage.write(txn.into_mutations())?;
+        }
+
+        let region = Region { id: 1, ..Default::default() };
+        let result = SplitChecker::new(500, 300).check(&storage, &region)?;
+        assert!(!result.split_keys.is_empty());
+        // every split key is the encoded user key of the versions following it.
+        for split_key in &result.split_keys {
+            let (key, rest) = decode_bytes(split_key)?;
+            assert!(rest.is_empty() && keys.contains(&key));
+        }
+        let split_keys = &result.split_keys;
+        let region_of = |encoded: &[u8]| split_keys.iter().filter(|split_key| encoded >= split_key.as_slice()).count();
+        for key in &keys {
+            let region = region_of(&encode_bytes(key));
+            for ts in 1..=4 {
+                assert_eq!(region_of(&encode_key(key, ts * 10)), region);
+                assert_eq!(region_of(&encode_key(key, ts * 10 + 1)), region);
+            }
+        }
+
+        let half = SplitChecker::new(500, 300).half_split_key(&storage, &region)?.unwrap();
+        assert!(decode_bytes(&half)?.1.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,1528 @@
+use std::{collections::{HashMap, HashSet}, path::PathBuf, sync::Arc, time::{Duration, Instant}};
+
+use parking_lot::RwLock;
+use tokio::sync::{mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender}, oneshot};
+
+use crate::{kv::{config::Config, error::{TkvError, TkvResult}, Storage}, proto::{metapb::{self, Region, RegionEpoch}, raft_serverpb::{RaftMessage, SnapshotMeta}, schedulerpb::StoreStats}, scheduler::hot_region::RegionFlow};
+
+use super::{apply::{ApplyDelegate, ApplyPool, ApplyRes, ApplyResult, ApplyTask}, cmd::{AdminCmd, ChangePeerType, CmdRequest, RaftCmd, SplitIds}, engine::{PeerState, RaftEngine}, heartbeat::{HeartbeatTask, HeartbeatWorker}, merge_check::MergeChecker, peer::{Entry, Peer}, region::region_epoch, snap::SnapManager, split_check::SplitChecker, transport::Transport, IdAllocator, StoreMeta};
+
+
+pub type Callback = oneshot::Sender<TkvResult<()>>;
+
+pub type StoreSender = UnboundedSender<StoreMsg>;
+pub type StoreReceiver = UnboundedReceiver<StoreMsg>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreTick {
+    Raft,
+    SplitRegionCheck,
+    MergeRegionCheck,
+    SchedulerHeartbeat,
+    SchedulerStoreHeartbeat,
+}
+
+#[derive(Debug)]
+pub enum StoreMsg {
+    // propose a command, the callback is notified once it is applied.
+    RaftCmd { cmd: RaftCmd, callback: Option<Callback> },
+    // a message from a peer hosted on another store.
+    RaftMessage(RaftMessage),
+    // sent by the apply pool once committed entries are applied.
+    ApplyRes(ApplyRes),
+    // the apply pool could not write an entry, the region stopped applying.
+    ApplyFailed { region_id: u64, error: TkvError },
+    // a snapshot received from the leader of a region.
+    Snapshot { meta: SnapshotMeta, path: PathBuf },
+    // a snapshot could not be sent to a follower.
+    SnapshotFailed { region_id: u64, to_peer_id: u64 },
+    Tick(StoreTick),
+    // asked by the scheduler to split a region with a heavy traffic.
+    HalfSplitRegion { region_id: u64, region_epoch: RegionEpoch },
+    // sent back by the split check worker for a region that is too large.
+    SplitRegion { region_id: u64, region_epoch: RegionEpoch, split_keys: Vec<Vec<u8>> },
+    // sent back by the merge check worker for a region small enough to be merged.
+    MergeRegion { source_id: u64, source_epoch: RegionEpoch, target: Region },
+    // asked by the scheduler to move the leadership of a region.
+    TransferLeader { region_id: u64, region_epoch: RegionEpoch, peer: metapb::Peer },
+    // sent back by the heartbeat worker once max_ts is raised for a leader.
+    MaxTsSynced { region_id: u64, term: u64 },
+    Stop,
+}
+
+/// RaftStore drives all the region peers hosted on a store.
+/// It runs as a single event loop fed by StoreMsg.
+pub struct RaftStore {
+    store_id: u64,
+    storage: Arc<dyn Storage>,
+    peers: HashMap<u64, Peer>,
+    meta: Arc<RwLock<StoreMeta>>,
+    id_allocator: Arc<dyn IdAllocator>,
+    transport: Arc<dyn Transport>,
+    apply_pool: ApplyPool,
+    raft_base_tick_interval: Duration,
+    // in base ticks.
+    raft_election_timeout: u64,
+    raft_log_gc_count_limit: u64,
+    split_checker: SplitChecker,
+    split_region_check_tick_interval: Duration,
+    merge_checker: MergeChecker,
+    merge_check_tick_interval: Duration,
+    // reports to the scheduler, once the store runs.
+    heartbeat_worker: Option<HeartbeatWorker>,
+    heartbeat_tx: Option<UnboundedSender<HeartbeatTask>>,
+    scheduler_heartbeat_tick_interval: Duration,
+    scheduler_store_heartbeat_tick_interval: Duration,
+    // the regions report their traffic since then.
+    last_region_heartbeat: Instant,
+    // source regions with a PrepareMerge proposed but not applied yet,
+    // source_id -> (target_id, index of the PrepareMerge).
+    preparing_merges: HashMap<u64, (u64, u64)>,
+    // source regions with a CommitMerge proposed to their target.
+    pending_merges: HashSet<u64>,
+    // pending proposals waiting to be handed to the apply pool: (region_id, index) -> callback
+    callbacks: HashMap<(u64, u64), Callback>,
+    store_tx: StoreSender,
+    store_rx: StoreReceiver,
+}
+
+impl RaftStore {
+    // Creates the store with the regions persisted by a previous run,
+    // plus the given regions that the store did not know about yet.
+    pub fn new(
+        store_id: u64,
+        config: &Config,
+        storage: Arc<dyn Storage>,
+        id_allocator: Arc<dyn IdAllocator>,
+        transport: Arc<dyn Transport>,
+        snap_manager: Arc<SnapManager>,
+        regions: Vec<Region>,
+    ) -> TkvResult<Self> {
+        let mut known = HashMap::new();
+        for state in RaftEngine::new(storage.clone()).region_states()? {
+            known.insert(state.region.id, state);
+        }
+        let mut regions: Vec<Region> = regions.into_iter()
+            .filter(|region| !known.contains_key(&region.id))
+            .collect();
+        regions.extend(known.into_values()
+            .filter(|state| state.state != PeerState::Tombstone)
+            .map(|state| state.region));
+
+        let raft_election_timeout = (config.raft_election_timeout_ticks.as_millis() / config.raft_base_tick_interval.as_millis().max(1)) as u64;
+        let mut meta = StoreMeta::default();
+        let mut peers = HashMap::new();
+        for region in regions {
+            meta.set_region(region.clone());
+            let peer = Peer::new(store_id, region, storage.clone())?.with_election_timeout(raft_election_timeout);
+            meta.set_leader(peer.region_id(), peer.leader_id, peer.term());
+            peers.insert(peer.region_id(), peer);
+        }
+        let (store_tx, store_rx) = unbounded_channel();
+        let apply_pool = ApplyPool::new(
+            config.apply_pool_size,
+            config.apply_queue_size,
+            storage.clone(),
+            snap_manager,
+            store_tx.clone(),
+        )?;
+        Ok(Self {
+            store_id,
+            storage,
+            peers,
+            meta: Arc::new(RwLock::new(meta)),
+            id_allocator,
+            transport,
+            apply_pool,
+            raft_base_tick_interval: config.raft_base_tick_interval,
+            raft_election_timeout,
+            raft_log_gc_count_limit: config.raft_log_gc_count_limit,
+            split_checker: SplitChecker::from_config(config),
+            split_region_check_tick_interval: config.split_region_check_tick_interval,
+            merge_checker: MergeChecker::from_config(config),
+            merge_check_tick_interval: config.merge_check_tick_interval,
+            heartbeat_worker: None,
+            heartbeat_tx: None,
+            scheduler_heartbeat_tick_interval: config.scheduler_heartbeat_tick_interval,
+            scheduler_store_heartbeat_tick_interval: config.scheduler_store_heartbeat_tick_interval,
+            last_region_heartbeat: Instant::now(),
+            preparing_merges: HashMap::new(),
+            pending_merges: HashSet::new(),
+            callbacks: HashMap::new(),
+            store_tx,
+            store_rx,
+        })
+    }
+
+    // Reports the store and the regions it leads to the scheduler.
+    pub fn with_scheduler(mut self, worker: HeartbeatWorker) -> Self {
+        self.heartbeat_worker = Some(worker);
+        self
+    }
+
+    pub fn sender(&self) -> StoreSender {
+        self.store_tx.clone()
+    }
+
+    pub fn meta(&self) -> Arc<RwLock<StoreMeta>> {
+        self.meta.clone()
+    }
+
+    pub async fn run(mut self) -> TkvResult<()> {
+        spawn_ticker(self.store_tx.clone(), self.raft_base_tick_interval, StoreTick::Raft);
+        spawn_ticker(self.store_tx.clone(), self.split_region_check_tick_interval, StoreTick::SplitRegionCheck);
+        spawn_ticker(self.store_tx.clone(), self.merge_check_tick_interval, StoreTick::MergeRegionCheck);
+        if let Some(worker) = self.heartbeat_worker.take() {
+            self.heartbeat_tx = Some(worker.spawn(self.store_tx.clone()));
+            spawn_ticker(self.store_tx.clone(), self.scheduler_heartbeat_tick_interval, StoreTick::SchedulerHeartbeat);
+            spawn_ticker(self.store_tx.clone(), self.scheduler_store_heartbeat_tick_interval, StoreTick::SchedulerStoreHeartbeat);
+        }
+        for peer in self.peers.values() {
+            self.apply_pool.schedule(ApplyTask::Register(ApplyDelegate::new(peer)))?;
+        }
+
+        while let Some(msg) = self.store_rx.recv().await {
+            match msg {
+                StoreMsg::RaftCmd { cmd, callback } => self.propose(cmd, callback),
+                StoreMsg::RaftMessage(msg) => self.on_raft_message(msg).await?,
+                StoreMsg::ApplyRes(res) => self.on_apply_res(res).await?,
+                StoreMsg::ApplyFailed { region_id, error } => self.on_apply_failed(region_id, error),
+                StoreMsg::Snapshot { meta, path } => {
+                    if let Err(err) = self.on_snapshot(meta, path.clone()).await {
+                        log::error!("failed to handle snapshot {:?}: {:?}", path, err);
+                        let _ = std::fs::remove_file(&path);
+                    }
+                },
+                StoreMsg::SnapshotFailed { region_id, to_peer_id } => {
+                    if let Some(peer) = self.peers.get_mut(&region_id) {
+                        peer.report_snapshot_failure(to_peer_id);
+                    }
+                },
+                StoreMsg::Tick(StoreTick::Raft) => self.on_raft_base_tick(),
+                StoreMsg::Tick(StoreTick::SplitRegionCheck) => self.on_split_region_check_tick(),
+                StoreMsg::Tick(StoreTick::MergeRegionCheck) => self.on_merge_region_check_tick(),
+                StoreMsg::Tick(StoreTick::SchedulerHeartbeat) => self.on_scheduler_heartbeat_tick(),
+                StoreMsg::Tick(StoreTick::SchedulerStoreHeartbeat) => self.on_scheduler_store_heartbeat_tick(),
+                StoreMsg::HalfSplitRegion { region_id, region_epoch } => {
+                    self.on_half_split_region(region_id, region_epoch);
+                },
+                StoreMsg::SplitRegion { region_id, region_epoch, split_keys } => {
+                    if let Err(err) = self.on_split_region(region_id, region_epoch, split_keys) {
+                        log::error!("failed to propose split for region {}: {:?}", region_id, err);
+                    }
+                },
+                StoreMsg::MergeRegion { source_id, source_epoch, target } => {
+                    self.on_merge_region(source_id, source_epoch, target);
+                },
+                StoreMsg::TransferLeader { region_id, region_epoch, peer } => {
+                    self.on_transfer_leader(region_id, region_epoch, peer);
+                },
+                StoreMsg::MaxTsSynced { region_id, term } => {
+                    if self.peers.get(&region_id).is_some_and(|peer| peer.is_leader() && peer.term() == term) {
+                        self.meta.write().set_max_ts_synced(region_id, term);
+                    }
+                },
+                StoreMsg::Stop => break,
+            }
+            self.persist()?;
+            self.handle_committed_entries().await?;
+            self.handle_snapshot_requests().await?;
+            self.send_messages();
+            self.sync_leaders();
+            // the workers make room as they apply, each sends back an ApplyRes.
+            self.apply_pool.flush()?;
+        }
+        Ok(())
+    }
+
+    // Publishes the leaders that changed, requests check them against
+    // their context.
+    fn sync_leaders(&self) {
+        let changed: Vec<_> = {
+            let meta = self.meta.read();
+            self.peers.values()
+                .map(|peer| (peer.region_id(), peer.leader_id, peer.term()))
+                .filter(|(region_id, leader_id, term)| meta.leader(*region_id) != Some((*leader_id, *term)))
+                .collect()
+        };
+        if changed.is_empty() {
+            return;
+        }
+        let mut meta = self.meta.write();
+        for (region_id, leader_id, term) in changed {
+            meta.set_leader(region_id, leader_id, term);
+            let leads = self.peers.get(&region_id).is_some_and(|peer| peer.is_leader());
+            if let (true, Some(heartbeat_tx)) = (leads, &self.heartbeat_tx) {
+                let _ = heartbeat_tx.send(HeartbeatTask::UpdateMaxTs { region_id, term });
+            }
+        }
+    }
+
+    fn propose(&mut self, cmd: RaftCmd, callback: Option<Callback>) {
+        let result = self.propose_cmd(&cmd);
+        if let (Ok(_), CmdRequest::Write(batch)) = (&result, &cmd.request) {
+            let bytes = batch.iter().map(|mutation| mutation.size() as u64).sum();
+            self.meta.write().record_write(cmd.region_id, batch.len() as u64, bytes);
+        }
+        match (result, callback) {
+            (Ok(index), Some(callback)) => {
+                self.callbacks.insert((cmd.region_id, index), callback);
+            },
+            (Err(err), Some(callback)) => {
+                let _ = callback.send(Err(err));
+            },
+            (Err(err), None) => log::warn!("failed to propose command: {:?}", err),
+            (Ok(_), None) => (),
+        }
+    }
+
+    fn propose_cmd(&mut self, cmd: &RaftCmd) -> TkvResult<u64> {
+        match self.peers.get_mut(&cmd.region_id) {
+            Some(peer) => {
+                let index = peer.propose(cmd)?;
+                peer.broadcast_append()?;
+                Ok(index)
+            },
+            None => Err(TkvError::new(format!("region {} not found", cmd.region_id))),
+        }
+    }
+
+    async fn on_raft_message(&mut self, msg: RaftMessage) -> TkvResult<()> {
+        let region_id = msg.region_id;
+        let Some(peer) = self.peers.get_mut(&region_id) else {
+            log::debug!("drop raft message for unknown region {}", region_id);
+            return Ok(());
+        };
+        if let Err(err) = peer.step(msg) {
+            log::warn!("failed to handle raft message for region {}: {:?}", region_id, err);
+        }
+        if peer.is_removed() {
+            log::info!("peer of region {} was removed, destroy it", region_id);
+            self.destroy_peer(region_id).await?;
+        }
+        Ok(())
+    }
+
+    fn on_transfer_leader(&mut self, region_id: u64, epoch: RegionEpoch, to: metapb::Peer) {
+        let Some(peer) = self.peers.get_mut(&region_id) else {
+            return;
+        };
+        if region_epoch(&peer.region) != epoch {
+            log::info!("drop stale leader transfer of region {}", region_id);
+            return;
+        }
+        if let Err(err) = peer.transfer_leader(&to) {
+            log::warn!("failed to transfer leader of region {}: {:?}", region_id, err);
+        }
+    }
+
+    // Destroys a peer removed from its region, along with the region data.
+    async fn destroy_peer(&mut self, region_id: u64) -> TkvResult<()> {
+        if let Some(peer) = self.peers.remove(&region_id) {
+            let region = peer.region.clone();
+            peer.destroy()?;
+            self.apply_pool.schedule(ApplyTask::Remove { region })?;
+            self.meta.write().remove_region(region_id);
+        }
+        Ok(())
+    }
+
+    // Leaders send heartbeats, the other voters may campaign.
+    fn on_raft_base_tick(&mut self) {
+        for peer in self.peers.values_mut() {
+            if let Err(err) = peer.tick() {
+                log::warn!("failed to replicate region {}: {:?}", peer.region_id(), err);
+            }
+        }
+    }
+
+    fn on_scheduler_heartbeat_tick(&mut self) {
+        let Some(heartbeat_tx) = &self.heartbeat_tx else {
+            return;
+        };
+        let interval = self.last_region_heartbeat.elapsed();
+        self.last_region_heartbeat = Instant::now();
+        let mut meta = self.meta.write();
+        for peer in self.peers.values() {
+            // a follower drops what it served while it was the leader.
+            let flow = RegionFlow { interval, ..meta.take_flow(peer.region_id()) };
+            if peer.is_leader() {
+                let _ = heartbeat_tx.send(HeartbeatTask::Region { region: peer.region.clone(), leader: peer.meta.clone(), flow });
+                // retries a failed update, or the first one of a leader since the store started.
+                if !meta.is_max_ts_synced(peer.region_id()) {
+                    let _ = heartbeat_tx.send(HeartbeatTask::UpdateMaxTs { region_id: peer.region_id(), term: peer.term() });
+                }
+            }
+        }
+    }
+
+    fn on_scheduler_store_heartbeat_tick(&self) {
+        let Some(heartbeat_tx) = &self.heartbeat_tx else {
+            return;
+        };
+        let stats = StoreStats {
+            store_id: self.store_id,
+            region_count: self.peers.len() as u32,
+            leader_count: self.peers.values().filter(|peer| peer.is_leader()).count() as u32,
+            ..Default::default()
+        };
+        let _ = heartbeat_tx.send(HeartbeatTask::Store(stats));
+    }
+
+    // Entries must be persisted before they are applied or acknowledged.
+    fn persist(&mut self) -> TkvResult<()> {
+        for peer in self.peers.values_mut() {
+            peer.persist()?;
+        }
+        Ok(())
+    }
+
+    fn send_messages(&mut self) {
+        for peer in self.peers.values_mut() {
+            for msg in peer.take_messages() {
+                if let Err(err) = self.transport.send(msg) {
+                    log::debug!("failed to send raft message of region {}: {:?}", peer.region_id(), err);
+                }
+            }
+        }
+    }
+
+    // Runs the size check of the regions led by this store on a blocking
+    // worker so that a large scan does not stall the event loop.
+    fn on_split_region_check_tick(&self) {
+        for peer in self.peers.values().filter(|peer| peer.is_leader()) {
+            let region = peer.region.clone();
+            let storage = self.storage.clone();
+            let checker = self.split_checker.clone();
+            let store_tx = self.store_tx.clone();
+            tokio::task::spawn_blocking(move || {
+                match checker.check(storage.as_ref(), &region) {
+                    Ok(result) if !result.split_keys.is_empty() => {
+                        let _ = store_tx.send(StoreMsg::SplitRegion {
+                            region_id: region.id,
+                            region_epoch: region_epoch(&region),
+                            split_keys: result.split_keys,
+                        });
+                    },
+                    Ok(_) => (),
+                    Err(err) => log::error!("split check failed for region {}: {:?}", region.id, err),
+                }
+            });
+        }
+    }
+
+    // Looks for the middle key of the region on a blocking worker, the
+    // split is then proposed like the one of a region that is too large.
+    fn on_half_split_region(&self, region_id: u64, epoch: RegionEpoch) {
+        let Some(peer) = self.peers.get(&region_id) else {
+            return;
+        };
+        if !peer.is_leader() || region_epoch(&peer.region) != epoch {
+            return;
+        }
+        let region = peer.region.clone();
+        let storage = self.storage.clone();
+        let checker = self.split_checker.clone();
+        let store_tx = self.store_tx.clone();
+        tokio::task::spawn_blocking(move || {
+            match checker.half_split_key(storage.as_ref(), &region) {
+                Ok(Some(key)) => {
+                    let _ = store_tx.send(StoreMsg::SplitRegion { region_id, region_epoch: epoch, split_keys: vec![key] });
+                },
+                Ok(None) => log::info!("region {} cannot be split in halves", region_id),
+                Err(err) => log::error!("split check failed for region {}: {:?}", region_id, err),
+            }
+        });
+    }
+
+    fn on_split_region(&mut self, region_id: u64, epoch: RegionEpoch, split_keys: Vec<Vec<u8>>) -> TkvResult<()> {
+        let Some(peer) = self.peers.get(&region_id) else {
+            return Ok(());
+        };
+        // the region changed since the check was scheduled.
+        if !peer.is_leader() || region_epoch(&peer.region) != epoch {
+            return Ok(());
+        }
+
+        let mut new_regions = vec![];
+        for _ in &split_keys {
+            let region_id = self.id_allocator.alloc_id()?;
+            let peer_ids = peer.region.peers.iter()
+                .map(|_| self.id_allocator.alloc_id())
+                .collect::<TkvResult<Vec<_>>>()?;
+            new_regions.push(SplitIds { region_id, peer_ids });
+        }
+
+        log::info!("propose split of region {} at {} keys", region_id, split_keys.len());
+        let cmd = RaftCmd::new(region_id, &epoch, CmdRequest::Admin(AdminCmd::Split { split_keys, new_regions }));
+        self.propose(cmd, None);
+        Ok(())
+    }
+
+    // Looks for small regions to merge into one of their neighbours. It also
+    // resumes the merges interrupted between the prepare and the commit.
+    fn on_merge_region_check_tick(&mut self) {
+        let mut resumed = vec![];
+        for peer in self.peers.values().filter(|peer| peer.is_leader()) {
+            match peer.merge_state() {
+                Some(state) => {
+                    if !self.pending_merges.contains(&peer.region_id()) {
+                        resumed.push((peer.region.clone(), state.commit));
+                    }
+                },
+                None => self.schedule_merge_check(peer),
+            }
+        }
+        for (source, commit) in resumed {
+            self.propose_commit_merge(source, commit);
+        }
+    }
+
+    fn schedule_merge_check(&self, peer: &Peer) {
+        let source = peer.region.clone();
+        let targets: Vec<Region> = {
+            let meta = self.meta.read();
+            meta.regions.values()
+                .filter(|region| region.id != source.id)
+                .filter(|region| self.peers.get(&region.id).is_some_and(|peer| peer.is_leader() && peer.merge_state().is_none()))
+                .cloned()
+                .collect()
+        };
+        if targets.is_empty() {
+            return;
+        }
+
+        let storage = self.storage.clone();
+        let checker = self.merge_checker.clone();
+        let store_tx = self.store_tx.clone();
+        tokio::task::spawn_blocking(move || {
+            for target in targets {
+                match checker.check(storage.as_ref(), &source, &target) {
+                    Ok(true) => {
+                        let _ = store_tx.send(StoreMsg::MergeRegion {
+                            source_id: source.id,
+                            source_epoch: region_epoch(&source),
+                            target,
+                        });
+                        return;
+                    },
+                    Ok(false) => (),
+                    Err(err) => {
+                        log::error!("merge check failed for region {}: {:?}", source.id, err);
+                        return;
+                    },
+                }
+            }
+        });
+    }
+
+    fn on_merge_region(&mut self, source_id: u64, source_epoch: RegionEpoch, target: Region) {
+        let is_valid = |region_id: u64, epoch: &RegionEpoch| {
+            let is_preparing = self.preparing_merges.iter()
+                .any(|(&source_id, &(target_id, _))| source_id == region_id || target_id == region_id);
+            !is_preparing && self.peers.get(&region_id).is_some_and(|peer| {
+                peer.is_leader() && peer.merge_state().is_none() && &region_epoch(&peer.region) == epoch
+            })
+        };
+        // either region changed since the check was scheduled.
+        if !is_valid(source_id, &source_epoch) || !is_valid(target.id, &region_epoch(&target)) {
+            return;
+        }
+
+        log::info!("propose merge of region {} into region {}", source_id, target.id);
+        let target_id = target.id;
+        let cmd = RaftCmd::new(source_id, &source_epoch, CmdRequest::Admin(AdminCmd::PrepareMerge { target }));
+        match self.propose_cmd(&cmd) {
+            Ok(index) => {
+                self.preparing_merges.insert(source_id, (target_id, index));
+            },
+            Err(err) => log::warn!("failed to propose merge of region {}: {:?}", source_id, err),
+        }
+    }
+
+    // Proposes the commit of a prepared merge to the target region,
+    // the source is rolled back if the target cannot take the proposal.
+    fn propose_commit_merge(&mut self, source: Region, commit: u64) {
+        let Some(peer) = self.peers.get(&source.id) else {
+            return;
+        };
+        let Some(target) = peer.merge_state().map(|state| state.target.clone()) else {
+            return;
+        };
+        let source_id = source.id;
+        let entries = peer.merge_entries(commit);
+        let cmd = RaftCmd::new(target.id, &region_epoch(&target), CmdRequest::Admin(AdminCmd::CommitMerge { source, commit, entries }));
+        match self.propose_cmd(&cmd) {
+            Ok(_) => {
+                self.pending_merges.insert(source_id);
+            },
+            Err(err) => {
+                log::warn!("failed to commit merge of region {}: {:?}", source_id, err);
+                self.propose_rollback_merge(source_id, commit);
+            },
+        }
+    }
+
+    fn propose_rollback_merge(&mut self, source_id: u64, commit: u64) {
+        let Some(source) = self.peers.get(&source_id) else {
+            return;
+        };
+        let cmd = RaftCmd::new(source_id, &region_epoch(&source.region), CmdRequest::Admin(AdminCmd::RollbackMerge { commit }));
+        self.propose(cmd, None);
+    }
+
+    // Hands the committed entries to the apply pool, along with
+    // the callbacks of the proposals they carry.
+    async fn handle_committed_entries(&mut self) -> TkvResult<()> {
+        let applied: HashMap<u64, u64> = self.peers.iter().map(|(&region_id, peer)| (region_id, peer.applied_index())).collect();
+        let mut entries = vec![];
+        let mut catch_ups = vec![];
+        for (&region_id, peer) in self.peers.iter_mut() {
+            let mut committed = peer.take_committed_entries();
+            // a merge waits for the source peer of the store to apply its log
+            // up to the PrepareMerge, its data would be lost otherwise.
+            let lagging = committed.iter().enumerate().find_map(|(pos, entry)| {
+                commit_merge(entry)
+                    .filter(|(source_id, commit, _)| applied.get(source_id).is_some_and(|applied| applied < commit))
+                    .map(|catch_up| (pos, catch_up))
+            });
+            if let Some((pos, catch_up)) = lagging {
+                peer.defer_entries(committed[pos].index);
+                committed.truncate(pos);
+                catch_ups.push(catch_up);
+            }
+            entries.push((region_id, committed));
+        }
+        // the source catches up with the entries carried by the merge.
+        for (source_id, commit, merge_entries) in catch_ups {
+            let Some(peer) = self.peers.get_mut(&source_id) else {
+                continue;
+            };
+            if let Err(err) = peer.append_merge_entries(commit, merge_entries).and_then(|_| peer.persist()) {
+                log::warn!("region {} failed to catch up before its merge: {:?}", source_id, err);
+            }
+            entries.push((source_id, peer.take_committed_entries()));
+        }
+
+        for (region_id, entries) in entries {
+            if entries.is_empty() {
+                continue;
+            }
+            let callbacks = entries.iter()
+                .filter_map(|entry| self.callbacks.remove(&(region_id, entry.index)).map(|callback| (entry.index, callback)))
+                .collect();
+            self.apply_pool.schedule(ApplyTask::Apply { region_id, entries, callbacks })?;
+        }
+        Ok(())
+    }
+
+    // Generates the snapshots requested by the leaders on the apply pool,
+    // so that they match the applied entries, then sends them.
+    async fn handle_snapshot_requests(&mut self) -> TkvResult<()> {
+        let mut requests = vec![];
+        for peer in self.peers.values_mut() {
+            for to_peer in peer.take_snapshot_requests() {
+                requests.push((peer.region_id(), peer.term(), peer.meta.clone(), to_peer));
+            }
+        }
+        for (region_id, term, from_peer, to_peer) in requests {
+            let (callback, generated) = oneshot::channel();
+            self.apply_pool.schedule(ApplyTask::GenSnapshot { region_id, term, callback })?;
+            let transport = self.transport.clone();
+            let store_tx = self.store_tx.clone();
+            tokio::spawn(async move {
+                let to_peer_id = to_peer.id;
+                let sent = match generated.await {
+                    Ok(Ok((mut meta, path))) => {
+                        meta.from_peer = Some(from_peer);
+                        meta.to_peer = Some(to_peer);
+                        transport.send_snapshot(meta, path).await
+                    },
+                    Ok(Err(err)) => Err(err),
+                    Err(err) => Err(TkvError::from(err)),
+                };
+                if let Err(err) = sent {
+                    log::warn!("failed to send snapshot of region {} to peer {}: {:?}", region_id, to_peer_id, err);
+                    let _ = store_tx.send(StoreMsg::SnapshotFailed { region_id, to_peer_id });
+                }
+            });
+        }
+        Ok(())
+    }
+
+    // Resets the peer to the snapshot, creating it when the store
+    // does not host the region yet.
+    async fn on_snapshot(&mut self, meta: SnapshotMeta, path: PathBuf) -> TkvResult<()> {
+        let region = meta.region.clone().unwrap_or_default();
+        if !self.peers.contains_key(&region.id) {
+            let peer = Peer::new(self.store_id, region.clone(), self.storage.clone())?.with_election_timeout(self.raft_election_timeout);
+            self.apply_pool.schedule(ApplyTask::Register(ApplyDelegate::new(&peer)))?;
+            self.peers.insert(region.id, peer);
+        }
+        let peer = self.peers.get_mut(&region.id).unwrap();
+        if peer.on_snapshot(&meta)? {
+            log::info!("apply snapshot of region {} at index {}", region.id, meta.index);
+            self.apply_pool.schedule(ApplyTask::ApplySnapshot { meta, path })?;
+        } else {
+            std::fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    async fn on_apply_res(&mut self, res: ApplyRes) -> TkvResult<()> {
+        let Some(peer) = self.peers.get_mut(&res.region_id) else {
+            return Ok(());
+        };
+        if let Err(err) = peer.on_apply_res(&res) {
+            log::warn!("failed to handle apply result of region {}: {:?}", res.region_id, err);
+        }
+        if let Err(err) = peer.compact(self.raft_log_gc_count_limit) {
+            log::warn!("failed to compact the log of region {}: {:?}", res.region_id, err);
+        }
+        // the merge is prepared, or was rejected.
+        if self.preparing_merges.get(&res.region_id).is_some_and(|&(_, index)| index <= res.applied_index) {
+            self.preparing_merges.remove(&res.region_id);
+        }
+        for result in res.results {
+            self.on_apply_result(result).await?;
+        }
+        Ok(())
+    }
+
+    // Stops serving a region whose entries can't be applied. Its state is
+    // left as persisted, the region is loaded again when the store restarts.
+    fn on_apply_failed(&mut self, region_id: u64, error: TkvError) {
+        log::error!("region {} stopped: {:?}", region_id, error);
+        self.peers.remove(&region_id);
+        self.meta.write().remove_region(region_id);
+        let pending: Vec<_> = self.callbacks.keys().filter(|(id, _)| *id == region_id).copied().collect();
+        for key in pending {
+            if let Some(callback) = self.callbacks.remove(&key) {
+                let _ = callback.send(Err(TkvError::new(format!("{:?}", error))));
+            }
+        }
+    }
+
+    async fn on_apply_result(&mut self, result: ApplyResult) -> TkvResult<()> {
+        match result {
+            ApplyResult::None => (),
+            ApplyResult::Split { regions } => {
+                for (idx, region) in regions.into_iter().enumerate() {
+                    self.meta.write().set_region(region.clone());
+                    if idx > 0 {
+                        let peer = Peer::new(self.store_id, region, self.storage.clone())?.with_election_timeout(self.raft_election_timeout);
+                        self.apply_pool.schedule(ApplyTask::Register(ApplyDelegate::new(&peer)))?;
+                        self.peers.insert(peer.region_id(), peer);
+                    }
+                }
+            },
+            ApplyResult::PrepareMerge { region, commit, .. } => {
+                self.meta.write().set_region(region.clone());
+                if self.peers.get(&region.id).is_some_and(|peer| peer.is_leader()) {
+                    self.propose_commit_merge(region, commit);
+                }
+            },
+            ApplyResult::CommitMerge { region, source } => {
+                // the source data is already in place, the
+                // target only has to take over its range.
+                self.pending_merges.remove(&source.id);
+                if let Some(peer) = self.peers.remove(&source.id) {
+                    peer.destroy()?;
+                    self.apply_pool.schedule(ApplyTask::Destroy { region_id: source.id })?;
+                }
+                let mut meta = self.meta.write();
+                meta.remove_region(source.id);
+                meta.set_region(region);
+            },
+            ApplyResult::CommitMergeFailed { source_id, commit } => {
+                self.pending_merges.remove(&source_id);
+                self.propose_rollback_merge(source_id, commit);
+            },
+            ApplyResult::RollbackMerge { region } | ApplyResult::Snapshot { region } => {
+                self.meta.write().set_region(region);
+            },
+            ApplyResult::ChangePeer { region, change_type, peer } => {
+                if change_type == ChangePeerType::Remove && peer.store_id == self.store_id {
+                    self.destroy_peer(region.id).await?;
+                } else {
+                    self.meta.write().set_region(region);
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+// The source, PrepareMerge index and source entries of a CommitMerge.
+fn commit_merge(entry: &Entry) -> Option<(u64, u64, Vec<Entry>)> {
+    match RaftCmd::decode(&entry.data).ok()?.request {
+        CmdRequest::Admin(AdminCmd::CommitMerge { source, commit, entries }) => Some((source.id, commit, entries)),
+        _ => None,
+    }
+}
+
+// Periodically sends the tick to the store until the store is gone.
+fn spawn_ticker(store_tx: StoreSender, interval: Duration, tick: StoreTick) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        // Get rid of first immediate tick
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            if store_tx.send(StoreMsg::Tick(tick)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, path::PathBuf, sync::Arc, time::Duration};
+
+    use async_trait::async_trait;
+    use parking_lot::Mutex;
+    use tempfile::tempdir;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::config::{Config, DiskSize};
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::{cmd::{CmdRequest, RaftCmd}, engine::RaftEngine, snap::SnapManager, transport::{LocalTransport, Transport}, LocalIdAllocator};
+    use crate::kv::storage::{disk::DiskStorage, memory::MemoryStorage, mutation::Mutation};
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::kv::raftstore::heartbeat::HeartbeatWorker;
+    use crate::proto::metapb::{Peer, Region, RegionEpoch, Store, StoreState};
+    use crate::proto::raft_serverpb::{RaftMessage, SnapshotMeta};
+    use crate::proto::schedulerpb::{scheduler_server::SchedulerServer, FlowKind};
+    use crate::scheduler::{client::SchedulerClient, config::Config as SchedulerConfig, server::SchedulerService};
+
+    use super::{RaftStore, StoreMsg};
+
+    // Drops the messages of some regions to some stores, (region_id, store_id).
+    #[derive(Debug, Default)]
+    struct PartitionTransport {
+        local: LocalTransport,
+        dropped: Mutex<HashSet<(u64, u64)>>,
+    }
+
+    #[async_trait]
+    impl Transport for PartitionTransport {
+        fn send(&self, msg: RaftMessage) -> TkvResult<()> {
+            let store_id = msg.to_peer.as_ref().map(|peer| peer.store_id).unwrap_or_default();
+            if self.dropped.lock().contains(&(msg.region_id, store_id)) {
+                return Ok(());
+            }
+            self.local.send(msg)
+        }
+
+        async fn send_snapshot(&self, meta: SnapshotMeta, path: PathBuf) -> TkvResult<()> {
+            self.local.send_snapshot(meta, path).await
+        }
+    }
+
+    #[tokio::test]
+    async fn split_large_region() -> TkvResult<()> {
+        let mut config = Config::for_test();
+        config.region_max_size = DiskSize::KiB(4);
+        config.region_split_size = DiskSize::KiB(2);
+        config.region_merge_max_size = DiskSize::KiB(1);
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &config)?);
+
+        let storage = Arc::new(MemoryStorage::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: vec![Peer { id: 1, store_id: 1, ..Default::default() }],
+        };
+        let store = RaftStore::new(1, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), Arc::new(LocalTransport::new()), snap_manager, vec![region])?;
+        let sender = store.sender();
+        let meta = store.meta();
+        let handle = tokio::spawn(store.run());
+
+        // write 10 KiB through raft
+        let batch = (0..100u32)
+            .map(|i| Mutation::Put { key: format!("key_{:03}", i).into_bytes(), value: vec![0; 100], cf: ColumnFamily::Default })
+            .collect();
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(batch));
+        sender.send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+        applied.await??;
+        assert_eq!(storage.get(ColumnFamily::Default, b"key_042")?, Some(vec![0; 100]));
+
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        {
+            let meta = meta.read();
+            assert_eq!(meta.regions.len(), 5);
+            assert!(meta.regions.values().all(|r| r.region_epoch.as_ref().unwrap().version == 2));
+            let region = meta.search_region(b"key_099").unwrap();
+            assert!(region.end_key.is_empty());
+            assert_ne!(region.id, 1);
+            assert_eq!(meta.search_region(b"key_000").unwrap().id, 1);
+        }
+
+        sender.send(StoreMsg::Stop)?;
+        handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_small_regions() -> TkvResult<()> {
+        let config = Config::for_test();
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &config)?);
+        let storage = Arc::new(MemoryStorage::new());
+        let new_region = |id, start_key: &[u8], end_key: &[u8]| Region {
+            id,
+            start_key: start_key.to_vec(),
+            end_key: end_key.to_vec(),
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 3 }),
+            peers: vec![Peer { id: id + 10, store_id: 1, ..Default::default() }],
+        };
+        let regions = vec![new_region(1, b"", b"g"), new_region(2, b"g", b"p"), new_region(3, b"p", b"")];
+        let store = RaftStore::new(1, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), Arc::new(LocalTransport::new()), snap_manager, regions)?;
+        let sender = store.sender();
+        let meta = store.meta();
+        let handle = tokio::spawn(store.run());
+
+        for (region_id, key) in [(1, b"a"), (2, b"h"), (3, b"x")] {
+            let put = Mutation::Put { key: key.to_vec(), value: vec![1], cf: ColumnFamily::Default };
+            let (callback, applied) = tokio::sync::oneshot::channel();
+            let cmd = RaftCmd::new(region_id, &RegionEpoch { config_version: 1, version: 3 }, CmdRequest::Write(vec![put]));
+            sender.send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+            applied.await??;
+        }
+
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        {
+            let meta = meta.read();
+            assert_eq!(meta.regions.len(), 1);
+            let region = meta.search_region(b"").unwrap();
+            assert!(region.start_key.is_empty() && region.end_key.is_empty());
+            assert!(region.region_epoch.as_ref().unwrap().version > 4);
+        }
+        // no data is lost by the merge.
+        for key in [b"a", b"h", b"x"] {
+            assert_eq!(storage.get(ColumnFamily::Default, key)?, Some(vec![1]));
+        }
+
+        sender.send(StoreMsg::Stop)?;
+        handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn recover_after_restart() -> TkvResult<()> {
+        let config = Config::for_test();
+        let temp_dir = tempdir()?;
+        let db_path = temp_dir.path().join("data");
+        std::fs::create_dir_all(&db_path)?;
+        let snap_manager = Arc::new(SnapManager::new(temp_dir.path().join("snap"), &config)?);
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: vec![Peer { id: 1, store_id: 1, ..Default::default() }],
+        };
+
+        let put = |key: &[u8]| {
+            let put = Mutation::Put { key: key.to_vec(), value: vec![1], cf: ColumnFamily::Default };
+            RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]))
+        };
+        {
+            let storage = Arc::new(DiskStorage::new(&db_path)?);
+            let store = RaftStore::new(1, &config, storage, Arc::new(LocalIdAllocator::new(100)), Arc::new(LocalTransport::new()), snap_manager.clone(), vec![region])?;
+            let sender = store.sender();
+            let handle = tokio::spawn(store.run());
+            for key in [b"a", b"b"] {
+                let (callback, applied) = tokio::sync::oneshot::channel();
+                sender.send(StoreMsg::RaftCmd { cmd: put(key), callback: Some(callback) })?;
+                applied.await??;
+            }
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+
+        // the region and its raft state are recovered from the storage.
+        let storage = Arc::new(DiskStorage::new(&db_path)?);
+        let engine = RaftEngine::new(storage.clone());
+        assert_eq!(engine.apply_state(1)?.map(|state| state.applied_index), Some(2));
+        let store = RaftStore::new(1, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), Arc::new(LocalTransport::new()), snap_manager, vec![])?;
+        assert!(store.meta().read().regions.contains_key(&1));
+        let sender = store.sender();
+        let handle = tokio::spawn(store.run());
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        sender.send(StoreMsg::RaftCmd { cmd: put(b"c"), callback: Some(callback) })?;
+        applied.await??;
+        // the peer is elected again, with an empty entry of its new term.
+        assert_eq!(engine.apply_state(1)?.map(|state| state.applied_index), Some(4));
+        for key in [b"a", b"b", b"c"] {
+            assert_eq!(storage.get(ColumnFamily::Default, key)?, Some(vec![1]));
+        }
+
+        sender.send(StoreMsg::Stop)?;
+        handle.await??;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replicate_to_other_stores() -> TkvResult<()> {
+        let config = Config::for_test();
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=3).map(|id| Peer { id, store_id: id, ..Default::default() }).collect(),
+        };
+
+        let snap_dir = tempdir()?;
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=3 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, vec![region.clone()])?;
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+        // store 3 is down, a quorum is still available.
+        transport.remove_store(3);
+
+        let put = Mutation::Put { key: b"k".to_vec(), value: vec![1], cf: ColumnFamily::Default };
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]));
+        senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+        applied.await??;
+
+        // store 3 catches up once it is back.
+        transport.add_store(3, senders[2].clone());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        for storage in &storages {
+            assert_eq!(storage.get(ColumnFamily::Default, b"k")?, Some(vec![1]));
+        }
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn elect_new_leader() -> TkvResult<()> {
+        let mut config = Config::for_test();
+        config.raft_election_timeout_ticks = Duration::from_millis(500);
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=3).map(|id| Peer { id, store_id: id, ..Default::default() }).collect(),
+        };
+
+        let snap_dir = tempdir()?;
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut metas = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=3 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, vec![region.clone()])?;
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            metas.push(store.meta());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+        let put = |key: &[u8]| {
+            let put = Mutation::Put { key: key.to_vec(), value: vec![1], cf: ColumnFamily::Default };
+            RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]))
+        };
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        senders[0].send(StoreMsg::RaftCmd { cmd: put(b"a"), callback: Some(callback) })?;
+        applied.await??;
+
+        // the leader goes down, one of the others takes over.
+        transport.remove_store(1);
+        senders[0].send(StoreMsg::Stop)?;
+        handles.remove(0).await??;
+        let mut leader = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            leader = (1..3).find(|&idx| metas[idx].read().leader(1).is_some_and(|(leader_id, _)| leader_id == idx as u64 + 1));
+            if leader.is_some() {
+                break;
+            }
+        }
+        let leader = leader.expect("no leader elected");
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        senders[leader].send(StoreMsg::RaftCmd { cmd: put(b"b"), callback: Some(callback) })?;
+        applied.await??;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        for storage in &storages[1..] {
+            for key in [b"a", b"b"] {
+                assert_eq!(storage.get(ColumnFamily::Default, key)?, Some(vec![1]));
+            }
+        }
+
+        for (sender, handle) in senders.into_iter().skip(1).zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn snapshot_to_lagging_store() -> TkvResult<()> {
+        let mut config = Config::for_test();
+        config.raft_log_gc_count_limit = 4;
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=3).map(|id| Peer { id, store_id: id, ..Default::default() }).collect(),
+        };
+
+        let snap_dir = tempdir()?;
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=3 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, vec![region.clone()])?;
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+        transport.remove_store(3);
+
+        // the log outgrows the gc limit while store 3 is down.
+        for i in 0..20u8 {
+            let batch = [ColumnFamily::Default, ColumnFamily::Lock, ColumnFamily::Write].into_iter()
+                .map(|cf| Mutation::Put { key: vec![i], value: vec![i], cf })
+                .collect();
+            let (callback, applied) = tokio::sync::oneshot::channel();
+            let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(batch));
+            senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+            applied.await??;
+        }
+
+        // store 3 catches up from a snapshot, then from the log.
+        transport.add_store(3, senders[2].clone());
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let put = Mutation::Put { key: b"k".to_vec(), value: vec![1], cf: ColumnFamily::Default };
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]));
+        senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+        applied.await??;
+        tokio::time::sleep(Duration::from_millis(300)).await;
+
+        for cf in [ColumnFamily::Default, ColumnFamily::Lock, ColumnFamily::Write] {
+            for i in 0..20u8 {
+                assert_eq!(storages[2].get(cf, &[i])?, Some(vec![i]));
+            }
+        }
+        assert_eq!(storages[2].get(ColumnFamily::Default, b"k")?, Some(vec![1]));
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn report_to_scheduler() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let service = SchedulerService::new(&SchedulerConfig::default(), Arc::new(MemoryStorage::new()))?;
+        let cluster = service.cluster();
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let data_dir = tempdir()?;
+        std::fs::write(data_dir.path().join("data.mdb"), vec![0; 1024])?;
+        let mut config = Config::for_test();
+        config.db_path = data_dir.path().to_string_lossy().to_string();
+        config.scheduler_addr = addr.to_string();
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &config)?);
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=2).map(|id| Peer { id, store_id: id, ..Default::default() }).collect(),
+        };
+
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=2 {
+            let client = SchedulerClient::connect(&config.scheduler_addr)?;
+            let store = RaftStore::new(store_id, &config, Arc::new(MemoryStorage::new()), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager.clone(), vec![region.clone()])?
+                .with_scheduler(HeartbeatWorker::new(store_id, &config, client));
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            handles.push(tokio::spawn(store.run()));
+        }
+        tokio::time::sleep(Duration::from_millis(800)).await;
+
+        {
+            let cluster = cluster.read();
+            assert_eq!(cluster.stores().count(), 2);
+            let info = cluster.get_store(1).unwrap();
+            assert_eq!(info.stats.capacity, DiskSize::GiB(1).num_bytes());
+            assert_eq!(info.stats.used_size, 1024);
+            assert_eq!(info.stats.available, info.stats.capacity - 1024);
+            assert_eq!((info.stats.region_count, info.stats.leader_count), (1, 1));
+            assert_eq!(cluster.get_store(2).unwrap().stats.leader_count, 0);
+
+            // only the leader reports the region.
+            let info = cluster.regions().search(b"a").unwrap();
+            assert_eq!(info.region, region);
+            assert_eq!(info.leader, Some(Peer { id: 1, store_id: 1, ..Default::default() }));
+        }
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn balance_with_scheduler() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let scheduler_config = SchedulerConfig {
+            max_peer_count: 2,
+            schedule_interval: Duration::from_millis(100),
+            ..Default::default()
+        };
+        let service = SchedulerService::new(&scheduler_config, Arc::new(MemoryStorage::new()))?;
+        let cluster = service.cluster();
+        let coordinator = service.spawn_coordinator();
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let mut config = Config::for_test();
+        config.scheduler_addr = addr.to_string();
+        config.merge_check_tick_interval = Duration::from_secs(3600);
+        let snap_dir = tempdir()?;
+        let transport = Arc::new(LocalTransport::new());
+        // both regions are on stores 1 and 2, and led by store 1.
+        let regions: Vec<Region> = [(1, b"".to_vec(), b"m".to_vec()), (2, b"m".to_vec(), b"".to_vec())].into_iter()
+            .map(|(id, start_key, end_key)| Region {
+                id,
+                start_key,
+                end_key,
+                region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+                peers: (1..=2).map(|store_id| Peer { id: id * 10 + store_id, store_id, ..Default::default() }).collect(),
+            })
+            .collect();
+
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=3 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let initial = if store_id == 3 { vec![] } else { regions.clone() };
+            let client = SchedulerClient::connect(&config.scheduler_addr)?;
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, initial)?
+                .with_scheduler(HeartbeatWorker::new(store_id, &config, client));
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+        for (region, key) in regions.iter().zip([b"a", b"z"]) {
+            let put = Mutation::Put { key: key.to_vec(), value: key.to_vec(), cf: ColumnFamily::Default };
+            let (callback, applied) = tokio::sync::oneshot::channel();
+            let cmd = RaftCmd::new(region.id, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]));
+            senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+            applied.await??;
+        }
+
+        // a peer moves to store 3, and the leaders spread.
+        let mut balanced = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let cluster = cluster.read();
+            let mut peer_counts = [0; 3];
+            let mut leader_counts = [0; 3];
+            for info in cluster.regions().iter() {
+                for peer in &info.region.peers {
+                    peer_counts[peer.store_id as usize - 1] += 1;
+                }
+                leader_counts[info.leader.as_ref().unwrap().store_id as usize - 1] += 1;
+            }
+            let idle = (1..=2).all(|region_id| cluster.operators().get(region_id).is_none());
+            if idle && peer_counts[2] == 1 && leader_counts.iter().all(|&count| count <= 1) {
+                balanced = true;
+                break;
+            }
+        }
+        assert!(balanced);
+
+        // store 3 holds the data of its region, the store that lost it does not.
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        let moved = cluster.read().regions().iter()
+            .find(|info| info.region.peers.iter().any(|peer| peer.store_id == 3))
+            .unwrap()
+            .clone();
+        let key = if moved.region.id == 1 { b"a" } else { b"z" };
+        for store_id in 1..=3u64 {
+            let value = storages[store_id as usize - 1].get(ColumnFamily::Default, key)?;
+            let has_peer = moved.region.peers.iter().any(|peer| peer.store_id == store_id);
+            assert_eq!(value, has_peer.then(|| key.to_vec()));
+        }
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        coordinator.abort();
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn split_hot_region() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let scheduler_config = SchedulerConfig {
+            max_peer_count: 2,
+            schedule_interval: Duration::from_millis(100),
+            hot_region_bytes_rate: 1000,
+            hot_region_min_degree: 2,
+            ..Default::default()
+        };
+        let service = SchedulerService::new(&scheduler_config, Arc::new(MemoryStorage::new()))?;
+        let cluster = service.cluster();
+        let coordinator = service.spawn_coordinator();
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let mut config = Config::for_test();
+        config.scheduler_addr = addr.to_string();
+        config.merge_check_tick_interval = Duration::from_secs(3600);
+        let snap_dir = tempdir()?;
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=2).map(|store_id| Peer { id: 10 + store_id, store_id, ..Default::default() }).collect(),
+            ..Default::default()
+        };
+
+        let mut metas = vec![];
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=2 {
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let client = SchedulerClient::connect(&config.scheduler_addr)?;
+            let store = RaftStore::new(store_id, &config, Arc::new(MemoryStorage::new()), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, vec![region.clone()])?
+                .with_scheduler(HeartbeatWorker::new(store_id, &config, client));
+            transport.add_store(store_id, store.sender());
+            metas.push(store.meta());
+            senders.push(store.sender());
+            handles.push(tokio::spawn(store.run()));
+        }
+        let puts = (b'a'..=b'j').map(|key| Mutation::Put { key: vec![key], value: vec![key; 10], cf: ColumnFamily::Default }).collect();
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(puts));
+        senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+        applied.await??;
+
+        // the leader of the region serves a lot of reads, moving the
+        // leadership does not help but splitting the region does.
+        let client = SchedulerClient::connect(&config.scheduler_addr)?;
+        let mut hot = vec![];
+        let mut split = false;
+        for _ in 0..100 {
+            metas[0].write().record_read(1, 10, 10_000);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            if hot.is_empty() {
+                hot = client.get_hot_regions(FlowKind::Read).await?;
+            }
+            if cluster.read().regions().len() > 1 {
+                split = true;
+                break;
+            }
+        }
+        assert_eq!((hot[0].region_id, hot[0].store_id), (1, 1));
+        assert!(split);
+        let info = cluster.read().regions().get(1).unwrap().clone();
+        assert_eq!((info.region.start_key, info.region.end_key), (vec![], vec![b'f']));
+        assert!(client.get_hot_regions(FlowKind::Write).await?.is_empty());
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        coordinator.abort();
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn decommission_store() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let scheduler_config = SchedulerConfig { schedule_interval: Duration::from_millis(100), ..Default::default() };
+        let service = SchedulerService::new(&scheduler_config, Arc::new(MemoryStorage::new()))?;
+        let cluster = service.cluster();
+        let coordinator = service.spawn_coordinator();
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let mut config = Config::for_test();
+        config.scheduler_addr = addr.to_string();
+        let snap_dir = tempdir()?;
+        let transport = Arc::new(LocalTransport::new());
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=3).map(|id| Peer { id, store_id: id, ..Default::default() }).collect(),
+        };
+
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=4 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let initial = if store_id == 4 { vec![] } else { vec![region.clone()] };
+            let client = SchedulerClient::connect(&config.scheduler_addr)?;
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, initial)?
+                .with_scheduler(HeartbeatWorker::new(store_id, &config, client));
+            transport.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+        let put = Mutation::Put { key: b"k".to_vec(), value: vec![1], cf: ColumnFamily::Default };
+        let (callback, applied) = tokio::sync::oneshot::channel();
+        let cmd = RaftCmd::new(1, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]));
+        senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+        applied.await??;
+
+        // store 1 leads the region, it is drained then buried.
+        let client = SchedulerClient::connect(&config.scheduler_addr)?;
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        client.remove_store(1).await?;
+        let mut buried = false;
+        for _ in 0..100 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            if client.get_all_stores().await?[0].state() == StoreState::Tomstone {
+                buried = true;
+                break;
+            }
+        }
+        assert!(buried);
+        let info = cluster.read().regions().get(1).unwrap().clone();
+        let mut stores: Vec<u64> = info.region.peers.iter().map(|peer| peer.store_id).collect();
+        stores.sort_unstable();
+        assert_eq!(stores, vec![2, 3, 4]);
+        assert_ne!(info.leader.unwrap().store_id, 1);
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(storages[0].get(ColumnFamily::Default, b"k")?, None);
+        assert_eq!(storages[3].get(ColumnFamily::Default, b"k")?, Some(vec![1]));
+
+        // the store cannot rejoin.
+        let store = Store { id: 1, address: config.store_addr.clone(), ..Default::default() };
+        assert!(client.put_store(store).await.is_err());
+        assert!(client.remove_store(1).await.is_err());
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        coordinator.abort();
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn merge_with_lagging_follower() -> TkvResult<()> {
+        let mut config = Config::for_test();
+        config.region_merge_max_size = DiskSize::KiB(1);
+        let transport = Arc::new(PartitionTransport::default());
+        let new_region = |id, start_key: &[u8], end_key: &[u8]| Region {
+            id,
+            start_key: start_key.to_vec(),
+            end_key: end_key.to_vec(),
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: (1..=3).map(|store_id| Peer { id: id * 10 + store_id, store_id, ..Default::default() }).collect(),
+        };
+        let regions = vec![new_region(1, b"", b"g"), new_region(2, b"g", b"")];
+        // store 3 never hears from region 1.
+        transport.dropped.lock().insert((1, 3));
+
+        let snap_dir = tempdir()?;
+        let mut storages = vec![];
+        let mut senders = vec![];
+        let mut metas = vec![];
+        let mut handles = vec![];
+        for store_id in 1..=3 {
+            let storage = Arc::new(MemoryStorage::new());
+            let snap_manager = Arc::new(SnapManager::new(snap_dir.path().join(store_id.to_string()), &config)?);
+            let store = RaftStore::new(store_id, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), transport.clone(), snap_manager, regions.clone())?;
+            transport.local.add_store(store_id, store.sender());
+            senders.push(store.sender());
+            metas.push(store.meta());
+            storages.push(storage);
+            handles.push(tokio::spawn(store.run()));
+        }
+
+        // region 2 is too large to be merged, region 1 is merged into it.
+        for (region_id, key, size) in [(2, b"h", 2048), (1, b"a", 1)] {
+            let put = Mutation::Put { key: key.to_vec(), value: vec![1; size], cf: ColumnFamily::Default };
+            let (callback, applied) = tokio::sync::oneshot::channel();
+            let cmd = RaftCmd::new(region_id, &RegionEpoch { config_version: 1, version: 1 }, CmdRequest::Write(vec![put]));
+            senders[0].send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })?;
+            applied.await??;
+        }
+
+        // store 3 applies the source entries carried by the merge before the merge itself.
+        tokio::time::sleep(Duration::from_millis(800)).await;
+        for meta in &metas {
+            let meta = meta.read();
+            assert_eq!(meta.regions.keys().copied().collect::<Vec<_>>(), vec![2]);
+        }
+        for storage in &storages {
+            assert_eq!(storage.get(ColumnFamily::Default, b"a")?, Some(vec![1]));
+        }
+
+        for (sender, handle) in senders.into_iter().zip(handles) {
+            sender.send(StoreMsg::Stop)?;
+            handle.await??;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,338 @@
+use std::{collections::HashMap, path::PathBuf, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{kv::{config::Config, error::{TkvError, TkvResult}}, proto::{raft_serverpb::{BatchRaftMessage, RaftMessage, SnapshotMeta}, tinykv::tiny_kv_client::TinyKvClient}};
+
+use super::{snap::SnapManager, store::{StoreMsg, StoreSender}};
+
+
+// Number of messages queued for a store before new ones are dropped,
+// raft retransmits whatever gets lost.
+const RAFT_CLIENT_QUEUE_SIZE: usize = 4096;
+
+/// Transport delivers raft messages to the store hosting the target peer.
+#[async_trait]
+pub trait Transport: std::fmt::Debug + Send + Sync {
+    fn send(&self, msg: RaftMessage) -> TkvResult<()>;
+
+    // Delivers the snapshot file to the store of meta.to_peer,
+    // the file belongs to the transport from now on.
+    async fn send_snapshot(&self, meta: SnapshotMeta, path: PathBuf) -> TkvResult<()>;
+}
+
+/// Resolves the address of a store from its id.
+pub trait StoreAddrResolver: std::fmt::Debug + Send + Sync {
+    fn resolve(&self, store_id: u64) -> TkvResult<String>;
+}
+
+#[derive(Debug, Default)]
+pub struct StaticResolver {
+    addrs: RwLock<HashMap<u64, String>>,
+}
+
+impl StaticResolver {
+    pub fn new(addrs: HashMap<u64, String>) -> Self {
+        Self { addrs: RwLock::new(addrs) }
+    }
+
+    pub fn insert(&self, store_id: u64, addr: String) {
+        self.addrs.write().insert(store_id, addr);
+    }
+}
+
+impl StoreAddrResolver for StaticResolver {
+    fn resolve(&self, store_id: u64) -> TkvResult<String> {
+        self.addrs.read()
+            .get(&store_id)
+            .cloned()
+            .ok_or(TkvError::new(format!("unknown address for store {}", store_id)))
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct RaftClientConfig {
+    pub max_batch_size: usize,
+    pub flush_interval: Duration,
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl RaftClientConfig {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_batch_size: config.raft_msg_max_batch_size,
+            flush_interval: config.raft_msg_flush_interval,
+            backoff: config.raft_client_backoff,
+            max_backoff: config.raft_client_max_backoff,
+        }
+    }
+}
+
+/// RaftClient sends raft messages to other stores over gRPC. It keeps a
+/// single stream per store and batches the messages of all the regions
+/// headed to that store.
+#[derive(Debug)]
+pub struct RaftClient {
+    config: RaftClientConfig,
+    resolver: Arc<dyn StoreAddrResolver>,
+    snap_manager: Arc<SnapManager>,
+    // store_id -> queue of the task streaming to that store.
+    connections: Mutex<HashMap<u64, mpsc::Sender<RaftMessage>>>,
+}
+
+impl RaftClient {
+    pub fn new(config: RaftClientConfig, resolver: Arc<dyn StoreAddrResolver>, snap_manager: Arc<SnapManager>) -> Self {
+        Self {
+            config,
+            resolver,
+            snap_manager,
+            connections: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for RaftClient {
+    fn send(&self, msg: RaftMessage) -> TkvResult<()> {
+        let store_id = msg.to_peer.as_ref().map(|peer| peer.store_id).unwrap_or_default();
+        let mut connections = self.connections.lock();
+        let sender = connections.entry(store_id).or_insert_with(|| {
+            let (sender, receiver) = mpsc::channel(RAFT_CLIENT_QUEUE_SIZE);
+            let connection = StoreConnection {
+                store_id,
+                config: self.config.clone(),
+                resolver: self.resolver.clone(),
+                receiver,
+            };
+            tokio::spawn(connection.run());
+            sender
+        });
+
+        match sender.try_send(msg) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(TkvError::new(format!("raft queue to store {} is full", store_id))),
+            Err(TrySendError::Closed(_)) => {
+                connections.remove(&store_id);
+                Err(TkvError::new(format!("raft connection to store {} is closed", store_id)))
+            },
+        }
+    }
+
+    // Snapshots get a connection of their own so that
+    // they do not delay the raft messages.
+    async fn send_snapshot(&self, meta: SnapshotMeta, path: PathBuf) -> TkvResult<()> {
+        let store_id = meta.to_peer.as_ref().map(|peer| peer.store_id).unwrap_or_default();
+        let result = async {
+            let addr = self.resolver.resolve(store_id)?;
+            let mut client = TinyKvClient::connect(format!("http://{}", addr)).await?;
+            self.snap_manager.send(&mut client, &meta, &path).await
+        }.await;
+        let _ = std::fs::remove_file(&path);
+        result
+    }
+}
+
+struct StoreConnection {
+    store_id: u64,
+    config: RaftClientConfig,
+    resolver: Arc<dyn StoreAddrResolver>,
+    receiver: mpsc::Receiver<RaftMessage>,
+}
+
+impl StoreConnection {
+    async fn run(mut self) {
+        let mut backoff = self.config.backoff;
+        loop {
+            match self.connect().await {
+                Ok(client) => {
+                    backoff = self.config.backoff;
+                    if self.stream(client).await {
+                        return; // the client is gone.
+                    }
+                    log::warn!("raft stream to store {} broken, reconnecting", self.store_id);
+                },
+                Err(err) => {
+                    log::warn!("failed to connect to store {}, retry in {:?}: {:?}", self.store_id, backoff, err);
+                    tokio::time::sleep(backoff).await;
+                    backoff = (backoff * 2).min(self.config.max_backoff);
+                    // messages queued while the store was unreachable are stale by now.
+                    while self.receiver.try_recv().is_ok() {}
+                    if self.receiver.is_closed() {
+                        return;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn connect(&self) -> TkvResult<TinyKvClient<tonic::transport::Channel>> {
+        let addr = self.resolver.resolve(self.store_id)?;
+        let client = TinyKvClient::connect(format!("http://{}", addr)).await?;
+        Ok(client)
+    }
+
+    // Streams batches until the stream breaks, returns
+    // true when there is nothing left to send.
+    async fn stream(&mut self, mut client: TinyKvClient<tonic::transport::Channel>) -> bool {
+        let (batch_tx, batch_rx) = mpsc::channel::<BatchRaftMessage>(16);
+        let call = tokio::spawn(async move {
+            if let Err(status) = client.batch_raft(ReceiverStream::new(batch_rx)).await {
+                log::debug!("batch raft call ended: {:?}", status);
+            }
+        });
+
+        let closed = loop {
+            let Some(msg) = self.receiver.recv().await else {
+                break true;
+            };
+            let mut msgs = vec![msg];
+            let deadline = tokio::time::sleep(self.config.flush_interval);
+            tokio::pin!(deadline);
+            while msgs.len() < self.config.max_batch_size {
+                tokio::select! {
+                    msg = self.receiver.recv() => match msg {
+                        Some(msg) => msgs.push(msg),
+                        None => break,
+                    },
+                    _ = &mut deadline => break,
+                }
+            }
+            if batch_tx.send(BatchRaftMessage { msgs }).await.is_err() {
+                break false;
+            }
+        };
+
+        drop(batch_tx);
+        let _ = call.await;
+        closed
+    }
+}
+
+
+/// LocalTransport routes raft messages between stores of the same process.
+#[derive(Debug, Default)]
+pub struct LocalTransport {
+    routers: RwLock<HashMap<u64, StoreSender>>,
+}
+
+impl LocalTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_store(&self, store_id: u64, router: StoreSender) {
+        self.routers.write().insert(store_id, router);
+    }
+
+    pub fn remove_store(&self, store_id: u64) {
+        self.routers.write().remove(&store_id);
+    }
+
+    fn route(&self, store_id: u64, msg: StoreMsg) -> TkvResult<()> {
+        match self.routers.read().get(&store_id) {
+            Some(router) => router.send(msg).map_err(TkvError::from),
+            None => Err(TkvError::new(format!("store {} is unreachable", store_id))),
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for LocalTransport {
+    fn send(&self, msg: RaftMessage) -> TkvResult<()> {
+        let store_id = msg.to_peer.as_ref().map(|peer| peer.store_id).unwrap_or_default();
+        self.route(store_id, StoreMsg::RaftMessage(msg))
+    }
+
+    // The receiving store reads the file in place.
+    async fn send_snapshot(&self, meta: SnapshotMeta, path: PathBuf) -> TkvResult<()> {
+        let store_id = meta.to_peer.as_ref().map(|peer| peer.store_id).unwrap_or_default();
+        let result = self.route(store_id, StoreMsg::Snapshot { meta, path: path.clone() });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&path);
+        }
+        result
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::{HashMap, HashSet}, sync::Arc, time::Duration};
+
+    use tempfile::tempdir;
+    use tokio::sync::mpsc::unbounded_channel;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::config::Config;
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::snap::SnapManager;
+    use crate::kv::raftstore::store::StoreMsg;
+    use crate::kv::server::TinyKvService;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::Peer;
+    use crate::proto::raft_serverpb::RaftMessage;
+    use crate::proto::tinykv::tiny_kv_server::TinyKvServer;
+
+    use super::{RaftClient, RaftClientConfig, StaticResolver, Transport};
+
+    fn new_message(region_id: u64) -> RaftMessage {
+        RaftMessage {
+            region_id,
+            to_peer: Some(Peer { id: region_id, store_id: 2, ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn batch_and_reconnect() -> TkvResult<()> {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let resolver = Arc::new(StaticResolver::new(HashMap::from([(2, addr.to_string())])));
+        let config = RaftClientConfig {
+            max_batch_size: 8,
+            flush_interval: Duration::from_millis(5),
+            backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(50),
+        };
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &Config::for_test())?);
+        let client = RaftClient::new(config, resolver, snap_manager);
+
+        // the store is unreachable, the client keeps backing off.
+        client.send(new_message(1))?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let (router, mut receiver) = unbounded_channel();
+        let service = TinyKvService::new(Arc::new(MemoryStorage::new())).with_raft_router(router);
+        let server = tokio::spawn(Server::builder()
+            .add_service(TinyKvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        // once the store is reachable again, messages get through.
+        let mut received = HashSet::new();
+        for _ in 0..50 {
+            for region_id in 0..100 {
+                if !received.contains(&region_id) {
+                    client.send(new_message(region_id))?;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            while let Ok(StoreMsg::RaftMessage(msg)) = receiver.try_recv() {
+                received.insert(msg.region_id);
+            }
+            if received.len() == 100 {
+                break;
+            }
+        }
+        assert_eq!(received.len(), 100);
+
+        server.abort();
+        Ok(())
+    }
+}
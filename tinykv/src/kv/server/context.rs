@@ -0,0 +1,117 @@
+use crate::{kv::raftstore::{region::{check_key_in_region, region_epoch}, StoreMeta}, proto::{errorpb::{self, EpochNotMatch, KeyNotInRegion, NotLeader, RegionNotFound, StaleCommand, StoreNotMatch}, kvpb::Context, metapb::Region}};
+
+
+// Checks that a request with ctx can be served by the peer on store_id,
+// returns the region it targets or the error telling the client where to retry.
+// The error is boxed, it is large and only built on the slow path.
+pub fn check_context(meta: &StoreMeta, store_id: u64, ctx: &Context) -> Result<Region, Box<errorpb::Error>> {
+    if let Some(peer) = ctx.peer.as_ref().filter(|peer| peer.store_id != store_id) {
+        return Err(Box::new(errorpb::Error {
+            message: format!("request for store {} sent to store {}", peer.store_id, store_id),
+            store_not_match: Some(StoreNotMatch { request_store_id: peer.store_id, actual_store_id: store_id }),
+            ..Default::default()
+        }));
+    }
+    let Some(region) = meta.regions.get(&ctx.region_id) else {
+        return Err(Box::new(errorpb::Error {
+            message: format!("region {} not found", ctx.region_id),
+            region_not_found: Some(RegionNotFound { region_id: ctx.region_id }),
+            ..Default::default()
+        }));
+    };
+    let (leader_id, term) = meta.leader(region.id).unwrap_or_default();
+    let leader = region.peers.iter().find(|peer| peer.id == leader_id);
+    if leader.is_none_or(|leader| leader.store_id != store_id) {
+        return Err(Box::new(errorpb::Error {
+            message: format!("peer of region {} on store {} is not the leader", region.id, store_id),
+            not_leader: Some(NotLeader { region_id: region.id, leader: leader.cloned() }),
+            ..Default::default()
+        }));
+    }
+    // the leadership moved away and back since the client saw it.
+    if ctx.term != 0 && ctx.term < term {
+        return Err(Box::new(errorpb::Error {
+            message: format!("stale term {} for region {}, current term {}", ctx.term, region.id, term),
+            stale_command: Some(StaleCommand {}),
+            ..Default::default()
+        }));
+    }
+    if ctx.region_epoch.clone().unwrap_or_default() != region_epoch(region) {
+        return Err(Box::new(errorpb::Error {
+            message: format!("epoch of region {} does not match, current {:?}", region.id, region_epoch(region)),
+            epoch_not_match: Some(EpochNotMatch { current_regions: vec![region.clone()] }),
+            ..Default::default()
+        }));
+    }
+    Ok(region.clone())
+}
+
+pub fn check_key(key: &[u8], region: &Region) -> Result<(), Box<errorpb::Error>> {
+    if check_key_in_region(key, region) {
+        return Ok(());
+    }
+    Err(Box::new(errorpb::Error {
+        message: format!("key {:?} is not in region {}", key, region.id),
+        key_not_in_region: Some(KeyNotInRegion {
+            key: key.to_vec(),
+            region_id: region.id,
+            start_key: region.start_key.clone(),
+            end_key: region.end_key.clone(),
+        }),
+        ..Default::default()
+    }))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::kv::raftstore::StoreMeta;
+    use crate::proto::kvpb::Context;
+    use crate::proto::metapb::{Peer, Region, RegionEpoch};
+
+    use super::{check_context, check_key};
+
+    #[test]
+    fn region_errors() {
+        let epoch = RegionEpoch { config_version: 2, version: 3 };
+        let region = Region {
+            id: 1,
+            start_key: b"b".to_vec(),
+            end_key: b"m".to_vec(),
+            region_epoch: Some(epoch.clone()),
+            peers: (1..=3).map(|store_id| Peer { id: store_id + 10, store_id, ..Default::default() }).collect(),
+        };
+        let mut meta = StoreMeta::default();
+        meta.set_region(region.clone());
+        meta.set_leader(1, 11, 5);
+
+        let ctx = Context {
+            region_id: 1,
+            region_epoch: Some(epoch.clone()),
+            peer: Some(region.peers[0].clone()),
+            term: 5,
+        };
+        assert_eq!(check_context(&meta, 1, &ctx), Ok(region.clone()));
+
+        let err = check_context(&meta, 2, &ctx).unwrap_err();
+        assert_eq!(err.store_not_match.map(|err| (err.request_store_id, err.actual_store_id)), Some((1, 2)));
+        let err = check_context(&meta, 1, &Context { region_id: 2, ..ctx.clone() }).unwrap_err();
+        assert_eq!(err.region_not_found.map(|err| err.region_id), Some(2));
+        let err = check_context(&meta, 1, &Context { term: 4, ..ctx.clone() }).unwrap_err();
+        assert!(err.stale_command.is_some());
+        let stale_epoch = RegionEpoch { config_version: 2, version: 2 };
+        let err = check_context(&meta, 1, &Context { region_epoch: Some(stale_epoch), ..ctx.clone() }).unwrap_err();
+        assert_eq!(err.epoch_not_match.map(|err| err.current_regions), Some(vec![region.clone()]));
+
+        // the followers point to the leader.
+        let err = check_context(&meta, 2, &Context { peer: Some(region.peers[1].clone()), ..ctx.clone() }).unwrap_err();
+        assert_eq!(err.not_leader.and_then(|err| err.leader), Some(region.peers[0].clone()));
+        meta.set_leader(1, 0, 6);
+        let err = check_context(&meta, 1, &ctx).unwrap_err();
+        assert_eq!(err.not_leader.map(|err| err.leader), Some(None));
+
+        assert!(check_key(b"b", &region).is_ok());
+        let err = check_key(b"m", &region).unwrap_err().key_not_in_region.unwrap();
+        assert_eq!((err.key, err.start_key, err.end_key), (b"m".to_vec(), b"b".to_vec(), b"m".to_vec()));
+    }
+}
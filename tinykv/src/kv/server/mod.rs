@@ -1,30 +1,345 @@
-use std::{ops::Bound, str::FromStr, sync::Arc};
+mod context;
 
+use std::{ops::Bound, str::FromStr, sync::Arc, time::Duration};
 
-use crate::proto::{kvpb::{KvPair, RawDeleteRequest, RawDeleteResponse, RawGetRequest, RawGetResponse, RawPutRequest, RawPutResponse, RawScanRequest, RawScanResponse}, tinykv::tiny_kv_server::TinyKv};
+use parking_lot::RwLock;
+use tokio::sync::oneshot;
 
-use super::{storage::mutation::Mutation, ColumnFamily, Storage};
+use crate::proto::{errorpb, kvpb::{BatchRollbackRequest, BatchRollbackResponse, CheckSecondaryLocksRequest, CheckSecondaryLocksResponse, CheckTxnStatusRequest, CheckTxnStatusResponse, CommitRequest, CommitResponse, Context, Deadlock, GetRequest, GetResponse, KeyError, KvPair, PessimisticLockRequest, PessimisticLockResponse, PessimisticRollbackRequest, PessimisticRollbackResponse, PrewriteRequest, PrewriteResponse, ResolveLockRequest, ResolveLockResponse, ScanRequest, ScanResponse, RawDeleteRequest, RawDeleteResponse, RawGetRequest, RawGetResponse, RawPutRequest, RawPutResponse, RawScanRequest, RawScanResponse}, metapb::Region, raft_serverpb::{BatchRaftMessage, Done, SnapshotChunk, SnapshotDone}, tinykv::tiny_kv_server::TinyKv};
+use crate::scheduler::client::SchedulerClient;
+
+use self::context::{check_context, check_key};
+
+use super::{error::TkvError, raftstore::{cmd::{CmdRequest, RaftCmd}, region::region_bounds, snap::SnapManager, StoreMeta, store::{StoreMsg, StoreSender}}, storage::mutation::Mutation, transaction::{commands::{self, WriteResult}, concurrency::ConcurrencyManager, mvcc::encode_bytes, latches::Latches, lock_wait::{key_hash, WaitTable}}, ColumnFamily, Storage};
+
+fn internal(err: TkvError) -> tonic::Status {
+    tonic::Status::internal(format!("{:?}", err))
+}
+
+// The column family of a raw request, the raft logs are not exposed to clients.
+fn raw_cf(cf: &str) -> Result<ColumnFamily, String> {
+    match ColumnFamily::from_str(cf) {
+        Ok(ColumnFamily::Raft) | Err(_) => Err(format!("invalid column family {:?}", cf)),
+        Ok(cf) => Ok(cf),
+    }
+}
 
 /// TinyKvService is a TinyKV server, it 'faces outwards', sending
 /// and receiving messages from clients such as TinySQL.
 pub struct TinyKvService {
     storage: Arc<dyn Storage>,
-    // latches: Latches,
+    // serialize the transactional commands writing the same keys.
+    latches: Latches,
+    // the pessimistic lock requests waiting for locked keys.
+    lock_waits: WaitTable,
+    // the scheduler keeps the wait-for graph of the cluster.
+    deadlock_detector: Option<SchedulerClient>,
+    // the largest version read, and the locks of the async commits being written.
+    concurrency_manager: Arc<ConcurrencyManager>,
+    // forwards raft messages received from other stores.
+    raft_router: Option<StoreSender>,
+    // receives the snapshots sent by other stores.
+    snap_manager: Option<Arc<SnapManager>>,
+    // the regions hosted on the store, requests are checked against them.
+    store: Option<(u64, Arc<RwLock<StoreMeta>>)>,
 }
 
 impl TinyKvService {
     pub fn new(storage: Arc<dyn Storage + Send + Sync>) -> Self {
-        Self { storage }
+        Self { storage, latches: Latches::default(), lock_waits: WaitTable::default(), deadlock_detector: None, concurrency_manager: Arc::default(), raft_router: None, snap_manager: None, store: None }
+    }
+
+    pub fn with_raft_router(mut self, raft_router: StoreSender) -> Self {
+        self.raft_router = Some(raft_router);
+        self
+    }
+
+    pub fn with_snap_manager(mut self, snap_manager: Arc<SnapManager>) -> Self {
+        self.snap_manager = Some(snap_manager);
+        self
+    }
+
+    pub fn with_store_meta(mut self, store_id: u64, meta: Arc<RwLock<StoreMeta>>) -> Self {
+        self.store = Some((store_id, meta));
+        self
+    }
+
+    pub fn with_deadlock_detector(mut self, client: SchedulerClient) -> Self {
+        self.deadlock_detector = Some(client);
+        self
+    }
+
+    // Shares max_ts with the raft store, raising it when a region changes leader.
+    pub fn with_concurrency_manager(mut self, concurrency_manager: Arc<ConcurrencyManager>) -> Self {
+        self.concurrency_manager = concurrency_manager;
+        self
+    }
+
+    // Checks that the request is sent to the leader of a region holding
+    // all the keys, returns that region. Nothing is checked without a raft store.
+    fn check_request<I>(&self, ctx: &Option<Context>, keys: I) -> Result<Option<Region>, Box<errorpb::Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let Some((store_id, meta)) = &self.store else {
+            return Ok(None);
+        };
+        let region = check_context(&meta.read(), *store_id, &ctx.clone().unwrap_or_default())?;
+        for key in keys {
+            check_key(key.as_ref(), &region)?;
+        }
+        Ok(Some(region))
+    }
+
+    // Same as check_request, the transactional keys are checked
+    // encoded, as they are stored.
+    fn check_txn_request<I>(&self, ctx: &Option<Context>, keys: I) -> Result<Option<Region>, Box<errorpb::Error>>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        self.check_request(ctx, keys.into_iter().map(|key| encode_bytes(key.as_ref())))
+    }
+
+    // Writes the mutations of a request, proposed to the region through raft
+    // on a raft store, returns once they are applied. A proposal that fails
+    // is reported as a region error, the client retries it.
+    async fn write(&self, ctx: &Option<Context>, region: &Option<Region>, mutations: Vec<Mutation>) -> Result<Option<errorpb::Error>, tonic::Status> {
+        if mutations.is_empty() {
+            return Ok(None);
+        }
+        let (Some(router), Some(region)) = (&self.raft_router, region) else {
+            return self.storage.write(mutations).map(|_| None).map_err(internal);
+        };
+        let cmd = RaftCmd::new(region.id, &region.region_epoch.clone().unwrap_or_default(), CmdRequest::Write(mutations));
+        let (callback, applied) = oneshot::channel();
+        router.send(StoreMsg::RaftCmd { cmd, callback: Some(callback) })
+            .map_err(|_| tonic::Status::unavailable("raft store is stopped"))?;
+        match applied.await {
+            Ok(Ok(())) => Ok(None),
+            // the region may have changed meanwhile, its context tells how.
+            Ok(Err(err)) => match self.check_request(ctx, std::iter::empty::<&[u8]>()) {
+                Err(region_error) => Ok(Some(*region_error)),
+                Ok(_) => Ok(Some(errorpb::Error { message: format!("{:?}", err), ..Default::default() })),
+            },
+            Err(_) => Err(tonic::Status::unavailable("raft store is stopped")),
+        }
+    }
+
+    // Whether the store leading the region raised its max_ts since, always
+    // true without a raft store.
+    fn max_ts_synced(&self, region: &Option<Region>) -> bool {
+        match (&self.store, region) {
+            (Some((_, meta)), Some(region)) => meta.read().is_max_ts_synced(region.id),
+            _ => true,
+        }
+    }
+
+    fn record_read(&self, region: &Option<Region>, keys: u64, bytes: u64) {
+        if let (Some((_, meta)), Some(region)) = (&self.store, region) {
+            meta.write().record_read(region.id, keys, bytes);
+        }
     }
 }
 
 #[tonic::async_trait]
 impl TinyKv for TinyKvService {
 
+    async fn kv_get(&self, request:tonic::Request<GetRequest>) ->  Result<tonic::Response<GetResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, [&req.key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(GetResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let response = commands::get(self.storage.as_ref(), &self.concurrency_manager, &req).map_err(internal)?;
+        self.record_read(&region, 1, (req.key.len() + response.value.len()) as u64);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_scan(&self, request:tonic::Request<ScanRequest>) ->  Result<tonic::Response<ScanResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, [&req.start_key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(ScanResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let end = region.as_ref().map_or(Bound::Unbounded, |region| region_bounds(region).1);
+        let response = commands::scan(self.storage.as_ref(), &self.concurrency_manager, &req, end).map_err(internal)?;
+        let bytes = response.pairs.iter().map(|pair| (pair.key.len() + pair.value.len()) as u64).sum();
+        self.record_read(&region, response.pairs.len() as u64, bytes);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_prewrite(&self, request:tonic::Request<PrewriteRequest>) ->  Result<tonic::Response<PrewriteResponse> ,tonic::Status> {
+        let mut req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, req.mutations.iter().map(|mutation| &mutation.key)) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(PrewriteResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        // a commit version above max_ts may be below a read served by the
+        // previous leader, the transaction falls back to two phase commit.
+        if !self.max_ts_synced(&region) {
+            req.use_async_commit = false;
+            req.try_one_pc = false;
+        }
+        let _latches = self.latches.acquire(req.mutations.iter().map(|mutation| &mutation.key)).await;
+        // the memory locks are held until the prewrite is written.
+        let WriteResult { response, mutations, memory_locks } = commands::prewrite(self.storage.as_ref(), &self.concurrency_manager, &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(PrewriteResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        drop(memory_locks);
+        if response.one_pc_commit_ts != 0 {
+            self.lock_waits.wake(req.mutations.iter().map(|mutation| &mutation.key));
+        }
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_commit(&self, request:tonic::Request<CommitRequest>) ->  Result<tonic::Response<CommitResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, &req.keys) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(CommitResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let _latches = self.latches.acquire(&req.keys).await;
+        let WriteResult { response, mutations, .. } = commands::commit(self.storage.as_ref(), &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(CommitResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake(&req.keys);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_pessimistic_lock(&self, request:tonic::Request<PessimisticLockRequest>) ->  Result<tonic::Response<PessimisticLockResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, &req.keys) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(PessimisticLockResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(req.wait_timeout);
+        loop {
+            let (waiter, lock) = {
+                let _latches = self.latches.acquire(&req.keys).await;
+                let WriteResult { response, mutations, .. } = commands::pessimistic_lock(self.storage.as_ref(), &req).map_err(internal)?;
+                if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+                    return Ok(tonic::Response::new(PessimisticLockResponse { region_error: Some(region_error), ..Default::default() }));
+                }
+                match response.errors.iter().find_map(|error| error.locked.as_ref()) {
+                    Some(lock) if tokio::time::Instant::now() < deadline => (self.lock_waits.wait_for(&lock.key), lock.clone()),
+                    _ => return Ok(tonic::Response::new(response)),
+                }
+            };
+            // retries once the lock is released, or a last time at the deadline.
+            // Without a detector, a deadlock only ends with the timeout.
+            let Some(detector) = &self.deadlock_detector else {
+                let _ = tokio::time::timeout_at(deadline, waiter).await;
+                continue;
+            };
+            match detector.detect(req.start_version, lock.lock_version, key_hash(&lock.key)).await {
+                Ok(Some(deadlock_key_hash)) => {
+                    let deadlock = Deadlock { lock_ts: lock.lock_version, lock_key: lock.key, deadlock_key_hash };
+                    let error = KeyError { deadlock: Some(deadlock), ..Default::default() };
+                    return Ok(tonic::Response::new(PessimisticLockResponse { errors: vec![error], ..Default::default() }));
+                },
+                Ok(None) => {},
+                Err(err) => log::warn!("failed to detect deadlocks of transaction {}: {:?}", req.start_version, err),
+            }
+            let _ = tokio::time::timeout_at(deadline, waiter).await;
+            if let Err(err) = detector.clean_up_wait_for(req.start_version, lock.lock_version).await {
+                log::warn!("failed to clean up the waits of transaction {}: {:?}", req.start_version, err);
+            }
+        }
+    }
+
+    async fn kv_pessimistic_rollback(&self, request:tonic::Request<PessimisticRollbackRequest>) ->  Result<tonic::Response<PessimisticRollbackResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, &req.keys) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(PessimisticRollbackResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let _latches = self.latches.acquire(&req.keys).await;
+        let WriteResult { response, mutations, .. } = commands::pessimistic_rollback(self.storage.as_ref(), &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(PessimisticRollbackResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake(&req.keys);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_check_txn_status(&self, request:tonic::Request<CheckTxnStatusRequest>) ->  Result<tonic::Response<CheckTxnStatusResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, [&req.primary_key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(CheckTxnStatusResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let _latches = self.latches.acquire([&req.primary_key]).await;
+        let WriteResult { response, mutations, .. } = commands::check_txn_status(self.storage.as_ref(), &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(CheckTxnStatusResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake([&req.primary_key]);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_check_secondary_locks(&self, request:tonic::Request<CheckSecondaryLocksRequest>) ->  Result<tonic::Response<CheckSecondaryLocksResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, &req.keys) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(CheckSecondaryLocksResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let _latches = self.latches.acquire(&req.keys).await;
+        let WriteResult { response, mutations, .. } = commands::check_secondary_locks(self.storage.as_ref(), &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(CheckSecondaryLocksResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake(&req.keys);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_batch_rollback(&self, request:tonic::Request<BatchRollbackRequest>) ->  Result<tonic::Response<BatchRollbackResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_txn_request(&req.context, &req.keys) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(BatchRollbackResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let _latches = self.latches.acquire(&req.keys).await;
+        let WriteResult { response, mutations, .. } = commands::batch_rollback(self.storage.as_ref(), &req).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(BatchRollbackResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake(&req.keys);
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn kv_resolve_lock(&self, request:tonic::Request<ResolveLockRequest>) ->  Result<tonic::Response<ResolveLockResponse> ,tonic::Status> {
+        let req = request.into_inner();
+        let region = match self.check_request(&req.context, std::iter::empty::<&[u8]>()) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(ResolveLockResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let (start, end) = region.as_ref().map_or((Bound::Unbounded, Bound::Unbounded), region_bounds);
+        // the locks are found first, resolving them again is a no-op.
+        let keys = commands::txn_locks(self.storage.as_ref(), start, end, req.start_version).map_err(internal)?;
+        let _latches = self.latches.acquire(&keys).await;
+        let WriteResult { response, mutations, .. } = commands::resolve_lock(self.storage.as_ref(), &req, keys.clone()).map_err(internal)?;
+        if let Some(region_error) = self.write(&req.context, &region, mutations).await? {
+            return Ok(tonic::Response::new(ResolveLockResponse { region_error: Some(region_error), ..Default::default() }));
+        }
+        self.lock_waits.wake(&keys);
+        Ok(tonic::Response::new(response))
+    }
+
     async fn raw_get(&self, request:tonic::Request<RawGetRequest>) ->  Result<tonic::Response<RawGetResponse> ,tonic::Status> {
         let raw_req = request.into_inner();
+        let region = match self.check_request(&raw_req.context, [&raw_req.key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(RawGetResponse { region_error: Some(*err), ..Default::default() })),
+        };
 
-        let cf = ColumnFamily::from_str(&raw_req.cf).unwrap();
+        let cf = match raw_cf(&raw_req.cf) {
+            Ok(cf) => cf,
+            Err(error) => return Ok(tonic::Response::new(RawGetResponse { error, ..Default::default() })),
+        };
         let value_opt = self.storage.get(cf, &raw_req.key).unwrap();
         
         let (value, not_found) = if let Some(value) = value_opt {
@@ -32,6 +347,7 @@ impl TinyKv for TinyKvService {
         } else {
             (vec![], true)
         };
+        self.record_read(&region, 1, (raw_req.key.len() + value.len()) as u64);
 
         Ok(tonic::Response::new(RawGetResponse{
             region_error: None, 
@@ -43,34 +359,64 @@ impl TinyKv for TinyKvService {
 
     async fn raw_put(&self, request:tonic::Request<RawPutRequest>) ->  Result<tonic::Response<RawPutResponse> ,tonic::Status> {
         let raw_req = request.into_inner();
+        let region = match self.check_request(&raw_req.context, [&raw_req.key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(RawPutResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let cf = match raw_cf(&raw_req.cf) {
+            Ok(cf) => cf,
+            Err(error) => return Ok(tonic::Response::new(RawPutResponse { error, ..Default::default() })),
+        };
         let mutation = Mutation::Put { 
             key: raw_req.key,
             value: raw_req.value,
-            cf: ColumnFamily::from_str(&raw_req.cf).unwrap(),
+            cf,
         };
-        self.storage.write(vec![mutation]).unwrap();
+        if let Some(region_error) = self.write(&raw_req.context, &region, vec![mutation]).await? {
+            return Ok(tonic::Response::new(RawPutResponse { region_error: Some(region_error), ..Default::default() }));
+        }
 
         Ok(tonic::Response::new(RawPutResponse{region_error: None, error: "".to_string()}))
     }
 
     async fn raw_delete(&self, request:tonic::Request<RawDeleteRequest>) ->  Result<tonic::Response<RawDeleteResponse> ,tonic::Status> {
         let raw_req = request.into_inner();
+        let region = match self.check_request(&raw_req.context, [&raw_req.key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(RawDeleteResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let cf = match raw_cf(&raw_req.cf) {
+            Ok(cf) => cf,
+            Err(error) => return Ok(tonic::Response::new(RawDeleteResponse { error, ..Default::default() })),
+        };
         let mutation = Mutation::Delete{ 
             key: raw_req.key,
-            cf: ColumnFamily::from_str(&raw_req.cf).unwrap(),
+            cf,
         };
-        self.storage.write(vec![mutation]).unwrap();
+        if let Some(region_error) = self.write(&raw_req.context, &region, vec![mutation]).await? {
+            return Ok(tonic::Response::new(RawDeleteResponse { region_error: Some(region_error), ..Default::default() }));
+        }
 
         Ok(tonic::Response::new(RawDeleteResponse{region_error: None, error: "".to_string()}))
     }
 
     async fn raw_scan(&self, request:tonic::Request<RawScanRequest>) ->  Result<tonic::Response<RawScanResponse> ,tonic::Status> {
         let raw_req = request.into_inner();
-        let cf = ColumnFamily::from_str(&raw_req.cf).unwrap();
-         
+        let region = match self.check_request(&raw_req.context, [&raw_req.start_key]) {
+            Ok(region) => region,
+            Err(err) => return Ok(tonic::Response::new(RawScanResponse { region_error: Some(*err), ..Default::default() })),
+        };
+        let cf = match raw_cf(&raw_req.cf) {
+            Ok(cf) => cf,
+            Err(error) => return Ok(tonic::Response::new(RawScanResponse { error, ..Default::default() })),
+        };
+        // the scan stops at the end of the region, a limit of 0 reads it all.
+        let end = region.as_ref().map_or(Bound::Unbounded, |region| region_bounds(region).1);
+        let limit = if raw_req.limit == 0 { usize::MAX } else { raw_req.limit as usize };
+
         let mut kvs = vec![];
-        let scanner = self.storage.scan(cf, Bound::Included(raw_req.start_key), Bound::Unbounded).unwrap();
-        for item in scanner.iter() {
+        let scanner = self.storage.scan(cf, Bound::Included(raw_req.start_key), end).unwrap();
+        for item in scanner.iter().take(limit) {
             let (key, value) = item.unwrap();
             kvs.push(KvPair{
                 error: None,
@@ -78,6 +424,8 @@ impl TinyKv for TinyKvService {
                 value, 
             })
         }
+        let bytes = kvs.iter().map(|kv| (kv.key.len() + kv.value.len()) as u64).sum();
+        self.record_read(&region, kvs.len() as u64, bytes);
 
         Ok(tonic::Response::new(RawScanResponse{
             region_error: None, 
@@ -86,5 +434,191 @@ impl TinyKv for TinyKvService {
         }))
     }
 
+    async fn batch_raft(&self, request:tonic::Request<tonic::Streaming<BatchRaftMessage>>) ->  Result<tonic::Response<Done> ,tonic::Status> {
+        let Some(router) = &self.raft_router else {
+            return Err(tonic::Status::unavailable("raft is not enabled on this store"));
+        };
+        let mut stream = request.into_inner();
+        while let Some(batch) = stream.message().await? {
+            for msg in batch.msgs {
+                router.send(StoreMsg::RaftMessage(msg))
+                    .map_err(|_| tonic::Status::unavailable("raft store is stopped"))?;
+            }
+        }
+        Ok(tonic::Response::new(Done{}))
+    }
+
+    async fn snapshot(&self, request:tonic::Request<tonic::Streaming<SnapshotChunk>>) ->  Result<tonic::Response<SnapshotDone> ,tonic::Status> {
+        let (Some(router), Some(snap_manager)) = (&self.raft_router, &self.snap_manager) else {
+            return Err(tonic::Status::unavailable("raft is not enabled on this store"));
+        };
+        let (received, snapshot) = snap_manager.recv(request.into_inner())
+            .await
+            .map_err(|err| tonic::Status::data_loss(format!("{:?}", err)))?;
+        if let Some((meta, path)) = snapshot {
+            router.send(StoreMsg::Snapshot { meta, path })
+                .map_err(|_| tonic::Status::unavailable("raft store is stopped"))?;
+        }
+        Ok(tonic::Response::new(SnapshotDone{ received }))
+    }
+
 }
 
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use tempfile::tempdir;
+
+    use crate::kv::config::Config as StoreConfig;
+    use crate::kv::error::TkvResult;
+    use crate::kv::raftstore::{snap::SnapManager, store::{RaftStore, StoreMsg}, transport::LocalTransport, LocalIdAllocator};
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::{ColumnFamily, Storage};
+    use crate::proto::kvpb::{BatchRollbackRequest, CommitRequest, Context, GetRequest, Mutation, Op, PessimisticLockRequest, PessimisticRollbackRequest, PrewriteRequest, RawGetRequest, RawPutRequest};
+    use crate::proto::metapb::{Peer, Region, RegionEpoch};
+    use crate::proto::schedulerpb::scheduler_server::SchedulerServer;
+    use crate::proto::tinykv::tiny_kv_server::TinyKv;
+    use crate::scheduler::{client::SchedulerClient, config::Config, server::SchedulerService};
+
+    use super::TinyKvService;
+
+    fn lock_request(key: &[u8], start_version: u64, wait_timeout: u64) -> tonic::Request<PessimisticLockRequest> {
+        tonic::Request::new(PessimisticLockRequest {
+            keys: vec![key.to_vec()],
+            primary_lock: key.to_vec(),
+            start_version,
+            lock_ttl: 3000,
+            for_update_ts: start_version,
+            wait_timeout,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn lock_waits_and_deadlocks() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let scheduler = SchedulerService::new(&Config::default(), Arc::new(MemoryStorage::new()))?;
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(scheduler))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+        let client = SchedulerClient::connect(&addr.to_string())?;
+        let service = Arc::new(TinyKvService::new(Arc::new(MemoryStorage::new())).with_deadlock_detector(client));
+
+        assert!(service.kv_pessimistic_lock(lock_request(b"a", 10, 0)).await?.into_inner().errors.is_empty());
+        assert!(service.kv_pessimistic_lock(lock_request(b"b", 20, 0)).await?.into_inner().errors.is_empty());
+        // the wait times out while b is locked.
+        let response = service.kv_pessimistic_lock(lock_request(b"b", 30, 100)).await?.into_inner();
+        assert!(response.errors[0].locked.is_some());
+
+        // 10 waits for 20, and 20 waiting for 10 would be a deadlock.
+        let waiter = tokio::spawn({
+            let service = service.clone();
+            async move { service.kv_pessimistic_lock(lock_request(b"b", 10, 5000)).await.map(|response| response.into_inner()) }
+        });
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        let response = service.kv_pessimistic_lock(lock_request(b"a", 20, 5000)).await?.into_inner();
+        let deadlock = response.errors[0].deadlock.clone().unwrap();
+        assert_eq!((deadlock.lock_ts, deadlock.lock_key), (10, b"a".to_vec()));
+
+        // the victim releases its lock, the waiter gets it.
+        let rollback = PessimisticRollbackRequest { start_version: 20, for_update_ts: 20, keys: vec![b"b".to_vec()], ..Default::default() };
+        service.kv_pessimistic_rollback(tonic::Request::new(rollback)).await?;
+        let response = tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap()?;
+        assert!(response.errors.is_empty());
+        server.abort();
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reject_invalid_column_families() -> TkvResult<()> {
+        let service = TinyKvService::new(Arc::new(MemoryStorage::new()));
+        for cf in ["raft", "unknown"] {
+            let put = RawPutRequest { cf: cf.to_string(), key: b"k".to_vec(), value: b"v".to_vec(), ..Default::default() };
+            assert!(!service.raw_put(tonic::Request::new(put)).await?.into_inner().error.is_empty());
+            let get = RawGetRequest { cf: cf.to_string(), key: b"k".to_vec(), ..Default::default() };
+            assert!(!service.raw_get(tonic::Request::new(get)).await?.into_inner().error.is_empty());
+        }
+        let put = RawPutRequest { cf: "default".to_string(), key: b"k".to_vec(), value: b"v".to_vec(), ..Default::default() };
+        assert!(service.raw_put(tonic::Request::new(put)).await?.into_inner().error.is_empty());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn write_through_raft() -> TkvResult<()> {
+        let config = StoreConfig::for_test();
+        let snap_dir = tempdir()?;
+        let snap_manager = Arc::new(SnapManager::new(snap_dir.path(), &config)?);
+        let storage = Arc::new(MemoryStorage::new());
+        let epoch = RegionEpoch { config_version: 1, version: 1 };
+        let region = Region {
+            id: 1,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(epoch.clone()),
+            peers: vec![Peer { id: 1, store_id: 1, ..Default::default() }],
+        };
+        let store = RaftStore::new(1, &config, storage.clone(), Arc::new(LocalIdAllocator::new(100)), Arc::new(LocalTransport::new()), snap_manager.clone(), vec![region.clone()])?;
+        let sender = store.sender();
+        let meta = store.meta();
+        let service = TinyKvService::new(storage.clone())
+            .with_raft_router(sender.clone())
+            .with_snap_manager(snap_manager)
+            .with_store_meta(1, meta.clone());
+        let handle = tokio::spawn(store.run());
+        while meta.read().leader(1).is_none_or(|(leader_id, _)| leader_id != 1) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let ctx = Context { region_id: 1, region_epoch: Some(epoch), peer: Some(region.peers[0].clone()), term: 0 };
+        let put = RawPutRequest { context: Some(ctx.clone()), cf: "default".to_string(), key: b"k".to_vec(), value: b"v".to_vec() };
+        let response = service.raw_put(tonic::Request::new(put)).await?.into_inner();
+        assert_eq!(response.region_error, None);
+        assert_eq!(storage.get(ColumnFamily::Default, b"k")?, Some(b"v".to_vec()));
+
+        // a transaction is prewritten and committed through raft.
+        let mutation = Mutation { op: Op::Put as i32, key: b"a".to_vec(), value: b"1".to_vec() };
+        let prewrite = PrewriteRequest { context: Some(ctx.clone()), mutations: vec![mutation], primary_lock: b"a".to_vec(), start_version: 10, lock_ttl: 3000, ..Default::default() };
+        let response = service.kv_prewrite(tonic::Request::new(prewrite)).await?.into_inner();
+        assert!(response.region_error.is_none() && response.errors.is_empty());
+        let commit = CommitRequest { context: Some(ctx.clone()), keys: vec![b"a".to_vec()], start_version: 10, commit_version: 20 };
+        assert_eq!(service.kv_commit(tonic::Request::new(commit)).await?.into_inner().region_error, None);
+        let get = GetRequest { context: Some(ctx.clone()), key: b"a".to_vec(), version: 30 };
+        assert_eq!(service.kv_get(tonic::Request::new(get)).await?.into_inner().value, b"1".to_vec());
+
+        // 1PC falls back to two phase commit until max_ts is raised for the leader.
+        let mutation = Mutation { op: Op::Put as i32, key: b"b".to_vec(), value: b"2".to_vec() };
+        let prewrite = PrewriteRequest { context: Some(ctx.clone()), mutations: vec![mutation], primary_lock: b"b".to_vec(), start_version: 30, lock_ttl: 3000, try_one_pc: true, ..Default::default() };
+        let response = service.kv_prewrite(tonic::Request::new(prewrite.clone())).await?.into_inner();
+        assert!(response.errors.is_empty() && response.one_pc_commit_ts == 0);
+        let rollback = BatchRollbackRequest { context: Some(ctx.clone()), start_version: 30, keys: vec![b"b".to_vec()] };
+        assert_eq!(service.kv_batch_rollback(tonic::Request::new(rollback)).await?.into_inner().error, None);
+        let (_, term) = meta.read().leader(1).unwrap_or_default();
+        sender.send(StoreMsg::MaxTsSynced { region_id: 1, term })?;
+        while !meta.read().is_max_ts_synced(1) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        let prewrite = PrewriteRequest { start_version: 40, ..prewrite };
+        assert_eq!(service.kv_prewrite(tonic::Request::new(prewrite)).await?.into_inner().one_pc_commit_ts, 41);
+
+        // a stale epoch or a peer of another store is rejected, nothing is written.
+        let stale = Context { region_epoch: Some(RegionEpoch { config_version: 1, version: 0 }), ..ctx.clone() };
+        let put = RawPutRequest { context: Some(stale), cf: "default".to_string(), key: b"x".to_vec(), value: b"v".to_vec() };
+        let response = service.raw_put(tonic::Request::new(put)).await?.into_inner();
+        assert!(response.region_error.and_then(|err| err.epoch_not_match).is_some());
+        let wrong_peer = Context { peer: Some(Peer { id: 2, store_id: 2, ..Default::default() }), ..ctx.clone() };
+        let commit = CommitRequest { context: Some(wrong_peer), keys: vec![b"x".to_vec()], start_version: 10, commit_version: 20 };
+        let response = service.kv_commit(tonic::Request::new(commit)).await?.into_inner();
+        assert!(response.region_error.and_then(|err| err.store_not_match).is_some());
+        assert_eq!(storage.get(ColumnFamily::Default, b"x")?, None);
+
+        sender.send(StoreMsg::Stop)?;
+        handle.await??;
+        Ok(())
+    }
+}
@@ -4,6 +4,7 @@ use std::ops::Bound;
 use std::path::Path;
 
 use heed::{Database, Env, EnvOpenOptions, RoRange, RoTxn};
+use heed::flags::Flags;
 use heed::types::*;
 use ouroboros::self_referencing;
 
@@ -30,9 +31,12 @@ impl Debug for DiskStorage {
 impl DiskStorage {
 
     pub fn new<P: AsRef<Path>>(path: P) -> TkvResult<Self> {
-        let env = EnvOpenOptions::new()
-            .map_size(1 * 1024 * 1024 * 1024) // 1GiB
-            .open(&path)?;
+        let mut options = EnvOpenOptions::new();
+        options.map_size(1 * 1024 * 1024 * 1024); // 1GiB
+        // each scan holds its own read transaction, and a thread may have
+        // several scans open at once.
+        unsafe { options.flag(Flags::MdbNoTls) };
+        let env = options.open(&path)?;
         if !path.as_ref().exists() {
             std::fs::create_dir_all(path)?;
         }
@@ -105,7 +109,7 @@ impl<'a> DiskScanner<'a > {
         Self {
             cf,
             storage,
-            bound: ByteArrayRangeBound(cf.add_bound_prefix(start), cf.add_bound_prefix(end)),
+            bound: ByteArrayRangeBound(cf.add_start_bound_prefix(start), cf.add_end_bound_prefix(end)),
         }
     }
 }
@@ -1,6 +1,6 @@
 use std::{collections::BTreeMap, ops::Bound, sync::Arc};
 
-use parking_lot::{Mutex, MutexGuard};
+use parking_lot::{RwLock, RwLockReadGuard};
 
 use crate::kv::{  error::TkvResult, ColumnFamily, KvIterator, Storage, StorageScanner};
 
@@ -12,14 +12,14 @@ type BTree = BTreeMap<Vec<u8>, Vec<u8>>;
 
 #[derive(Debug)]
 pub struct MemoryStorage {
-    store: Arc<Mutex<BTree>>,
+    store: Arc<RwLock<BTree>>,
 }
 
 impl MemoryStorage {
 
     pub fn new() -> Self {
         Self {
-            store: Arc::new(Mutex::new(BTreeMap::new())),
+            store: Arc::new(RwLock::new(BTreeMap::new())),
         }
     }
 
@@ -36,7 +36,7 @@ impl Storage for MemoryStorage {
     }
 
     fn write(&self, batch: Vec<Mutation>) -> TkvResult<()> {
-        let mut lock_guard = self.store.lock();
+        let mut lock_guard = self.store.write();
         for mutation in batch {
             match mutation {
                 Mutation::Put { key, value, cf } => lock_guard.insert(cf.add_prefix(&key), value),
@@ -47,12 +47,13 @@ impl Storage for MemoryStorage {
     }
 
     fn get(&self, cf: ColumnFamily, key: &[u8]) -> TkvResult<Option<Vec<u8>>> {
-        let value = self.store.lock().get(&cf.add_prefix(key)).cloned();
+        let value = self.store.read_recursive().get(&cf.add_prefix(key)).cloned();
         Ok(value)
     }
 
     fn scan(&self, cf: ColumnFamily, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> TkvResult<Box<dyn StorageScanner + '_>> {
-        let storage = self.store.lock();
+        // scans may be open together, e.g. one per column family.
+        let storage = self.store.read_recursive();
         Ok(Box::new(MemoryScanner::new(storage, cf, start, end)))
     }
 
@@ -62,14 +63,14 @@ impl Storage for MemoryStorage {
 
 // MemoryReader
 pub struct MemoryScanner<'a > {
-    storage: MutexGuard<'a, BTree>,  // The underlying storage.
+    storage: RwLockReadGuard<'a, BTree>,  // The underlying storage.
     cf: ColumnFamily,
     bound: (Bound<Vec<u8>>, Bound<Vec<u8>>),
 }
 
 impl<'a> MemoryScanner<'a > {
     pub fn new(
-        storage: MutexGuard<'a, BTree>,
+        storage: RwLockReadGuard<'a, BTree>,
         cf: ColumnFamily,
         start: Bound<Vec<u8>>,
         end: Bound<Vec<u8>>,
@@ -77,7 +78,7 @@ impl<'a> MemoryScanner<'a > {
         Self { 
             storage,
             cf: cf,
-            bound: (cf.add_bound_prefix(start), cf.add_bound_prefix(end)),
+            bound: (cf.add_start_bound_prefix(start), cf.add_end_bound_prefix(end)),
         }
     }
 }
@@ -1,8 +1,11 @@
+use serde::{Deserialize, Serialize};
+
 use crate::kv::ColumnFamily;
 
 
 // Mutation is a single modification to the TinyKV's 
 // underlying storage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Mutation {
     Put{key: Vec<u8>, value: Vec<u8>, cf: ColumnFamily},
     Delete{key: Vec<u8>, cf: ColumnFamily},
@@ -23,6 +26,14 @@ impl Mutation {
         }
     }
 
+    // Number of bytes written by the mutation.
+    pub fn size(&self) -> usize {
+        match self {
+            Mutation::Put { key, value, .. } => key.len() + value.len(),
+            Mutation::Delete { key, .. } => key.len(),
+        }
+    }
+
     pub fn column_family(&self) -> ColumnFamily {
         match self {
             Mutation::Put { cf, .. } => cf.clone(),
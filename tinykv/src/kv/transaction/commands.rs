@@ -0,0 +1,761 @@
+use std::ops::Bound;
+
+use crate::{kv::{error::TkvResult, storage::mutation::Mutation, Storage}, proto::kvpb::{Action, BatchRollbackRequest, BatchRollbackResponse, CheckSecondaryLocksRequest, CheckSecondaryLocksResponse, CheckTxnStatusRequest, CheckTxnStatusResponse, CommitRequest, CommitResponse, GetRequest, GetResponse, KeyError, KvPair, PessimisticLockRequest, PessimisticLockResponse, PessimisticRollbackRequest, PessimisticRollbackResponse, PrewriteRequest, PrewriteResponse, ResolveLockRequest, ResolveLockResponse, ScanRequest, ScanResponse, WriteConflict}};
+
+use super::{concurrency::{ConcurrencyManager, MemoryLockGuard}, mvcc::{encode_bytes, lock::Lock, scanner::{before_end, Scanner}, write::{Write, WriteKind}, MvccReader, MvccTxn}};
+
+
+/// WriteResult is the outcome of a command writing to the store. Its
+/// mutations are written, through raft on a raft store, before the
+/// response is sent. The memory locks of a prewrite are held until then.
+#[derive(Debug)]
+pub struct WriteResult<'a, R> {
+    pub response: R,
+    pub mutations: Vec<Mutation>,
+    pub memory_locks: Option<MemoryLockGuard<'a>>,
+}
+
+impl<'a, R> WriteResult<'a, R> {
+    fn new(response: R, mutations: Vec<Mutation>) -> Self {
+        Self { response, mutations, memory_locks: None }
+    }
+
+    // Writes the mutations straight to storage.
+    pub fn write(self, storage: &dyn Storage) -> TkvResult<R> {
+        if !self.mutations.is_empty() {
+            storage.write(self.mutations)?;
+        }
+        Ok(self.response)
+    }
+}
+
+// A response with nothing to write.
+impl<'a, R> From<R> for WriteResult<'a, R> {
+    fn from(response: R) -> Self {
+        Self::new(response, vec![])
+    }
+}
+
+fn abort(message: String) -> KeyError {
+    KeyError { abort: message, ..Default::default() }
+}
+
+// Rolls back the transaction of txn on key. A rollback record is left even
+// when the key was never locked, so that a late prewrite can't lock it.
+// Another transaction may have committed at start_ts, its write is kept
+// and marked instead.
+fn rollback_key(txn: &mut MvccTxn, key: &[u8]) -> TkvResult<Result<(), KeyError>> {
+    match txn.reader().current_write(key, txn.start_ts)? {
+        Some((_, write)) if write.kind == WriteKind::Rollback => return Ok(Ok(())),
+        Some((commit_ts, _)) => return Ok(Err(abort(format!("transaction {} is committed at {}", txn.start_ts, commit_ts)))),
+        None => (),
+    }
+    if let Some(lock) = txn.reader().get_lock(key)?.filter(|lock| lock.ts == txn.start_ts) {
+        txn.delete_lock(key);
+        if lock.kind == WriteKind::Put {
+            txn.delete_value(key);
+        }
+    }
+    let rollback = match txn.reader().get_write(key, txn.start_ts)? {
+        Some(write) => Write { has_overlapped_rollback: true, ..write },
+        None => Write::new(txn.start_ts, WriteKind::Rollback),
+    };
+    txn.put_write(key, txn.start_ts, &rollback)?;
+    Ok(Ok(()))
+}
+
+// Writes the commit record of txn on key, keeping the rollback of the
+// transaction started at commit_ts if there is one.
+fn put_commit(txn: &mut MvccTxn, key: &[u8], commit_ts: u64, kind: WriteKind) -> TkvResult<()> {
+    let overlapped = txn.reader().get_write(key, commit_ts)?.is_some_and(|write| write.kind == WriteKind::Rollback);
+    txn.put_write(key, commit_ts, &Write { has_overlapped_rollback: overlapped, ..Write::new(txn.start_ts, kind) })
+}
+
+// Reads the value of a key at the request version, unless a transaction
+// that may commit before that version still holds its lock.
+pub fn get(storage: &dyn Storage, manager: &ConcurrencyManager, request: &GetRequest) -> TkvResult<GetResponse> {
+    if let Some(lock) = manager.read_key_check(&request.key, request.version) {
+        let error = KeyError { locked: Some(lock), ..Default::default() };
+        return Ok(GetResponse { error: Some(error), ..Default::default() });
+    }
+    let reader = MvccReader::new(storage);
+    if let Some(lock) = reader.get_lock(&request.key)?.filter(|lock| lock.is_blocking(request.version)) {
+        let error = KeyError { locked: Some(lock.info(&request.key)), ..Default::default() };
+        return Ok(GetResponse { error: Some(error), ..Default::default() });
+    }
+    let value = reader.get_value(&request.key, request.version)?;
+    Ok(GetResponse { not_found: value.is_none(), value: value.unwrap_or_default(), ..Default::default() })
+}
+
+// Reads up to limit keys from start_key to end, as seen at the request
+// version. A limit of 0 reads them all. The end bounds the encoded keys.
+pub fn scan(storage: &dyn Storage, manager: &ConcurrencyManager, request: &ScanRequest, end: Bound<Vec<u8>>) -> TkvResult<ScanResponse> {
+    let limit = if request.limit == 0 { usize::MAX } else { request.limit as usize };
+    // past the end, the first lock being written is outside of the scan.
+    let memory_lock = manager.read_range_check(&request.start_key, &Bound::Unbounded, request.version)
+        .filter(|lock| before_end(&encode_bytes(&lock.key), &end));
+    let mut pairs: Vec<KvPair> = Scanner::new(storage, request.start_key.clone(), end, request.version)?
+        .take(limit)
+        .collect::<TkvResult<_>>()?;
+    // the scan stops at a lock being written.
+    if let Some(lock) = memory_lock.filter(|lock| pairs.len() < limit || pairs.last().is_some_and(|pair| pair.key > lock.key)) {
+        pairs.retain(|pair| pair.key < lock.key);
+        pairs.truncate(limit - 1);
+        let key = lock.key.clone();
+        pairs.push(KvPair { error: Some(KeyError { locked: Some(lock), ..Default::default() }), key, value: vec![] });
+    }
+    Ok(ScanResponse { pairs, ..Default::default() })
+}
+
+// Locks the keys of the mutations and writes their values at start_version.
+// Nothing is written if any key is locked or was written since then.
+// Async commit locks get a min_commit_ts above any version read so far,
+// 1PC writes the commit records at that version instead of locks.
+// A prewrite retried once the transaction is done reports how it ended.
+pub fn prewrite<'a>(storage: &dyn Storage, manager: &'a ConcurrencyManager, request: &PrewriteRequest) -> TkvResult<WriteResult<'a, PrewriteResponse>> {
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    let mut errors = vec![];
+    let mut locks = vec![];
+    let mut committed = None;
+    for (i, mutation) in request.mutations.iter().enumerate() {
+        let key = &mutation.key;
+        let kind = WriteKind::from_op(mutation.op());
+        match txn.reader().current_write(key, txn.start_ts)? {
+            Some((_, write)) if write.kind == WriteKind::Rollback => {
+                errors.push(abort(format!("transaction {} is rolled back", txn.start_ts)));
+                continue;
+            },
+            Some((commit_ts, _)) => {
+                committed = Some(commit_ts);
+                continue;
+            },
+            None => (),
+        }
+        // a lock of the same transaction is a retried prewrite.
+        let current = txn.reader().get_lock(key)?;
+        if request.is_pessimistic_lock.get(i).copied().unwrap_or(false) {
+            // the conflicts were checked when the key was locked.
+            if current.as_ref().is_none_or(|lock| lock.ts != txn.start_ts) {
+                errors.push(abort(format!("pessimistic lock of transaction {} on {:?} not found", txn.start_ts, key)));
+            }
+        } else if let Some((commit_ts, _)) = txn.reader().most_recent_write(key)?.filter(|(commit_ts, _)| *commit_ts >= txn.start_ts) {
+            let conflict = WriteConflict {
+                start_ts: txn.start_ts,
+                conflict_ts: commit_ts,
+                key: key.clone(),
+                primary: request.primary_lock.clone(),
+            };
+            errors.push(KeyError { conflict: Some(conflict), ..Default::default() });
+        } else if let Some(lock) = current.filter(|lock| lock.ts != txn.start_ts) {
+            errors.push(KeyError { locked: Some(lock.info(key)), ..Default::default() });
+        }
+        if !errors.is_empty() {
+            continue;
+        }
+        let mut lock = Lock::new(request.primary_lock.clone(), txn.start_ts, request.lock_ttl, kind);
+        if request.use_async_commit && *key == request.primary_lock {
+            lock.secondaries = request.secondaries.clone();
+        }
+        locks.push((key.clone(), lock));
+        if kind == WriteKind::Put {
+            txn.put_value(key, mutation.value.clone());
+        }
+    }
+    if !errors.is_empty() {
+        return Ok(PrewriteResponse { errors, ..Default::default() }.into());
+    }
+    if let Some(commit_ts) = committed.filter(|_| locks.is_empty()) {
+        let one_pc_commit_ts = if request.try_one_pc { commit_ts } else { 0 };
+        return Ok(PrewriteResponse { one_pc_commit_ts, ..Default::default() }.into());
+    }
+
+    let mut response = PrewriteResponse::default();
+    let memory_locks = (request.use_async_commit || request.try_one_pc).then(|| manager.lock_keys(locks.clone()));
+    if memory_locks.is_some() {
+        let min_commit_ts = manager.max_ts().max(txn.start_ts) + 1;
+        // past max_commit_ts, the transaction falls back to two phase commit.
+        if request.max_commit_ts == 0 || min_commit_ts <= request.max_commit_ts {
+            if request.try_one_pc {
+                response.one_pc_commit_ts = min_commit_ts;
+            } else {
+                response.min_commit_ts = min_commit_ts;
+            }
+        }
+    }
+    for (key, mut lock) in locks {
+        if response.one_pc_commit_ts != 0 {
+            put_commit(&mut txn, &key, response.one_pc_commit_ts, lock.kind)?;
+            // drops the pessimistic lock of the key, if any.
+            txn.delete_lock(&key);
+            continue;
+        }
+        if response.min_commit_ts != 0 {
+            lock.use_async_commit = true;
+            lock.min_commit_ts = response.min_commit_ts;
+        } else {
+            lock.secondaries.clear();
+        }
+        txn.put_lock(&key, &lock)?;
+    }
+    Ok(WriteResult { response, mutations: txn.into_mutations(), memory_locks })
+}
+
+// Locks the keys for a pessimistic transaction, if none of them was written
+// after for_update_ts. Nothing is locked if any key is locked by another
+// transaction, the caller may wait for it and retry.
+pub fn pessimistic_lock(storage: &dyn Storage, request: &PessimisticLockRequest) -> TkvResult<WriteResult<'static, PessimisticLockResponse>> {
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    let failed = |error| Ok(PessimisticLockResponse { errors: vec![error], ..Default::default() }.into());
+    let mut values = vec![];
+    for key in &request.keys {
+        match txn.reader().get_lock(key)? {
+            // locked again by a later statement.
+            Some(mut lock) if lock.ts == txn.start_ts => {
+                if lock.pessimistic && lock.for_update_ts < request.for_update_ts {
+                    lock.for_update_ts = request.for_update_ts;
+                    txn.put_lock(key, &lock)?;
+                }
+            },
+            Some(lock) => return failed(KeyError { locked: Some(lock.info(key)), ..Default::default() }),
+            None => {
+                if let Some((commit_ts, _)) = txn.reader().most_recent_commit(key)?.filter(|(commit_ts, _)| *commit_ts > request.for_update_ts) {
+                    let conflict = WriteConflict {
+                        start_ts: txn.start_ts,
+                        conflict_ts: commit_ts,
+                        key: key.clone(),
+                        primary: request.primary_lock.clone(),
+                    };
+                    return failed(KeyError { conflict: Some(conflict), ..Default::default() });
+                }
+                if txn.reader().current_write(key, txn.start_ts)?.is_some() {
+                    return failed(abort(format!("transaction {} is already committed or rolled back", txn.start_ts)));
+                }
+                let lock = Lock {
+                    pessimistic: true,
+                    for_update_ts: request.for_update_ts,
+                    ..Lock::new(request.primary_lock.clone(), txn.start_ts, request.lock_ttl, WriteKind::Lock)
+                };
+                txn.put_lock(key, &lock)?;
+            },
+        }
+        if request.return_values {
+            values.push(txn.reader().get_value(key, request.for_update_ts)?.unwrap_or_default());
+        }
+    }
+    Ok(WriteResult::new(PessimisticLockResponse { values, ..Default::default() }, txn.into_mutations()))
+}
+
+// Releases the pessimistic locks of the transaction taken up to for_update_ts.
+pub fn pessimistic_rollback(storage: &dyn Storage, request: &PessimisticRollbackRequest) -> TkvResult<WriteResult<'static, PessimisticRollbackResponse>> {
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    for key in &request.keys {
+        let lock = txn.reader().get_lock(key)?;
+        if lock.is_some_and(|lock| lock.ts == txn.start_ts && lock.pessimistic && lock.for_update_ts <= request.for_update_ts) {
+            txn.delete_lock(key);
+        }
+    }
+    Ok(WriteResult::new(PessimisticRollbackResponse::default(), txn.into_mutations()))
+}
+
+// Turns the locks of the transaction into writes at commit_version.
+// Committing keys that are already committed is a no-op.
+pub fn commit(storage: &dyn Storage, request: &CommitRequest) -> TkvResult<WriteResult<'static, CommitResponse>> {
+    if request.commit_version <= request.start_version {
+        let error = abort(format!("commit_ts {} is not after start_ts {}", request.commit_version, request.start_version));
+        return Ok(CommitResponse { error: Some(error), ..Default::default() }.into());
+    }
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    for key in &request.keys {
+        match txn.reader().get_lock(key)? {
+            Some(lock) if lock.ts == txn.start_ts && lock.pessimistic => {
+                let error = abort(format!("key {:?} of transaction {} is not prewritten", key, txn.start_ts));
+                return Ok(CommitResponse { error: Some(error), ..Default::default() }.into());
+            },
+            Some(lock) if lock.ts == txn.start_ts && lock.min_commit_ts > request.commit_version => {
+                let error = abort(format!("commit_ts {} is before min_commit_ts {}", request.commit_version, lock.min_commit_ts));
+                return Ok(CommitResponse { error: Some(error), ..Default::default() }.into());
+            },
+            Some(lock) if lock.ts == txn.start_ts => {
+                put_commit(&mut txn, key, request.commit_version, lock.kind)?;
+                txn.delete_lock(key);
+            },
+            lock => {
+                let error = match txn.reader().current_write(key, txn.start_ts)? {
+                    Some((_, write)) if write.kind != WriteKind::Rollback => continue,
+                    Some(_) => abort(format!("transaction {} is rolled back", txn.start_ts)),
+                    None if lock.is_some() => KeyError { retryable: format!("key {:?} is locked by another transaction", key), ..Default::default() },
+                    None => abort(format!("lock of transaction {} on {:?} not found", txn.start_ts, key)),
+                };
+                return Ok(CommitResponse { error: Some(error), ..Default::default() }.into());
+            },
+        }
+    }
+    Ok(WriteResult::new(CommitResponse::default(), txn.into_mutations()))
+}
+
+// Reports whether the transaction holding the primary lock is committed,
+// rolled back or alive, rolling it back if its lock expired.
+pub fn check_txn_status(storage: &dyn Storage, request: &CheckTxnStatusRequest) -> TkvResult<WriteResult<'static, CheckTxnStatusResponse>> {
+    let mut txn = MvccTxn::new(storage, request.lock_ts);
+    let key = &request.primary_key;
+    let mut response = CheckTxnStatusResponse::default();
+    match txn.reader().get_lock(key)?.filter(|lock| lock.ts == txn.start_ts) {
+        // the transaction may be committed already, see check_secondary_locks.
+        Some(lock) if lock.use_async_commit => {
+            response.lock_ttl = lock.ttl;
+            response.lock_info = Some(lock.info(key));
+        },
+        Some(lock) if lock.is_expired(request.current_ts) => {
+            // the lock is there, the transaction can't be committed.
+            let _ = rollback_key(&mut txn, key)?;
+            response.action = Action::TtlExpireRollback as i32;
+        },
+        Some(lock) => response.lock_ttl = lock.ttl,
+        None => match txn.reader().current_write(key, txn.start_ts)? {
+            Some((commit_ts, write)) if write.kind != WriteKind::Rollback => response.commit_version = commit_ts,
+            Some(_) => (),
+            None => {
+                let _ = rollback_key(&mut txn, key)?;
+                response.action = Action::LockNotExistRollback as i32;
+            },
+        },
+    }
+    Ok(WriteResult::new(response, txn.into_mutations()))
+}
+
+// Reports the locks of an async commit transaction on its secondaries,
+// rolling back the keys that were never prewritten, including the ones
+// only locked pessimistically. It then can't be committed.
+pub fn check_secondary_locks(storage: &dyn Storage, request: &CheckSecondaryLocksRequest) -> TkvResult<WriteResult<'static, CheckSecondaryLocksResponse>> {
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    let mut response = CheckSecondaryLocksResponse::default();
+    for key in &request.keys {
+        if let Some(lock) = txn.reader().get_lock(key)?.filter(|lock| lock.ts == txn.start_ts && !lock.pessimistic) {
+            response.locks.push(lock.info(key));
+            continue;
+        }
+        match txn.reader().current_write(key, txn.start_ts)? {
+            Some((commit_ts, write)) if write.kind != WriteKind::Rollback => {
+                return Ok(CheckSecondaryLocksResponse { commit_ts, ..Default::default() }.into());
+            },
+            // the transaction is rolled back, so are its other keys.
+            _ => {
+                for key in &request.keys {
+                    if let Err(error) = rollback_key(&mut txn, key)? {
+                        return Ok(CheckSecondaryLocksResponse { error: Some(error), ..Default::default() }.into());
+                    }
+                }
+                response.locks.clear();
+                break;
+            },
+        }
+    }
+    Ok(WriteResult::new(response, txn.into_mutations()))
+}
+
+// Rolls back the keys of an uncommitted transaction, nothing is rolled
+// back if any of them is committed.
+pub fn batch_rollback(storage: &dyn Storage, request: &BatchRollbackRequest) -> TkvResult<WriteResult<'static, BatchRollbackResponse>> {
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    for key in &request.keys {
+        if let Err(error) = rollback_key(&mut txn, key)? {
+            return Ok(BatchRollbackResponse { error: Some(error), ..Default::default() }.into());
+        }
+    }
+    Ok(WriteResult::new(BatchRollbackResponse::default(), txn.into_mutations()))
+}
+
+// The keys locked by the transaction started at start_ts, their encoded keys in [start, end).
+pub fn txn_locks(storage: &dyn Storage, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>, start_ts: u64) -> TkvResult<Vec<Vec<u8>>> {
+    let locks = MvccReader::new(storage).scan_locks(start, end, start_ts)?;
+    Ok(locks.into_iter().map(|(key, _)| key).collect())
+}
+
+// Commits or rolls back the locks of a transaction on keys, found by txn_locks.
+pub fn resolve_lock(storage: &dyn Storage, request: &ResolveLockRequest, keys: Vec<Vec<u8>>) -> TkvResult<WriteResult<'static, ResolveLockResponse>> {
+    if keys.is_empty() {
+        return Ok(ResolveLockResponse::default().into());
+    }
+    if request.commit_version == 0 {
+        let request = BatchRollbackRequest { start_version: request.start_version, keys, ..Default::default() };
+        let result = batch_rollback(storage, &request)?;
+        let response = ResolveLockResponse { error: result.response.error, ..Default::default() };
+        return Ok(WriteResult::new(response, result.mutations));
+    }
+    // the pessimistic locks left were never prewritten, they are released.
+    let mut txn = MvccTxn::new(storage, request.start_version);
+    let mut prewritten = vec![];
+    for key in keys {
+        match txn.reader().get_lock(&key)? {
+            Some(lock) if lock.ts == txn.start_ts && lock.pessimistic => txn.delete_lock(&key),
+            _ => prewritten.push(key),
+        }
+    }
+    let request = CommitRequest { start_version: request.start_version, keys: prewritten, commit_version: request.commit_version, ..Default::default() };
+    let result = commit(storage, &request)?;
+    let mut mutations = txn.into_mutations();
+    mutations.extend(result.mutations);
+    Ok(WriteResult::new(ResolveLockResponse { error: result.response.error, ..Default::default() }, mutations))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::transaction::concurrency::ConcurrencyManager;
+    use crate::proto::kvpb::{Action, BatchRollbackRequest, CheckSecondaryLocksRequest, CheckTxnStatusRequest, CommitRequest, GetRequest, Mutation, Op, PessimisticLockRequest, PessimisticRollbackRequest, PrewriteRequest, ResolveLockRequest, ScanRequest, ScanResponse};
+    use crate::scheduler::tso::compose_ts;
+
+    use super::{encode_bytes, batch_rollback, check_secondary_locks, check_txn_status, commit, get, pessimistic_lock, pessimistic_rollback, prewrite, resolve_lock, scan, txn_locks};
+
+    fn put(key: &[u8], value: &[u8]) -> Mutation {
+        Mutation { op: Op::Put as i32, key: key.to_vec(), value: value.to_vec() }
+    }
+
+    fn prewrite_request(mutations: Vec<Mutation>, start_version: u64) -> PrewriteRequest {
+        let primary_lock = mutations[0].key.clone();
+        PrewriteRequest { mutations, primary_lock, start_version, lock_ttl: 3000, ..Default::default() }
+    }
+
+    fn commit_request(keys: &[&[u8]], start_version: u64, commit_version: u64) -> CommitRequest {
+        let keys = keys.iter().map(|key| key.to_vec()).collect();
+        CommitRequest { keys, start_version, commit_version, ..Default::default() }
+    }
+
+    fn get_request(key: &[u8], version: u64) -> GetRequest {
+        GetRequest { key: key.to_vec(), version, ..Default::default() }
+    }
+
+    #[test]
+    fn two_phase_commit() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        let response = prewrite(&storage, &manager, &prewrite_request(vec![put(b"a", b"1"), put(b"b", b"2")], 10))?.write(&storage)?;
+        assert!(response.errors.is_empty());
+
+        // readers after start_ts wait for the locks, readers before do not.
+        let response = get(&storage, &manager, &get_request(b"a", 15))?;
+        assert_eq!(response.error.and_then(|err| err.locked).map(|lock| (lock.primary_lock, lock.lock_version)), Some((b"a".to_vec(), 10)));
+        assert!(get(&storage, &manager, &get_request(b"a", 5))?.not_found);
+
+        // another transaction can't lock the keys.
+        let response = prewrite(&storage, &manager, &prewrite_request(vec![put(b"b", b"3")], 12))?.write(&storage)?;
+        assert!(response.errors[0].locked.is_some());
+
+        assert_eq!(commit(&storage, &commit_request(&[b"a", b"b"], 10, 20))?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"a", 20))?.value, b"1".to_vec());
+        assert!(get(&storage, &manager, &get_request(b"b", 19))?.not_found);
+        // commits are idempotent.
+        assert_eq!(commit(&storage, &commit_request(&[b"a"], 10, 20))?.write(&storage)?.error, None);
+
+        // a transaction started before the commit conflicts with it.
+        let response = prewrite(&storage, &manager, &prewrite_request(vec![put(b"c", b"4"), put(b"b", b"4")], 15))?.write(&storage)?;
+        let conflict = response.errors[0].conflict.clone().unwrap();
+        assert_eq!((conflict.start_ts, conflict.conflict_ts, conflict.key), (15, 20, b"b".to_vec()));
+        assert!(get(&storage, &manager, &get_request(b"c", 30))?.not_found);
+
+        let delete = Mutation { op: Op::Del as i32, key: b"a".to_vec(), value: vec![] };
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![delete], 30))?.write(&storage)?.errors.is_empty());
+        assert_eq!(commit(&storage, &commit_request(&[b"a"], 30, 40))?.write(&storage)?.error, None);
+        assert!(get(&storage, &manager, &get_request(b"a", 40))?.not_found);
+        assert_eq!(get(&storage, &manager, &get_request(b"a", 39))?.value, b"1".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn commit_errors() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        assert!(commit(&storage, &commit_request(&[b"a"], 10, 20))?.write(&storage)?.error.unwrap().abort.contains("not found"));
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"a", b"1")], 10))?.write(&storage)?.errors.is_empty());
+        assert!(commit(&storage, &commit_request(&[b"a"], 10, 10))?.write(&storage)?.error.is_some());
+        assert!(!commit(&storage, &commit_request(&[b"a"], 5, 20))?.write(&storage)?.error.unwrap().retryable.is_empty());
+        // a retried prewrite keeps the lock.
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"a", b"1")], 10))?.write(&storage)?.errors.is_empty());
+        assert_eq!(commit(&storage, &commit_request(&[b"a"], 10, 20))?.write(&storage)?.error, None);
+        // once committed, it succeeds without locking the key again.
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"a", b"1")], 10))?.write(&storage)?.errors.is_empty());
+        assert_eq!(get(&storage, &manager, &get_request(b"a", 30))?.value, b"1".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn scan_versions() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        let delete = |key: &[u8]| Mutation { op: Op::Del as i32, key: key.to_vec(), value: vec![] };
+        let write = |mutations: Vec<Mutation>, start_version, commit_version| -> TkvResult<()> {
+            let keys: Vec<Vec<u8>> = mutations.iter().map(|mutation| mutation.key.clone()).collect();
+            assert!(prewrite(&storage, &manager, &prewrite_request(mutations, start_version))?.write(&storage)?.errors.is_empty());
+            if let Some(commit_version) = commit_version {
+                let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+                assert_eq!(commit(&storage, &commit_request(&keys, start_version, commit_version))?.write(&storage)?.error, None);
+            }
+            Ok(())
+        };
+        write(vec![put(b"a", b"1"), put(b"b", b"1"), put(b"c", b"1"), put(b"c\0", b"1")], 10, Some(20))?;
+        write(vec![put(b"a", b"2"), delete(b"b")], 30, Some(40))?;
+        // pending, c is locked and d only exists in the lock column family.
+        write(vec![put(b"c", b"3"), put(b"d", b"3")], 50, None)?;
+
+        let request = |start_key: &[u8], limit, version| ScanRequest { start_key: start_key.to_vec(), limit, version, ..Default::default() };
+        let pairs = |response: ScanResponse| -> Vec<(Vec<u8>, Vec<u8>, bool)> {
+            response.pairs.into_iter().map(|pair| (pair.key, pair.value, pair.error.is_some())).collect()
+        };
+        let expected = |items: &[(&[u8], &[u8], bool)]| -> Vec<(Vec<u8>, Vec<u8>, bool)> {
+            items.iter().map(|(key, value, locked)| (key.to_vec(), value.to_vec(), *locked)).collect()
+        };
+
+        assert_eq!(pairs(scan(&storage, &manager, &request(b"", 0, 5), Bound::Unbounded)?), vec![]);
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"", 0, 25), Bound::Unbounded)?),
+            expected(&[(b"a", b"1", false), (b"b", b"1", false), (b"c", b"1", false), (b"c\0", b"1", false)]),
+        );
+        // b is deleted, c and d are locked at 60.
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"", 0, 60), Bound::Unbounded)?),
+            expected(&[(b"a", b"2", false), (b"c", b"", true), (b"c\0", b"1", false), (b"d", b"", true)]),
+        );
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"b", 2, 45), Bound::Unbounded)?),
+            expected(&[(b"c", b"1", false), (b"c\0", b"1", false)]),
+        );
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"a", 0, 45), Bound::Excluded(encode_bytes(b"c\0")))?),
+            expected(&[(b"a", b"2", false), (b"c", b"1", false)]),
+        );
+
+        // the cursors move past the newer versions of keys longer than a group.
+        write(vec![put(b"long_key_1", b"1"), put(b"long_key_2", b"1")], 70, Some(80))?;
+        write(vec![put(b"long_key_1", b"2"), delete(b"long_key_2")], 90, Some(100))?;
+        write(vec![put(b"long_key_1", b"3"), put(b"long_key_2", b"3")], 110, Some(120))?;
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"long", 0, 85), Bound::Unbounded)?),
+            expected(&[(b"long_key_1", b"1", false), (b"long_key_2", b"1", false)]),
+        );
+        assert_eq!(
+            pairs(scan(&storage, &manager, &request(b"long", 0, 105), Bound::Unbounded)?),
+            expected(&[(b"long_key_1", b"2", false)]),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn clean_up_locks() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        let ts = |ms| compose_ts(ms, 0);
+        let check = |primary: &[u8], lock_ts, current_ts| {
+            let request = CheckTxnStatusRequest { primary_key: primary.to_vec(), lock_ts, current_ts, ..Default::default() };
+            check_txn_status(&storage, &request)?.write(&storage).map(|response| (response.lock_ttl, response.commit_version, response.action()))
+        };
+        // a, b and c are locked by a transaction at 1000ms with a ttl of 3000ms.
+        let mutations = vec![put(b"a", b"1"), put(b"b", b"1"), put(b"c", b"1")];
+        assert!(prewrite(&storage, &manager, &prewrite_request(mutations, ts(1000)))?.write(&storage)?.errors.is_empty());
+
+        assert_eq!(check(b"a", ts(1000), ts(2000))?, (3000, 0, Action::NoAction));
+        assert_eq!(check(b"a", ts(1000), ts(5000))?, (0, 0, Action::TtlExpireRollback));
+        assert_eq!(check(b"a", ts(1000), ts(5000))?, (0, 0, Action::NoAction));
+        // the transaction can't commit its primary anymore.
+        assert!(commit(&storage, &commit_request(&[b"a"], ts(1000), ts(6000)))?.write(&storage)?.error.is_some());
+
+        // readers roll back the secondaries.
+        let request = ResolveLockRequest { start_version: ts(1000), commit_version: 0, ..Default::default() };
+        let keys = txn_locks(&storage, Bound::Unbounded, Bound::Excluded(encode_bytes(b"c")), ts(1000))?;
+        assert_eq!(keys, vec![b"b".to_vec()]);
+        assert_eq!(resolve_lock(&storage, &request, keys)?.write(&storage)?.error, None);
+        assert!(get(&storage, &manager, &get_request(b"b", ts(6000)))?.not_found);
+        assert!(get(&storage, &manager, &get_request(b"c", ts(6000)))?.error.is_some());
+        let keys = txn_locks(&storage, Bound::Unbounded, Bound::Unbounded, ts(1000))?;
+        assert_eq!(resolve_lock(&storage, &request, keys)?.write(&storage)?.error, None);
+        assert!(get(&storage, &manager, &get_request(b"c", ts(6000)))?.not_found);
+
+        // a missing primary is rolled back, and stays so.
+        assert_eq!(check(b"d", ts(2000), ts(2500))?, (0, 0, Action::LockNotExistRollback));
+        let response = prewrite(&storage, &manager, &prewrite_request(vec![put(b"d", b"1")], ts(2000)))?.write(&storage)?;
+        assert!(response.errors[0].abort.contains("rolled back"));
+
+        // a committed transaction is reported so, and can't be rolled back.
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"e", b"1"), put(b"f", b"1")], ts(3000)))?.write(&storage)?.errors.is_empty());
+        assert_eq!(commit(&storage, &commit_request(&[b"e"], ts(3000), ts(3500)))?.write(&storage)?.error, None);
+        assert_eq!(check(b"e", ts(3000), ts(9000))?, (0, ts(3500), Action::NoAction));
+        let request = BatchRollbackRequest { start_version: ts(3000), keys: vec![b"f".to_vec(), b"e".to_vec()], ..Default::default() };
+        assert!(batch_rollback(&storage, &request)?.write(&storage)?.error.unwrap().abort.contains("committed"));
+        let request = ResolveLockRequest { start_version: ts(3000), commit_version: ts(3500), ..Default::default() };
+        let keys = txn_locks(&storage, Bound::Unbounded, Bound::Unbounded, ts(3000))?;
+        assert_eq!(resolve_lock(&storage, &request, keys)?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"f", ts(4000)))?.value, b"1".to_vec());
+
+        let request = BatchRollbackRequest { start_version: ts(3000), keys: vec![b"g".to_vec()], ..Default::default() };
+        assert_eq!(batch_rollback(&storage, &request)?.write(&storage)?.error, None);
+        Ok(())
+    }
+
+    #[test]
+    fn async_commit() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        let async_request = |mutations, start_version, secondaries: &[&[u8]]| PrewriteRequest {
+            use_async_commit: true,
+            secondaries: secondaries.iter().map(|key| key.to_vec()).collect(),
+            ..prewrite_request(mutations, start_version)
+        };
+        // a read at 50 pushes the commit of the transaction started at 10 past it.
+        assert!(get(&storage, &manager, &get_request(b"x", 50))?.not_found);
+        let response = prewrite(&storage, &manager, &async_request(vec![put(b"a", b"1")], 10, &[b"b"]))?.write(&storage)?;
+        assert_eq!(response.min_commit_ts, 51);
+        let response = prewrite(&storage, &manager, &async_request(vec![put(b"b", b"1")], 10, &[]))?.write(&storage)?;
+        assert_eq!(response.min_commit_ts, 51);
+
+        // readers below min_commit_ts don't wait for the transaction.
+        assert!(get(&storage, &manager, &get_request(b"a", 50))?.not_found);
+        assert!(get(&storage, &manager, &get_request(b"a", 60))?.error.is_some());
+
+        // the primary expired, the transaction is committed since both keys are locked.
+        let request = CheckTxnStatusRequest { primary_key: b"a".to_vec(), lock_ts: 10, current_ts: u64::MAX, ..Default::default() };
+        let lock = check_txn_status(&storage, &request)?.write(&storage)?.lock_info.unwrap();
+        assert_eq!((lock.use_async_commit, lock.secondaries), (true, vec![b"b".to_vec()]));
+        let request = CheckSecondaryLocksRequest { keys: vec![b"b".to_vec()], start_version: 10, ..Default::default() };
+        let response = check_secondary_locks(&storage, &request)?.write(&storage)?;
+        assert_eq!(response.locks.iter().map(|lock| lock.min_commit_ts).collect::<Vec<_>>(), vec![51]);
+        assert!(commit(&storage, &commit_request(&[b"a", b"b"], 10, 50))?.write(&storage)?.error.is_some());
+        assert_eq!(commit(&storage, &commit_request(&[b"a", b"b"], 10, 51))?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"b", 51))?.value, b"1".to_vec());
+        assert_eq!(check_secondary_locks(&storage, &request)?.write(&storage)?.commit_ts, 51);
+
+        // a secondary that was never prewritten rolls the transaction back.
+        assert!(prewrite(&storage, &manager, &async_request(vec![put(b"c", b"2")], 60, &[b"d", b"e"]))?.write(&storage)?.errors.is_empty());
+        assert!(prewrite(&storage, &manager, &async_request(vec![put(b"d", b"2")], 60, &[]))?.write(&storage)?.errors.is_empty());
+        let request = CheckSecondaryLocksRequest { keys: vec![b"d".to_vec(), b"e".to_vec()], start_version: 60, ..Default::default() };
+        assert!(check_secondary_locks(&storage, &request)?.write(&storage)?.locks.is_empty());
+        assert!(!prewrite(&storage, &manager, &async_request(vec![put(b"e", b"2")], 60, &[]))?.write(&storage)?.errors.is_empty());
+        assert!(get(&storage, &manager, &get_request(b"d", 100))?.not_found);
+
+        // past max_commit_ts, the keys are locked for two phase commit.
+        let request = PrewriteRequest { max_commit_ts: 80, ..async_request(vec![put(b"f", b"3")], 70, &[]) };
+        manager.update_max_ts(90);
+        assert_eq!(prewrite(&storage, &manager, &request)?.write(&storage)?.min_commit_ts, 0);
+        assert!(get(&storage, &manager, &get_request(b"f", 75))?.error.is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn one_phase_commit() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        manager.update_max_ts(20);
+        let request = PrewriteRequest { try_one_pc: true, ..prewrite_request(vec![put(b"a", b"1"), put(b"b", b"2")], 10) };
+        let response = prewrite(&storage, &manager, &request)?.write(&storage)?;
+        assert_eq!((response.errors.len(), response.one_pc_commit_ts), (0, 21));
+        assert!(get(&storage, &manager, &get_request(b"a", 20))?.not_found);
+        assert_eq!(get(&storage, &manager, &get_request(b"b", 21))?.value, b"2".to_vec());
+        let request = ScanRequest { version: 30, ..Default::default() };
+        assert_eq!(scan(&storage, &manager, &request, Bound::Unbounded)?.pairs.len(), 2);
+
+        // a retried prewrite reports the commit, and writes nothing.
+        manager.update_max_ts(40);
+        let request = PrewriteRequest { try_one_pc: true, ..prewrite_request(vec![put(b"a", b"1"), put(b"b", b"2")], 10) };
+        let result = prewrite(&storage, &manager, &request)?;
+        assert!(result.mutations.is_empty());
+        assert_eq!((result.response.errors.len(), result.response.one_pc_commit_ts), (0, 21));
+        Ok(())
+    }
+
+    #[test]
+    fn overlapped_rollback() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        let check = |primary: &[u8], lock_ts| {
+            let request = CheckTxnStatusRequest { primary_key: primary.to_vec(), lock_ts, current_ts: u64::MAX, ..Default::default() };
+            check_txn_status(&storage, &request)?.write(&storage).map(|response| (response.commit_version, response.action()))
+        };
+        // a 1PC transaction commits at the start_ts of a transaction rolled back later.
+        manager.update_max_ts(19);
+        let request = PrewriteRequest { try_one_pc: true, ..prewrite_request(vec![put(b"a", b"1")], 10) };
+        assert_eq!(prewrite(&storage, &manager, &request)?.write(&storage)?.one_pc_commit_ts, 20);
+        let request = BatchRollbackRequest { start_version: 20, keys: vec![b"a".to_vec()], ..Default::default() };
+        assert_eq!(batch_rollback(&storage, &request)?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"a", 20))?.value, b"1".to_vec());
+        assert_eq!(check(b"a", 10)?, (20, Action::NoAction));
+        assert_eq!(check(b"a", 20)?, (0, Action::NoAction));
+        assert!(commit(&storage, &commit_request(&[b"a"], 20, 30))?.write(&storage)?.error.unwrap().abort.contains("rolled back"));
+
+        // a transaction commits at the start_ts of a transaction rolled back before.
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"b", b"2")], 30))?.write(&storage)?.errors.is_empty());
+        assert_eq!(check(b"b", 40)?, (0, Action::LockNotExistRollback));
+        assert_eq!(commit(&storage, &commit_request(&[b"b"], 30, 40))?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"b", 40))?.value, b"2".to_vec());
+        assert_eq!(check(b"b", 40)?, (0, Action::NoAction));
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"b", b"3")], 40))?.write(&storage)?.errors[0].abort.contains("rolled back"));
+        Ok(())
+    }
+
+    #[test]
+    fn pessimistic_locks() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let manager = ConcurrencyManager::default();
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"a", b"1")], 10))?.write(&storage)?.errors.is_empty());
+        assert_eq!(commit(&storage, &commit_request(&[b"a"], 10, 20))?.write(&storage)?.error, None);
+
+        let lock_request = |keys: &[&[u8]], start_version, for_update_ts| PessimisticLockRequest {
+            keys: keys.iter().map(|key| key.to_vec()).collect(),
+            primary_lock: b"a".to_vec(),
+            start_version,
+            lock_ttl: 3000,
+            for_update_ts,
+            return_values: true,
+            ..Default::default()
+        };
+        // a transaction started before the last commit locks the keys at a later for_update_ts.
+        let conflict = pessimistic_lock(&storage, &lock_request(&[b"a"], 15, 15))?.write(&storage)?.errors[0].conflict.clone().unwrap();
+        assert_eq!((conflict.start_ts, conflict.conflict_ts), (15, 20));
+        let response = pessimistic_lock(&storage, &lock_request(&[b"a", b"b"], 15, 25))?.write(&storage)?;
+        assert_eq!((response.errors.len(), response.values), (0, vec![b"1".to_vec(), vec![]]));
+
+        // the locks don't block readers, but they block other transactions.
+        assert_eq!(get(&storage, &manager, &get_request(b"a", 30))?.value, b"1".to_vec());
+        assert!(pessimistic_lock(&storage, &lock_request(&[b"b"], 30, 30))?.write(&storage)?.errors[0].locked.is_some());
+        assert!(prewrite(&storage, &manager, &prewrite_request(vec![put(b"b", b"3")], 30))?.write(&storage)?.errors[0].locked.is_some());
+        assert!(commit(&storage, &commit_request(&[b"a"], 15, 40))?.write(&storage)?.error.unwrap().abort.contains("not prewritten"));
+
+        // a statement rolled back releases its locks only.
+        let rollback = PessimisticRollbackRequest { start_version: 15, for_update_ts: 25, keys: vec![b"b".to_vec()], ..Default::default() };
+        pessimistic_rollback(&storage, &rollback)?.write(&storage)?;
+        assert!(pessimistic_lock(&storage, &lock_request(&[b"b"], 15, 26))?.write(&storage)?.errors.is_empty());
+        let rollback = PessimisticRollbackRequest { for_update_ts: 25, ..rollback };
+        pessimistic_rollback(&storage, &rollback)?.write(&storage)?;
+
+        // the prewrite turns the pessimistic locks into regular ones.
+        let mut request = prewrite_request(vec![put(b"a", b"2"), put(b"b", b"2"), put(b"c", b"2")], 15);
+        request.is_pessimistic_lock = vec![true, true, false];
+        assert!(prewrite(&storage, &manager, &request)?.write(&storage)?.errors.is_empty());
+        assert!(get(&storage, &manager, &get_request(b"b", 30))?.error.is_some());
+        assert_eq!(commit(&storage, &commit_request(&[b"a", b"b", b"c"], 15, 40))?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"b", 40))?.value, b"2".to_vec());
+
+        // without its pessimistic lock, the key can't be prewritten.
+        request.start_version = 50;
+        assert!(prewrite(&storage, &manager, &request)?.write(&storage)?.errors[0].abort.contains("not found"));
+
+        // a secondary still locked pessimistically rolls the transaction back.
+        assert!(pessimistic_lock(&storage, &lock_request(&[b"d"], 60, 60))?.write(&storage)?.errors.is_empty());
+        let request = CheckSecondaryLocksRequest { keys: vec![b"d".to_vec()], start_version: 60, ..Default::default() };
+        let response = check_secondary_locks(&storage, &request)?.write(&storage)?;
+        assert_eq!((response.locks.len(), response.commit_ts), (0, 0));
+        assert!(pessimistic_lock(&storage, &lock_request(&[b"d"], 70, 70))?.write(&storage)?.errors.is_empty());
+
+        // resolving a committed transaction releases its pessimistic locks.
+        assert!(pessimistic_lock(&storage, &lock_request(&[b"g", b"h"], 80, 80))?.write(&storage)?.errors.is_empty());
+        let request = PrewriteRequest { is_pessimistic_lock: vec![true], ..prewrite_request(vec![put(b"g", b"4")], 80) };
+        assert!(prewrite(&storage, &manager, &request)?.write(&storage)?.errors.is_empty());
+        let keys = txn_locks(&storage, Bound::Unbounded, Bound::Unbounded, 80)?;
+        assert_eq!(keys, vec![b"g".to_vec(), b"h".to_vec()]);
+        let request = ResolveLockRequest { start_version: 80, commit_version: 90, ..Default::default() };
+        assert_eq!(resolve_lock(&storage, &request, keys)?.write(&storage)?.error, None);
+        assert_eq!(get(&storage, &manager, &get_request(b"g", 90))?.value, b"4".to_vec());
+        assert!(txn_locks(&storage, Bound::Unbounded, Bound::Unbounded, 80)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+use std::{collections::BTreeMap, ops::Bound, sync::atomic::{AtomicU64, Ordering}};
+
+use parking_lot::Mutex;
+
+use crate::proto::kvpb::LockInfo;
+
+use super::mvcc::lock::Lock;
+
+
+/// ConcurrencyManager tracks the largest timestamp read on the store, the
+/// commit version of an async commit or 1PC transaction must be above it.
+/// The locks of such prewrites are published in memory before max_ts is
+/// read, so that a reader either sees them or bumps max_ts first.
+#[derive(Debug, Default)]
+pub struct ConcurrencyManager {
+    max_ts: AtomicU64,
+    memory_locks: Mutex<BTreeMap<Vec<u8>, Lock>>,
+}
+
+/// MemoryLockGuard removes the memory locks once the prewrite is written.
+#[derive(Debug)]
+pub struct MemoryLockGuard<'a> {
+    manager: &'a ConcurrencyManager,
+    keys: Vec<Vec<u8>>,
+}
+
+impl ConcurrencyManager {
+    pub fn update_max_ts(&self, ts: u64) {
+        self.max_ts.fetch_max(ts, Ordering::SeqCst);
+    }
+
+    pub fn max_ts(&self) -> u64 {
+        self.max_ts.load(Ordering::SeqCst)
+    }
+
+    pub fn lock_keys(&self, locks: Vec<(Vec<u8>, Lock)>) -> MemoryLockGuard<'_> {
+        let mut memory_locks = self.memory_locks.lock();
+        let keys = locks.iter().map(|(key, _)| key.clone()).collect();
+        memory_locks.extend(locks);
+        MemoryLockGuard { manager: self, keys }
+    }
+
+    // Bumps max_ts to a read at ts of key, then checks the memory locks.
+    pub fn read_key_check(&self, key: &[u8], ts: u64) -> Option<LockInfo> {
+        self.update_max_ts(ts);
+        let memory_locks = self.memory_locks.lock();
+        memory_locks.get(key).filter(|lock| lock.is_blocking(ts)).map(|lock| lock.info(key))
+    }
+
+    // Same as read_key_check, for the first blocking lock in [start, end).
+    pub fn read_range_check(&self, start: &[u8], end: &Bound<Vec<u8>>, ts: u64) -> Option<LockInfo> {
+        self.update_max_ts(ts);
+        let memory_locks = self.memory_locks.lock();
+        memory_locks.range::<[u8], _>((Bound::Included(start), end.as_ref().map(|end| end.as_slice())))
+            .find(|(_, lock)| lock.is_blocking(ts))
+            .map(|(key, lock)| lock.info(key))
+    }
+}
+
+impl Drop for MemoryLockGuard<'_> {
+    fn drop(&mut self) {
+        let mut memory_locks = self.manager.memory_locks.lock();
+        for key in &self.keys {
+            memory_locks.remove(key);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Bound;
+
+    use crate::kv::transaction::mvcc::{lock::Lock, write::WriteKind};
+
+    use super::ConcurrencyManager;
+
+    #[test]
+    fn memory_locks() {
+        let manager = ConcurrencyManager::default();
+        manager.update_max_ts(10);
+        manager.update_max_ts(5);
+        assert_eq!(manager.max_ts(), 10);
+
+        let guard = manager.lock_keys(vec![(b"b".to_vec(), Lock::new(b"b".to_vec(), 20, 3000, WriteKind::Put))]);
+        assert_eq!(manager.read_key_check(b"b", 15), None);
+        assert_eq!(manager.read_key_check(b"b", 30).map(|lock| lock.lock_version), Some(20));
+        assert_eq!(manager.max_ts(), 30);
+        assert!(manager.read_range_check(b"a", &Bound::Excluded(b"b".to_vec()), 30).is_none());
+        assert!(manager.read_range_check(b"a", &Bound::Unbounded, 30).is_some());
+
+        drop(guard);
+        assert_eq!(manager.read_key_check(b"b", 30), None);
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::hash_map::DefaultHasher, hash::{Hash, Hasher}};
+
+use tokio::sync::{Mutex, MutexGuard};
+
+
+pub const DEFAULT_LATCH_SLOTS: usize = 2048;
+
+/// Latches serialize the transactional commands writing the same keys,
+/// between the reads of a command and the write of its result. Keys are
+/// hashed into a fixed number of slots, so unrelated keys may share a latch.
+#[derive(Debug)]
+pub struct Latches {
+    slots: Vec<Mutex<()>>,
+}
+
+/// LatchGuard holds the latches of a command until it is dropped.
+#[derive(Debug)]
+pub struct LatchGuard<'a> {
+    _guards: Vec<MutexGuard<'a, ()>>,
+}
+
+impl Latches {
+    pub fn new(slot_count: usize) -> Self {
+        Self { slots: (0..slot_count.max(1)).map(|_| Mutex::new(())).collect() }
+    }
+
+    fn slot(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.slots.len() as u64) as usize
+    }
+
+    // Waits for the latches of all the keys. They are taken in the order
+    // of their slots, so two commands never wait for each other.
+    pub async fn acquire<I>(&self, keys: I) -> LatchGuard<'_>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut slots: Vec<usize> = keys.into_iter().map(|key| self.slot(key.as_ref())).collect();
+        slots.sort_unstable();
+        slots.dedup();
+        let mut guards = Vec::with_capacity(slots.len());
+        for slot in slots {
+            guards.push(self.slots[slot].lock().await);
+        }
+        LatchGuard { _guards: guards }
+    }
+}
+
+impl Default for Latches {
+    fn default() -> Self {
+        Self::new(DEFAULT_LATCH_SLOTS)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use super::Latches;
+
+    #[tokio::test]
+    async fn serialize_conflicting_keys() {
+        let latches = Arc::new(Latches::new(256));
+        let guard = latches.acquire([b"b", b"a"]).await;
+
+        // other keys are not blocked.
+        let other = tokio::time::timeout(Duration::from_millis(50), latches.acquire([b"c"])).await;
+        assert!(other.is_ok());
+        drop(other);
+
+        let (acquired_tx, mut acquired_rx) = tokio::sync::oneshot::channel();
+        let waiter = tokio::spawn({
+            let latches = latches.clone();
+            async move {
+                let _guard = latches.acquire([b"a", b"a", b"d"]).await;
+                let _ = acquired_tx.send(());
+            }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(acquired_rx.try_recv().is_err());
+
+        drop(guard);
+        assert!(tokio::time::timeout(Duration::from_secs(1), acquired_rx).await.is_ok());
+        waiter.await.unwrap();
+    }
+}
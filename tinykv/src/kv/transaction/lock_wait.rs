@@ -0,0 +1,72 @@
+use std::{collections::{hash_map::DefaultHasher, HashMap}, hash::{Hash, Hasher}};
+
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+
+// Waits are reported to the deadlock detector by the hash of their key.
+pub fn key_hash(key: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// WaitTable holds the pessimistic lock requests waiting for keys locked
+/// by other transactions. A waiter is registered while the latches of its
+/// keys are held, so it can't miss the release of the lock it waits for.
+#[derive(Debug, Default)]
+pub struct WaitTable {
+    waiters: Mutex<HashMap<Vec<u8>, Vec<oneshot::Sender<()>>>>,
+}
+
+impl WaitTable {
+    // The receiver is notified once the lock on key may be released.
+    pub fn wait_for(&self, key: &[u8]) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        let mut waiters = self.waiters.lock();
+        let queue = waiters.entry(key.to_vec()).or_default();
+        // the waiters which timed out are dropped.
+        queue.retain(|waiter| !waiter.is_closed());
+        queue.push(tx);
+        rx
+    }
+
+    // Wakes all the waiters of the keys, in the order they came. They
+    // retry their requests, those which still find a lock wait again.
+    pub fn wake<I>(&self, keys: I)
+    where
+        I: IntoIterator,
+        I::Item: AsRef<[u8]>,
+    {
+        let mut waiters = self.waiters.lock();
+        for key in keys {
+            for waiter in waiters.remove(key.as_ref()).into_iter().flatten() {
+                let _ = waiter.send(());
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WaitTable;
+
+    #[tokio::test]
+    async fn wake_waiters() {
+        let table = WaitTable::default();
+        let first = table.wait_for(b"a");
+        let second = table.wait_for(b"a");
+        let other = table.wait_for(b"b");
+
+        table.wake([b"a"]);
+        assert!(first.await.is_ok());
+        assert!(second.await.is_ok());
+        assert!(tokio::time::timeout(Duration::from_millis(50), other).await.is_err());
+        // the waiter on b timed out, it is dropped.
+        table.wait_for(b"b");
+        assert_eq!(table.waiters.lock()[&b"b".to_vec()].len(), 1);
+    }
+}
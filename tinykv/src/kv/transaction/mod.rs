@@ -0,0 +1,5 @@
+pub mod commands;
+pub mod concurrency;
+pub mod latches;
+pub mod lock_wait;
+pub mod mvcc;
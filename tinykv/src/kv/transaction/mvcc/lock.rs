@@ -0,0 +1,66 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{kv::error::TkvResult, proto::kvpb::LockInfo, scheduler::tso::extract_physical};
+
+use super::write::WriteKind;
+
+
+/// Lock is taken on a key by a transaction during prewrite, stored in
+/// the Lock column family under the encoded key. It points to the primary
+/// key of the transaction, whose status decides the fate of the lock.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Lock {
+    pub primary: Vec<u8>,
+    // start_ts of the transaction holding the lock.
+    pub ts: u64,
+    // time to live in milliseconds, from the physical part of ts.
+    pub ttl: u64,
+    pub kind: WriteKind,
+    // an async commit transaction is committed once all its locks are
+    // written, at the largest of their min_commit_ts.
+    pub use_async_commit: bool,
+    // the other keys of an async commit transaction, kept on its primary.
+    pub secondaries: Vec<Vec<u8>>,
+    pub min_commit_ts: u64,
+    // taken by KvPessimisticLock before the prewrite, it holds no value.
+    pub pessimistic: bool,
+    pub for_update_ts: u64,
+}
+
+impl Lock {
+    pub fn new(primary: Vec<u8>, ts: u64, ttl: u64, kind: WriteKind) -> Self {
+        Self { primary, ts, ttl, kind, use_async_commit: false, secondaries: vec![], min_commit_ts: 0, pessimistic: false, for_update_ts: 0 }
+    }
+
+    pub fn to_bytes(&self) -> TkvResult<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> TkvResult<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+
+    pub fn info(&self, key: &[u8]) -> LockInfo {
+        LockInfo {
+            primary_lock: self.primary.clone(),
+            lock_version: self.ts,
+            key: key.to_vec(),
+            lock_ttl: self.ttl,
+            use_async_commit: self.use_async_commit,
+            secondaries: self.secondaries.clone(),
+            min_commit_ts: self.min_commit_ts,
+        }
+    }
+
+    // Whether the ttl of the lock is over at current_ts.
+    pub fn is_expired(&self, current_ts: u64) -> bool {
+        extract_physical(self.ts) + self.ttl < extract_physical(current_ts)
+    }
+
+    // Whether the lock blocks a reader at ts, an async commit lock
+    // can't be committed before its min_commit_ts. A pessimistic lock
+    // doesn't block readers, the key is not written yet.
+    pub fn is_blocking(&self, ts: u64) -> bool {
+        !self.pessimistic && self.ts <= ts && self.min_commit_ts <= ts
+    }
+}
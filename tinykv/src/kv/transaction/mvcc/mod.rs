@@ -0,0 +1,296 @@
+pub mod lock;
+pub mod scanner;
+pub mod write;
+
+use std::ops::Bound;
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::kv::{error::{TkvError, TkvResult}, storage::mutation::Mutation, ColumnFamily, Storage};
+
+use self::{lock::Lock, write::{Write, WriteKind}};
+
+
+// Keys of the Default and Write column families are versioned:
+//   encoded key | ^ts
+// the user key is encoded so that no key is the prefix of another, and ts
+// is inverted big endian so that the versions of a key sort newest first.
+// The Lock column family holds at most one lock per key, under the encoded
+// key, so that a key sorts the same in every column family and a region
+// bound between encoded keys keeps a key's lock with its versions.
+const ENC_GROUP_SIZE: usize = 8;
+const ENC_MARKER: u8 = 0xFF;
+
+// Memcomparable encoding: the key is split into groups of 8 bytes, each
+// padded with zeros and followed by 0xFF minus the padding length.
+pub fn encode_bytes(key: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity((key.len() / ENC_GROUP_SIZE + 1) * (ENC_GROUP_SIZE + 1));
+    for group in key.chunks(ENC_GROUP_SIZE).chain(key.len().is_multiple_of(ENC_GROUP_SIZE).then_some(&[][..])) {
+        let pad = ENC_GROUP_SIZE - group.len();
+        encoded.extend(group);
+        encoded.extend(std::iter::repeat_n(0, pad));
+        encoded.push(ENC_MARKER - pad as u8);
+    }
+    encoded
+}
+
+// Decodes a key encoded by encode_bytes, returns it with the bytes following it.
+pub fn decode_bytes(encoded: &[u8]) -> TkvResult<(Vec<u8>, &[u8])> {
+    let mut key = vec![];
+    let mut rest = encoded;
+    loop {
+        if rest.len() < ENC_GROUP_SIZE + 1 {
+            return Err(TkvError::new(format!("invalid encoded key {:?}", encoded)));
+        }
+        let (group, tail) = rest.split_at(ENC_GROUP_SIZE + 1);
+        rest = tail;
+        let pad = (ENC_MARKER - group[ENC_GROUP_SIZE]) as usize;
+        if pad > ENC_GROUP_SIZE {
+            return Err(TkvError::new(format!("invalid encoded key {:?}", encoded)));
+        }
+        key.extend(&group[..ENC_GROUP_SIZE - pad]);
+        if pad > 0 {
+            return Ok((key, rest));
+        }
+    }
+}
+
+pub fn encode_key(key: &[u8], ts: u64) -> Vec<u8> {
+    let mut encoded = encode_bytes(key);
+    let mut ts_bytes = [0u8; 8];
+    BigEndian::write_u64(&mut ts_bytes, !ts);
+    encoded.extend(ts_bytes);
+    encoded
+}
+
+pub fn decode_key(encoded: &[u8]) -> TkvResult<(Vec<u8>, u64)> {
+    let (key, rest) = decode_bytes(encoded)?;
+    if rest.len() != 8 {
+        return Err(TkvError::new(format!("invalid versioned key {:?}", encoded)));
+    }
+    Ok((key, !BigEndian::read_u64(rest)))
+}
+
+
+/// MvccReader reads the versioned data of the transactional API.
+#[derive(Debug, Clone, Copy)]
+pub struct MvccReader<'a> {
+    storage: &'a dyn Storage,
+}
+
+impl<'a> MvccReader<'a> {
+    pub fn new(storage: &'a dyn Storage) -> Self {
+        Self { storage }
+    }
+
+    pub fn get_lock(&self, key: &[u8]) -> TkvResult<Option<Lock>> {
+        match self.storage.get(ColumnFamily::Lock, &encode_bytes(key))? {
+            Some(value) => Lock::from_bytes(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // The locks taken by the transaction started at start_ts on the
+    // encoded keys in [start, end), such as the bounds of a region.
+    pub fn scan_locks(&self, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>, start_ts: u64) -> TkvResult<Vec<(Vec<u8>, Lock)>> {
+        let scanner = self.storage.scan(ColumnFamily::Lock, start, end)?;
+        let mut locks = vec![];
+        for item in scanner.iter() {
+            let (encoded, value) = item?;
+            let lock = Lock::from_bytes(&value)?;
+            if lock.ts == start_ts {
+                locks.push((decode_bytes(&encoded)?.0, lock));
+            }
+        }
+        Ok(locks)
+    }
+
+    // The value of key visible at ts, from the last write committed at
+    // or before ts, rollbacks and locks aside. A write keeps its value
+    // when a rollback overlaps it.
+    pub fn get_value(&self, key: &[u8], ts: u64) -> TkvResult<Option<Vec<u8>>> {
+        let write = self.seek_write(key, ts, |_, write| !matches!(write.kind, WriteKind::Rollback | WriteKind::Lock))?;
+        match write {
+            Some((_, Write { start_ts, kind: WriteKind::Put, .. })) => self.storage.get(ColumnFamily::Default, &encode_key(key, start_ts)),
+            _ => Ok(None),
+        }
+    }
+
+    // The write on key committed at exactly commit_ts.
+    pub fn get_write(&self, key: &[u8], commit_ts: u64) -> TkvResult<Option<Write>> {
+        match self.storage.get(ColumnFamily::Write, &encode_key(key, commit_ts))? {
+            Some(value) => Write::from_bytes(&value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // The write of the transaction started at start_ts on key, with its
+    // commit_ts. A rollback overlapped by the write of another transaction
+    // is reported as a rollback.
+    pub fn current_write(&self, key: &[u8], start_ts: u64) -> TkvResult<Option<(u64, Write)>> {
+        let write = self.seek_write(key, u64::MAX, |commit_ts, write| {
+            write.start_ts == start_ts || (commit_ts == start_ts && write.has_overlapped_rollback)
+        })?;
+        Ok(write.map(|(commit_ts, write)| {
+            if write.start_ts == start_ts { (commit_ts, write) } else { (commit_ts, Write::new(start_ts, WriteKind::Rollback)) }
+        }))
+    }
+
+    // The last write on key, with its commit_ts.
+    pub fn most_recent_write(&self, key: &[u8]) -> TkvResult<Option<(u64, Write)>> {
+        self.seek_write(key, u64::MAX, |_, _| true)
+    }
+
+    // The last write committed on key, with its commit_ts.
+    pub fn most_recent_commit(&self, key: &[u8]) -> TkvResult<Option<(u64, Write)>> {
+        self.seek_write(key, u64::MAX, |_, write| write.kind != WriteKind::Rollback)
+    }
+
+    // Walks the writes of key committed at or before ts, newest first,
+    // until one matches.
+    fn seek_write<F>(&self, key: &[u8], ts: u64, mut matches: F) -> TkvResult<Option<(u64, Write)>>
+    where
+        F: FnMut(u64, &Write) -> bool,
+    {
+        let scanner = self.storage.scan(
+            ColumnFamily::Write,
+            Bound::Included(encode_key(key, ts)),
+            Bound::Included(encode_key(key, 0)),
+        )?;
+        for item in scanner.iter() {
+            let (encoded, value) = item?;
+            let (_, commit_ts) = decode_key(&encoded)?;
+            let write = Write::from_bytes(&value)?;
+            if matches(commit_ts, &write) {
+                return Ok(Some((commit_ts, write)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+
+/// MvccTxn collects the writes of a transactional command, to be written
+/// in a single batch once the command is done reading.
+#[derive(Debug)]
+pub struct MvccTxn<'a> {
+    pub start_ts: u64,
+    reader: MvccReader<'a>,
+    mutations: Vec<Mutation>,
+}
+
+impl<'a> MvccTxn<'a> {
+    pub fn new(storage: &'a dyn Storage, start_ts: u64) -> Self {
+        Self { start_ts, reader: MvccReader::new(storage), mutations: vec![] }
+    }
+
+    // Reads the storage, the writes of the txn are not visible yet.
+    pub fn reader(&self) -> MvccReader<'a> {
+        self.reader
+    }
+
+    pub fn put_lock(&mut self, key: &[u8], lock: &Lock) -> TkvResult<()> {
+        self.mutations.push(Mutation::Put { key: encode_bytes(key), value: lock.to_bytes()?, cf: ColumnFamily::Lock });
+        Ok(())
+    }
+
+    pub fn delete_lock(&mut self, key: &[u8]) {
+        self.mutations.push(Mutation::Delete { key: encode_bytes(key), cf: ColumnFamily::Lock });
+    }
+
+    pub fn put_value(&mut self, key: &[u8], value: Vec<u8>) {
+        self.mutations.push(Mutation::Put { key: encode_key(key, self.start_ts), value, cf: ColumnFamily::Default });
+    }
+
+    pub fn delete_value(&mut self, key: &[u8]) {
+        self.mutations.push(Mutation::Delete { key: encode_key(key, self.start_ts), cf: ColumnFamily::Default });
+    }
+
+    pub fn put_write(&mut self, key: &[u8], commit_ts: u64, write: &Write) -> TkvResult<()> {
+        self.mutations.push(Mutation::Put { key: encode_key(key, commit_ts), value: write.to_bytes()?, cf: ColumnFamily::Write });
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mutations.is_empty()
+    }
+
+    pub fn into_mutations(self) -> Vec<Mutation> {
+        self.mutations
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::{ColumnFamily, Storage};
+
+    use super::lock::Lock;
+    use super::write::{Write, WriteKind};
+    use super::{decode_bytes, decode_key, encode_bytes, encode_key, MvccTxn};
+
+    #[test]
+    fn encoding() -> TkvResult<()> {
+        let keys: [&[u8]; 6] = [b"", b"\0", b"a", b"a\0", b"abcdefgh", b"abcdefgh\0"];
+        for key in keys {
+            assert_eq!(decode_bytes(&encode_bytes(key))?, (key.to_vec(), &[][..]));
+            assert_eq!(decode_key(&encode_key(key, 42))?, (key.to_vec(), 42));
+        }
+        // the keys keep their order, and their versions stay together.
+        for pair in keys.windows(2) {
+            assert!(encode_bytes(pair[0]) < encode_bytes(pair[1]));
+            assert!(encode_key(pair[0], 0) < encode_key(pair[1], u64::MAX));
+        }
+        assert!(encode_key(b"a", 2) < encode_key(b"a", 1));
+        assert!(decode_key(&encode_bytes(b"a")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn versions() -> TkvResult<()> {
+        let storage = MemoryStorage::new();
+        let commit = |key: &[u8], start_ts, commit_ts, kind, value: Option<&[u8]>| -> TkvResult<()> {
+            let mut txn = MvccTxn::new(&storage, start_ts);
+            if let Some(value) = value {
+                txn.put_value(key, value.to_vec());
+            }
+            txn.put_write(key, commit_ts, &Write::new(start_ts, kind))?;
+            storage.write(txn.into_mutations())
+        };
+        commit(b"a", 10, 20, WriteKind::Put, Some(b"v1"))?;
+        commit(b"a\0", 25, 26, WriteKind::Put, Some(b"other"))?;
+        commit(b"a", 30, 40, WriteKind::Delete, None)?;
+        commit(b"a", 50, 50, WriteKind::Rollback, None)?;
+        commit(b"a", 60, 70, WriteKind::Put, Some(b"v2"))?;
+
+        let reader = MvccTxn::new(&storage, 0).reader();
+        assert_eq!(reader.get_value(b"a", 15)?, None);
+        assert_eq!(reader.get_value(b"a", 20)?, Some(b"v1".to_vec()));
+        assert_eq!(reader.get_value(b"a", 39)?, Some(b"v1".to_vec()));
+        assert_eq!(reader.get_value(b"a", 55)?, None);
+        assert_eq!(reader.get_value(b"a", 80)?, Some(b"v2".to_vec()));
+        assert_eq!(reader.get_value(b"a\0", 30)?, Some(b"other".to_vec()));
+        assert_eq!(reader.get_value(b"b", 80)?, None);
+
+        assert_eq!(reader.current_write(b"a", 30)?, Some((40, Write::new(30, WriteKind::Delete))));
+        assert_eq!(reader.current_write(b"a", 25)?, None);
+        assert_eq!(reader.most_recent_write(b"a")?, Some((70, Write::new(60, WriteKind::Put))));
+        assert_eq!(reader.most_recent_write(b"b")?, None);
+
+        // locks are kept under the encoded key.
+        let lock = Lock::new(b"a".to_vec(), 80, 3000, WriteKind::Put);
+        let mut txn = MvccTxn::new(&storage, 80);
+        txn.put_lock(b"a", &lock)?;
+        storage.write(txn.into_mutations())?;
+        assert_eq!(reader.get_lock(b"a")?, Some(lock));
+        assert_eq!(reader.get_lock(b"a\0")?, None);
+        assert!(storage.get(ColumnFamily::Lock, &encode_bytes(b"a"))?.is_some());
+        let mut txn = MvccTxn::new(&storage, 80);
+        txn.delete_lock(b"a");
+        storage.write(txn.into_mutations())?;
+        assert_eq!(reader.get_lock(b"a")?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use std::{iter::Peekable, ops::Bound};
+
+use ouroboros::self_referencing;
+
+use crate::{kv::{error::TkvResult, ColumnFamily, KvIterator, Storage, StorageScanner}, proto::kvpb::{KeyError, KvPair}};
+
+use super::{decode_bytes, decode_key, encode_bytes, encode_key, lock::Lock, write::{Write, WriteKind}};
+
+
+/// Scanner iterates over the keys visible at a version, in order. It merges
+/// the keys written in the Write column family with the keys locked in the
+/// Lock column family, a key locked before the version is reported as such.
+/// The end bounds the encoded keys, as the region bounds do.
+/// A cursor is kept open on each column family for the whole scan, they
+/// only move forward, together, one key at a time.
+pub struct Scanner<'a> {
+    writes: Cursor<'a>,
+    locks: Cursor<'a>,
+    values: Cursor<'a>,
+    version: u64,
+    end: Bound<Vec<u8>>,
+    done: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(storage: &'a dyn Storage, start_key: Vec<u8>, end: Bound<Vec<u8>>, version: u64) -> TkvResult<Self> {
+        let start = encode_bytes(&start_key);
+        // the versions of the end key sort after it, up to ts 0.
+        let versions_end = match &end {
+            Bound::Included(end) => Bound::Included([end.as_slice(), &[0xFF; 8]].concat()),
+            end => end.clone(),
+        };
+        Ok(Self {
+            writes: Cursor::open(storage, ColumnFamily::Write, Bound::Included(start.clone()), versions_end.clone())?,
+            locks: Cursor::open(storage, ColumnFamily::Lock, Bound::Included(start.clone()), end.clone())?,
+            values: Cursor::open(storage, ColumnFamily::Default, Bound::Included(start), versions_end)?,
+            version,
+            end,
+            done: false,
+        })
+    }
+
+    fn next_pair(&mut self) -> TkvResult<Option<KvPair>> {
+        loop {
+            // the next encoded key with a write or a lock.
+            let written = match self.writes.peek_key()? {
+                Some(encoded) => Some(encoded_user_key(&encoded)?.to_vec()),
+                None => None,
+            };
+            let locked = self.locks.peek_key()?;
+            let encoded = match (&written, &locked) {
+                (Some(written), Some(locked)) => written.min(locked).clone(),
+                (Some(key), None) | (None, Some(key)) => key.clone(),
+                (None, None) => return Ok(None),
+            };
+            if !before_end(&encoded, &self.end) {
+                return Ok(None);
+            }
+            let (key, _) = decode_bytes(&encoded)?;
+
+            let lock = match locked.filter(|locked| *locked == encoded) {
+                Some(_) => self.locks.next()?.map(|(_, value)| Lock::from_bytes(&value)).transpose()?,
+                None => None,
+            };
+            if let Some(lock) = lock.filter(|lock| lock.is_blocking(self.version)) {
+                self.writes.skip_prefix(&encoded)?;
+                let error = KeyError { locked: Some(lock.info(&key)), ..Default::default() };
+                return Ok(Some(KvPair { error: Some(error), key, value: vec![] }));
+            }
+
+            // the last write committed at or before the version, rollbacks
+            // and locks aside.
+            let mut visible = None;
+            while let Some(versioned) = self.writes.peek_key()?.filter(|versioned| versioned.starts_with(&encoded)) {
+                let Some((_, value)) = self.writes.next()? else { break };
+                let (_, commit_ts) = decode_key(&versioned)?;
+                if commit_ts > self.version {
+                    continue;
+                }
+                let write = Write::from_bytes(&value)?;
+                if !matches!(write.kind, WriteKind::Rollback | WriteKind::Lock) {
+                    visible = Some(write);
+                    break;
+                }
+            }
+            self.writes.skip_prefix(&encoded)?;
+
+            // deleted keys and keys only locked are skipped.
+            if let Some(Write { start_ts, kind: WriteKind::Put, .. }) = visible {
+                if let Some(value) = self.values.seek(&encode_key(&key, start_ts))? {
+                    return Ok(Some(KvPair { error: None, key, value }));
+                }
+            }
+        }
+    }
+}
+
+// Whether the encoded key is within the end bound.
+pub fn before_end(encoded: &[u8], end: &Bound<Vec<u8>>) -> bool {
+    match end {
+        Bound::Included(end) => encoded <= end.as_slice(),
+        Bound::Excluded(end) => encoded < end.as_slice(),
+        Bound::Unbounded => true,
+    }
+}
+
+// The encoded user key of a versioned key.
+fn encoded_user_key(versioned: &[u8]) -> TkvResult<&[u8]> {
+    let (_, ts) = decode_bytes(versioned)?;
+    Ok(&versioned[..versioned.len() - ts.len()])
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = TkvResult<KvPair>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let pair = self.next_pair();
+        if !matches!(pair, Ok(Some(_))) {
+            self.done = true;
+        }
+        pair.transpose()
+    }
+}
+
+
+// A forward cursor over a column family.
+#[self_referencing]
+struct Cursor<'a> {
+    scanner: Box<dyn StorageScanner<'a> + 'a>,
+    #[borrows(scanner)]
+    #[not_covariant]
+    iter: Peekable<Box<dyn KvIterator + 'this>>,
+}
+
+impl<'a> Cursor<'a> {
+    fn open(storage: &'a dyn Storage, cf: ColumnFamily, start: Bound<Vec<u8>>, end: Bound<Vec<u8>>) -> TkvResult<Self> {
+        let scanner = storage.scan(cf, start, end)?;
+        Ok(CursorBuilder { scanner, iter_builder: |scanner| scanner.iter().peekable() }.build())
+    }
+
+    fn peek_key(&mut self) -> TkvResult<Option<Vec<u8>>> {
+        self.with_iter_mut(|iter| match iter.peek() {
+            Some(Ok((key, _))) => Ok(Some(key.clone())),
+            Some(Err(_)) => iter.next().transpose().map(|_| None),
+            None => Ok(None),
+        })
+    }
+
+    fn next(&mut self) -> TkvResult<Option<(Vec<u8>, Vec<u8>)>> {
+        self.with_iter_mut(|iter| iter.next().transpose())
+    }
+
+    // Moves past the keys starting with prefix.
+    fn skip_prefix(&mut self, prefix: &[u8]) -> TkvResult<()> {
+        while self.peek_key()?.is_some_and(|key| key.starts_with(prefix)) {
+            self.next()?;
+        }
+        Ok(())
+    }
+
+    // Moves to the first key at or after key, returns its value if it is key.
+    fn seek(&mut self, key: &[u8]) -> TkvResult<Option<Vec<u8>>> {
+        while self.peek_key()?.is_some_and(|next| next.as_slice() < key) {
+            self.next()?;
+        }
+        match self.peek_key()? {
+            Some(next) if next == key => Ok(self.next()?.map(|(_, value)| value)),
+            _ => Ok(None),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{kv::error::TkvResult, proto::kvpb::Op};
+
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WriteKind {
+    Put,
+    Delete,
+    Rollback,
+    // the key was locked but not changed by the transaction.
+    Lock,
+}
+
+impl WriteKind {
+    pub fn from_op(op: Op) -> Self {
+        match op {
+            Op::Put => WriteKind::Put,
+            Op::Del => WriteKind::Delete,
+            Op::Rollback => WriteKind::Rollback,
+            Op::Lock => WriteKind::Lock,
+        }
+    }
+}
+
+/// Write is the record of a committed or rolled back transaction,
+/// stored in the Write column family at its commit_ts. The value of
+/// a Put is in the Default column family at the write's start_ts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Write {
+    pub start_ts: u64,
+    pub kind: WriteKind,
+    // the transaction started at the commit_ts of this write is rolled
+    // back, its rollback record would have overwritten this one.
+    pub has_overlapped_rollback: bool,
+}
+
+impl Write {
+    pub fn new(start_ts: u64, kind: WriteKind) -> Self {
+        Self { start_ts, kind, has_overlapped_rollback: false }
+    }
+
+    pub fn to_bytes(&self) -> TkvResult<Vec<u8>> {
+        Ok(bincode::serialize(self)?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> TkvResult<Self> {
+        Ok(bincode::deserialize(bytes)?)
+    }
+}
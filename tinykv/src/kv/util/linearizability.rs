@@ -0,0 +1,422 @@
+use std::{collections::{HashMap, HashSet}, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{Duration, Instant}};
+
+use parking_lot::Mutex;
+use tonic::transport::Channel;
+
+use crate::{kv::error::TkvResult, proto::{kvpb::{RawDeleteRequest, RawGetRequest, RawPutRequest}, tinykv::tiny_kv_client::TinyKvClient}};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Input {
+    Get { cf: String, key: Vec<u8> },
+    Put { cf: String, key: Vec<u8>, value: Vec<u8> },
+    Delete { cf: String, key: Vec<u8> },
+}
+
+impl Input {
+    // Operations on different keys are independent, the
+    // history is checked key by key.
+    fn key(&self) -> (&str, &[u8]) {
+        match self {
+            Input::Get { cf, key } | Input::Put { cf, key, .. } | Input::Delete { cf, key } => (cf, key),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Output {
+    Value(Option<Vec<u8>>),
+    Done,
+}
+
+// An operation from its invocation to its completion. An operation that
+// never completed (a timeout, a lost connection) may or may not have taken
+// effect, at any time after it was invoked.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operation {
+    pub client_id: u64,
+    pub input: Input,
+    pub output: Option<Output>,
+    pub call: u64,
+    pub ret: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheckResult {
+    Ok,
+    // no linearization exists for the operations on this key.
+    Illegal { cf: String, key: Vec<u8>, ops: Vec<Operation> },
+    // the check did not finish in time.
+    Unknown,
+}
+
+/// History records the operations of concurrent clients against a store.
+/// Events are ordered by a logical clock shared by all the clients.
+#[derive(Debug, Default)]
+pub struct History {
+    clock: AtomicU64,
+    ops: Mutex<Vec<Operation>>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Records the invocation of an operation, returns its id.
+    pub fn invoke(&self, client_id: u64, input: Input) -> usize {
+        let mut ops = self.ops.lock();
+        let call = self.clock.fetch_add(1, Ordering::SeqCst);
+        ops.push(Operation { client_id, input, output: None, call, ret: None });
+        ops.len() - 1
+    }
+
+    pub fn complete(&self, op_id: usize, output: Output) {
+        let ret = self.clock.fetch_add(1, Ordering::SeqCst);
+        let mut ops = self.ops.lock();
+        ops[op_id].output = Some(output);
+        ops[op_id].ret = Some(ret);
+    }
+
+    pub fn operations(&self) -> Vec<Operation> {
+        self.ops.lock().clone()
+    }
+
+    pub fn check(&self) -> CheckResult {
+        self.check_with_timeout(Duration::MAX)
+    }
+
+    // Checks that the history is linearizable, key by key.
+    pub fn check_with_timeout(&self, timeout: Duration) -> CheckResult {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut partitions: HashMap<(String, Vec<u8>), Vec<Operation>> = HashMap::new();
+        for op in self.operations() {
+            // a read without a result has no effect to check.
+            if op.ret.is_none() && matches!(op.input, Input::Get { .. }) {
+                continue;
+            }
+            let (cf, key) = op.input.key();
+            partitions.entry((cf.to_string(), key.to_vec())).or_default().push(op);
+        }
+
+        for ((cf, key), ops) in partitions {
+            match check_partition(&ops, deadline) {
+                Some(true) => (),
+                Some(false) => return CheckResult::Illegal { cf, key, ops },
+                None => return CheckResult::Unknown,
+            }
+        }
+        CheckResult::Ok
+    }
+}
+
+
+// The model of a single key: its value, if any.
+type State = Option<Vec<u8>>;
+
+// Returns whether the operation can be applied to state, along with the new state.
+fn step(state: &State, input: &Input, output: &Option<Output>) -> (bool, State) {
+    match input {
+        Input::Get { .. } => match output {
+            Some(Output::Value(value)) => (value == state, state.clone()),
+            _ => (true, state.clone()),
+        },
+        Input::Put { value, .. } => (true, Some(value.clone())),
+        Input::Delete { .. } => (true, None),
+    }
+}
+
+// A call or return event in a doubly linked list, so that the
+// calls being linearized can be lifted out and put back in place.
+#[derive(Debug, Clone)]
+struct Node {
+    op: usize,
+    is_call: bool,
+    // the return event of a call.
+    matched: usize,
+    prev: usize,
+    next: Option<usize>,
+}
+
+struct Events {
+    // nodes[0] is the head of the list.
+    nodes: Vec<Node>,
+}
+
+impl Events {
+    fn new(ops: &[Operation]) -> Self {
+        // (time, is_return, op), a pending operation returns after everything else.
+        let mut events: Vec<(u64, bool, usize)> = vec![];
+        for (idx, op) in ops.iter().enumerate() {
+            events.push((op.call, false, idx));
+            events.push((op.ret.unwrap_or(u64::MAX), true, idx));
+        }
+        events.sort();
+
+        let head = Node { op: usize::MAX, is_call: false, matched: 0, prev: 0, next: None };
+        let mut nodes = vec![head];
+        let mut calls = HashMap::new();
+        for (pos, &(_, is_return, op)) in events.iter().enumerate() {
+            let idx = pos + 1;
+            nodes[idx - 1].next = Some(idx);
+            nodes.push(Node { op, is_call: !is_return, matched: 0, prev: idx - 1, next: None });
+            if is_return {
+                let call: usize = calls[&op];
+                nodes[call].matched = idx;
+            } else {
+                calls.insert(op, idx);
+            }
+        }
+        Self { nodes }
+    }
+
+    fn unlink(&mut self, idx: usize) {
+        let Node { prev, next, .. } = self.nodes[idx];
+        self.nodes[prev].next = next;
+        if let Some(next) = next {
+            self.nodes[next].prev = prev;
+        }
+    }
+
+    fn relink(&mut self, idx: usize) {
+        let Node { prev, next, .. } = self.nodes[idx];
+        self.nodes[prev].next = Some(idx);
+        if let Some(next) = next {
+            self.nodes[next].prev = idx;
+        }
+    }
+
+    // Removes a linearized call along with its return.
+    fn lift(&mut self, call: usize) {
+        self.unlink(call);
+        self.unlink(self.nodes[call].matched);
+    }
+
+    fn unlift(&mut self, call: usize) {
+        self.relink(self.nodes[call].matched);
+        self.relink(call);
+    }
+}
+
+// Searches a linearization of the operations of a single key, following
+// the algorithm of Wing & Gong as improved by Lowe: calls are linearized
+// in order until a return is reached whose call is not linearized yet,
+// then the search backtracks. Configurations already explored are cached.
+// Returns None when the deadline is reached.
+fn check_partition(ops: &[Operation], deadline: Option<Instant>) -> Option<bool> {
+    let mut events = Events::new(ops);
+    let mut state: State = None;
+    let mut linearized = vec![0u64; ops.len().div_ceil(64)];
+    let mut cache: HashSet<(Vec<u64>, State)> = HashSet::new();
+    // (call node, state before the call)
+    let mut stack: Vec<(usize, State)> = vec![];
+
+    let mut entry = events.nodes[0].next;
+    while let Some(idx) = entry {
+        if deadline.is_some_and(|deadline| Instant::now() > deadline) {
+            return None;
+        }
+        let node = events.nodes[idx].clone();
+        if node.is_call {
+            let op = &ops[node.op];
+            let (ok, new_state) = step(&state, &op.input, &op.output);
+            if ok {
+                let mut new_linearized = linearized.clone();
+                new_linearized[node.op / 64] |= 1 << (node.op % 64);
+                if cache.insert((new_linearized.clone(), new_state.clone())) {
+                    stack.push((idx, std::mem::replace(&mut state, new_state)));
+                    linearized = new_linearized;
+                    events.lift(idx);
+                    entry = events.nodes[0].next;
+                    continue;
+                }
+            }
+            entry = node.next;
+        } else {
+            // the operation returned before any linearization point was found.
+            let Some((call, prev_state)) = stack.pop() else {
+                return Some(false);
+            };
+            let op = events.nodes[call].op;
+            linearized[op / 64] &= !(1 << (op % 64));
+            state = prev_state;
+            events.unlift(call);
+            entry = events.nodes[call].next;
+        }
+    }
+    Some(true)
+}
+
+
+/// RecordingClient issues raw requests to a TinyKV server and
+/// records them in a history shared with the other clients.
+#[derive(Debug)]
+pub struct RecordingClient {
+    client_id: u64,
+    client: TinyKvClient<Channel>,
+    history: Arc<History>,
+}
+
+impl RecordingClient {
+    pub fn new(client_id: u64, client: TinyKvClient<Channel>, history: Arc<History>) -> Self {
+        Self { client_id, client, history }
+    }
+
+    // A failed request is left pending as it may have taken effect.
+    pub async fn raw_get(&mut self, cf: &str, key: &[u8]) -> TkvResult<Option<Vec<u8>>> {
+        let op_id = self.history.invoke(self.client_id, Input::Get { cf: cf.to_string(), key: key.to_vec() });
+        let request = RawGetRequest { cf: cf.to_string(), key: key.to_vec(), ..Default::default() };
+        let response = self.client.raw_get(request).await?.into_inner();
+        let value = if response.not_found { None } else { Some(response.value) };
+        self.history.complete(op_id, Output::Value(value.clone()));
+        Ok(value)
+    }
+
+    pub async fn raw_put(&mut self, cf: &str, key: &[u8], value: &[u8]) -> TkvResult<()> {
+        let input = Input::Put { cf: cf.to_string(), key: key.to_vec(), value: value.to_vec() };
+        let op_id = self.history.invoke(self.client_id, input);
+        let request = RawPutRequest { cf: cf.to_string(), key: key.to_vec(), value: value.to_vec(), ..Default::default() };
+        self.client.raw_put(request).await?;
+        self.history.complete(op_id, Output::Done);
+        Ok(())
+    }
+
+    pub async fn raw_delete(&mut self, cf: &str, key: &[u8]) -> TkvResult<()> {
+        let op_id = self.history.invoke(self.client_id, Input::Delete { cf: cf.to_string(), key: key.to_vec() });
+        let request = RawDeleteRequest { cf: cf.to_string(), key: key.to_vec(), ..Default::default() };
+        self.client.raw_delete(request).await?;
+        self.history.complete(op_id, Output::Done);
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use rand::Rng;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::server::TinyKvService;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::tinykv::{tiny_kv_client::TinyKvClient, tiny_kv_server::TinyKvServer};
+
+    use super::{CheckResult, History, Input, Output, RecordingClient};
+
+    fn get(key: &[u8]) -> Input {
+        Input::Get { cf: "default".to_string(), key: key.to_vec() }
+    }
+
+    fn put(key: &[u8], value: &[u8]) -> Input {
+        Input::Put { cf: "default".to_string(), key: key.to_vec(), value: value.to_vec() }
+    }
+
+    fn value(value: &[u8]) -> Output {
+        Output::Value(Some(value.to_vec()))
+    }
+
+    #[test]
+    fn sequential() {
+        let history = History::new();
+        let op = history.invoke(0, get(b"a"));
+        history.complete(op, Output::Value(None));
+        let op = history.invoke(0, put(b"a", b"1"));
+        history.complete(op, Output::Done);
+        let op = history.invoke(1, get(b"a"));
+        history.complete(op, value(b"1"));
+        let op = history.invoke(1, Input::Delete { cf: "default".to_string(), key: b"a".to_vec() });
+        history.complete(op, Output::Done);
+        let op = history.invoke(0, get(b"a"));
+        history.complete(op, Output::Value(None));
+        assert_eq!(history.check(), CheckResult::Ok);
+    }
+
+    #[test]
+    fn concurrent() {
+        // the read overlaps both writes, it may see either.
+        let history = History::new();
+        let put1 = history.invoke(0, put(b"a", b"1"));
+        let read = history.invoke(1, get(b"a"));
+        let put2 = history.invoke(2, put(b"a", b"2"));
+        history.complete(put2, Output::Done);
+        history.complete(read, value(b"1"));
+        history.complete(put1, Output::Done);
+        assert_eq!(history.check(), CheckResult::Ok);
+    }
+
+    #[test]
+    fn stale_read() {
+        let history = History::new();
+        let op = history.invoke(0, put(b"a", b"1"));
+        history.complete(op, Output::Done);
+        let op = history.invoke(0, put(b"a", b"2"));
+        history.complete(op, Output::Done);
+        let op = history.invoke(1, get(b"a"));
+        history.complete(op, value(b"1"));
+        // other keys are fine.
+        let op = history.invoke(1, put(b"b", b"1"));
+        history.complete(op, Output::Done);
+        assert!(matches!(history.check(), CheckResult::Illegal { key, .. } if key == b"a".to_vec()));
+    }
+
+    #[test]
+    fn pending_write() {
+        // a write that never completed may take effect at any later time.
+        let history = History::new();
+        history.invoke(0, put(b"a", b"1"));
+        let op = history.invoke(1, get(b"a"));
+        history.complete(op, Output::Value(None));
+        let op = history.invoke(1, get(b"a"));
+        history.complete(op, value(b"1"));
+        assert_eq!(history.check(), CheckResult::Ok);
+
+        // but only once.
+        let op = history.invoke(1, put(b"a", b"2"));
+        history.complete(op, Output::Done);
+        let op = history.invoke(1, get(b"a"));
+        history.complete(op, value(b"1"));
+        assert!(matches!(history.check(), CheckResult::Illegal { .. }));
+    }
+
+    #[tokio::test]
+    async fn check_server_history() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let service = TinyKvService::new(Arc::new(MemoryStorage::new()));
+        let server = tokio::spawn(Server::builder()
+            .add_service(TinyKvServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        let history = Arc::new(History::new());
+        let mut handles = vec![];
+        for client_id in 0..4 {
+            let client = TinyKvClient::connect(format!("http://{}", addr)).await?;
+            let mut client = RecordingClient::new(client_id, client, history.clone());
+            handles.push(tokio::spawn(async move {
+                for i in 0..50u8 {
+                    let (key, action) = {
+                        let mut rng = rand::thread_rng();
+                        ([rng.gen_range(0..3u8)], rng.gen_range(0..3))
+                    };
+                    match action {
+                        0 => { client.raw_get("default", &key).await?; },
+                        1 => client.raw_put("default", &key, &[client_id as u8, i]).await?,
+                        _ => client.raw_delete("default", &key).await?,
+                    }
+                }
+                Ok::<_, crate::kv::error::TkvError>(())
+            }));
+        }
+        for handle in handles {
+            handle.await??;
+        }
+
+        assert_eq!(history.operations().len(), 200);
+        assert_eq!(history.check(), CheckResult::Ok);
+        server.abort();
+        Ok(())
+    }
+}
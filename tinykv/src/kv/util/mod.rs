@@ -1 +1,2 @@
 pub mod cf_iterator;
+pub mod linearizability;
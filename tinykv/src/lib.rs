@@ -1,6 +1,7 @@
 pub mod kv;
 pub mod proto;
 pub mod raft;
+pub mod scheduler;
 mod utils;
 
 
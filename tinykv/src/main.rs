@@ -1,10 +1,12 @@
-use std::sync::Arc;
+use std::{path::Path, sync::Arc, time::Duration};
 
-use anyhow::Result;
-use tinykv::{kv::{server::TinyKvService, storage::standalone::StandaloneStorage}, proto::{self, tinykv::tiny_kv_server::TinyKvServer}};
+use anyhow::{anyhow, Result};
+use tinykv::{kv::{config::Config, error::TkvResult, raftstore::{engine::RaftEngine, heartbeat::HeartbeatWorker, snap::SnapManager, store::RaftStore, transport::{RaftClient, RaftClientConfig, StaticResolver}, IdAllocator, SchedulerIdAllocator}, server::TinyKvService, storage::disk::DiskStorage, transaction::concurrency::ConcurrencyManager, Storage}, proto::{self, metapb::{Peer, Region, RegionEpoch, Store, StoreLabel}, tinykv::tiny_kv_server::TinyKvServer}, scheduler::client::SchedulerClient};
 use tonic::transport::Server;
 
 
+// interval to refresh the addresses of the other stores.
+const RESOLVE_INTERVAL: Duration = Duration::from_secs(10);
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -13,9 +15,43 @@ async fn main() -> Result<()> {
     env_logger::init();
 
 
-    let storage = Arc::new(StandaloneStorage::in_memory());
-    let service = TinyKvService::new(storage);
-    let addr = "127.0.0.1:8080".parse()?;
+    let mut config = Config::default();
+    let mut args = std::env::args().skip(1);
+    if let Some(addr) = args.next() {
+        config.store_addr = addr;
+    }
+    if let Some(addr) = args.next() {
+        config.scheduler_addr = addr;
+    }
+    std::fs::create_dir_all(Path::new(&config.db_path).join("kv"))?;
+    let storage: Arc<dyn Storage> = Arc::new(DiskStorage::new(Path::new(&config.db_path).join("kv")).map_err(|err| anyhow!("{:?}", err))?);
+
+    let client = SchedulerClient::connect(&config.scheduler_addr).map_err(|err| anyhow!("{:?}", err))?;
+    let id_allocator = Arc::new(SchedulerIdAllocator::new(client.clone()).await.map_err(|err| anyhow!("{:?}", err))?);
+    let (store_id, regions) = bootstrap(&config, &storage, &client, id_allocator.as_ref()).await.map_err(|err| anyhow!("{:?}", err))?;
+
+    let snap_manager = Arc::new(SnapManager::new(Path::new(&config.db_path).join("snap"), &config).map_err(|err| anyhow!("{:?}", err))?);
+    let resolver = Arc::new(StaticResolver::default());
+    resolver.insert(store_id, config.store_addr.clone());
+    tokio::spawn(refresh_addresses(client.clone(), resolver.clone()));
+    let transport = RaftClient::new(RaftClientConfig::from_config(&config), resolver, snap_manager.clone());
+
+    let concurrency_manager = Arc::new(ConcurrencyManager::default());
+    let store = RaftStore::new(store_id, &config, storage.clone(), id_allocator, Arc::new(transport), snap_manager.clone(), regions)
+        .map_err(|err| anyhow!("{:?}", err))?
+        .with_scheduler(HeartbeatWorker::new(store_id, &config, client.clone()).with_concurrency_manager(concurrency_manager.clone()));
+    let service = TinyKvService::new(storage)
+        .with_raft_router(store.sender())
+        .with_snap_manager(snap_manager)
+        .with_store_meta(store_id, store.meta())
+        .with_deadlock_detector(client)
+        .with_concurrency_manager(concurrency_manager);
+    tokio::spawn(async move {
+        if let Err(err) = store.run().await {
+            log::error!("raft store {} stopped: {:?}", store_id, err);
+        }
+    });
+    let addr = config.store_addr.parse()?;
 
     let reflection_service = tonic_reflection::server::Builder::configure()
         .register_encoded_file_descriptor_set(proto::FILE_DESCRIPTOR_SET)
@@ -31,3 +67,44 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+// Returns the id of the store, allocated by the scheduler on its first
+// start. The first store of the cluster also creates the first region,
+// the others get their peers from the scheduler. The stores of a new
+// cluster are expected to start one after the other.
+async fn bootstrap(config: &Config, storage: &Arc<dyn Storage>, client: &SchedulerClient, id_allocator: &dyn IdAllocator) -> TkvResult<(u64, Vec<Region>)> {
+    let engine = RaftEngine::new(storage.clone());
+    if let Some(store_id) = engine.store_id()? {
+        return Ok((store_id, vec![]));
+    }
+    let store_id = id_allocator.alloc_id()?;
+    let mut regions = vec![];
+    let first = client.get_all_stores().await?.is_empty();
+    let labels = config.labels.iter().map(|(key, value)| StoreLabel { key: key.clone(), value: value.clone() }).collect();
+    client.put_store(Store { id: store_id, address: config.store_addr.clone(), labels, ..Default::default() }).await?;
+    if first {
+        regions.push(Region {
+            id: id_allocator.alloc_id()?,
+            start_key: vec![],
+            end_key: vec![],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: vec![Peer { id: id_allocator.alloc_id()?, store_id, ..Default::default() }],
+        });
+    }
+    engine.set_store_id(store_id)?;
+    Ok((store_id, regions))
+}
+
+// Keeps the addresses of the stores registered on the scheduler.
+async fn refresh_addresses(client: SchedulerClient, resolver: Arc<StaticResolver>) {
+    loop {
+        match client.get_all_stores().await {
+            Ok(stores) => {
+                for store in stores {
+                    resolver.insert(store.id, store.address);
+                }
+            },
+            Err(err) => log::warn!("failed to fetch the stores: {:?}", err),
+        }
+        tokio::time::sleep(RESOLVE_INTERVAL).await;
+    }
+}
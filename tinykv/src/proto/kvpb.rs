@@ -1,3 +1,4 @@
+// This file is @generated by prost-build.
 /// Raw commands.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -84,6 +85,306 @@ pub struct RawScanResponse {
     #[prost(message, repeated, tag = "3")]
     pub kvs: ::prost::alloc::vec::Vec<KvPair>,
 }
+/// Read the value of a key at the given time.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "3")]
+    pub version: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, optional, tag = "2")]
+    pub error: ::core::option::Option<KeyError>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub value: ::prost::alloc::vec::Vec<u8>,
+    /// True if the requested key doesn't exist; another error will not be signalled.
+    #[prost(bool, tag = "4")]
+    pub not_found: bool,
+}
+/// Scan fetches the values of consecutive keys, as seen at the given version.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub start_key: ::prost::alloc::vec::Vec<u8>,
+    /// The maximum number of values read.
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+    #[prost(uint64, tag = "4")]
+    pub version: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    /// Other errors are recorded for each key in pairs.
+    #[prost(message, repeated, tag = "2")]
+    pub pairs: ::prost::alloc::vec::Vec<KvPair>,
+}
+/// Prewrite is the first phase of two phase commit. A prewrite contains all the
+/// writes (mutations) which a client would like to make as part of a transaction.
+/// The request succeeds if none of the keys are locked or written since the
+/// transaction started, all those keys are then locked. If the prewrite fails,
+/// no changes are made to the DB.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrewriteRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(message, repeated, tag = "2")]
+    pub mutations: ::prost::alloc::vec::Vec<Mutation>,
+    /// Key of the primary lock.
+    #[prost(bytes = "vec", tag = "3")]
+    pub primary_lock: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "4")]
+    pub start_version: u64,
+    #[prost(uint64, tag = "5")]
+    pub lock_ttl: u64,
+    /// The transaction counts as committed once all its keys are prewritten,
+    /// its commit version is the largest min_commit_ts of the prewrites.
+    #[prost(bool, tag = "6")]
+    pub use_async_commit: bool,
+    /// The other keys of an async commit transaction, set on the primary's prewrite.
+    #[prost(bytes = "vec", repeated, tag = "7")]
+    pub secondaries: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    /// All the keys of the transaction are in this request, they are committed
+    /// right away instead of locked.
+    #[prost(bool, tag = "8")]
+    pub try_one_pc: bool,
+    /// Async commit and 1PC fall back to two phase commit if the commit
+    /// version would exceed it, 0 if there is no such bound.
+    #[prost(uint64, tag = "9")]
+    pub max_commit_ts: u64,
+    /// For a pessimistic transaction, whether each mutation's key was locked
+    /// by KvPessimisticLock. Such keys are not checked for write conflicts again.
+    #[prost(bool, repeated, tag = "10")]
+    pub is_pessimistic_lock: ::prost::alloc::vec::Vec<bool>,
+}
+/// Errors is empty if the prewrite is successful.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PrewriteResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, repeated, tag = "2")]
+    pub errors: ::prost::alloc::vec::Vec<KeyError>,
+    /// Set if the keys are locked for an async commit.
+    #[prost(uint64, tag = "3")]
+    pub min_commit_ts: u64,
+    /// Set if the keys were committed by 1PC.
+    #[prost(uint64, tag = "4")]
+    pub one_pc_commit_ts: u64,
+}
+/// Commit is the second phase of two phase commit. The client must have
+/// successfully prewritten the transaction to all nodes. If all the keys are
+/// locked by the given transaction the commit succeeds. If any key is locked
+/// by a different transaction or is not locked at all (rolled back or
+/// expired), the commit fails.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommitRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    /// Identifies the transaction, must match the start_version of its prewrite.
+    #[prost(uint64, tag = "2")]
+    pub start_version: u64,
+    /// Must match the keys mutated by the transaction's prewrite.
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    /// Must be greater than start_version.
+    #[prost(uint64, tag = "4")]
+    pub commit_version: u64,
+}
+/// Empty if the commit is successful.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CommitResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, optional, tag = "2")]
+    pub error: ::core::option::Option<KeyError>,
+}
+/// PessimisticLock locks keys for a pessimistic transaction as its statements
+/// run, before the transaction prewrites them. The keys must not be written
+/// after for_update_ts. A key locked by another transaction is waited for,
+/// up to wait_timeout.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PessimisticLockRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(bytes = "vec", tag = "3")]
+    pub primary_lock: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "4")]
+    pub start_version: u64,
+    #[prost(uint64, tag = "5")]
+    pub lock_ttl: u64,
+    #[prost(uint64, tag = "6")]
+    pub for_update_ts: u64,
+    /// In milliseconds, 0 to fail at once on a locked key.
+    #[prost(uint64, tag = "7")]
+    pub wait_timeout: u64,
+    /// Returns the values of the keys at for_update_ts.
+    #[prost(bool, tag = "8")]
+    pub return_values: bool,
+}
+/// Errors is empty if the keys are locked.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PessimisticLockResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, repeated, tag = "2")]
+    pub errors: ::prost::alloc::vec::Vec<KeyError>,
+    /// In the order of the keys, empty for a missing key.
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub values: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+/// Releases the pessimistic locks taken by a statement that failed, without
+/// rolling the transaction back.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PessimisticRollbackRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(uint64, tag = "2")]
+    pub start_version: u64,
+    #[prost(uint64, tag = "3")]
+    pub for_update_ts: u64,
+    #[prost(bytes = "vec", repeated, tag = "4")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PessimisticRollbackResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, repeated, tag = "2")]
+    pub errors: ::prost::alloc::vec::Vec<KeyError>,
+}
+/// CheckTxnStatus reports on the status of a transaction from its primary lock,
+/// and may roll back an expired transaction.
+/// If the transaction was committed or rolled back, returns that information.
+/// If the TTL of its lock is exhausted, the transaction is rolled back.
+/// Otherwise, returns the TTL of the lock.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckTxnStatusRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub primary_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "3")]
+    pub lock_ts: u64,
+    #[prost(uint64, tag = "4")]
+    pub current_ts: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckTxnStatusResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    /// Three kinds of txn status:
+    /// locked: lock_ttl > 0
+    /// committed: commit_version > 0
+    /// rolled back: lock_ttl == 0 && commit_version == 0
+    #[prost(uint64, tag = "2")]
+    pub lock_ttl: u64,
+    #[prost(uint64, tag = "3")]
+    pub commit_version: u64,
+    /// The action performed by TinyKV in response to the CheckTxnStatus request.
+    #[prost(enumeration = "Action", tag = "4")]
+    pub action: i32,
+    /// The primary lock of an async commit transaction, its status depends
+    /// on its secondaries and is never rolled back by CheckTxnStatus.
+    #[prost(message, optional, tag = "5")]
+    pub lock_info: ::core::option::Option<LockInfo>,
+}
+/// CheckSecondaryLocks checks the secondary locks of an async commit
+/// transaction whose primary lock expired. Keys that were never locked are
+/// rolled back, so that the transaction can't be committed anymore.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckSecondaryLocksRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(bytes = "vec", repeated, tag = "2")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(uint64, tag = "3")]
+    pub start_version: u64,
+}
+/// The transaction is committed if commit_ts is set, it is rolled back if no
+/// lock is returned. Otherwise all the keys are locked, the transaction can
+/// be committed with the largest min_commit_ts of the locks.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CheckSecondaryLocksResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, optional, tag = "2")]
+    pub error: ::core::option::Option<KeyError>,
+    #[prost(message, repeated, tag = "3")]
+    pub locks: ::prost::alloc::vec::Vec<LockInfo>,
+    #[prost(uint64, tag = "4")]
+    pub commit_ts: u64,
+}
+/// Rolls back an uncommitted transaction. Fails if the transaction was already
+/// committed. Keys that were never locked are not an error, a rollback record
+/// keeps a late prewrite from locking them. On success the keys of the
+/// transaction are unlocked and its uncommitted values removed.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchRollbackRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(uint64, tag = "2")]
+    pub start_version: u64,
+    #[prost(bytes = "vec", repeated, tag = "3")]
+    pub keys: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchRollbackResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, optional, tag = "2")]
+    pub error: ::core::option::Option<KeyError>,
+}
+/// ResolveLock is sent by a transaction that found the lock of another
+/// transaction. It commits or rolls back all the locks of that transaction
+/// in the region.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResolveLockRequest {
+    #[prost(message, optional, tag = "1")]
+    pub context: ::core::option::Option<Context>,
+    #[prost(uint64, tag = "2")]
+    pub start_version: u64,
+    /// 0 if the transaction is rolled back.
+    #[prost(uint64, tag = "3")]
+    pub commit_version: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResolveLockResponse {
+    #[prost(message, optional, tag = "1")]
+    pub region_error: ::core::option::Option<super::errorpb::Error>,
+    #[prost(message, optional, tag = "2")]
+    pub error: ::core::option::Option<KeyError>,
+}
 /// Either a key/value pair or an error for a particular key.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -122,6 +423,9 @@ pub struct KeyError {
     /// Another transaction is trying to write a key. The client can retry.
     #[prost(message, optional, tag = "4")]
     pub conflict: ::core::option::Option<WriteConflict>,
+    /// The transaction waits for a lock in a cycle of waits. The client should abort the txn.
+    #[prost(message, optional, tag = "5")]
+    pub deadlock: ::core::option::Option<Deadlock>,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -134,6 +438,12 @@ pub struct LockInfo {
     pub key: ::prost::alloc::vec::Vec<u8>,
     #[prost(uint64, tag = "4")]
     pub lock_ttl: u64,
+    #[prost(bool, tag = "5")]
+    pub use_async_commit: bool,
+    #[prost(bytes = "vec", repeated, tag = "6")]
+    pub secondaries: ::prost::alloc::vec::Vec<::prost::alloc::vec::Vec<u8>>,
+    #[prost(uint64, tag = "7")]
+    pub min_commit_ts: u64,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -147,6 +457,17 @@ pub struct WriteConflict {
     #[prost(bytes = "vec", tag = "4")]
     pub primary: ::prost::alloc::vec::Vec<u8>,
 }
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Deadlock {
+    /// the transaction holding the lock waited for.
+    #[prost(uint64, tag = "1")]
+    pub lock_ts: u64,
+    #[prost(bytes = "vec", tag = "2")]
+    pub lock_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint64, tag = "3")]
+    pub deadlock_key_hash: u64,
+}
 /// Miscellaneous data present in each request.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -166,7 +487,7 @@ pub enum Op {
     Put = 0,
     Del = 1,
     Rollback = 2,
-    /// Used by TinySQL but not TinyKV.
+    /// Locks a key without changing it, e.g. for SELECT FOR UPDATE.
     Lock = 3,
 }
 impl Op {
@@ -1,3 +1,4 @@
+// This file is @generated by prost-build.
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Cluster {
@@ -20,6 +21,17 @@ pub struct Store {
     pub address: ::prost::alloc::string::String,
     #[prost(enumeration = "StoreState", tag = "3")]
     pub state: i32,
+    /// location of the store, such as its zone, rack and host.
+    #[prost(message, repeated, tag = "4")]
+    pub labels: ::prost::alloc::vec::Vec<StoreLabel>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreLabel {
+    #[prost(string, tag = "1")]
+    pub key: ::prost::alloc::string::String,
+    #[prost(string, tag = "2")]
+    pub value: ::prost::alloc::string::String,
 }
 #[allow(clippy::derive_partial_eq_without_eq)]
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -53,6 +65,8 @@ pub struct Peer {
     pub id: u64,
     #[prost(uint64, tag = "2")]
     pub store_id: u64,
+    #[prost(enumeration = "PeerRole", tag = "3")]
+    pub role: i32,
 }
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
 #[repr(i32)]
@@ -83,3 +97,31 @@ impl StoreState {
         }
     }
 }
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum PeerRole {
+    /// votes and counts in the quorum.
+    Voter = 0,
+    /// receives the log but does not count in the quorum.
+    Learner = 1,
+}
+impl PeerRole {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            PeerRole::Voter => "Voter",
+            PeerRole::Learner => "Learner",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "Voter" => Some(Self::Voter),
+            "Learner" => Some(Self::Learner),
+            _ => None,
+        }
+    }
+}
@@ -11,6 +11,14 @@ pub mod kvpb {
     include!("kvpb.rs");
 }
 
+pub mod raft_serverpb {
+    include!("raft_serverpb.rs");
+}
+
+pub mod schedulerpb {
+    include!("schedulerpb.rs");
+}
+
 pub mod tinykv {
     include!("tinykvpb.rs");
 }
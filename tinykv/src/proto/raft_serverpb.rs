@@ -0,0 +1,68 @@
+// This file is @generated by prost-build.
+/// A raft message exchanged between two peers of the same region.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RaftMessage {
+    #[prost(uint64, tag = "1")]
+    pub region_id: u64,
+    #[prost(message, optional, tag = "2")]
+    pub from_peer: ::core::option::Option<super::metapb::Peer>,
+    #[prost(message, optional, tag = "3")]
+    pub to_peer: ::core::option::Option<super::metapb::Peer>,
+    #[prost(message, optional, tag = "4")]
+    pub region_epoch: ::core::option::Option<super::metapb::RegionEpoch>,
+    /// the encoded raft message, opaque to the transport.
+    #[prost(bytes = "vec", tag = "5")]
+    pub message: ::prost::alloc::vec::Vec<u8>,
+}
+/// Raft messages of all the regions headed to the same store.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct BatchRaftMessage {
+    #[prost(message, repeated, tag = "1")]
+    pub msgs: ::prost::alloc::vec::Vec<RaftMessage>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Done {}
+/// Describes a region snapshot, the snapshot holds the data of the
+/// region's key range in every column family as of index.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SnapshotMeta {
+    #[prost(message, optional, tag = "1")]
+    pub region: ::core::option::Option<super::metapb::Region>,
+    #[prost(message, optional, tag = "2")]
+    pub from_peer: ::core::option::Option<super::metapb::Peer>,
+    #[prost(message, optional, tag = "3")]
+    pub to_peer: ::core::option::Option<super::metapb::Peer>,
+    #[prost(uint64, tag = "4")]
+    pub term: u64,
+    #[prost(uint64, tag = "5")]
+    pub index: u64,
+    /// size in bytes and crc32 of the snapshot file.
+    #[prost(uint64, tag = "6")]
+    pub size: u64,
+    #[prost(uint32, tag = "7")]
+    pub checksum: u32,
+}
+/// A piece of a snapshot file starting at offset. Every
+/// chunk carries the meta of the snapshot it belongs to.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SnapshotChunk {
+    #[prost(message, optional, tag = "1")]
+    pub meta: ::core::option::Option<SnapshotMeta>,
+    #[prost(uint64, tag = "2")]
+    pub offset: u64,
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: ::prost::alloc::vec::Vec<u8>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SnapshotDone {
+    /// bytes of the snapshot held by the receiver, a sender
+    /// resumes from there when the snapshot is incomplete.
+    #[prost(uint64, tag = "1")]
+    pub received: u64,
+}
@@ -0,0 +1,1830 @@
+// This file is @generated by prost-build.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Error {
+    #[prost(enumeration = "ErrorType", tag = "1")]
+    pub r#type: i32,
+    #[prost(string, tag = "2")]
+    pub message: ::prost::alloc::string::String,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ResponseHeader {
+    #[prost(message, optional, tag = "1")]
+    pub error: ::core::option::Option<Error>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutStoreRequest {
+    #[prost(message, optional, tag = "1")]
+    pub store: ::core::option::Option<super::metapb::Store>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutStoreResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStoreRequest {
+    #[prost(uint64, tag = "1")]
+    pub store_id: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetStoreResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub store: ::core::option::Option<super::metapb::Store>,
+    #[prost(message, optional, tag = "3")]
+    pub stats: ::core::option::Option<StoreStats>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetAllStoresRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetAllStoresResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(message, repeated, tag = "2")]
+    pub stores: ::prost::alloc::vec::Vec<super::metapb::Store>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RemoveStoreRequest {
+    #[prost(uint64, tag = "1")]
+    pub store_id: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RemoveStoreResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreStats {
+    #[prost(uint64, tag = "1")]
+    pub store_id: u64,
+    /// total space of the store, in bytes.
+    #[prost(uint64, tag = "2")]
+    pub capacity: u64,
+    /// space left for the store to grow.
+    #[prost(uint64, tag = "3")]
+    pub available: u64,
+    /// space used by the store's data.
+    #[prost(uint64, tag = "4")]
+    pub used_size: u64,
+    #[prost(uint32, tag = "5")]
+    pub region_count: u32,
+    #[prost(uint32, tag = "6")]
+    pub leader_count: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreHeartbeatRequest {
+    #[prost(message, optional, tag = "1")]
+    pub stats: ::core::option::Option<StoreStats>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct StoreHeartbeatResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+}
+/// Sent by the leader of a region, along with the traffic
+/// the region served since its previous heartbeat.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RegionHeartbeatRequest {
+    #[prost(message, optional, tag = "1")]
+    pub region: ::core::option::Option<super::metapb::Region>,
+    #[prost(message, optional, tag = "2")]
+    pub leader: ::core::option::Option<super::metapb::Peer>,
+    #[prost(uint64, tag = "3")]
+    pub read_bytes: u64,
+    #[prost(uint64, tag = "4")]
+    pub read_keys: u64,
+    #[prost(uint64, tag = "5")]
+    pub written_bytes: u64,
+    #[prost(uint64, tag = "6")]
+    pub written_keys: u64,
+    /// time covered by the traffic, in milliseconds.
+    #[prost(uint64, tag = "7")]
+    pub interval_ms: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ChangePeer {
+    #[prost(message, optional, tag = "1")]
+    pub peer: ::core::option::Option<super::metapb::Peer>,
+    #[prost(enumeration = "ConfChangeType", tag = "2")]
+    pub change_type: i32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TransferLeader {
+    #[prost(message, optional, tag = "1")]
+    pub peer: ::core::option::Option<super::metapb::Peer>,
+}
+/// Splits the region in two halves of about the same size.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct SplitRegion {}
+/// Carries the next step of the operator running on the region, if any.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RegionHeartbeatResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(uint64, tag = "2")]
+    pub region_id: u64,
+    #[prost(message, optional, tag = "3")]
+    pub region_epoch: ::core::option::Option<super::metapb::RegionEpoch>,
+    #[prost(message, optional, tag = "4")]
+    pub change_peer: ::core::option::Option<ChangePeer>,
+    #[prost(message, optional, tag = "5")]
+    pub transfer_leader: ::core::option::Option<TransferLeader>,
+    #[prost(message, optional, tag = "6")]
+    pub split_region: ::core::option::Option<SplitRegion>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Timestamp {
+    /// milliseconds since the unix epoch.
+    #[prost(int64, tag = "1")]
+    pub physical: i64,
+    #[prost(int64, tag = "2")]
+    pub logical: i64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TsoRequest {
+    #[prost(uint32, tag = "1")]
+    pub count: u32,
+}
+/// Hands out count consecutive timestamps, starting at timestamp.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct TsoResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(uint32, tag = "2")]
+    pub count: u32,
+    #[prost(message, optional, tag = "3")]
+    pub timestamp: ::core::option::Option<Timestamp>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AllocIdRequest {
+    #[prost(uint32, tag = "1")]
+    pub count: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct AllocIdResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    /// the first of count consecutive ids.
+    #[prost(uint64, tag = "2")]
+    pub id: u64,
+    #[prost(uint32, tag = "3")]
+    pub count: u32,
+}
+/// Finds the region holding region_key.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRegionRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub region_key: ::prost::alloc::vec::Vec<u8>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRegionByIdRequest {
+    #[prost(uint64, tag = "1")]
+    pub region_id: u64,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetRegionResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub region: ::core::option::Option<super::metapb::Region>,
+    #[prost(message, optional, tag = "3")]
+    pub leader: ::core::option::Option<super::metapb::Peer>,
+}
+/// Lists the regions overlapping [start_key, end_key) in key order, an
+/// empty end_key is unbounded and a limit of 0 returns all of them.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanRegionsRequest {
+    #[prost(bytes = "vec", tag = "1")]
+    pub start_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes = "vec", tag = "2")]
+    pub end_key: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, tag = "3")]
+    pub limit: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct ScanRegionsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(message, repeated, tag = "2")]
+    pub regions: ::prost::alloc::vec::Vec<super::metapb::Region>,
+    /// leaders\[i\] leads regions\[i\].
+    #[prost(message, repeated, tag = "3")]
+    pub leaders: ::prost::alloc::vec::Vec<super::metapb::Peer>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetClusterConfigRequest {}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetClusterConfigResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(message, optional, tag = "2")]
+    pub cluster: ::core::option::Option<super::metapb::Cluster>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutClusterConfigRequest {
+    #[prost(message, optional, tag = "1")]
+    pub cluster: ::core::option::Option<super::metapb::Cluster>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct PutClusterConfigResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetHotRegionsRequest {
+    #[prost(enumeration = "FlowKind", tag = "1")]
+    pub kind: i32,
+}
+/// The rates are averaged over the last heartbeats of the region.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct HotRegion {
+    #[prost(uint64, tag = "1")]
+    pub region_id: u64,
+    /// the store of the region's leader.
+    #[prost(uint64, tag = "2")]
+    pub store_id: u64,
+    #[prost(uint64, tag = "3")]
+    pub bytes_rate: u64,
+    #[prost(uint64, tag = "4")]
+    pub keys_rate: u64,
+    /// number of heartbeats the region has been hot for.
+    #[prost(uint32, tag = "5")]
+    pub hot_degree: u32,
+}
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct GetHotRegionsResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    /// hottest first.
+    #[prost(message, repeated, tag = "2")]
+    pub regions: ::prost::alloc::vec::Vec<HotRegion>,
+}
+/// txn waits for the lock of wait_for_txn on the key hashed to key_hash.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DetectRequest {
+    #[prost(enumeration = "DetectType", tag = "1")]
+    pub r#type: i32,
+    #[prost(uint64, tag = "2")]
+    pub txn: u64,
+    #[prost(uint64, tag = "3")]
+    pub wait_for_txn: u64,
+    #[prost(uint64, tag = "4")]
+    pub key_hash: u64,
+}
+/// A deadlock is reported to the waiter whose wait would close the cycle,
+/// the wait is not registered.
+#[allow(clippy::derive_partial_eq_without_eq)]
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DetectResponse {
+    #[prost(message, optional, tag = "1")]
+    pub header: ::core::option::Option<ResponseHeader>,
+    #[prost(bool, tag = "2")]
+    pub deadlock: bool,
+    /// the key txn holds a lock on, in the cycle.
+    #[prost(uint64, tag = "3")]
+    pub deadlock_key_hash: u64,
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ErrorType {
+    Ok = 0,
+    Unknown = 1,
+    StoreNotFound = 2,
+    /// the region in the heartbeat is older than the one known by the scheduler.
+    StaleRegion = 3,
+    RegionNotFound = 4,
+    /// the store was removed from the cluster and cannot come back.
+    StoreTombstone = 5,
+}
+impl ErrorType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ErrorType::Ok => "OK",
+            ErrorType::Unknown => "UNKNOWN",
+            ErrorType::StoreNotFound => "STORE_NOT_FOUND",
+            ErrorType::StaleRegion => "STALE_REGION",
+            ErrorType::RegionNotFound => "REGION_NOT_FOUND",
+            ErrorType::StoreTombstone => "STORE_TOMBSTONE",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "OK" => Some(Self::Ok),
+            "UNKNOWN" => Some(Self::Unknown),
+            "STORE_NOT_FOUND" => Some(Self::StoreNotFound),
+            "STALE_REGION" => Some(Self::StaleRegion),
+            "REGION_NOT_FOUND" => Some(Self::RegionNotFound),
+            "STORE_TOMBSTONE" => Some(Self::StoreTombstone),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum ConfChangeType {
+    /// adds a voter, or promotes a learner.
+    AddNode = 0,
+    RemoveNode = 1,
+    AddLearnerNode = 2,
+}
+impl ConfChangeType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            ConfChangeType::AddNode => "AddNode",
+            ConfChangeType::RemoveNode => "RemoveNode",
+            ConfChangeType::AddLearnerNode => "AddLearnerNode",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "AddNode" => Some(Self::AddNode),
+            "RemoveNode" => Some(Self::RemoveNode),
+            "AddLearnerNode" => Some(Self::AddLearnerNode),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum FlowKind {
+    Write = 0,
+    Read = 1,
+}
+impl FlowKind {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            FlowKind::Write => "WRITE",
+            FlowKind::Read => "READ",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "WRITE" => Some(Self::Write),
+            "READ" => Some(Self::Read),
+            _ => None,
+        }
+    }
+}
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+#[repr(i32)]
+pub enum DetectType {
+    /// Registers a wait, unless it would close a cycle.
+    Detect = 0,
+    /// The wait for wait_for_txn is over.
+    CleanUpWaitFor = 1,
+    /// All the waits of txn are over.
+    CleanUp = 2,
+}
+impl DetectType {
+    /// String value of the enum field names used in the ProtoBuf definition.
+    ///
+    /// The values are not transformed in any way and thus are considered stable
+    /// (if the ProtoBuf definition does not change) and safe for programmatic use.
+    pub fn as_str_name(&self) -> &'static str {
+        match self {
+            DetectType::Detect => "DETECT",
+            DetectType::CleanUpWaitFor => "CLEAN_UP_WAIT_FOR",
+            DetectType::CleanUp => "CLEAN_UP",
+        }
+    }
+    /// Creates an enum from field names used in the ProtoBuf definition.
+    pub fn from_str_name(value: &str) -> ::core::option::Option<Self> {
+        match value {
+            "DETECT" => Some(Self::Detect),
+            "CLEAN_UP_WAIT_FOR" => Some(Self::CleanUpWaitFor),
+            "CLEAN_UP" => Some(Self::CleanUp),
+            _ => None,
+        }
+    }
+}
+/// Generated client implementations.
+pub mod scheduler_client {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    use tonic::codegen::http::Uri;
+    /// The scheduler keeps the metadata of the cluster: its stores and
+    /// regions, as reported by the stores' heartbeats.
+    #[derive(Debug, Clone)]
+    pub struct SchedulerClient<T> {
+        inner: tonic::client::Grpc<T>,
+    }
+    impl SchedulerClient<tonic::transport::Channel> {
+        /// Attempt to create a new client by connecting to a given endpoint.
+        pub async fn connect<D>(dst: D) -> Result<Self, tonic::transport::Error>
+        where
+            D: TryInto<tonic::transport::Endpoint>,
+            D::Error: Into<StdError>,
+        {
+            let conn = tonic::transport::Endpoint::new(dst)?.connect().await?;
+            Ok(Self::new(conn))
+        }
+    }
+    impl<T> SchedulerClient<T>
+    where
+        T: tonic::client::GrpcService<tonic::body::BoxBody>,
+        T::Error: Into<StdError>,
+        T::ResponseBody: Body<Data = Bytes> + Send + 'static,
+        <T::ResponseBody as Body>::Error: Into<StdError> + Send,
+    {
+        pub fn new(inner: T) -> Self {
+            let inner = tonic::client::Grpc::new(inner);
+            Self { inner }
+        }
+        pub fn with_origin(inner: T, origin: Uri) -> Self {
+            let inner = tonic::client::Grpc::with_origin(inner, origin);
+            Self { inner }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> SchedulerClient<InterceptedService<T, F>>
+        where
+            F: tonic::service::Interceptor,
+            T::ResponseBody: Default,
+            T: tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+                Response = http::Response<
+                    <T as tonic::client::GrpcService<tonic::body::BoxBody>>::ResponseBody,
+                >,
+            >,
+            <T as tonic::codegen::Service<
+                http::Request<tonic::body::BoxBody>,
+            >>::Error: Into<StdError> + Send + Sync,
+        {
+            SchedulerClient::new(InterceptedService::new(inner, interceptor))
+        }
+        /// Compress requests with the given encoding.
+        ///
+        /// This requires the server to support it otherwise it might respond with an
+        /// error.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.send_compressed(encoding);
+            self
+        }
+        /// Enable decompressing responses.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.inner = self.inner.accept_compressed(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_decoding_message_size(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.inner = self.inner.max_encoding_message_size(limit);
+            self
+        }
+        pub async fn put_store(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PutStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PutStoreResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/PutStore",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "PutStore"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_store(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetStoreResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetStore",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetStore"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_all_stores(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetAllStoresRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetAllStoresResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetAllStores",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetAllStores"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Marks a store Offline, its peers are moved away and it becomes
+        /// Tombstone once it holds none.
+        pub async fn remove_store(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RemoveStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RemoveStoreResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/RemoveStore",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "RemoveStore"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn store_heartbeat(
+            &mut self,
+            request: impl tonic::IntoRequest<super::StoreHeartbeatRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::StoreHeartbeatResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/StoreHeartbeat",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "StoreHeartbeat"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn region_heartbeat(
+            &mut self,
+            request: impl tonic::IntoRequest<super::RegionHeartbeatRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RegionHeartbeatResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/RegionHeartbeat",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "RegionHeartbeat"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn tso(
+            &mut self,
+            request: impl tonic::IntoRequest<super::TsoRequest>,
+        ) -> std::result::Result<tonic::Response<super::TsoResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/Tso",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("schedulerpb.Scheduler", "Tso"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Unique ids for the new regions and peers.
+        pub async fn alloc_id(
+            &mut self,
+            request: impl tonic::IntoRequest<super::AllocIdRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::AllocIdResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/AllocID",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "AllocID"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// Routing, where the regions and their leaders are.
+        pub async fn get_region(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetRegionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRegionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetRegion",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetRegion"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_region_by_id(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetRegionByIdRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRegionResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetRegionByID",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetRegionByID"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn scan_regions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::ScanRegionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ScanRegionsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/ScanRegions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "ScanRegions"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// The regions with a sustained read or write traffic.
+        pub async fn get_hot_regions(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetHotRegionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetHotRegionsResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetHotRegions",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetHotRegions"));
+            self.inner.unary(req, path, codec).await
+        }
+        /// The wait-for graph of the pessimistic transactions waiting for locks.
+        pub async fn detect(
+            &mut self,
+            request: impl tonic::IntoRequest<super::DetectRequest>,
+        ) -> std::result::Result<tonic::Response<super::DetectResponse>, tonic::Status> {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/Detect",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "Detect"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn get_cluster_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::GetClusterConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetClusterConfigResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/GetClusterConfig",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "GetClusterConfig"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn put_cluster_config(
+            &mut self,
+            request: impl tonic::IntoRequest<super::PutClusterConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PutClusterConfigResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/schedulerpb.Scheduler/PutClusterConfig",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("schedulerpb.Scheduler", "PutClusterConfig"));
+            self.inner.unary(req, path, codec).await
+        }
+    }
+}
+/// Generated server implementations.
+pub mod scheduler_server {
+    #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
+    use tonic::codegen::*;
+    /// Generated trait containing gRPC methods that should be implemented for use with SchedulerServer.
+    #[async_trait]
+    pub trait Scheduler: Send + Sync + 'static {
+        async fn put_store(
+            &self,
+            request: tonic::Request<super::PutStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PutStoreResponse>,
+            tonic::Status,
+        >;
+        async fn get_store(
+            &self,
+            request: tonic::Request<super::GetStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetStoreResponse>,
+            tonic::Status,
+        >;
+        async fn get_all_stores(
+            &self,
+            request: tonic::Request<super::GetAllStoresRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetAllStoresResponse>,
+            tonic::Status,
+        >;
+        /// Marks a store Offline, its peers are moved away and it becomes
+        /// Tombstone once it holds none.
+        async fn remove_store(
+            &self,
+            request: tonic::Request<super::RemoveStoreRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RemoveStoreResponse>,
+            tonic::Status,
+        >;
+        async fn store_heartbeat(
+            &self,
+            request: tonic::Request<super::StoreHeartbeatRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::StoreHeartbeatResponse>,
+            tonic::Status,
+        >;
+        async fn region_heartbeat(
+            &self,
+            request: tonic::Request<super::RegionHeartbeatRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::RegionHeartbeatResponse>,
+            tonic::Status,
+        >;
+        async fn tso(
+            &self,
+            request: tonic::Request<super::TsoRequest>,
+        ) -> std::result::Result<tonic::Response<super::TsoResponse>, tonic::Status>;
+        /// Unique ids for the new regions and peers.
+        async fn alloc_id(
+            &self,
+            request: tonic::Request<super::AllocIdRequest>,
+        ) -> std::result::Result<tonic::Response<super::AllocIdResponse>, tonic::Status>;
+        /// Routing, where the regions and their leaders are.
+        async fn get_region(
+            &self,
+            request: tonic::Request<super::GetRegionRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRegionResponse>,
+            tonic::Status,
+        >;
+        async fn get_region_by_id(
+            &self,
+            request: tonic::Request<super::GetRegionByIdRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetRegionResponse>,
+            tonic::Status,
+        >;
+        async fn scan_regions(
+            &self,
+            request: tonic::Request<super::ScanRegionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::ScanRegionsResponse>,
+            tonic::Status,
+        >;
+        /// The regions with a sustained read or write traffic.
+        async fn get_hot_regions(
+            &self,
+            request: tonic::Request<super::GetHotRegionsRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetHotRegionsResponse>,
+            tonic::Status,
+        >;
+        /// The wait-for graph of the pessimistic transactions waiting for locks.
+        async fn detect(
+            &self,
+            request: tonic::Request<super::DetectRequest>,
+        ) -> std::result::Result<tonic::Response<super::DetectResponse>, tonic::Status>;
+        async fn get_cluster_config(
+            &self,
+            request: tonic::Request<super::GetClusterConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::GetClusterConfigResponse>,
+            tonic::Status,
+        >;
+        async fn put_cluster_config(
+            &self,
+            request: tonic::Request<super::PutClusterConfigRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::PutClusterConfigResponse>,
+            tonic::Status,
+        >;
+    }
+    /// The scheduler keeps the metadata of the cluster: its stores and
+    /// regions, as reported by the stores' heartbeats.
+    #[derive(Debug)]
+    pub struct SchedulerServer<T: Scheduler> {
+        inner: _Inner<T>,
+        accept_compression_encodings: EnabledCompressionEncodings,
+        send_compression_encodings: EnabledCompressionEncodings,
+        max_decoding_message_size: Option<usize>,
+        max_encoding_message_size: Option<usize>,
+    }
+    struct _Inner<T>(Arc<T>);
+    impl<T: Scheduler> SchedulerServer<T> {
+        pub fn new(inner: T) -> Self {
+            Self::from_arc(Arc::new(inner))
+        }
+        pub fn from_arc(inner: Arc<T>) -> Self {
+            let inner = _Inner(inner);
+            Self {
+                inner,
+                accept_compression_encodings: Default::default(),
+                send_compression_encodings: Default::default(),
+                max_decoding_message_size: None,
+                max_encoding_message_size: None,
+            }
+        }
+        pub fn with_interceptor<F>(
+            inner: T,
+            interceptor: F,
+        ) -> InterceptedService<Self, F>
+        where
+            F: tonic::service::Interceptor,
+        {
+            InterceptedService::new(Self::new(inner), interceptor)
+        }
+        /// Enable decompressing requests with the given encoding.
+        #[must_use]
+        pub fn accept_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.accept_compression_encodings.enable(encoding);
+            self
+        }
+        /// Compress responses with the given encoding, if the client supports it.
+        #[must_use]
+        pub fn send_compressed(mut self, encoding: CompressionEncoding) -> Self {
+            self.send_compression_encodings.enable(encoding);
+            self
+        }
+        /// Limits the maximum size of a decoded message.
+        ///
+        /// Default: `4MB`
+        #[must_use]
+        pub fn max_decoding_message_size(mut self, limit: usize) -> Self {
+            self.max_decoding_message_size = Some(limit);
+            self
+        }
+        /// Limits the maximum size of an encoded message.
+        ///
+        /// Default: `usize::MAX`
+        #[must_use]
+        pub fn max_encoding_message_size(mut self, limit: usize) -> Self {
+            self.max_encoding_message_size = Some(limit);
+            self
+        }
+    }
+    impl<T, B> tonic::codegen::Service<http::Request<B>> for SchedulerServer<T>
+    where
+        T: Scheduler,
+        B: Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+    {
+        type Response = http::Response<tonic::body::BoxBody>;
+        type Error = std::convert::Infallible;
+        type Future = BoxFuture<Self::Response, Self::Error>;
+        fn poll_ready(
+            &mut self,
+            _cx: &mut Context<'_>,
+        ) -> Poll<std::result::Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+        fn call(&mut self, req: http::Request<B>) -> Self::Future {
+            let inner = self.inner.clone();
+            match req.uri().path() {
+                "/schedulerpb.Scheduler/PutStore" => {
+                    #[allow(non_camel_case_types)]
+                    struct PutStoreSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::PutStoreRequest>
+                    for PutStoreSvc<T> {
+                        type Response = super::PutStoreResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PutStoreRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::put_store(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PutStoreSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetStore" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetStoreSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetStoreRequest>
+                    for GetStoreSvc<T> {
+                        type Response = super::GetStoreResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetStoreRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_store(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetStoreSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetAllStores" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetAllStoresSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetAllStoresRequest>
+                    for GetAllStoresSvc<T> {
+                        type Response = super::GetAllStoresResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetAllStoresRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_all_stores(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetAllStoresSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/RemoveStore" => {
+                    #[allow(non_camel_case_types)]
+                    struct RemoveStoreSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::RemoveStoreRequest>
+                    for RemoveStoreSvc<T> {
+                        type Response = super::RemoveStoreResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RemoveStoreRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::remove_store(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = RemoveStoreSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/StoreHeartbeat" => {
+                    #[allow(non_camel_case_types)]
+                    struct StoreHeartbeatSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::StoreHeartbeatRequest>
+                    for StoreHeartbeatSvc<T> {
+                        type Response = super::StoreHeartbeatResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::StoreHeartbeatRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::store_heartbeat(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = StoreHeartbeatSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/RegionHeartbeat" => {
+                    #[allow(non_camel_case_types)]
+                    struct RegionHeartbeatSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::RegionHeartbeatRequest>
+                    for RegionHeartbeatSvc<T> {
+                        type Response = super::RegionHeartbeatResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::RegionHeartbeatRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::region_heartbeat(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = RegionHeartbeatSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/Tso" => {
+                    #[allow(non_camel_case_types)]
+                    struct TsoSvc<T: Scheduler>(pub Arc<T>);
+                    impl<T: Scheduler> tonic::server::UnaryService<super::TsoRequest>
+                    for TsoSvc<T> {
+                        type Response = super::TsoResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::TsoRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::tso(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = TsoSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/AllocID" => {
+                    #[allow(non_camel_case_types)]
+                    struct AllocIDSvc<T: Scheduler>(pub Arc<T>);
+                    impl<T: Scheduler> tonic::server::UnaryService<super::AllocIdRequest>
+                    for AllocIDSvc<T> {
+                        type Response = super::AllocIdResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::AllocIdRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::alloc_id(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = AllocIDSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetRegion" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetRegionSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetRegionRequest>
+                    for GetRegionSvc<T> {
+                        type Response = super::GetRegionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetRegionRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_region(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetRegionSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetRegionByID" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetRegionByIDSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetRegionByIdRequest>
+                    for GetRegionByIDSvc<T> {
+                        type Response = super::GetRegionResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetRegionByIdRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_region_by_id(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetRegionByIDSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/ScanRegions" => {
+                    #[allow(non_camel_case_types)]
+                    struct ScanRegionsSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::ScanRegionsRequest>
+                    for ScanRegionsSvc<T> {
+                        type Response = super::ScanRegionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::ScanRegionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::scan_regions(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = ScanRegionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetHotRegions" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetHotRegionsSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetHotRegionsRequest>
+                    for GetHotRegionsSvc<T> {
+                        type Response = super::GetHotRegionsResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetHotRegionsRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_hot_regions(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetHotRegionsSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/Detect" => {
+                    #[allow(non_camel_case_types)]
+                    struct DetectSvc<T: Scheduler>(pub Arc<T>);
+                    impl<T: Scheduler> tonic::server::UnaryService<super::DetectRequest>
+                    for DetectSvc<T> {
+                        type Response = super::DetectResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::DetectRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::detect(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = DetectSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/GetClusterConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct GetClusterConfigSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::GetClusterConfigRequest>
+                    for GetClusterConfigSvc<T> {
+                        type Response = super::GetClusterConfigResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::GetClusterConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::get_cluster_config(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = GetClusterConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/schedulerpb.Scheduler/PutClusterConfig" => {
+                    #[allow(non_camel_case_types)]
+                    struct PutClusterConfigSvc<T: Scheduler>(pub Arc<T>);
+                    impl<
+                        T: Scheduler,
+                    > tonic::server::UnaryService<super::PutClusterConfigRequest>
+                    for PutClusterConfigSvc<T> {
+                        type Response = super::PutClusterConfigResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::PutClusterConfigRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as Scheduler>::put_cluster_config(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = PutClusterConfigSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                _ => {
+                    Box::pin(async move {
+                        Ok(
+                            http::Response::builder()
+                                .status(200)
+                                .header("grpc-status", "12")
+                                .header("content-type", "application/grpc")
+                                .body(empty_body())
+                                .unwrap(),
+                        )
+                    })
+                }
+            }
+        }
+    }
+    impl<T: Scheduler> Clone for SchedulerServer<T> {
+        fn clone(&self) -> Self {
+            let inner = self.inner.clone();
+            Self {
+                inner,
+                accept_compression_encodings: self.accept_compression_encodings,
+                send_compression_encodings: self.send_compression_encodings,
+                max_decoding_message_size: self.max_decoding_message_size,
+                max_encoding_message_size: self.max_encoding_message_size,
+            }
+        }
+    }
+    impl<T: Scheduler> Clone for _Inner<T> {
+        fn clone(&self) -> Self {
+            Self(Arc::clone(&self.0))
+        }
+    }
+    impl<T: std::fmt::Debug> std::fmt::Debug for _Inner<T> {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "{:?}", self.0)
+        }
+    }
+    impl<T: Scheduler> tonic::server::NamedService for SchedulerServer<T> {
+        const NAME: &'static str = "schedulerpb.Scheduler";
+    }
+}
@@ -1,3 +1,4 @@
+// This file is @generated by prost-build.
 /// Generated client implementations.
 pub mod tiny_kv_client {
     #![allow(unused_variables, dead_code, missing_docs, clippy::let_unit_value)]
@@ -85,6 +86,252 @@ pub mod tiny_kv_client {
             self.inner = self.inner.max_encoding_message_size(limit);
             self
         }
+        /// KV commands with mvcc/txn supported.
+        pub async fn kv_get(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::GetRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::GetResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/tinykvpb.TinyKv/KvGet");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "KvGet"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_scan(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::ScanRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::ScanResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/tinykvpb.TinyKv/KvScan");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "KvScan"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_prewrite(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::PrewriteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PrewriteResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvPrewrite",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvPrewrite"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_commit(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::CommitRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CommitResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/tinykvpb.TinyKv/KvCommit");
+            let mut req = request.into_request();
+            req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "KvCommit"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_pessimistic_lock(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::PessimisticLockRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PessimisticLockResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvPessimisticLock",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvPessimisticLock"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_pessimistic_rollback(
+            &mut self,
+            request: impl tonic::IntoRequest<
+                super::super::kvpb::PessimisticRollbackRequest,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PessimisticRollbackResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvPessimisticRollback",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvPessimisticRollback"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_check_txn_status(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::CheckTxnStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CheckTxnStatusResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvCheckTxnStatus",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvCheckTxnStatus"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_check_secondary_locks(
+            &mut self,
+            request: impl tonic::IntoRequest<
+                super::super::kvpb::CheckSecondaryLocksRequest,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CheckSecondaryLocksResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvCheckSecondaryLocks",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvCheckSecondaryLocks"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_batch_rollback(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::BatchRollbackRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::BatchRollbackResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvBatchRollback",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvBatchRollback"));
+            self.inner.unary(req, path, codec).await
+        }
+        pub async fn kv_resolve_lock(
+            &mut self,
+            request: impl tonic::IntoRequest<super::super::kvpb::ResolveLockRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::ResolveLockResponse>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/KvResolveLock",
+            );
+            let mut req = request.into_request();
+            req.extensions_mut()
+                .insert(GrpcMethod::new("tinykvpb.TinyKv", "KvResolveLock"));
+            self.inner.unary(req, path, codec).await
+        }
         /// RawKV commands.
         pub async fn raw_get(
             &mut self,
@@ -176,6 +423,57 @@ pub mod tiny_kv_client {
             req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "RawScan"));
             self.inner.unary(req, path, codec).await
         }
+        /// Raft commands (tinykv <-> tinykv).
+        pub async fn batch_raft(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<
+                Message = super::super::raft_serverpb::BatchRaftMessage,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::raft_serverpb::Done>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static(
+                "/tinykvpb.TinyKv/BatchRaft",
+            );
+            let mut req = request.into_streaming_request();
+            req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "BatchRaft"));
+            self.inner.client_streaming(req, path, codec).await
+        }
+        pub async fn snapshot(
+            &mut self,
+            request: impl tonic::IntoStreamingRequest<
+                Message = super::super::raft_serverpb::SnapshotChunk,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::raft_serverpb::SnapshotDone>,
+            tonic::Status,
+        > {
+            self.inner
+                .ready()
+                .await
+                .map_err(|e| {
+                    tonic::Status::new(
+                        tonic::Code::Unknown,
+                        format!("Service was not ready: {}", e.into()),
+                    )
+                })?;
+            let codec = tonic::codec::ProstCodec::default();
+            let path = http::uri::PathAndQuery::from_static("/tinykvpb.TinyKv/Snapshot");
+            let mut req = request.into_streaming_request();
+            req.extensions_mut().insert(GrpcMethod::new("tinykvpb.TinyKv", "Snapshot"));
+            self.inner.client_streaming(req, path, codec).await
+        }
     }
 }
 /// Generated server implementations.
@@ -185,6 +483,77 @@ pub mod tiny_kv_server {
     /// Generated trait containing gRPC methods that should be implemented for use with TinyKvServer.
     #[async_trait]
     pub trait TinyKv: Send + Sync + 'static {
+        /// KV commands with mvcc/txn supported.
+        async fn kv_get(
+            &self,
+            request: tonic::Request<super::super::kvpb::GetRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::GetResponse>,
+            tonic::Status,
+        >;
+        async fn kv_scan(
+            &self,
+            request: tonic::Request<super::super::kvpb::ScanRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::ScanResponse>,
+            tonic::Status,
+        >;
+        async fn kv_prewrite(
+            &self,
+            request: tonic::Request<super::super::kvpb::PrewriteRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PrewriteResponse>,
+            tonic::Status,
+        >;
+        async fn kv_commit(
+            &self,
+            request: tonic::Request<super::super::kvpb::CommitRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CommitResponse>,
+            tonic::Status,
+        >;
+        async fn kv_pessimistic_lock(
+            &self,
+            request: tonic::Request<super::super::kvpb::PessimisticLockRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PessimisticLockResponse>,
+            tonic::Status,
+        >;
+        async fn kv_pessimistic_rollback(
+            &self,
+            request: tonic::Request<super::super::kvpb::PessimisticRollbackRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::PessimisticRollbackResponse>,
+            tonic::Status,
+        >;
+        async fn kv_check_txn_status(
+            &self,
+            request: tonic::Request<super::super::kvpb::CheckTxnStatusRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CheckTxnStatusResponse>,
+            tonic::Status,
+        >;
+        async fn kv_check_secondary_locks(
+            &self,
+            request: tonic::Request<super::super::kvpb::CheckSecondaryLocksRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::CheckSecondaryLocksResponse>,
+            tonic::Status,
+        >;
+        async fn kv_batch_rollback(
+            &self,
+            request: tonic::Request<super::super::kvpb::BatchRollbackRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::BatchRollbackResponse>,
+            tonic::Status,
+        >;
+        async fn kv_resolve_lock(
+            &self,
+            request: tonic::Request<super::super::kvpb::ResolveLockRequest>,
+        ) -> std::result::Result<
+            tonic::Response<super::super::kvpb::ResolveLockResponse>,
+            tonic::Status,
+        >;
         /// RawKV commands.
         async fn raw_get(
             &self,
@@ -214,6 +583,25 @@ pub mod tiny_kv_server {
             tonic::Response<super::super::kvpb::RawScanResponse>,
             tonic::Status,
         >;
+        /// Raft commands (tinykv <-> tinykv).
+        async fn batch_raft(
+            &self,
+            request: tonic::Request<
+                tonic::Streaming<super::super::raft_serverpb::BatchRaftMessage>,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::raft_serverpb::Done>,
+            tonic::Status,
+        >;
+        async fn snapshot(
+            &self,
+            request: tonic::Request<
+                tonic::Streaming<super::super::raft_serverpb::SnapshotChunk>,
+            >,
+        ) -> std::result::Result<
+            tonic::Response<super::super::raft_serverpb::SnapshotDone>,
+            tonic::Status,
+        >;
     }
     /// Serve as a distributed kv database.
     /// See the request and response definitions in kv.proto
@@ -296,6 +684,485 @@ pub mod tiny_kv_server {
         fn call(&mut self, req: http::Request<B>) -> Self::Future {
             let inner = self.inner.clone();
             match req.uri().path() {
+                "/tinykvpb.TinyKv/KvGet" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvGetSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<super::super::kvpb::GetRequest>
+                    for KvGetSvc<T> {
+                        type Response = super::super::kvpb::GetResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::super::kvpb::GetRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_get(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvGetSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvScan" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvScanSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<super::super::kvpb::ScanRequest>
+                    for KvScanSvc<T> {
+                        type Response = super::super::kvpb::ScanResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::super::kvpb::ScanRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_scan(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvScanSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvPrewrite" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvPrewriteSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<super::super::kvpb::PrewriteRequest>
+                    for KvPrewriteSvc<T> {
+                        type Response = super::super::kvpb::PrewriteResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::super::kvpb::PrewriteRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_prewrite(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvPrewriteSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvCommit" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvCommitSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<super::super::kvpb::CommitRequest>
+                    for KvCommitSvc<T> {
+                        type Response = super::super::kvpb::CommitResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<super::super::kvpb::CommitRequest>,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_commit(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvCommitSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvPessimisticLock" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvPessimisticLockSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<
+                        super::super::kvpb::PessimisticLockRequest,
+                    > for KvPessimisticLockSvc<T> {
+                        type Response = super::super::kvpb::PessimisticLockResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::PessimisticLockRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_pessimistic_lock(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvPessimisticLockSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvPessimisticRollback" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvPessimisticRollbackSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<
+                        super::super::kvpb::PessimisticRollbackRequest,
+                    > for KvPessimisticRollbackSvc<T> {
+                        type Response = super::super::kvpb::PessimisticRollbackResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::PessimisticRollbackRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_pessimistic_rollback(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvPessimisticRollbackSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvCheckTxnStatus" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvCheckTxnStatusSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<
+                        super::super::kvpb::CheckTxnStatusRequest,
+                    > for KvCheckTxnStatusSvc<T> {
+                        type Response = super::super::kvpb::CheckTxnStatusResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::CheckTxnStatusRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_check_txn_status(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvCheckTxnStatusSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvCheckSecondaryLocks" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvCheckSecondaryLocksSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<
+                        super::super::kvpb::CheckSecondaryLocksRequest,
+                    > for KvCheckSecondaryLocksSvc<T> {
+                        type Response = super::super::kvpb::CheckSecondaryLocksResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::CheckSecondaryLocksRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_check_secondary_locks(&inner, request)
+                                    .await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvCheckSecondaryLocksSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvBatchRollback" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvBatchRollbackSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<
+                        super::super::kvpb::BatchRollbackRequest,
+                    > for KvBatchRollbackSvc<T> {
+                        type Response = super::super::kvpb::BatchRollbackResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::BatchRollbackRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_batch_rollback(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvBatchRollbackSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/KvResolveLock" => {
+                    #[allow(non_camel_case_types)]
+                    struct KvResolveLockSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::UnaryService<super::super::kvpb::ResolveLockRequest>
+                    for KvResolveLockSvc<T> {
+                        type Response = super::super::kvpb::ResolveLockResponse;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                super::super::kvpb::ResolveLockRequest,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::kv_resolve_lock(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = KvResolveLockSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.unary(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 "/tinykvpb.TinyKv/RawGet" => {
                     #[allow(non_camel_case_types)]
                     struct RawGetSvc<T: TinyKv>(pub Arc<T>);
@@ -480,6 +1347,106 @@ pub mod tiny_kv_server {
                     };
                     Box::pin(fut)
                 }
+                "/tinykvpb.TinyKv/BatchRaft" => {
+                    #[allow(non_camel_case_types)]
+                    struct BatchRaftSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::ClientStreamingService<
+                        super::super::raft_serverpb::BatchRaftMessage,
+                    > for BatchRaftSvc<T> {
+                        type Response = super::super::raft_serverpb::Done;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<
+                                    super::super::raft_serverpb::BatchRaftMessage,
+                                >,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::batch_raft(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = BatchRaftSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
+                "/tinykvpb.TinyKv/Snapshot" => {
+                    #[allow(non_camel_case_types)]
+                    struct SnapshotSvc<T: TinyKv>(pub Arc<T>);
+                    impl<
+                        T: TinyKv,
+                    > tonic::server::ClientStreamingService<
+                        super::super::raft_serverpb::SnapshotChunk,
+                    > for SnapshotSvc<T> {
+                        type Response = super::super::raft_serverpb::SnapshotDone;
+                        type Future = BoxFuture<
+                            tonic::Response<Self::Response>,
+                            tonic::Status,
+                        >;
+                        fn call(
+                            &mut self,
+                            request: tonic::Request<
+                                tonic::Streaming<super::super::raft_serverpb::SnapshotChunk>,
+                            >,
+                        ) -> Self::Future {
+                            let inner = Arc::clone(&self.0);
+                            let fut = async move {
+                                <T as TinyKv>::snapshot(&inner, request).await
+                            };
+                            Box::pin(fut)
+                        }
+                    }
+                    let accept_compression_encodings = self.accept_compression_encodings;
+                    let send_compression_encodings = self.send_compression_encodings;
+                    let max_decoding_message_size = self.max_decoding_message_size;
+                    let max_encoding_message_size = self.max_encoding_message_size;
+                    let inner = self.inner.clone();
+                    let fut = async move {
+                        let inner = inner.0;
+                        let method = SnapshotSvc(inner);
+                        let codec = tonic::codec::ProstCodec::default();
+                        let mut grpc = tonic::server::Grpc::new(codec)
+                            .apply_compression_config(
+                                accept_compression_encodings,
+                                send_compression_encodings,
+                            )
+                            .apply_max_message_size_config(
+                                max_decoding_message_size,
+                                max_encoding_message_size,
+                            );
+                        let res = grpc.client_streaming(method, req).await;
+                        Ok(res)
+                    };
+                    Box::pin(fut)
+                }
                 _ => {
                     Box::pin(async move {
                         Ok(
@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::kv::raftstore::region::{find_peer, is_learner};
+
+use super::{cluster::{RaftCluster, RegionInfo}, operator::{OpKind, OpStep, Operator}, placement::keeps_isolation};
+
+
+// Moving a region only helps when the stores differ by at least this much.
+const BALANCE_TOLERANCE: usize = 2;
+
+// The serving stores with their number of region peers, or of region
+// leaders, least loaded first.
+pub fn store_counts(cluster: &RaftCluster, leaders: bool) -> Vec<(u64, usize)> {
+    let max_down_time = cluster.config().max_store_down_time;
+    let mut counts: HashMap<u64, usize> = cluster.stores()
+        .filter(|info| info.is_serving(max_down_time))
+        .map(|info| (info.store.id, 0))
+        .collect();
+    for info in cluster.regions().iter() {
+        let store_ids: Vec<u64> = match (&info.leader, leaders) {
+            (Some(leader), true) => vec![leader.store_id],
+            (None, true) => vec![],
+            (_, false) => info.region.peers.iter().map(|peer| peer.store_id).collect(),
+        };
+        for store_id in store_ids {
+            if let Some(count) = counts.get_mut(&store_id) {
+                *count += 1;
+            }
+        }
+    }
+    let mut counts: Vec<(u64, usize)> = counts.into_iter().collect();
+    counts.sort_by_key(|&(store_id, count)| (count, store_id));
+    counts
+}
+
+// Regions in the middle of a membership change, or already
+// being moved, are left alone.
+pub fn is_schedulable(cluster: &RaftCluster, info: &RegionInfo) -> bool {
+    info.leader.is_some()
+        && !info.region.peers.iter().any(is_learner)
+        && cluster.operators().get(info.region.id).is_none()
+}
+
+// Moves the peer of a region on source to target. The new peer is added
+// as a learner and promoted before the old one is removed, so the region
+// never loses a replica.
+pub fn move_peer(cluster: &RaftCluster, info: &RegionInfo, source: u64, target: u64, kind: OpKind) -> Option<Operator> {
+    let source_peer = find_peer(&info.region, source)?.id;
+    let peer_id = cluster.alloc_id().ok()?;
+    let mut steps = vec![
+        OpStep::AddLearner { store_id: target, peer_id },
+        OpStep::PromoteLearner { store_id: target, peer_id },
+    ];
+    if info.leader.as_ref().is_some_and(|leader| leader.store_id == source) {
+        steps.push(OpStep::TransferLeader { from_store: source, to_store: target });
+    }
+    steps.push(OpStep::RemovePeer { store_id: source, peer_id: source_peer });
+    Some(Operator::new(info.region.id, kind, steps, cluster.config().region_operator_timeout))
+}
+
+// Moves a peer from the store holding the most region peers to the
+// one holding the fewest.
+pub fn balance_region(cluster: &RaftCluster) -> Option<Operator> {
+    let counts = store_counts(cluster, false);
+    let mut picked = None;
+    'sources: for &(source, source_count) in counts.iter().rev() {
+        for info in cluster.regions().iter().filter(|info| find_peer(&info.region, source).is_some()) {
+            if !is_schedulable(cluster, info) {
+                continue;
+            }
+            let target = counts.iter()
+                .find(|(store_id, _)| find_peer(&info.region, *store_id).is_none() && keeps_isolation(cluster, &info.region, source, *store_id));
+            if let Some(&(target, target_count)) = target {
+                if source_count >= target_count + BALANCE_TOLERANCE {
+                    picked = Some((info.clone(), source, target));
+                    break 'sources;
+                }
+            }
+        }
+    }
+
+    let (info, source, target) = picked?;
+    move_peer(cluster, &info, source, target, OpKind::Region)
+}
+
+// Moves a leader from the store leading the most regions to the voter
+// of the region on the store leading the fewest.
+pub fn balance_leader(cluster: &RaftCluster) -> Option<Operator> {
+    let counts = store_counts(cluster, true);
+    for &(source, source_count) in counts.iter().rev() {
+        let led = cluster.regions().iter()
+            .filter(|info| info.leader.as_ref().is_some_and(|leader| leader.store_id == source));
+        for info in led {
+            if !is_schedulable(cluster, info) {
+                continue;
+            }
+            let target = counts.iter()
+                .find(|(store_id, _)| info.region.peers.iter().any(|peer| peer.store_id == *store_id && !is_learner(peer)));
+            if let Some(&(target, target_count)) = target {
+                if source_count >= target_count + BALANCE_TOLERANCE {
+                    let step = OpStep::TransferLeader { from_store: source, to_store: target };
+                    return Some(Operator::new(info.region.id, OpKind::Leader, vec![step], cluster.config().leader_operator_timeout));
+                }
+            }
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::{Peer, Region, RegionEpoch, Store};
+    use crate::proto::schedulerpb::StoreStats;
+    use crate::scheduler::{cluster::RaftCluster, config::Config, operator::{OpKind, OpStep}};
+
+    use super::{balance_leader, balance_region};
+
+    fn new_cluster(store_count: u64) -> RaftCluster {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        for store_id in 1..=store_count {
+            cluster.put_store(Store { id: store_id, ..Default::default() }).unwrap();
+            cluster.handle_store_heartbeat(StoreStats { store_id, ..Default::default() }).unwrap();
+        }
+        cluster
+    }
+
+    // A region in [id, id + 1) with a peer on each store, led by the first one.
+    fn add_region(cluster: &mut RaftCluster, id: u8, stores: &[u64]) {
+        let region = Region {
+            id: id as u64,
+            start_key: vec![id],
+            end_key: vec![id + 1],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: stores.iter().map(|&store_id| Peer { id: id as u64 * 10 + store_id, store_id, ..Default::default() }).collect(),
+        };
+        let leader = region.peers[0].clone();
+        cluster.handle_region_heartbeat(region, leader).unwrap();
+    }
+
+    #[test]
+    fn balance_regions() {
+        let mut cluster = new_cluster(3);
+        add_region(&mut cluster, 1, &[1, 2]);
+        assert!(balance_region(&cluster).is_none());
+
+        add_region(&mut cluster, 2, &[1, 2]);
+        let op = balance_region(&cluster).unwrap();
+        // the peer with the highest id is 22, the new one gets the next id.
+        assert_eq!((op.region_id, op.kind), (1, OpKind::Region));
+        assert_eq!(op.steps, vec![
+            OpStep::AddLearner { store_id: 3, peer_id: 23 },
+            OpStep::PromoteLearner { store_id: 3, peer_id: 23 },
+            OpStep::RemovePeer { store_id: 2, peer_id: 12 },
+        ]);
+
+        // the leader moves away before its peer is removed.
+        let mut cluster = new_cluster(3);
+        add_region(&mut cluster, 1, &[1]);
+        add_region(&mut cluster, 2, &[1]);
+        let op = balance_region(&cluster).unwrap();
+        assert_eq!(op.steps[2..], [
+            OpStep::TransferLeader { from_store: 1, to_store: 2 },
+            OpStep::RemovePeer { store_id: 1, peer_id: 11 },
+        ]);
+
+        // down stores are not scheduled to.
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        for store_id in 1..=3 {
+            cluster.put_store(Store { id: store_id, ..Default::default() }).unwrap();
+        }
+        cluster.handle_store_heartbeat(StoreStats { store_id: 1, ..Default::default() }).unwrap();
+        add_region(&mut cluster, 1, &[1]);
+        add_region(&mut cluster, 2, &[1]);
+        assert!(balance_region(&cluster).is_none());
+    }
+
+    #[test]
+    fn balance_leaders() {
+        let mut cluster = new_cluster(3);
+        add_region(&mut cluster, 1, &[1, 2, 3]);
+        assert!(balance_leader(&cluster).is_none());
+        add_region(&mut cluster, 2, &[1, 2, 3]);
+        let op = balance_leader(&cluster).unwrap();
+        assert_eq!((op.region_id, op.kind), (1, OpKind::Leader));
+        assert_eq!(op.steps, vec![OpStep::TransferLeader { from_store: 1, to_store: 2 }]);
+
+        // a region has a single operator at a time.
+        assert_eq!(cluster.schedule(), 1);
+        let op = balance_leader(&cluster).unwrap();
+        assert_eq!(op.region_id, 2);
+        assert_eq!(op.steps, vec![OpStep::TransferLeader { from_store: 1, to_store: 2 }]);
+    }
+}
@@ -0,0 +1,163 @@
+use tonic::transport::Channel;
+
+use crate::{kv::error::{TkvError, TkvResult}, proto::{metapb::{Cluster, Peer, Region, Store}, schedulerpb::{scheduler_client, AllocIdRequest, DetectRequest, DetectType, FlowKind, GetAllStoresRequest, GetClusterConfigRequest, GetHotRegionsRequest, GetRegionByIdRequest, GetRegionRequest, GetRegionResponse, GetStoreRequest, HotRegion, PutClusterConfigRequest, PutStoreRequest, RegionHeartbeatRequest, RegionHeartbeatResponse, RemoveStoreRequest, ResponseHeader, ScanRegionsRequest, StoreHeartbeatRequest, StoreStats, TsoRequest}}};
+
+use super::{cluster::RegionInfo, hot_region::RegionFlow, tso::compose_ts};
+
+
+fn check_header(header: Option<ResponseHeader>) -> TkvResult<()> {
+    match header.and_then(|header| header.error) {
+        Some(err) => Err(TkvError::new(format!("scheduler error {:?}: {}", err.r#type(), err.message))),
+        None => Ok(()),
+    }
+}
+
+fn region_info(response: GetRegionResponse) -> TkvResult<RegionInfo> {
+    check_header(response.header)?;
+    Ok(RegionInfo { region: response.region.unwrap_or_default(), leader: response.leader })
+}
+
+/// SchedulerClient talks to the scheduler on behalf of a store or a client.
+#[derive(Debug, Clone)]
+pub struct SchedulerClient {
+    client: scheduler_client::SchedulerClient<Channel>,
+}
+
+impl SchedulerClient {
+    // Connects lazily, the scheduler may not be up yet.
+    pub fn connect(addr: &str) -> TkvResult<Self> {
+        let channel = Channel::from_shared(format!("http://{}", addr))?.connect_lazy();
+        Ok(Self { client: scheduler_client::SchedulerClient::new(channel) })
+    }
+
+    pub async fn put_store(&self, store: Store) -> TkvResult<()> {
+        let request = PutStoreRequest { store: Some(store) };
+        let response = self.client.clone().put_store(request).await?.into_inner();
+        check_header(response.header)
+    }
+
+    pub async fn get_store(&self, store_id: u64) -> TkvResult<(Store, StoreStats)> {
+        let response = self.client.clone().get_store(GetStoreRequest { store_id }).await?.into_inner();
+        check_header(response.header)?;
+        Ok((response.store.unwrap_or_default(), response.stats.unwrap_or_default()))
+    }
+
+    pub async fn get_all_stores(&self) -> TkvResult<Vec<Store>> {
+        let response = self.client.clone().get_all_stores(GetAllStoresRequest {}).await?.into_inner();
+        check_header(response.header)?;
+        Ok(response.stores)
+    }
+
+    // Starts decommissioning a store.
+    pub async fn remove_store(&self, store_id: u64) -> TkvResult<()> {
+        let response = self.client.clone().remove_store(RemoveStoreRequest { store_id }).await?.into_inner();
+        check_header(response.header)
+    }
+
+    pub async fn store_heartbeat(&self, stats: StoreStats) -> TkvResult<()> {
+        let request = StoreHeartbeatRequest { stats: Some(stats) };
+        let response = self.client.clone().store_heartbeat(request).await?.into_inner();
+        check_header(response.header)
+    }
+
+    pub async fn get_ts(&self) -> TkvResult<u64> {
+        Ok(self.batch_get_ts(1).await?[0])
+    }
+
+    // Gets count consecutive timestamps in a single request.
+    pub async fn batch_get_ts(&self, count: u32) -> TkvResult<Vec<u64>> {
+        let response = self.client.clone().tso(TsoRequest { count }).await?.into_inner();
+        check_header(response.header)?;
+        let timestamp = response.timestamp.unwrap_or_default();
+        let first = compose_ts(timestamp.physical as u64, timestamp.logical as u64);
+        Ok((first..first + response.count as u64).collect())
+    }
+
+    // Returns the first of count consecutive unique ids.
+    pub async fn alloc_ids(&self, count: u32) -> TkvResult<u64> {
+        let response = self.client.clone().alloc_id(AllocIdRequest { count }).await?.into_inner();
+        check_header(response.header)?;
+        Ok(response.id)
+    }
+
+    pub async fn get_region(&self, key: &[u8]) -> TkvResult<RegionInfo> {
+        let request = GetRegionRequest { region_key: key.to_vec() };
+        region_info(self.client.clone().get_region(request).await?.into_inner())
+    }
+
+    pub async fn get_region_by_id(&self, region_id: u64) -> TkvResult<RegionInfo> {
+        let request = GetRegionByIdRequest { region_id };
+        region_info(self.client.clone().get_region_by_id(request).await?.into_inner())
+    }
+
+    pub async fn scan_regions(&self, start_key: &[u8], end_key: &[u8], limit: u32) -> TkvResult<Vec<RegionInfo>> {
+        let request = ScanRegionsRequest { start_key: start_key.to_vec(), end_key: end_key.to_vec(), limit };
+        let response = self.client.clone().scan_regions(request).await?.into_inner();
+        check_header(response.header)?;
+        let infos = response.regions.into_iter()
+            .zip(response.leaders)
+            .map(|(region, leader)| RegionInfo { region, leader: Some(leader).filter(|leader| leader.id != 0) })
+            .collect();
+        Ok(infos)
+    }
+
+    // Reports the region along with its traffic since the previous heartbeat.
+    // Returns the operator step to run on the region, if any.
+    pub async fn region_heartbeat(&self, region: Region, leader: Peer, flow: &RegionFlow) -> TkvResult<RegionHeartbeatResponse> {
+        let request = RegionHeartbeatRequest {
+            region: Some(region),
+            leader: Some(leader),
+            read_bytes: flow.read_bytes,
+            read_keys: flow.read_keys,
+            written_bytes: flow.written_bytes,
+            written_keys: flow.written_keys,
+            interval_ms: flow.interval.as_millis() as u64,
+        };
+        let response = self.client.clone().region_heartbeat(request).await?.into_inner();
+        check_header(response.header.clone())?;
+        Ok(response)
+    }
+
+    // The regions with a sustained traffic of kind, hottest first.
+    pub async fn get_hot_regions(&self, kind: FlowKind) -> TkvResult<Vec<HotRegion>> {
+        let request = GetHotRegionsRequest { kind: kind as i32 };
+        let response = self.client.clone().get_hot_regions(request).await?.into_inner();
+        check_header(response.header)?;
+        Ok(response.regions)
+    }
+
+    // Registers that txn waits for the lock of wait_for_txn on the key
+    // hashed to key_hash. On a deadlock, returns the hash of the key txn
+    // holds in the cycle, and txn must not wait.
+    pub async fn detect(&self, txn: u64, wait_for_txn: u64, key_hash: u64) -> TkvResult<Option<u64>> {
+        let request = DetectRequest { r#type: DetectType::Detect as i32, txn, wait_for_txn, key_hash };
+        let response = self.client.clone().detect(request).await?.into_inner();
+        check_header(response.header)?;
+        Ok(response.deadlock.then_some(response.deadlock_key_hash))
+    }
+
+    pub async fn clean_up_wait_for(&self, txn: u64, wait_for_txn: u64) -> TkvResult<()> {
+        let request = DetectRequest { r#type: DetectType::CleanUpWaitFor as i32, txn, wait_for_txn, ..Default::default() };
+        let response = self.client.clone().detect(request).await?.into_inner();
+        check_header(response.header)
+    }
+
+    // Drops all the waits of txn, once it is over.
+    pub async fn clean_up(&self, txn: u64) -> TkvResult<()> {
+        let request = DetectRequest { r#type: DetectType::CleanUp as i32, txn, ..Default::default() };
+        let response = self.client.clone().detect(request).await?.into_inner();
+        check_header(response.header)
+    }
+
+    pub async fn get_cluster_config(&self) -> TkvResult<Cluster> {
+        let response = self.client.clone().get_cluster_config(GetClusterConfigRequest {}).await?.into_inner();
+        check_header(response.header)?;
+        Ok(response.cluster.unwrap_or_default())
+    }
+
+    pub async fn put_cluster_config(&self, cluster: Cluster) -> TkvResult<()> {
+        let request = PutClusterConfigRequest { cluster: Some(cluster) };
+        let response = self.client.clone().put_cluster_config(request).await?.into_inner();
+        check_header(response.header)
+    }
+}
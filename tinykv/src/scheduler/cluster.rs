@@ -0,0 +1,539 @@
+use std::{collections::{BTreeMap, HashMap}, ops::Bound, sync::Arc, time::{Duration, Instant, SystemTime, UNIX_EPOCH}};
+
+use crate::{kv::{error::TkvResult, raftstore::region::{check_key_in_region, region_epoch}, Storage}, proto::{metapb::{Cluster, Peer, Region, Store, StoreState}, schedulerpb::{ErrorType, FlowKind, StoreStats}}};
+
+use super::{balance::{balance_leader, balance_region}, config::Config, hot_region::{balance_hot_region, HotCache, HotPeerStat, RegionFlow}, id::IdAllocator, new_error, operator::{OpKind, OpStep, OperatorController}, replica_checker::check_replicas, storage::MetaStorage, storage_error, SchedulerResult};
+
+
+#[derive(Debug, Clone)]
+pub struct StoreInfo {
+    pub store: Store,
+    pub stats: StoreStats,
+    // None until the first heartbeat of the store.
+    pub last_heartbeat: Option<Instant>,
+}
+
+impl StoreInfo {
+    // A store is up when it reported recently enough.
+    pub fn is_up(&self, max_down_time: Duration) -> bool {
+        self.last_heartbeat.is_some_and(|last| last.elapsed() < max_down_time)
+    }
+
+    // A store taking new peers and leaders: up and not being removed.
+    pub fn is_serving(&self, max_down_time: Duration) -> bool {
+        self.store.state() == StoreState::Up && self.is_up(max_down_time)
+    }
+
+    // A store short of space, the stores that did not report
+    // their capacity yet are not.
+    pub fn is_low_space(&self, low_space_ratio: f64) -> bool {
+        self.stats.capacity > 0
+            && self.stats.used_size as f64 > self.stats.capacity as f64 * low_space_ratio
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionInfo {
+    pub region: Region,
+    pub leader: Option<Peer>,
+}
+
+/// RegionTree indexes the regions of the cluster by id and by key range.
+#[derive(Debug, Default)]
+pub struct RegionTree {
+    regions: HashMap<u64, RegionInfo>,
+    // start_key -> region_id
+    ranges: BTreeMap<Vec<u8>, u64>,
+}
+
+impl RegionTree {
+    pub fn get(&self, region_id: u64) -> Option<&RegionInfo> {
+        self.regions.get(&region_id)
+    }
+
+    // Finds the region holding key.
+    pub fn search(&self, key: &[u8]) -> Option<&RegionInfo> {
+        let (_, region_id) = self.ranges.range(..=key.to_vec()).next_back()?;
+        self.regions.get(region_id).filter(|info| check_key_in_region(key, &info.region))
+    }
+
+    pub fn len(&self) -> usize {
+        self.regions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.regions.is_empty()
+    }
+
+    // Regions in key order.
+    pub fn iter(&self) -> impl Iterator<Item = &RegionInfo> {
+        self.ranges.values().filter_map(|region_id| self.regions.get(region_id))
+    }
+
+    // Regions overlapping [start_key, end_key) in key order, an empty
+    // end_key is unbounded. A limit of 0 returns all of them.
+    pub fn scan_range(&self, start_key: &[u8], end_key: &[u8], limit: usize) -> Vec<&RegionInfo> {
+        let mut region_ids = vec![];
+        if let Some(info) = self.search(start_key) {
+            region_ids.push(info.region.id);
+        }
+        let ranges = self.ranges.range((Bound::Excluded(start_key.to_vec()), Bound::Unbounded));
+        for (start, region_id) in ranges {
+            if !end_key.is_empty() && start.as_slice() >= end_key {
+                break;
+            }
+            region_ids.push(*region_id);
+        }
+        if limit > 0 {
+            region_ids.truncate(limit);
+        }
+        region_ids.iter().filter_map(|region_id| self.regions.get(region_id)).collect()
+    }
+
+    // The other regions overlapping the range of region.
+    fn overlaps(&self, region: &Region) -> Vec<u64> {
+        self.scan_range(&region.start_key, &region.end_key, 0).into_iter()
+            .map(|info| info.region.id)
+            .filter(|region_id| *region_id != region.id)
+            .collect()
+    }
+
+    // A region is stale when the tree knows a newer epoch of it, or a
+    // newer region in its range that results from a split or a merge.
+    pub fn check_stale(&self, region: &Region) -> SchedulerResult<()> {
+        let epoch = region_epoch(region);
+        if let Some(origin) = self.regions.get(&region.id) {
+            let origin_epoch = region_epoch(&origin.region);
+            if epoch.version < origin_epoch.version || epoch.config_version < origin_epoch.config_version {
+                return Err(new_error(ErrorType::StaleRegion, format!(
+                    "region {} epoch {:?} is older than {:?}", region.id, epoch, origin_epoch
+                )));
+            }
+        }
+        for region_id in self.overlaps(region) {
+            let other_epoch = region_epoch(&self.regions[&region_id].region);
+            if epoch.version < other_epoch.version {
+                return Err(new_error(ErrorType::StaleRegion, format!(
+                    "region {} overlaps the newer region {}", region.id, region_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    // Inserts a region, replacing its previous version and the regions
+    // it overlaps. Returns the overlapped regions.
+    pub fn insert(&mut self, info: RegionInfo) -> Vec<RegionInfo> {
+        let overlaps = self.overlaps(&info.region);
+        self.remove(info.region.id);
+        let removed = overlaps.into_iter().filter_map(|region_id| self.remove(region_id)).collect();
+        self.ranges.insert(info.region.start_key.clone(), info.region.id);
+        self.regions.insert(info.region.id, info);
+        removed
+    }
+
+    pub fn remove(&mut self, region_id: u64) -> Option<RegionInfo> {
+        let info = self.regions.remove(&region_id)?;
+        if self.ranges.get(&info.region.start_key) == Some(&region_id) {
+            self.ranges.remove(&info.region.start_key);
+        }
+        Some(info)
+    }
+}
+
+
+// The high bits are the creation time, so that two clusters
+// are unlikely to share an id.
+fn new_cluster_id() -> u64 {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+    (secs << 32) | rand::random::<u32>() as u64
+}
+
+/// RaftCluster is the scheduler's view of the cluster, built from
+/// the heartbeats of its stores and region leaders. It also keeps the
+/// operators moving regions around, sent back in the region heartbeats.
+/// The stores and regions are persisted, their stats and leaders are
+/// only known again after the next heartbeats.
+#[derive(Debug)]
+pub struct RaftCluster {
+    config: Config,
+    meta: Cluster,
+    stores: HashMap<u64, StoreInfo>,
+    regions: RegionTree,
+    operators: OperatorController,
+    hot_cache: HotCache,
+    storage: MetaStorage,
+    id_allocator: IdAllocator,
+}
+
+impl RaftCluster {
+    // Loads the cluster persisted in storage, or creates a new one.
+    pub fn new(config: &Config, storage: Arc<dyn Storage>) -> TkvResult<Self> {
+        let meta_storage = MetaStorage::new(storage.clone());
+        let meta = match meta_storage.load_cluster()? {
+            Some(meta) => meta,
+            None => {
+                let meta = Cluster { id: new_cluster_id(), max_peer_count: config.max_peer_count };
+                meta_storage.save_cluster(&meta)?;
+                meta
+            },
+        };
+        let stores = meta_storage.load_stores()?.into_iter()
+            .map(|store| {
+                let stats = StoreStats { store_id: store.id, ..Default::default() };
+                (store.id, StoreInfo { store, stats, last_heartbeat: None })
+            })
+            .collect();
+        let mut regions = RegionTree::default();
+        for region in meta_storage.load_regions()? {
+            regions.insert(RegionInfo { region, leader: None });
+        }
+        Ok(Self {
+            config: config.clone(),
+            meta,
+            stores,
+            regions,
+            operators: OperatorController::default(),
+            hot_cache: HotCache::default(),
+            storage: meta_storage,
+            id_allocator: IdAllocator::new(storage, config.id_batch_size)?,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn meta(&self) -> &Cluster {
+        &self.meta
+    }
+
+    // Updates the settings of the cluster, its id cannot change.
+    pub fn put_meta(&mut self, meta: Cluster) -> SchedulerResult<()> {
+        if meta.max_peer_count == 0 {
+            return Err(new_error(ErrorType::Unknown, "invalid max peer count 0".to_string()));
+        }
+        let mut updated = self.meta.clone();
+        updated.max_peer_count = meta.max_peer_count;
+        self.storage.save_cluster(&updated).map_err(storage_error)?;
+        self.meta = updated;
+        Ok(())
+    }
+
+    pub fn operators(&self) -> &OperatorController {
+        &self.operators
+    }
+
+    pub fn alloc_id(&self) -> SchedulerResult<u64> {
+        self.id_allocator.alloc().map_err(storage_error)
+    }
+
+    // Returns the first of count consecutive ids.
+    pub fn alloc_ids(&self, count: u64) -> SchedulerResult<u64> {
+        self.id_allocator.alloc_batch(count).map_err(storage_error)
+    }
+
+    // Registers a store, or updates its address. The state of a known
+    // store only changes through remove_store.
+    pub fn put_store(&mut self, mut store: Store) -> SchedulerResult<()> {
+        if store.id == 0 {
+            return Err(new_error(ErrorType::Unknown, "invalid store id 0".to_string()));
+        }
+        match self.stores.get_mut(&store.id) {
+            Some(info) if info.store.state() == StoreState::Tomstone => {
+                return Err(new_error(ErrorType::StoreTombstone, format!("store {} is tombstone", store.id)));
+            },
+            Some(info) => {
+                store.state = info.store.state;
+                self.storage.save_store(&store).map_err(storage_error)?;
+                info.store = store;
+            },
+            None => {
+                self.storage.save_store(&store).map_err(storage_error)?;
+                let stats = StoreStats { store_id: store.id, ..Default::default() };
+                self.stores.insert(store.id, StoreInfo { store, stats, last_heartbeat: None });
+            },
+        }
+        Ok(())
+    }
+
+    pub fn get_store(&self, store_id: u64) -> Option<&StoreInfo> {
+        self.stores.get(&store_id)
+    }
+
+    // Starts decommissioning a store, the schedulers move its peers away.
+    pub fn remove_store(&mut self, store_id: u64) -> SchedulerResult<()> {
+        let Some(info) = self.stores.get_mut(&store_id) else {
+            return Err(new_error(ErrorType::StoreNotFound, format!("store {} not found", store_id)));
+        };
+        match info.store.state() {
+            StoreState::Tomstone => Err(new_error(ErrorType::StoreTombstone, format!("store {} is tombstone", store_id))),
+            _ => {
+                let mut store = info.store.clone();
+                store.set_state(StoreState::Offline);
+                self.storage.save_store(&store).map_err(storage_error)?;
+                log::info!("store {} is offline", store_id);
+                info.store = store;
+                Ok(())
+            },
+        }
+    }
+
+    // Offline stores left without any peer are done with.
+    fn bury_stores(&mut self) {
+        for info in self.stores.values_mut().filter(|info| info.store.state() == StoreState::Offline) {
+            let store_id = info.store.id;
+            if !self.regions.iter().any(|region| region.region.peers.iter().any(|peer| peer.store_id == store_id)) {
+                let mut store = info.store.clone();
+                store.set_state(StoreState::Tomstone);
+                match self.storage.save_store(&store) {
+                    Ok(()) => {
+                        log::info!("store {} is tombstone", store_id);
+                        info.store = store;
+                    },
+                    Err(err) => log::error!("failed to save store {}: {:?}", store_id, err),
+                }
+            }
+        }
+    }
+
+    pub fn stores(&self) -> impl Iterator<Item = &StoreInfo> {
+        self.stores.values()
+    }
+
+    pub fn regions(&self) -> &RegionTree {
+        &self.regions
+    }
+
+    pub fn handle_store_heartbeat(&mut self, stats: StoreStats) -> SchedulerResult<()> {
+        let Some(info) = self.stores.get_mut(&stats.store_id) else {
+            return Err(new_error(ErrorType::StoreNotFound, format!("store {} not found", stats.store_id)));
+        };
+        if info.store.state() == StoreState::Tomstone {
+            return Err(new_error(ErrorType::StoreTombstone, format!("store {} is tombstone", stats.store_id)));
+        }
+        info.stats = stats;
+        info.last_heartbeat = Some(Instant::now());
+        Ok(())
+    }
+
+    // Returns the next step of the operator running on the region, if any.
+    pub fn handle_region_heartbeat(&mut self, region: Region, leader: Peer) -> SchedulerResult<Option<OpStep>> {
+        if region.id == 0 || !region.peers.contains(&leader) {
+            return Err(new_error(ErrorType::Unknown, format!(
+                "invalid heartbeat of region {} with leader {:?}", region.id, leader
+            )));
+        }
+        self.regions.check_stale(&region)?;
+        let max_id = region.peers.iter().map(|peer| peer.id).fold(region.id, u64::max);
+        self.id_allocator.rebase(max_id).map_err(storage_error)?;
+        if self.regions.get(region.id).is_none_or(|origin| origin.region != region) {
+            self.storage.save_region(&region).map_err(storage_error)?;
+        }
+        let info = RegionInfo { region, leader: Some(leader) };
+        for overlap in self.regions.insert(info.clone()) {
+            log::info!("region {} is overlapped, removed from the cluster", overlap.region.id);
+            self.storage.delete_region(overlap.region.id).map_err(storage_error)?;
+            self.hot_cache.remove(overlap.region.id);
+        }
+        Ok(self.operators.dispatch(&info))
+    }
+
+    // Adds the traffic reported along with the heartbeat of a region.
+    pub fn handle_region_flow(&mut self, region_id: u64, flow: &RegionFlow) {
+        let Some(leader) = self.regions.get(region_id).and_then(|info| info.leader.as_ref()) else {
+            return;
+        };
+        self.hot_cache.update(region_id, leader.store_id, flow, &self.config);
+    }
+
+    // The regions with a sustained traffic of kind, hottest first.
+    pub fn hot_regions(&self, kind: FlowKind) -> Vec<HotPeerStat> {
+        self.hot_cache.hot_regions(kind, self.config.hot_region_min_degree).into_iter().cloned().collect()
+    }
+
+    // Runs the schedulers once, within the operator limits.
+    // Returns the number of operators added.
+    pub fn schedule(&mut self) -> usize {
+        self.operators.remove_timeouts();
+        self.bury_stores();
+        let mut added = 0;
+        // regions missing replicas come first.
+        let infos: Vec<RegionInfo> = self.regions.iter()
+            .filter(|info| self.operators.get(info.region.id).is_none())
+            .cloned()
+            .collect();
+        for info in infos {
+            if self.operators.count(OpKind::Replica) >= self.config.replica_schedule_limit {
+                break;
+            }
+            if let Some(op) = check_replicas(self, &info) {
+                added += self.operators.add(op) as usize;
+            }
+        }
+        if self.operators.count(OpKind::Leader) < self.config.leader_schedule_limit {
+            if let Some(op) = balance_leader(self) {
+                added += self.operators.add(op) as usize;
+            }
+        }
+        if self.operators.count(OpKind::Region) < self.config.region_schedule_limit {
+            if let Some(op) = balance_region(self) {
+                added += self.operators.add(op) as usize;
+            }
+        }
+        for kind in [FlowKind::Write, FlowKind::Read] {
+            if self.operators.count(OpKind::Hot) >= self.config.hot_region_schedule_limit {
+                break;
+            }
+            if let Some(op) = balance_hot_region(self, kind) {
+                added += self.operators.add(op) as usize;
+            }
+        }
+        added
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::{Cluster, Peer, Region, RegionEpoch, Store, StoreState};
+    use crate::proto::schedulerpb::{ErrorType, StoreStats};
+    use crate::scheduler::config::Config;
+
+    use super::RaftCluster;
+
+    fn new_region(id: u64, start_key: &[u8], end_key: &[u8], version: u64) -> Region {
+        Region {
+            id,
+            start_key: start_key.to_vec(),
+            end_key: end_key.to_vec(),
+            region_epoch: Some(RegionEpoch { config_version: 1, version }),
+            peers: vec![Peer { id: id * 10, store_id: 1, ..Default::default() }],
+        }
+    }
+
+    fn leader(region: &Region) -> Peer {
+        region.peers[0].clone()
+    }
+
+    #[test]
+    fn region_heartbeats() {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        let region = new_region(1, b"", b"", 1);
+        cluster.handle_region_heartbeat(region.clone(), leader(&region)).unwrap();
+        assert_eq!(cluster.regions().search(b"any").unwrap().region, region);
+
+        // region 1 splits into [, m) and [m, ), region 2 reports first.
+        let right = new_region(2, b"m", b"", 2);
+        cluster.handle_region_heartbeat(right.clone(), leader(&right)).unwrap();
+        assert!(cluster.regions().get(1).is_none());
+        assert!(cluster.regions().search(b"a").is_none());
+        assert_eq!(cluster.regions().search(b"z").unwrap().region.id, 2);
+
+        // the region before the split is stale.
+        let err = cluster.handle_region_heartbeat(region.clone(), leader(&region)).unwrap_err();
+        assert_eq!(err.r#type(), ErrorType::StaleRegion);
+
+        let left = new_region(1, b"", b"m", 2);
+        cluster.handle_region_heartbeat(left.clone(), leader(&left)).unwrap();
+        assert_eq!(cluster.regions().search(b"a").unwrap().region, left);
+        assert_eq!(cluster.regions().iter().map(|info| info.region.id).collect::<Vec<_>>(), vec![1, 2]);
+
+        // both are merged into region 2.
+        let merged = new_region(2, b"", b"", 3);
+        cluster.handle_region_heartbeat(merged.clone(), leader(&merged)).unwrap();
+        assert_eq!(cluster.regions().len(), 1);
+        assert_eq!(cluster.regions().search(b"a").unwrap().region, merged);
+
+        // the leader must be a peer of the region.
+        let err = cluster.handle_region_heartbeat(merged, Peer { id: 5, store_id: 5, ..Default::default() }).unwrap_err();
+        assert_eq!(err.r#type(), ErrorType::Unknown);
+    }
+
+    #[test]
+    fn store_heartbeats() {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        let stats = StoreStats { store_id: 1, capacity: 100, available: 60, used_size: 40, ..Default::default() };
+        let err = cluster.handle_store_heartbeat(stats.clone()).unwrap_err();
+        assert_eq!(err.r#type(), ErrorType::StoreNotFound);
+
+        cluster.put_store(Store { id: 1, address: "127.0.0.1:20160".to_string(), ..Default::default() }).unwrap();
+        assert!(cluster.get_store(1).unwrap().last_heartbeat.is_none());
+        cluster.handle_store_heartbeat(stats.clone()).unwrap();
+        let info = cluster.get_store(1).unwrap();
+        assert_eq!(info.stats, stats);
+        assert!(info.last_heartbeat.is_some());
+
+        // registering again keeps the stats.
+        cluster.put_store(Store { id: 1, address: "127.0.0.1:20161".to_string(), ..Default::default() }).unwrap();
+        assert_eq!(cluster.get_store(1).unwrap().stats, stats);
+        assert_eq!(cluster.get_store(1).unwrap().store.address, "127.0.0.1:20161");
+    }
+
+    #[test]
+    fn store_lifecycle() {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        for store_id in 1..=2 {
+            cluster.put_store(Store { id: store_id, ..Default::default() }).unwrap();
+        }
+        assert_eq!(cluster.remove_store(3).unwrap_err().r#type(), ErrorType::StoreNotFound);
+        cluster.remove_store(1).unwrap();
+        assert_eq!(cluster.get_store(1).unwrap().store.state(), StoreState::Offline);
+        // registering again does not bring it back.
+        cluster.put_store(Store { id: 1, ..Default::default() }).unwrap();
+        assert_eq!(cluster.get_store(1).unwrap().store.state(), StoreState::Offline);
+
+        // the store is buried once its last peer is gone.
+        let mut region = new_region(1, b"", b"", 1);
+        cluster.handle_region_heartbeat(region.clone(), leader(&region)).unwrap();
+        cluster.schedule();
+        assert_eq!(cluster.get_store(1).unwrap().store.state(), StoreState::Offline);
+        region.peers = vec![Peer { id: 11, store_id: 2, ..Default::default() }];
+        region.region_epoch.as_mut().unwrap().config_version += 1;
+        cluster.handle_region_heartbeat(region.clone(), leader(&region)).unwrap();
+        cluster.schedule();
+        assert_eq!(cluster.get_store(1).unwrap().store.state(), StoreState::Tomstone);
+        assert_eq!(cluster.get_store(2).unwrap().store.state(), StoreState::Up);
+
+        // a tombstone store cannot rejoin.
+        let err = cluster.put_store(Store { id: 1, ..Default::default() }).unwrap_err();
+        assert_eq!(err.r#type(), ErrorType::StoreTombstone);
+        let err = cluster.handle_store_heartbeat(StoreStats { store_id: 1, ..Default::default() }).unwrap_err();
+        assert_eq!(err.r#type(), ErrorType::StoreTombstone);
+        assert_eq!(cluster.remove_store(1).unwrap_err().r#type(), ErrorType::StoreTombstone);
+    }
+
+    #[test]
+    fn reload_from_storage() {
+        let storage = Arc::new(MemoryStorage::new());
+        let mut cluster = RaftCluster::new(&Config::default(), storage.clone()).unwrap();
+        let cluster_id = cluster.meta().id;
+        assert_ne!(cluster_id, 0);
+        cluster.put_meta(Cluster { max_peer_count: 5, ..Default::default() }).unwrap();
+        for store_id in 1..=2 {
+            cluster.put_store(Store { id: store_id, address: format!("store{}", store_id), ..Default::default() }).unwrap();
+        }
+        cluster.remove_store(2).unwrap();
+        for region in [new_region(1, b"", b"m", 2), new_region(2, b"m", b"t", 2), new_region(3, b"t", b"", 2)] {
+            cluster.handle_region_heartbeat(region.clone(), leader(&region)).unwrap();
+        }
+        // regions 2 and 3 are merged.
+        let merged = new_region(3, b"m", b"", 3);
+        cluster.handle_region_heartbeat(merged.clone(), leader(&merged)).unwrap();
+        let id = cluster.alloc_id().unwrap();
+        assert!(id > 30);
+        drop(cluster);
+
+        let cluster = RaftCluster::new(&Config::default(), storage).unwrap();
+        assert_eq!((cluster.meta().id, cluster.meta().max_peer_count), (cluster_id, 5));
+        assert_eq!(cluster.get_store(1).unwrap().store.address, "store1");
+        assert_eq!(cluster.get_store(2).unwrap().store.state(), StoreState::Offline);
+        assert!(cluster.get_store(1).unwrap().last_heartbeat.is_none());
+        let regions: Vec<_> = cluster.regions().iter().map(|info| (info.region.clone(), info.leader.clone())).collect();
+        assert_eq!(regions, vec![(new_region(1, b"", b"m", 2), None), (merged, None)]);
+        assert!(cluster.alloc_id().unwrap() > id);
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+/// Config of the scheduler server.
+#[derive(Debug, Clone)]
+pub struct Config {
+    // address the scheduler listens on, the stores
+    // reach it through their scheduler_addr.
+    pub addr: String,
+    // directory holding the scheduler's metadata.
+    pub data_dir: String,
+    // the timestamp oracle persists a high-water mark this far (ms)
+    // ahead of the timestamps it hands out.
+    pub tso_save_interval: Duration,
+    // number of ids the allocator persists ahead of the ids it hands out.
+    pub id_batch_size: u64,
+    // number of replicas of each region, the cluster can change it later.
+    pub max_peer_count: u32,
+    // the store labels the replicas of a region are isolated by, from
+    // the largest location to the smallest. Two replicas never share the
+    // last one, and share the others only when there is no other way.
+    pub location_labels: Vec<String>,
+    // a store using more than this ratio of its capacity only gets
+    // new peers when no other store can take them.
+    pub low_space_ratio: f64,
+    // how often the schedulers look for regions to move.
+    pub schedule_interval: Duration,
+    // a store that did not report for this long is not scheduled to.
+    pub max_store_down_time: Duration,
+    // maximum number of running operators of each kind.
+    pub replica_schedule_limit: usize,
+    pub region_schedule_limit: usize,
+    pub leader_schedule_limit: usize,
+    pub hot_region_schedule_limit: usize,
+    // operators not finished in time are cancelled.
+    pub region_operator_timeout: Duration,
+    pub leader_operator_timeout: Duration,
+    // a region is hot once its average read or write traffic, per
+    // second, stays above either rate for hot_region_min_degree heartbeats.
+    pub hot_region_bytes_rate: u64,
+    pub hot_region_keys_rate: u64,
+    pub hot_region_min_degree: u32,
+    // a lock wait not cleaned up by its store for this long is dropped
+    // from the wait-for graph.
+    pub deadlock_ttl: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            addr: "127.0.0.1:2379".to_string(),
+            data_dir: "/tmp/scheduler".to_string(),
+            tso_save_interval: Duration::from_secs(3),
+            id_batch_size: 1000,
+            max_peer_count: 3,
+            location_labels: vec!["zone".to_string(), "rack".to_string(), "host".to_string()],
+            low_space_ratio: 0.8,
+            schedule_interval: Duration::from_secs(1),
+            max_store_down_time: Duration::from_secs(30 * 60),
+            replica_schedule_limit: 4,
+            region_schedule_limit: 4,
+            leader_schedule_limit: 4,
+            hot_region_schedule_limit: 4,
+            region_operator_timeout: Duration::from_secs(10 * 60),
+            leader_operator_timeout: Duration::from_secs(10),
+            hot_region_bytes_rate: 1 << 20,
+            hot_region_keys_rate: 1000,
+            hot_region_min_degree: 3,
+            deadlock_ttl: Duration::from_secs(60),
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{collections::{HashMap, HashSet}, time::{Duration, Instant}};
+
+
+#[derive(Debug, Clone, Copy)]
+struct Wait {
+    key_hash: u64,
+    since: Instant,
+}
+
+/// WaitForGraph tracks which transactions wait for the locks of which
+/// others, across the cluster. A wait closing a cycle is a deadlock, it
+/// is refused and its transaction is the victim. Waits not cleaned up
+/// by their store, e.g. after a crash, expire after ttl.
+#[derive(Debug)]
+pub struct WaitForGraph {
+    // txn -> the transactions it waits for.
+    waits: HashMap<u64, HashMap<u64, Wait>>,
+    ttl: Duration,
+}
+
+impl WaitForGraph {
+    pub fn new(ttl: Duration) -> Self {
+        Self { waits: HashMap::new(), ttl }
+    }
+
+    // Registers that txn waits for the lock of wait_for_txn on the key
+    // hashed to key_hash. On a deadlock, returns the hash of the key
+    // txn holds in the cycle and registers nothing.
+    pub fn detect(&mut self, txn: u64, wait_for_txn: u64, key_hash: u64) -> Option<u64> {
+        self.expire();
+        if let Some(deadlock_key_hash) = self.find_wait(wait_for_txn, txn) {
+            return Some(deadlock_key_hash);
+        }
+        self.waits.entry(txn).or_default().insert(wait_for_txn, Wait { key_hash, since: Instant::now() });
+        None
+    }
+
+    pub fn clean_up_wait_for(&mut self, txn: u64, wait_for_txn: u64) {
+        if let Some(waits) = self.waits.get_mut(&txn) {
+            waits.remove(&wait_for_txn);
+            if waits.is_empty() {
+                self.waits.remove(&txn);
+            }
+        }
+    }
+
+    pub fn clean_up(&mut self, txn: u64) {
+        self.waits.remove(&txn);
+    }
+
+    fn expire(&mut self) {
+        let ttl = self.ttl;
+        self.waits.retain(|_, waits| {
+            waits.retain(|_, wait| wait.since.elapsed() <= ttl);
+            !waits.is_empty()
+        });
+    }
+
+    // Walks the waits from txn, returns the key hash of a wait for target.
+    fn find_wait(&self, txn: u64, target: u64) -> Option<u64> {
+        let mut visited = HashSet::from([txn]);
+        let mut stack = vec![txn];
+        while let Some(txn) = stack.pop() {
+            for (&wait_for_txn, wait) in self.waits.get(&txn).into_iter().flatten() {
+                if wait_for_txn == target {
+                    return Some(wait.key_hash);
+                }
+                if visited.insert(wait_for_txn) {
+                    stack.push(wait_for_txn);
+                }
+            }
+        }
+        None
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::WaitForGraph;
+
+    #[test]
+    fn detect_cycles() {
+        let mut graph = WaitForGraph::new(Duration::from_secs(60));
+        assert_eq!(graph.detect(1, 2, 12), None);
+        assert_eq!(graph.detect(2, 3, 23), None);
+        assert_eq!(graph.detect(4, 3, 43), None);
+        // 3 -> 1 closes the cycle, 2 waits for the key of 3 hashed to 23.
+        assert_eq!(graph.detect(3, 1, 31), Some(23));
+        assert_eq!(graph.detect(3, 4, 34), Some(43));
+
+        // once 2 stops waiting, there is no cycle left.
+        graph.clean_up_wait_for(2, 3);
+        assert_eq!(graph.detect(3, 1, 31), None);
+        assert_eq!(graph.detect(2, 3, 23), Some(12));
+        graph.clean_up(1);
+        assert_eq!(graph.detect(2, 3, 23), None);
+    }
+
+    #[test]
+    fn expire_waits() {
+        let mut graph = WaitForGraph::new(Duration::from_millis(50));
+        assert_eq!(graph.detect(1, 2, 12), None);
+        assert_eq!(graph.detect(2, 1, 21), Some(12));
+        std::thread::sleep(Duration::from_millis(100));
+        assert_eq!(graph.detect(2, 1, 21), None);
+        assert!(!graph.waits.contains_key(&1));
+    }
+}
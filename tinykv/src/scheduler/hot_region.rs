@@ -0,0 +1,322 @@
+use std::{collections::{HashMap, VecDeque}, time::Duration};
+
+use crate::{kv::raftstore::region::{find_peer, is_learner, region_epoch}, proto::schedulerpb::FlowKind};
+
+use super::{balance::{is_schedulable, move_peer}, cluster::{RaftCluster, RegionInfo}, config::Config, operator::{OpKind, OpStep, Operator}, placement::keeps_isolation};
+
+
+// The rates of a region are averaged over its last heartbeats.
+const ROLLING_WINDOW: usize = 5;
+
+/// RegionFlow is the traffic a region served over a heartbeat interval.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegionFlow {
+    pub read_bytes: u64,
+    pub read_keys: u64,
+    pub written_bytes: u64,
+    pub written_keys: u64,
+    pub interval: Duration,
+}
+
+impl RegionFlow {
+    // Bytes and keys per second.
+    fn rates(&self, kind: FlowKind) -> (f64, f64) {
+        let secs = self.interval.as_secs_f64();
+        let (bytes, keys) = match kind {
+            FlowKind::Write => (self.written_bytes, self.written_keys),
+            FlowKind::Read => (self.read_bytes, self.read_keys),
+        };
+        (bytes as f64 / secs, keys as f64 / secs)
+    }
+}
+
+/// HotPeerStat is the rolling read or write traffic of a region.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HotPeerStat {
+    pub region_id: u64,
+    // the store of the region leader.
+    pub store_id: u64,
+    pub bytes_rate: f64,
+    pub keys_rate: f64,
+    // grows with every hot heartbeat and decays with the cold ones.
+    pub hot_degree: u32,
+    samples: VecDeque<(f64, f64)>,
+}
+
+impl HotPeerStat {
+    fn new(region_id: u64, store_id: u64) -> Self {
+        Self { region_id, store_id, bytes_rate: 0.0, keys_rate: 0.0, hot_degree: 0, samples: VecDeque::new() }
+    }
+
+    fn add_sample(&mut self, (bytes_rate, keys_rate): (f64, f64), config: &Config) {
+        self.samples.push_back((bytes_rate, keys_rate));
+        if self.samples.len() > ROLLING_WINDOW {
+            self.samples.pop_front();
+        }
+        let count = self.samples.len() as f64;
+        self.bytes_rate = self.samples.iter().map(|(bytes, _)| bytes).sum::<f64>() / count;
+        self.keys_rate = self.samples.iter().map(|(_, keys)| keys).sum::<f64>() / count;
+        if self.bytes_rate >= config.hot_region_bytes_rate as f64 || self.keys_rate >= config.hot_region_keys_rate as f64 {
+            self.hot_degree += 1;
+        } else {
+            self.hot_degree = self.hot_degree.saturating_sub(1);
+        }
+    }
+}
+
+/// HotCache keeps the rolling traffic of the regions that served some
+/// recently, one entry per region and kind of flow.
+#[derive(Debug, Default)]
+pub struct HotCache {
+    write: HashMap<u64, HotPeerStat>,
+    read: HashMap<u64, HotPeerStat>,
+}
+
+impl HotCache {
+    // Adds the traffic reported by the leader of a region on store_id.
+    pub fn update(&mut self, region_id: u64, store_id: u64, flow: &RegionFlow, config: &Config) {
+        // there is no rate over an empty interval.
+        if flow.interval.is_zero() {
+            return;
+        }
+        for kind in [FlowKind::Write, FlowKind::Read] {
+            let stats = self.stats_mut(kind);
+            let stat = stats.entry(region_id).or_insert_with(|| HotPeerStat::new(region_id, store_id));
+            stat.store_id = store_id;
+            stat.add_sample(flow.rates(kind), config);
+            // forget the regions without any traffic over the whole window.
+            if stat.bytes_rate == 0.0 && stat.keys_rate == 0.0 {
+                stats.remove(&region_id);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, region_id: u64) {
+        self.write.remove(&region_id);
+        self.read.remove(&region_id);
+    }
+
+    // The regions hot for at least min_degree heartbeats, hottest first.
+    pub fn hot_regions(&self, kind: FlowKind, min_degree: u32) -> Vec<&HotPeerStat> {
+        let stats = match kind {
+            FlowKind::Write => &self.write,
+            FlowKind::Read => &self.read,
+        };
+        let mut hot: Vec<&HotPeerStat> = stats.values().filter(|stat| stat.hot_degree >= min_degree).collect();
+        hot.sort_by(|a, b| b.bytes_rate.total_cmp(&a.bytes_rate).then(a.region_id.cmp(&b.region_id)));
+        hot
+    }
+
+    fn stats_mut(&mut self, kind: FlowKind) -> &mut HashMap<u64, HotPeerStat> {
+        match kind {
+            FlowKind::Write => &mut self.write,
+            FlowKind::Read => &mut self.read,
+        }
+    }
+}
+
+// The stores serving the flow of a region: all of its
+// peers for the writes, only its leader for the reads.
+fn flow_stores(info: &RegionInfo, kind: FlowKind) -> Vec<u64> {
+    match kind {
+        FlowKind::Write => info.region.peers.iter().map(|peer| peer.store_id).collect(),
+        FlowKind::Read => info.leader.iter().map(|leader| leader.store_id).collect(),
+    }
+}
+
+// The bytes rate of the hot regions served by each serving store.
+fn store_loads(cluster: &RaftCluster, kind: FlowKind) -> HashMap<u64, f64> {
+    let max_down_time = cluster.config().max_store_down_time;
+    let mut loads: HashMap<u64, f64> = cluster.stores()
+        .filter(|info| info.is_serving(max_down_time))
+        .map(|info| (info.store.id, 0.0))
+        .collect();
+    for stat in cluster.hot_regions(kind) {
+        let Some(info) = cluster.regions().get(stat.region_id) else {
+            continue;
+        };
+        for store_id in flow_stores(info, kind) {
+            if let Some(load) = loads.get_mut(&store_id) {
+                *load += stat.bytes_rate;
+            }
+        }
+    }
+    loads
+}
+
+// The least loaded store that could take rate off source: a voter of
+// the region for the reads, a store without a peer of it, keeping the
+// replicas as isolated, for the writes. Moving must lower the load of
+// the hottest of both stores.
+fn pick_target(cluster: &RaftCluster, info: &RegionInfo, kind: FlowKind, source: u64, loads: &HashMap<u64, f64>, rate: f64) -> Option<u64> {
+    let source_load = loads[&source];
+    loads.iter()
+        .filter(|(&store_id, _)| match kind {
+            FlowKind::Write => find_peer(&info.region, store_id).is_none() && keeps_isolation(cluster, &info.region, source, store_id),
+            FlowKind::Read => store_id != source && find_peer(&info.region, store_id).is_some_and(|peer| !is_learner(peer)),
+        })
+        .filter(|(_, &load)| load + rate < source_load)
+        .min_by(|(a, a_load), (b, b_load)| a_load.total_cmp(b_load).then(a.cmp(b)))
+        .map(|(&store_id, _)| store_id)
+}
+
+// Spreads the hot regions of the store serving the heaviest flow of kind.
+// The leader of a hot region is moved to a cooler voter for the reads, a
+// peer to a cooler store for the writes. A hot region that cannot move as
+// a whole is split when one of its halves could.
+pub fn balance_hot_region(cluster: &RaftCluster, kind: FlowKind) -> Option<Operator> {
+    let loads = store_loads(cluster, kind);
+    let mut sources: Vec<(u64, f64)> = loads.iter().map(|(&store_id, &load)| (store_id, load)).collect();
+    sources.sort_by(|(a, a_load), (b, b_load)| b_load.total_cmp(a_load).then(a.cmp(b)));
+
+    for (source, load) in sources {
+        if load == 0.0 {
+            break;
+        }
+        let hot: Vec<(&RegionInfo, f64)> = cluster.hot_regions(kind).into_iter()
+            .filter_map(|stat| Some((cluster.regions().get(stat.region_id)?, stat.bytes_rate)))
+            .filter(|(info, _)| flow_stores(info, kind).contains(&source) && is_schedulable(cluster, info))
+            .collect();
+        for &(info, rate) in &hot {
+            let Some(target) = pick_target(cluster, info, kind, source, &loads, rate) else {
+                continue;
+            };
+            return match kind {
+                FlowKind::Read => {
+                    let step = OpStep::TransferLeader { from_store: source, to_store: target };
+                    Some(Operator::new(info.region.id, OpKind::Hot, vec![step], cluster.config().leader_operator_timeout))
+                },
+                FlowKind::Write => move_peer(cluster, info, source, target, OpKind::Hot),
+            };
+        }
+        for &(info, rate) in &hot {
+            if pick_target(cluster, info, kind, source, &loads, rate / 2.0).is_some() {
+                let step = OpStep::SplitRegion { version: region_epoch(&info.region).version };
+                return Some(Operator::new(info.region.id, OpKind::Hot, vec![step], cluster.config().region_operator_timeout));
+            }
+        }
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::{Peer, Region, RegionEpoch, Store};
+    use crate::proto::schedulerpb::{FlowKind, StoreStats};
+    use crate::scheduler::{cluster::RaftCluster, config::Config, operator::{OpKind, OpStep}};
+
+    use super::{balance_hot_region, HotCache, RegionFlow};
+
+    const MIB: u64 = 1 << 20;
+
+    fn new_cluster(store_count: u64) -> RaftCluster {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        for store_id in 1..=store_count {
+            cluster.put_store(Store { id: store_id, ..Default::default() }).unwrap();
+            cluster.handle_store_heartbeat(StoreStats { store_id, ..Default::default() }).unwrap();
+        }
+        cluster
+    }
+
+    // A region in [id, id + 1) with a peer on each store, led by the first one.
+    fn add_region(cluster: &mut RaftCluster, id: u8, stores: &[u64]) {
+        let region = Region {
+            id: id as u64,
+            start_key: vec![id],
+            end_key: vec![id + 1],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: stores.iter().map(|&store_id| Peer { id: id as u64 * 10 + store_id, store_id, ..Default::default() }).collect(),
+        };
+        let leader = region.peers[0].clone();
+        cluster.handle_region_heartbeat(region, leader).unwrap();
+    }
+
+    // Reports the same traffic for a few heartbeats, so that the region gets hot.
+    fn report_flow(cluster: &mut RaftCluster, region_id: u64, flow: RegionFlow) {
+        for _ in 0..cluster.config().hot_region_min_degree {
+            cluster.handle_region_flow(region_id, &flow);
+        }
+    }
+
+    fn flow(read_bytes: u64, written_bytes: u64) -> RegionFlow {
+        RegionFlow { read_bytes, written_bytes, interval: Duration::from_secs(10), ..Default::default() }
+    }
+
+    #[test]
+    fn rolling_stats() {
+        let config = Config::default();
+        let mut cache = HotCache::default();
+        cache.update(1, 1, &RegionFlow { written_bytes: 100 * MIB, ..Default::default() }, &config);
+        assert!(cache.hot_regions(FlowKind::Write, 0).is_empty());
+
+        // 2 MiB/s, then 0, averaged over the window.
+        for written_bytes in [20 * MIB, 20 * MIB, 20 * MIB, 0] {
+            cache.update(1, 1, &flow(0, written_bytes), &config);
+        }
+        let hot = cache.hot_regions(FlowKind::Write, 3);
+        assert_eq!(hot.len(), 1);
+        assert_eq!((hot[0].bytes_rate, hot[0].hot_degree), (1.5 * MIB as f64, 4));
+        assert!(cache.hot_regions(FlowKind::Read, 0).is_empty());
+
+        // a few small heartbeats cool the region down.
+        cache.update(1, 2, &RegionFlow { written_keys: 100, ..flow(0, 0) }, &config);
+        let hot = cache.hot_regions(FlowKind::Write, 0);
+        assert_eq!((hot[0].store_id, hot[0].hot_degree), (2, 5));
+        for _ in 0..4 {
+            cache.update(1, 2, &RegionFlow { written_keys: 100, ..flow(0, 0) }, &config);
+        }
+        assert_eq!(cache.hot_regions(FlowKind::Write, 0)[0].hot_degree, 1);
+        assert!(cache.hot_regions(FlowKind::Write, 3).is_empty());
+
+        // the region is dropped once it has no traffic at all.
+        for _ in 0..5 {
+            cache.update(1, 2, &flow(0, 0), &config);
+        }
+        assert!(cache.hot_regions(FlowKind::Write, 0).is_empty());
+    }
+
+    #[test]
+    fn spread_hot_reads() {
+        let mut cluster = new_cluster(3);
+        add_region(&mut cluster, 1, &[1, 2, 3]);
+        add_region(&mut cluster, 2, &[1, 2, 3]);
+        report_flow(&mut cluster, 1, flow(100 * MIB, 0));
+        assert_eq!(cluster.hot_regions(FlowKind::Read).len(), 1);
+        assert!(cluster.hot_regions(FlowKind::Write).is_empty());
+
+        // a single hot region cannot be spread by moving it around.
+        let op = balance_hot_region(&cluster, FlowKind::Read).unwrap();
+        assert_eq!((op.region_id, op.kind), (1, OpKind::Hot));
+        assert_eq!(op.steps, vec![OpStep::SplitRegion { version: 1 }]);
+
+        report_flow(&mut cluster, 2, flow(50 * MIB, 0));
+        let op = balance_hot_region(&cluster, FlowKind::Read).unwrap();
+        assert_eq!(op.region_id, 1);
+        assert_eq!(op.steps, vec![OpStep::TransferLeader { from_store: 1, to_store: 2 }]);
+    }
+
+    #[test]
+    fn spread_hot_writes() {
+        let mut cluster = new_cluster(4);
+        add_region(&mut cluster, 1, &[1, 2, 3]);
+        add_region(&mut cluster, 2, &[1, 2, 3]);
+        report_flow(&mut cluster, 1, flow(0, 100 * MIB));
+        report_flow(&mut cluster, 2, flow(0, 50 * MIB));
+
+        // the hottest region moves to the idle store.
+        let op = balance_hot_region(&cluster, FlowKind::Write).unwrap();
+        assert_eq!(op.region_id, 1);
+        assert!(matches!(op.steps[0], OpStep::AddLearner { store_id: 4, .. }));
+        assert_eq!(op.steps.last(), Some(&OpStep::RemovePeer { store_id: 1, peer_id: 11 }));
+
+        // all the stores host the hot region.
+        let mut cluster = new_cluster(3);
+        add_region(&mut cluster, 1, &[1, 2, 3]);
+        report_flow(&mut cluster, 1, flow(0, 100 * MIB));
+        assert!(balance_hot_region(&cluster, FlowKind::Write).is_none());
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use byteorder::{BigEndian, ByteOrder};
+use parking_lot::Mutex;
+
+use crate::kv::{error::{TkvError, TkvResult}, ColumnFamily, Storage};
+
+
+const ALLOC_ID_KEY: &[u8] = b"alloc_id";
+
+#[derive(Debug)]
+struct IdRange {
+    // the last id handed out.
+    last: u64,
+    // persisted upper bound of the ids handed out.
+    end: u64,
+}
+
+/// IdAllocator hands out the cluster wide unique ids of regions and
+/// peers. The upper bound of the ids is persisted a batch ahead, so
+/// that a restart skips what is left of the batch instead of reusing it.
+#[derive(Debug)]
+pub struct IdAllocator {
+    storage: Arc<dyn Storage>,
+    batch_size: u64,
+    range: Mutex<IdRange>,
+}
+
+impl IdAllocator {
+    pub fn new(storage: Arc<dyn Storage>, batch_size: u64) -> TkvResult<Self> {
+        let end = match storage.get(ColumnFamily::Default, ALLOC_ID_KEY)? {
+            Some(value) => BigEndian::read_u64(&value),
+            None => 0,
+        };
+        Ok(Self { storage, batch_size: batch_size.max(1), range: Mutex::new(IdRange { last: end, end }) })
+    }
+
+    pub fn alloc(&self) -> TkvResult<u64> {
+        self.alloc_batch(1)
+    }
+
+    // Returns the first of count consecutive ids.
+    pub fn alloc_batch(&self, count: u64) -> TkvResult<u64> {
+        if count == 0 {
+            return Err(TkvError::new("invalid id count 0".to_string()));
+        }
+        let mut range = self.range.lock();
+        if range.last + count > range.end {
+            let end = range.last + count + self.batch_size;
+            self.save(&mut range, end)?;
+        }
+        let first = range.last + 1;
+        range.last += count;
+        Ok(first)
+    }
+
+    // Makes sure the next ids are above id, an id that was not handed
+    // out by this allocator.
+    pub fn rebase(&self, id: u64) -> TkvResult<()> {
+        let mut range = self.range.lock();
+        if id <= range.last {
+            return Ok(());
+        }
+        if id > range.end {
+            self.save(&mut range, id + self.batch_size)?;
+        }
+        range.last = id;
+        Ok(())
+    }
+
+    fn save(&self, range: &mut IdRange, end: u64) -> TkvResult<()> {
+        let mut value = vec![0; 8];
+        BigEndian::write_u64(&mut value, end);
+        self.storage.put(ColumnFamily::Default, ALLOC_ID_KEY, value)?;
+        range.end = end;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+
+    use super::IdAllocator;
+
+    #[test]
+    fn persistent_ids() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let allocator = IdAllocator::new(storage.clone(), 10)?;
+        assert_eq!(allocator.alloc()?, 1);
+        assert_eq!(allocator.alloc_batch(15)?, 2);
+        assert_eq!(allocator.alloc()?, 17);
+        assert!(allocator.alloc_batch(0).is_err());
+
+        // a restart skips the rest of the batch.
+        let allocator = IdAllocator::new(storage.clone(), 10)?;
+        let id = allocator.alloc()?;
+        assert!(id > 17);
+
+        // ids seen elsewhere are never handed out.
+        allocator.rebase(100)?;
+        assert_eq!(allocator.alloc()?, 101);
+        allocator.rebase(50)?;
+        assert_eq!(allocator.alloc()?, 102);
+        let allocator = IdAllocator::new(storage, 10)?;
+        assert!(allocator.alloc()? > 102);
+        Ok(())
+    }
+}
@@ -0,0 +1,28 @@
+pub mod balance;
+pub mod client;
+pub mod cluster;
+pub mod config;
+pub mod deadlock;
+pub mod hot_region;
+pub mod id;
+pub mod operator;
+pub mod placement;
+pub mod region_cache;
+pub mod replica_checker;
+pub mod server;
+pub mod storage;
+pub mod tso;
+
+use crate::{kv::error::TkvError, proto::schedulerpb::{Error, ErrorType}};
+
+
+pub type SchedulerResult<T> = std::result::Result<T, Error>;
+
+pub fn new_error(error_type: ErrorType, message: String) -> Error {
+    Error { r#type: error_type as i32, message }
+}
+
+// The metadata could not be read or persisted.
+pub fn storage_error(err: TkvError) -> Error {
+    new_error(ErrorType::Unknown, format!("storage error: {:?}", err))
+}
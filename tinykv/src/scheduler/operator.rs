@@ -0,0 +1,249 @@
+use std::{collections::HashMap, time::{Duration, Instant}};
+
+use crate::{kv::raftstore::region::{find_peer, is_learner, region_epoch}, proto::{metapb::{Peer, PeerRole}, schedulerpb::{ChangePeer, ConfChangeType, RegionHeartbeatResponse, SplitRegion, TransferLeader}}};
+
+use super::cluster::RegionInfo;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpKind {
+    // fixes the number of replicas of a region.
+    Replica,
+    // moves peers of a region across stores.
+    Region,
+    // only moves the leadership of a region.
+    Leader,
+    // spreads the regions with a heavy traffic.
+    Hot,
+}
+
+/// OpStep is a single membership or leadership change of a region.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OpStep {
+    AddLearner { store_id: u64, peer_id: u64 },
+    PromoteLearner { store_id: u64, peer_id: u64 },
+    TransferLeader { from_store: u64, to_store: u64 },
+    RemovePeer { store_id: u64, peer_id: u64 },
+    // splits the region, version is its epoch version when the step was created.
+    SplitRegion { version: u64 },
+}
+
+impl OpStep {
+    // A step is finished once the region reported by its leader reflects it.
+    fn is_finished(&self, info: &RegionInfo) -> bool {
+        match *self {
+            OpStep::AddLearner { store_id, peer_id } => {
+                find_peer(&info.region, store_id).is_some_and(|peer| peer.id == peer_id)
+            },
+            OpStep::PromoteLearner { store_id, peer_id } => {
+                find_peer(&info.region, store_id).is_some_and(|peer| peer.id == peer_id && !is_learner(peer))
+            },
+            OpStep::TransferLeader { to_store, .. } => {
+                info.leader.as_ref().is_some_and(|leader| leader.store_id == to_store)
+            },
+            OpStep::RemovePeer { store_id, .. } => find_peer(&info.region, store_id).is_none(),
+            OpStep::SplitRegion { version } => region_epoch(&info.region).version > version,
+        }
+    }
+
+    // The heartbeat response asking the leader of the region to run the step.
+    pub fn to_response(&self, info: &RegionInfo) -> RegionHeartbeatResponse {
+        let change_peer = |change_type: ConfChangeType, store_id: u64, peer_id: u64, role: PeerRole| ChangePeer {
+            peer: Some(Peer { id: peer_id, store_id, role: role as i32 }),
+            change_type: change_type as i32,
+        };
+        let mut response = RegionHeartbeatResponse {
+            region_id: info.region.id,
+            region_epoch: Some(region_epoch(&info.region)),
+            ..Default::default()
+        };
+        match *self {
+            OpStep::AddLearner { store_id, peer_id } => {
+                response.change_peer = Some(change_peer(ConfChangeType::AddLearnerNode, store_id, peer_id, PeerRole::Learner));
+            },
+            OpStep::PromoteLearner { store_id, peer_id } => {
+                response.change_peer = Some(change_peer(ConfChangeType::AddNode, store_id, peer_id, PeerRole::Voter));
+            },
+            OpStep::TransferLeader { to_store, .. } => {
+                response.transfer_leader = Some(TransferLeader { peer: find_peer(&info.region, to_store).cloned() });
+            },
+            OpStep::RemovePeer { store_id, peer_id } => {
+                response.change_peer = Some(change_peer(ConfChangeType::RemoveNode, store_id, peer_id, PeerRole::Voter));
+            },
+            OpStep::SplitRegion { .. } => {
+                response.split_region = Some(SplitRegion {});
+            },
+        }
+        response
+    }
+}
+
+/// Operator is a sequence of steps moving a region toward a better
+/// placement. It is cancelled when it does not finish in time.
+#[derive(Debug, Clone)]
+pub struct Operator {
+    pub region_id: u64,
+    pub kind: OpKind,
+    pub steps: Vec<OpStep>,
+    // index of the first unfinished step.
+    current: usize,
+    created_at: Instant,
+    timeout: Duration,
+}
+
+impl Operator {
+    pub fn new(region_id: u64, kind: OpKind, steps: Vec<OpStep>, timeout: Duration) -> Self {
+        Self { region_id, kind, steps, current: 0, created_at: Instant::now(), timeout }
+    }
+
+    // Skips the steps already reflected by the region, returns the
+    // step to run next or None once the operator is finished.
+    pub fn check(&mut self, info: &RegionInfo) -> Option<&OpStep> {
+        while self.current < self.steps.len() && self.steps[self.current].is_finished(info) {
+            self.current += 1;
+        }
+        self.steps.get(self.current)
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.current >= self.steps.len()
+    }
+
+    pub fn is_timeout(&self) -> bool {
+        self.created_at.elapsed() > self.timeout
+    }
+}
+
+/// OperatorController keeps the running operators, at most one per region.
+#[derive(Debug, Default)]
+pub struct OperatorController {
+    operators: HashMap<u64, Operator>,
+}
+
+impl OperatorController {
+    // Returns false when the region already has an operator.
+    pub fn add(&mut self, op: Operator) -> bool {
+        if self.operators.contains_key(&op.region_id) {
+            return false;
+        }
+        log::info!("add operator {:?}", op);
+        self.operators.insert(op.region_id, op);
+        true
+    }
+
+    pub fn get(&self, region_id: u64) -> Option<&Operator> {
+        self.operators.get(&region_id)
+    }
+
+    pub fn count(&self, kind: OpKind) -> usize {
+        self.operators.values().filter(|op| op.kind == kind).count()
+    }
+
+    // Drops the operators that did not finish in time.
+    pub fn remove_timeouts(&mut self) {
+        self.operators.retain(|_, op| {
+            if op.is_timeout() {
+                log::warn!("operator {:?} timed out", op);
+            }
+            !op.is_timeout()
+        });
+    }
+
+    // Returns the step to run on a region that just reported, the
+    // operator is dropped once finished or timed out.
+    pub fn dispatch(&mut self, info: &RegionInfo) -> Option<OpStep> {
+        let op = self.operators.get_mut(&info.region.id)?;
+        if op.is_timeout() {
+            log::warn!("operator {:?} timed out", op);
+            self.operators.remove(&info.region.id);
+            return None;
+        }
+        match op.check(info).cloned() {
+            Some(step) => Some(step),
+            None => {
+                log::info!("operator of region {} finished", info.region.id);
+                self.operators.remove(&info.region.id);
+                None
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::proto::metapb::{Peer, PeerRole, Region, RegionEpoch};
+    use crate::proto::schedulerpb::ConfChangeType;
+    use crate::scheduler::cluster::RegionInfo;
+
+    use super::{OpKind, OpStep, Operator, OperatorController};
+
+    fn new_info(peers: Vec<Peer>, leader: usize) -> RegionInfo {
+        let region = Region {
+            id: 1,
+            region_epoch: Some(RegionEpoch { config_version: peers.len() as u64, version: 1 }),
+            peers,
+            ..Default::default()
+        };
+        let leader = Some(region.peers[leader].clone());
+        RegionInfo { region, leader }
+    }
+
+    fn voter(id: u64, store_id: u64) -> Peer {
+        Peer { id, store_id, ..Default::default() }
+    }
+
+    #[test]
+    fn move_peer_steps() {
+        let steps = vec![
+            OpStep::AddLearner { store_id: 3, peer_id: 5 },
+            OpStep::PromoteLearner { store_id: 3, peer_id: 5 },
+            OpStep::TransferLeader { from_store: 1, to_store: 3 },
+            OpStep::RemovePeer { store_id: 1, peer_id: 1 },
+        ];
+        let mut controller = OperatorController::default();
+        assert!(controller.add(Operator::new(1, OpKind::Region, steps.clone(), Duration::from_secs(60))));
+        assert!(!controller.add(Operator::new(1, OpKind::Leader, vec![], Duration::from_secs(60))));
+        assert_eq!(controller.count(OpKind::Region), 1);
+
+        let info = new_info(vec![voter(1, 1), voter(2, 2)], 0);
+        let step = controller.dispatch(&info).unwrap();
+        assert_eq!(step, steps[0]);
+        let change = step.to_response(&info).change_peer.unwrap();
+        assert_eq!(change.change_type(), ConfChangeType::AddLearnerNode);
+        assert_eq!(change.peer.unwrap().role(), PeerRole::Learner);
+
+        let learner = Peer { id: 5, store_id: 3, role: PeerRole::Learner as i32 };
+        let info = new_info(vec![voter(1, 1), voter(2, 2), learner], 0);
+        assert_eq!(controller.dispatch(&info), Some(steps[1].clone()));
+
+        // the leader moved and the peer was promoted within one heartbeat.
+        let info = new_info(vec![voter(1, 1), voter(2, 2), voter(5, 3)], 2);
+        let step = controller.dispatch(&info).unwrap();
+        assert_eq!(step, steps[3]);
+        assert_eq!(step.to_response(&info).change_peer.unwrap().change_type(), ConfChangeType::RemoveNode);
+
+        let info = new_info(vec![voter(2, 2), voter(5, 3)], 1);
+        assert_eq!(controller.dispatch(&info), None);
+        assert!(controller.get(1).is_none());
+    }
+
+    #[test]
+    fn operator_timeout() {
+        let mut controller = OperatorController::default();
+        let step = OpStep::TransferLeader { from_store: 1, to_store: 2 };
+        controller.add(Operator::new(1, OpKind::Leader, vec![step.clone()], Duration::ZERO));
+        let info = new_info(vec![voter(1, 1), voter(2, 2)], 0);
+        std::thread::sleep(Duration::from_millis(1));
+        assert_eq!(controller.dispatch(&info), None);
+        assert!(controller.get(1).is_none());
+
+        controller.add(Operator::new(1, OpKind::Leader, vec![step.clone()], Duration::from_secs(60)));
+        let response = controller.dispatch(&info).unwrap().to_response(&info);
+        assert_eq!(response.transfer_leader.unwrap().peer, Some(voter(2, 2)));
+        controller.remove_timeouts();
+        assert!(controller.get(1).is_some());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::proto::metapb::{Region, Store};
+
+use super::cluster::RaftCluster;
+
+
+fn label_value<'a>(store: &'a Store, key: &str) -> Option<&'a str> {
+    store.labels.iter().find(|label| label.key == key).map(|label| label.value.as_str())
+}
+
+// Number of location levels, from the largest, that two stores share.
+// A store missing a label shares nothing from that level on.
+pub fn shared_levels(a: &Store, b: &Store, location_labels: &[String]) -> usize {
+    location_labels.iter()
+        .take_while(|key| matches!((label_value(a, key), label_value(b, key)), (Some(x), Some(y)) if x == y))
+        .count()
+}
+
+// The most location levels store_id shares with one of the others, the
+// replicas of a region are the most isolated when it is the lowest.
+pub fn isolation_score(cluster: &RaftCluster, store_id: u64, others: &[u64]) -> usize {
+    let location_labels = &cluster.config().location_labels;
+    let Some(store) = cluster.get_store(store_id) else {
+        return 0;
+    };
+    others.iter()
+        .filter(|other| **other != store_id)
+        .filter_map(|other| cluster.get_store(*other))
+        .map(|other| shared_levels(&store.store, &other.store, location_labels))
+        .max()
+        .unwrap_or(0)
+}
+
+// Whether a replica on store_id would share a host, the smallest
+// location, with the replica on one of the others.
+pub fn shares_host(cluster: &RaftCluster, store_id: u64, others: &[u64]) -> bool {
+    let levels = cluster.config().location_labels.len();
+    levels > 0 && isolation_score(cluster, store_id, others) == levels
+}
+
+// Whether moving the replica of region on source to target leaves
+// the replicas at least as isolated as they are.
+pub fn keeps_isolation(cluster: &RaftCluster, region: &Region, source: u64, target: u64) -> bool {
+    let others: Vec<u64> = region.peers.iter()
+        .map(|peer| peer.store_id)
+        .filter(|store_id| *store_id != source)
+        .collect();
+    !shares_host(cluster, target, &others)
+        && isolation_score(cluster, target, &others) <= isolation_score(cluster, source, &others)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::{Peer, Region, Store, StoreLabel};
+    use crate::scheduler::{cluster::RaftCluster, config::Config};
+
+    use super::{isolation_score, keeps_isolation, shared_levels, shares_host};
+
+    fn new_store(id: u64, location: &[&str]) -> Store {
+        let labels = ["zone", "rack", "host"].iter().zip(location)
+            .map(|(key, value)| StoreLabel { key: key.to_string(), value: value.to_string() })
+            .collect();
+        Store { id, labels, ..Default::default() }
+    }
+
+    #[test]
+    fn isolation() {
+        let location_labels = Config::default().location_labels;
+        let store = new_store(1, &["z1", "r1", "h1"]);
+        assert_eq!(shared_levels(&store, &new_store(2, &["z1", "r1", "h2"]), &location_labels), 2);
+        assert_eq!(shared_levels(&store, &new_store(2, &["z1", "r2", "h1"]), &location_labels), 1);
+        assert_eq!(shared_levels(&store, &new_store(2, &["z1"]), &location_labels), 1);
+        assert_eq!(shared_levels(&new_store(1, &[]), &new_store(2, &[]), &location_labels), 0);
+
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        let locations = [["z1", "r1", "h1"], ["z1", "r1", "h2"], ["z1", "r2", "h3"], ["z1", "r3", "h4"], ["z1", "r1", "h1"], ["z1", "r1", "h6"]];
+        for (store_id, location) in (1..).zip(locations) {
+            cluster.put_store(new_store(store_id, &location)).unwrap();
+        }
+        assert_eq!(isolation_score(&cluster, 1, &[2, 3]), 2);
+        assert_eq!(isolation_score(&cluster, 4, &[2, 3]), 1);
+        assert!(shares_host(&cluster, 5, &[1, 3]));
+        assert!(!shares_host(&cluster, 2, &[1, 3]));
+
+        // the region is on racks r1, r1 and r2.
+        let region = Region {
+            peers: [1, 2, 3].iter().map(|&store_id| Peer { id: store_id, store_id, ..Default::default() }).collect(),
+            ..Default::default()
+        };
+        assert!(keeps_isolation(&cluster, &region, 1, 4));
+        assert!(keeps_isolation(&cluster, &region, 3, 4));
+        assert!(!keeps_isolation(&cluster, &region, 3, 6));
+        assert!(!keeps_isolation(&cluster, &region, 2, 5));
+    }
+}
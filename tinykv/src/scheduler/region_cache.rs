@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use parking_lot::RwLock;
+
+use crate::{kv::error::TkvResult, proto::errorpb};
+
+use super::{client::SchedulerClient, cluster::{RegionInfo, RegionTree}};
+
+
+/// RegionCache keeps the routing information fetched from the scheduler,
+/// so that a client can send its requests straight to the region leaders.
+/// Entries are dropped when a store reports that they are out of date.
+#[derive(Debug)]
+pub struct RegionCache {
+    client: SchedulerClient,
+    regions: RwLock<RegionTree>,
+    // store_id -> address
+    stores: RwLock<HashMap<u64, String>>,
+}
+
+impl RegionCache {
+    pub fn new(client: SchedulerClient) -> Self {
+        Self { client, regions: RwLock::new(RegionTree::default()), stores: RwLock::new(HashMap::new()) }
+    }
+
+    // Finds the region holding key and its leader.
+    pub async fn locate_key(&self, key: &[u8]) -> TkvResult<RegionInfo> {
+        if let Some(info) = self.regions.read().search(key) {
+            return Ok(info.clone());
+        }
+        let info = self.client.get_region(key).await?;
+        self.regions.write().insert(info.clone());
+        Ok(info)
+    }
+
+    pub async fn locate_region_by_id(&self, region_id: u64) -> TkvResult<RegionInfo> {
+        if let Some(info) = self.regions.read().get(region_id) {
+            return Ok(info.clone());
+        }
+        let info = self.client.get_region_by_id(region_id).await?;
+        self.regions.write().insert(info.clone());
+        Ok(info)
+    }
+
+    pub async fn store_addr(&self, store_id: u64) -> TkvResult<String> {
+        if let Some(addr) = self.stores.read().get(&store_id) {
+            return Ok(addr.clone());
+        }
+        let (store, _) = self.client.get_store(store_id).await?;
+        self.stores.write().insert(store_id, store.address.clone());
+        Ok(store.address)
+    }
+
+    // Updates the cache after a store rejected a request for region_id.
+    pub fn on_region_error(&self, region_id: u64, err: &errorpb::Error) {
+        if let Some(not_leader) = &err.not_leader {
+            let mut regions = self.regions.write();
+            let info = regions.get(not_leader.region_id).cloned();
+            match (info, &not_leader.leader) {
+                // follow the leader known by the store.
+                (Some(mut info), Some(leader)) if info.region.peers.contains(leader) => {
+                    info.leader = Some(leader.clone());
+                    regions.insert(info);
+                },
+                _ => {
+                    regions.remove(not_leader.region_id);
+                },
+            }
+        } else if err.epoch_not_match.is_some() || err.region_not_found.is_some() {
+            self.invalidate_region(region_id);
+        }
+    }
+
+    pub fn invalidate_region(&self, region_id: u64) {
+        self.regions.write().remove(region_id);
+    }
+
+    // Forgets the address of a store that could not be reached.
+    pub fn invalidate_store(&self, store_id: u64) {
+        self.stores.write().remove(&store_id);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::errorpb::{EpochNotMatch, Error, NotLeader, RegionNotFound};
+    use crate::proto::metapb::{Cluster, Peer, Region, RegionEpoch, Store};
+    use crate::proto::schedulerpb::scheduler_server::SchedulerServer;
+    use crate::scheduler::{client::SchedulerClient, config::Config, server::SchedulerService};
+
+    use super::RegionCache;
+
+    fn new_region(id: u64, start_key: &[u8], end_key: &[u8], version: u64) -> Region {
+        Region {
+            id,
+            start_key: start_key.to_vec(),
+            end_key: end_key.to_vec(),
+            region_epoch: Some(RegionEpoch { config_version: 1, version }),
+            peers: (1..=3).map(|store_id| Peer { id: id * 10 + store_id, store_id, ..Default::default() }).collect(),
+        }
+    }
+
+    #[tokio::test]
+    async fn route_and_invalidate() -> TkvResult<()> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let service = SchedulerService::new(&Config::default(), Arc::new(MemoryStorage::new()))?;
+        let cluster = service.cluster();
+        let server = tokio::spawn(Server::builder()
+            .add_service(SchedulerServer::new(service))
+            .serve_with_incoming(TcpListenerStream::new(listener)));
+
+        {
+            let mut cluster = cluster.write();
+            cluster.put_store(Store { id: 2, address: "127.0.0.1:20162".to_string(), ..Default::default() }).unwrap();
+            for region in [new_region(1, b"", b"m", 2), new_region(2, b"m", b"", 2)] {
+                let leader = region.peers[0].clone();
+                cluster.handle_region_heartbeat(region, leader).unwrap();
+            }
+        }
+        let client = SchedulerClient::connect(&addr.to_string())?;
+        let infos = client.scan_regions(b"a", b"", 0).await?;
+        assert_eq!(infos.iter().map(|info| info.region.id).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(client.scan_regions(b"a", b"m", 0).await?.len(), 1);
+        assert_eq!(client.scan_regions(b"", b"", 1).await?.len(), 1);
+        assert!(client.get_region_by_id(3).await.is_err());
+
+        assert_eq!(client.get_cluster_config().await?.max_peer_count, 3);
+        client.put_cluster_config(Cluster { max_peer_count: 5, ..Default::default() }).await?;
+        assert_eq!(client.get_cluster_config().await?.max_peer_count, 5);
+
+        let cache = RegionCache::new(client);
+        assert_eq!(cache.store_addr(2).await?, "127.0.0.1:20162");
+        assert!(cache.store_addr(3).await.is_err());
+        let info = cache.locate_key(b"z").await?;
+        assert_eq!((info.region.id, info.leader.unwrap().id), (2, 21));
+
+        // region 2 splits, the cache is stale until a store says so.
+        {
+            let mut cluster = cluster.write();
+            for region in [new_region(2, b"m", b"t", 3), new_region(3, b"t", b"", 3)] {
+                let leader = region.peers[1].clone();
+                cluster.handle_region_heartbeat(region, leader).unwrap();
+            }
+        }
+        assert_eq!(cache.locate_key(b"z").await?.region.id, 2);
+        let err = Error { epoch_not_match: Some(EpochNotMatch::default()), ..Default::default() };
+        cache.on_region_error(2, &err);
+        let info = cache.locate_key(b"z").await?;
+        assert_eq!((info.region.id, info.leader.unwrap().id), (3, 32));
+        assert_eq!(cache.locate_key(b"n").await?.region, new_region(2, b"m", b"t", 3));
+
+        // the leader is moved without asking the scheduler.
+        let err = Error {
+            not_leader: Some(NotLeader { region_id: 1, leader: Some(Peer { id: 13, store_id: 3, ..Default::default() }) }),
+            ..Default::default()
+        };
+        cache.locate_key(b"a").await?;
+        cache.on_region_error(1, &err);
+        assert_eq!(cache.locate_key(b"a").await?.leader.unwrap().id, 13);
+        assert_eq!(cluster.read().regions().get(1).unwrap().leader.as_ref().unwrap().id, 11);
+
+        let err = Error { region_not_found: Some(RegionNotFound { region_id: 1 }), ..Default::default() };
+        cache.on_region_error(1, &err);
+        assert_eq!(cache.locate_region_by_id(1).await?.leader.unwrap().id, 11);
+
+        server.abort();
+        Ok(())
+    }
+}
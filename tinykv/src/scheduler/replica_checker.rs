@@ -0,0 +1,281 @@
+use std::{cmp::Reverse, collections::HashMap};
+
+use crate::{kv::raftstore::region::{find_peer, is_learner}, proto::metapb::{Peer, Region}};
+
+use super::{balance::store_counts, cluster::{RaftCluster, RegionInfo}, operator::{OpKind, OpStep, Operator}, placement::{isolation_score, shares_host}};
+
+
+// Stores are ranked by the space they have left, then by the number
+// of regions they hold, then by their free space. Lower is better.
+type StoreRank = (bool, usize, Reverse<u64>);
+
+fn store_ranks(cluster: &RaftCluster) -> HashMap<u64, StoreRank> {
+    let low_space_ratio = cluster.config().low_space_ratio;
+    store_counts(cluster, false).into_iter()
+        .filter_map(|(store_id, count)| {
+            let info = cluster.get_store(store_id)?;
+            Some((store_id, (info.is_low_space(low_space_ratio), count, Reverse(info.stats.available))))
+        })
+        .collect()
+}
+
+fn other_stores(region: &Region, peer_id: u64) -> Vec<u64> {
+    region.peers.iter().filter(|peer| peer.id != peer_id).map(|peer| peer.store_id).collect()
+}
+
+// The best serving store not holding a peer of region yet, the most
+// isolated from the stores of the other replicas first. A store sharing
+// a host with one of them is never picked.
+fn best_store(cluster: &RaftCluster, ranks: &HashMap<u64, StoreRank>, region: &Region, others: &[u64]) -> Option<u64> {
+    ranks.iter()
+        .filter(|(store_id, _)| find_peer(region, **store_id).is_none() && !shares_host(cluster, **store_id, others))
+        .min_by_key(|(store_id, rank)| (isolation_score(cluster, **store_id, others), **rank, **store_id))
+        .map(|(store_id, _)| *store_id)
+}
+
+// The voter to drop from an over-replicated region: one on a down
+// store first, then the least isolated one, then the one on the worst
+// store. Never the leader.
+fn worst_peer<'a>(cluster: &RaftCluster, ranks: &HashMap<u64, StoreRank>, info: &'a RegionInfo) -> Option<&'a Peer> {
+    let leader = info.leader.as_ref()?;
+    info.region.peers.iter()
+        .filter(|peer| peer.id != leader.id && !is_learner(peer))
+        .max_by_key(|peer| (
+            ranks.get(&peer.store_id).is_none(),
+            isolation_score(cluster, peer.store_id, &other_stores(&info.region, peer.id)),
+            ranks.get(&peer.store_id).copied(),
+            peer.store_id,
+        ))
+}
+
+// The voter sharing the most locations with the other replicas, along
+// with a store that would isolate it better, if there is one.
+fn poorly_isolated<'a>(cluster: &RaftCluster, ranks: &HashMap<u64, StoreRank>, voters: &[&'a Peer], region: &Region) -> Option<(&'a Peer, u64)> {
+    let mut scored: Vec<(usize, &Peer)> = voters.iter()
+        .map(|peer| (isolation_score(cluster, peer.store_id, &other_stores(region, peer.id)), *peer))
+        .filter(|(score, _)| *score > 0)
+        .collect();
+    scored.sort_by_key(|(score, peer)| (Reverse(*score), peer.store_id));
+    scored.into_iter().find_map(|(score, peer)| {
+        let others = other_stores(region, peer.id);
+        let target = best_store(cluster, ranks, region, &others)?;
+        (isolation_score(cluster, target, &others) < score).then_some((peer, target))
+    })
+}
+
+// Returns the store to add a peer to, if any, and the steps run once
+// the new peer is a voter.
+fn plan(cluster: &RaftCluster, info: &RegionInfo) -> Option<(Option<u64>, Vec<OpStep>)> {
+    let leader = info.leader.as_ref()?;
+    let max_peer_count = cluster.meta().max_peer_count as usize;
+    let ranks = store_ranks(cluster);
+    let is_down = |peer: &Peer| !ranks.contains_key(&peer.store_id);
+    let voters: Vec<&Peer> = info.region.peers.iter().filter(|peer| !is_learner(peer)).collect();
+
+    // a learner left behind by a cancelled operator.
+    if let Some(learner) = info.region.peers.iter().find(|peer| is_learner(peer)) {
+        let step = if voters.len() < max_peer_count && !is_down(learner) {
+            OpStep::PromoteLearner { store_id: learner.store_id, peer_id: learner.id }
+        } else {
+            OpStep::RemovePeer { store_id: learner.store_id, peer_id: learner.id }
+        };
+        return Some((None, vec![step]));
+    }
+    if voters.len() < max_peer_count {
+        let others: Vec<u64> = info.region.peers.iter().map(|peer| peer.store_id).collect();
+        return Some((Some(best_store(cluster, &ranks, &info.region, &others)?), vec![]));
+    }
+    // the leader leaves a store being removed before its peer is replaced.
+    if is_down(leader) {
+        let to = voters.iter()
+            .filter(|peer| ranks.contains_key(&peer.store_id))
+            .min_by_key(|peer| (ranks[&peer.store_id], peer.store_id))?;
+        return Some((None, vec![OpStep::TransferLeader { from_store: leader.store_id, to_store: to.store_id }]));
+    }
+    // a peer on a down or offline store is replaced before it is removed.
+    if let Some(down) = voters.iter().find(|peer| is_down(peer) && peer.id != leader.id) {
+        let remove = OpStep::RemovePeer { store_id: down.store_id, peer_id: down.id };
+        let others = other_stores(&info.region, down.id);
+        return Some((Some(best_store(cluster, &ranks, &info.region, &others)?), vec![remove]));
+    }
+    if voters.len() > max_peer_count {
+        let peer = worst_peer(cluster, &ranks, info)?;
+        return Some((None, vec![OpStep::RemovePeer { store_id: peer.store_id, peer_id: peer.id }]));
+    }
+    // a replica sharing a location with another one moves to a store
+    // isolating it better, once one is available.
+    let (peer, target) = poorly_isolated(cluster, &ranks, &voters, &info.region)?;
+    let mut then = vec![];
+    if peer.id == leader.id {
+        then.push(OpStep::TransferLeader { from_store: peer.store_id, to_store: target });
+    }
+    then.push(OpStep::RemovePeer { store_id: peer.store_id, peer_id: peer.id });
+    Some((Some(target), then))
+}
+
+// Keeps the region at the cluster's max_peer_count voters, adding
+// peers to under-replicated regions, replacing the peers on down or
+// offline stores and removing the extra ones. It also moves the
+// replicas sharing a location when a better isolated store is up.
+pub fn check_replicas(cluster: &RaftCluster, info: &RegionInfo) -> Option<Operator> {
+    let (add_to, then) = plan(cluster, info)?;
+    let mut steps = vec![];
+    if let Some(store_id) = add_to {
+        let peer_id = cluster.alloc_id().ok()?;
+        steps.push(OpStep::AddLearner { store_id, peer_id });
+        steps.push(OpStep::PromoteLearner { store_id, peer_id });
+    }
+    steps.extend(then);
+    Some(Operator::new(info.region.id, OpKind::Replica, steps, cluster.config().region_operator_timeout))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::proto::metapb::{Cluster, Peer, PeerRole, Region, RegionEpoch, Store, StoreLabel};
+    use crate::proto::schedulerpb::StoreStats;
+    use crate::scheduler::{cluster::{RaftCluster, RegionInfo}, config::Config, operator::OpStep};
+
+    use super::check_replicas;
+
+    fn new_cluster(stats: &[(u64, u64, u64)]) -> RaftCluster {
+        let mut cluster = RaftCluster::new(&Config::default(), Arc::new(MemoryStorage::new())).unwrap();
+        for &(store_id, capacity, used_size) in stats {
+            cluster.put_store(Store { id: store_id, ..Default::default() }).unwrap();
+            let stats = StoreStats { store_id, capacity, used_size, available: capacity - used_size, ..Default::default() };
+            cluster.handle_store_heartbeat(stats).unwrap();
+        }
+        cluster
+    }
+
+    fn new_info(id: u64, peers: &[Peer]) -> RegionInfo {
+        let region = Region {
+            id,
+            start_key: vec![id as u8],
+            end_key: vec![id as u8 + 1],
+            region_epoch: Some(RegionEpoch { config_version: 1, version: 1 }),
+            peers: peers.to_vec(),
+        };
+        RegionInfo { leader: Some(region.peers[0].clone()), region }
+    }
+
+    fn voter(id: u64, store_id: u64) -> Peer {
+        Peer { id, store_id, ..Default::default() }
+    }
+
+    #[test]
+    fn add_missing_replicas() {
+        // store 3 is nearly full, store 4 is the emptiest.
+        let mut cluster = new_cluster(&[(1, 100, 10), (2, 100, 10), (3, 100, 90), (4, 100, 5), (5, 100, 10)]);
+        let info = new_info(1, &[voter(1, 1), voter(2, 2), voter(3, 3)]);
+        cluster.handle_region_heartbeat(info.region.clone(), voter(1, 1)).unwrap();
+        assert!(check_replicas(&cluster, &info).is_none());
+
+        let info = new_info(2, &[voter(4, 1)]);
+        cluster.handle_region_heartbeat(info.region.clone(), voter(4, 1)).unwrap();
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![
+            OpStep::AddLearner { store_id: 4, peer_id: 5 },
+            OpStep::PromoteLearner { store_id: 4, peer_id: 5 },
+        ]);
+
+        // store 5 holds fewer regions than store 2, the low space store 3 comes last.
+        let info = new_info(2, &[voter(4, 1), voter(5, 4)]);
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps[0], OpStep::AddLearner { store_id: 5, peer_id: 6 });
+
+        // a learner is promoted rather than replaced.
+        let learner = Peer { id: 6, store_id: 5, role: PeerRole::Learner as i32 };
+        let op = check_replicas(&cluster, &new_info(2, &[voter(4, 1), voter(5, 4), learner])).unwrap();
+        assert_eq!(op.steps, vec![OpStep::PromoteLearner { store_id: 5, peer_id: 6 }]);
+    }
+
+    #[test]
+    fn replace_and_remove_replicas() {
+        let mut cluster = new_cluster(&[(1, 100, 10), (2, 100, 10), (3, 100, 10), (4, 100, 20)]);
+        // store 5 is known but never reported.
+        cluster.put_store(Store { id: 5, ..Default::default() }).unwrap();
+
+        let info = new_info(1, &[voter(1, 1), voter(2, 2), voter(3, 5)]);
+        cluster.handle_region_heartbeat(info.region.clone(), voter(1, 1)).unwrap();
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![
+            OpStep::AddLearner { store_id: 3, peer_id: 4 },
+            OpStep::PromoteLearner { store_id: 3, peer_id: 4 },
+            OpStep::RemovePeer { store_id: 5, peer_id: 3 },
+        ]);
+
+        // the peer on the store holding the most regions goes, the leader stays.
+        let info = new_info(1, &[voter(1, 4), voter(2, 1), voter(3, 2), voter(4, 3)]);
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![OpStep::RemovePeer { store_id: 2, peer_id: 3 }]);
+
+        let mut cluster = RaftCluster::new(&Config { max_peer_count: 1, ..Default::default() }, Arc::new(MemoryStorage::new())).unwrap();
+        assert!(cluster.put_meta(Cluster { max_peer_count: 0, ..Default::default() }).is_err());
+        assert!(check_replicas(&cluster, &new_info(1, &[voter(1, 1)])).is_none());
+    }
+
+    #[test]
+    fn drain_offline_store() {
+        let mut cluster = new_cluster(&[(1, 100, 10), (2, 100, 10), (3, 100, 10), (4, 100, 10)]);
+        cluster.remove_store(1).unwrap();
+        let info = new_info(1, &[voter(1, 1), voter(2, 2), voter(3, 3)]);
+        cluster.handle_region_heartbeat(info.region.clone(), voter(1, 1)).unwrap();
+
+        // the leader moves away first.
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![OpStep::TransferLeader { from_store: 1, to_store: 2 }]);
+
+        let info = RegionInfo { leader: Some(voter(2, 2)), ..info };
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![
+            OpStep::AddLearner { store_id: 4, peer_id: 4 },
+            OpStep::PromoteLearner { store_id: 4, peer_id: 4 },
+            OpStep::RemovePeer { store_id: 1, peer_id: 1 },
+        ]);
+    }
+
+    #[test]
+    fn isolate_replicas() {
+        let mut cluster = new_cluster(&[]);
+        // stores 1 and 4 share a host, stores 1 and 2 a rack.
+        let add_store = |cluster: &mut RaftCluster, store_id: u64, rack: &str, host: &str| {
+            let labels = [("zone", "z1"), ("rack", rack), ("host", host)].into_iter()
+                .map(|(key, value)| StoreLabel { key: key.to_string(), value: value.to_string() })
+                .collect();
+            cluster.put_store(Store { id: store_id, labels, ..Default::default() }).unwrap();
+            cluster.handle_store_heartbeat(StoreStats { store_id, ..Default::default() }).unwrap();
+        };
+        add_store(&mut cluster, 1, "r1", "h1");
+        add_store(&mut cluster, 2, "r1", "h2");
+        add_store(&mut cluster, 3, "r2", "h3");
+        add_store(&mut cluster, 4, "r1", "h1");
+
+        let info = new_info(1, &[voter(1, 1)]);
+        cluster.handle_region_heartbeat(info.region.clone(), voter(1, 1)).unwrap();
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps[0], OpStep::AddLearner { store_id: 3, peer_id: 2 });
+        // a rack is shared only when there is no other way, a host never.
+        let op = check_replicas(&cluster, &new_info(1, &[voter(1, 1), voter(2, 3)])).unwrap();
+        assert_eq!(op.steps[0], OpStep::AddLearner { store_id: 2, peer_id: 3 });
+        let info = new_info(1, &[voter(1, 1), voter(2, 3), voter(3, 2)]);
+        assert!(check_replicas(&cluster, &info).is_none());
+
+        // a third rack comes up, one of the replicas of rack r1 moves to it.
+        add_store(&mut cluster, 5, "r3", "h5");
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![
+            OpStep::AddLearner { store_id: 5, peer_id: 4 },
+            OpStep::PromoteLearner { store_id: 5, peer_id: 4 },
+            OpStep::TransferLeader { from_store: 1, to_store: 5 },
+            OpStep::RemovePeer { store_id: 1, peer_id: 1 },
+        ]);
+        // an extra replica sharing a rack goes first, the one on the busiest store.
+        let info = new_info(1, &[voter(1, 3), voter(2, 1), voter(3, 2), voter(4, 5)]);
+        let op = check_replicas(&cluster, &info).unwrap();
+        assert_eq!(op.steps, vec![OpStep::RemovePeer { store_id: 1, peer_id: 2 }]);
+    }
+}
@@ -0,0 +1,251 @@
+use std::{sync::Arc, time::Duration};
+
+use parking_lot::{Mutex, RwLock};
+use tokio::task::JoinHandle;
+
+use crate::{kv::{error::TkvResult, Storage}, proto::schedulerpb::{scheduler_server::Scheduler, AllocIdRequest, AllocIdResponse, DetectRequest, DetectResponse, DetectType, ErrorType, GetAllStoresRequest, GetAllStoresResponse, GetClusterConfigRequest, GetClusterConfigResponse, GetHotRegionsRequest, GetHotRegionsResponse, GetRegionByIdRequest, GetRegionRequest, GetRegionResponse, GetStoreRequest, GetStoreResponse, HotRegion, PutClusterConfigRequest, PutClusterConfigResponse, PutStoreRequest, PutStoreResponse, RegionHeartbeatRequest, RegionHeartbeatResponse, RemoveStoreRequest, RemoveStoreResponse, ResponseHeader, ScanRegionsRequest, ScanRegionsResponse, StoreHeartbeatRequest, StoreHeartbeatResponse, Timestamp, TsoRequest, TsoResponse}};
+
+use super::{cluster::{RaftCluster, RegionInfo}, config::Config, deadlock::WaitForGraph, hot_region::RegionFlow, new_error, tso::{extract_logical, extract_physical, Tso}, SchedulerResult};
+
+
+// Errors are reported in the response header rather than as a status,
+// so that clients can tell them apart from transport errors.
+fn header(result: SchedulerResult<()>) -> Option<ResponseHeader> {
+    Some(ResponseHeader { error: result.err() })
+}
+
+fn region_response(region_id: u64, info: Option<&RegionInfo>) -> GetRegionResponse {
+    match info {
+        Some(info) => GetRegionResponse {
+            header: header(Ok(())),
+            region: Some(info.region.clone()),
+            leader: info.leader.clone(),
+        },
+        None => GetRegionResponse {
+            header: header(Err(new_error(ErrorType::RegionNotFound, format!("region {} not found", region_id)))),
+            ..Default::default()
+        },
+    }
+}
+
+/// SchedulerService is the gRPC front of the scheduler.
+#[derive(Debug, Clone)]
+pub struct SchedulerService {
+    cluster: Arc<RwLock<RaftCluster>>,
+    tso: Arc<Tso>,
+    wait_for_graph: Arc<Mutex<WaitForGraph>>,
+    schedule_interval: Duration,
+}
+
+impl SchedulerService {
+    // The scheduler's metadata lives in storage.
+    pub fn new(config: &Config, storage: Arc<dyn Storage>) -> TkvResult<Self> {
+        let cluster = RaftCluster::new(config, storage.clone())?;
+        let tso = Tso::new(storage, config.tso_save_interval)?;
+        Ok(Self {
+            cluster: Arc::new(RwLock::new(cluster)),
+            tso: Arc::new(tso),
+            wait_for_graph: Arc::new(Mutex::new(WaitForGraph::new(config.deadlock_ttl))),
+            schedule_interval: config.schedule_interval,
+        })
+    }
+
+    pub fn cluster(&self) -> Arc<RwLock<RaftCluster>> {
+        self.cluster.clone()
+    }
+
+    // Periodically runs the schedulers, their operators are handed
+    // to the region leaders in the heartbeat responses.
+    pub fn spawn_coordinator(&self) -> JoinHandle<()> {
+        let cluster = self.cluster.clone();
+        let mut interval = tokio::time::interval(self.schedule_interval);
+        tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                cluster.write().schedule();
+            }
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Scheduler for SchedulerService {
+
+    async fn put_store(&self, request: tonic::Request<PutStoreRequest>) -> Result<tonic::Response<PutStoreResponse>, tonic::Status> {
+        let result = match request.into_inner().store {
+            Some(store) => self.cluster.write().put_store(store),
+            None => Err(new_error(ErrorType::Unknown, "missing store".to_string())),
+        };
+        Ok(tonic::Response::new(PutStoreResponse { header: header(result) }))
+    }
+
+    async fn get_store(&self, request: tonic::Request<GetStoreRequest>) -> Result<tonic::Response<GetStoreResponse>, tonic::Status> {
+        let store_id = request.into_inner().store_id;
+        let response = match self.cluster.read().get_store(store_id) {
+            Some(info) => GetStoreResponse {
+                header: header(Ok(())),
+                store: Some(info.store.clone()),
+                stats: Some(info.stats.clone()),
+            },
+            None => GetStoreResponse {
+                header: header(Err(new_error(ErrorType::StoreNotFound, format!("store {} not found", store_id)))),
+                ..Default::default()
+            },
+        };
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn get_all_stores(&self, _request: tonic::Request<GetAllStoresRequest>) -> Result<tonic::Response<GetAllStoresResponse>, tonic::Status> {
+        let mut stores: Vec<_> = self.cluster.read().stores().map(|info| info.store.clone()).collect();
+        stores.sort_by_key(|store| store.id);
+        Ok(tonic::Response::new(GetAllStoresResponse { header: header(Ok(())), stores }))
+    }
+
+    async fn remove_store(&self, request: tonic::Request<RemoveStoreRequest>) -> Result<tonic::Response<RemoveStoreResponse>, tonic::Status> {
+        let result = self.cluster.write().remove_store(request.into_inner().store_id);
+        Ok(tonic::Response::new(RemoveStoreResponse { header: header(result) }))
+    }
+
+    async fn store_heartbeat(&self, request: tonic::Request<StoreHeartbeatRequest>) -> Result<tonic::Response<StoreHeartbeatResponse>, tonic::Status> {
+        let result = match request.into_inner().stats {
+            Some(stats) => self.cluster.write().handle_store_heartbeat(stats),
+            None => Err(new_error(ErrorType::Unknown, "missing store stats".to_string())),
+        };
+        Ok(tonic::Response::new(StoreHeartbeatResponse { header: header(result) }))
+    }
+
+    async fn region_heartbeat(&self, request: tonic::Request<RegionHeartbeatRequest>) -> Result<tonic::Response<RegionHeartbeatResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let (region, leader) = match (request.region, request.leader) {
+            (Some(region), Some(leader)) => (region, leader),
+            _ => {
+                let err = new_error(ErrorType::Unknown, "missing region or leader".to_string());
+                return Ok(tonic::Response::new(RegionHeartbeatResponse { header: header(Err(err)), ..Default::default() }));
+            },
+        };
+        let info = RegionInfo { region: region.clone(), leader: Some(leader.clone()) };
+        let flow = RegionFlow {
+            read_bytes: request.read_bytes,
+            read_keys: request.read_keys,
+            written_bytes: request.written_bytes,
+            written_keys: request.written_keys,
+            interval: Duration::from_millis(request.interval_ms),
+        };
+        let mut cluster = self.cluster.write();
+        let result = cluster.handle_region_heartbeat(region, leader);
+        if result.is_ok() {
+            cluster.handle_region_flow(info.region.id, &flow);
+        }
+        let response = match result {
+            Ok(Some(step)) => RegionHeartbeatResponse { header: header(Ok(())), ..step.to_response(&info) },
+            result => RegionHeartbeatResponse { header: header(result.map(|_| ())), ..Default::default() },
+        };
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn tso(&self, request: tonic::Request<TsoRequest>) -> Result<tonic::Response<TsoResponse>, tonic::Status> {
+        let count = request.into_inner().count;
+        let response = match self.tso.get_ts(count) {
+            Ok(ts) => TsoResponse {
+                header: header(Ok(())),
+                count,
+                timestamp: Some(Timestamp {
+                    physical: extract_physical(ts) as i64,
+                    logical: extract_logical(ts) as i64,
+                }),
+            },
+            Err(err) => TsoResponse {
+                header: header(Err(new_error(ErrorType::Unknown, format!("{:?}", err)))),
+                ..Default::default()
+            },
+        };
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn alloc_id(&self, request: tonic::Request<AllocIdRequest>) -> Result<tonic::Response<AllocIdResponse>, tonic::Status> {
+        let count = request.into_inner().count;
+        let response = match self.cluster.read().alloc_ids(count as u64) {
+            Ok(id) => AllocIdResponse { header: header(Ok(())), id, count },
+            Err(err) => AllocIdResponse { header: header(Err(err)), ..Default::default() },
+        };
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn get_region(&self, request: tonic::Request<GetRegionRequest>) -> Result<tonic::Response<GetRegionResponse>, tonic::Status> {
+        let key = request.into_inner().region_key;
+        let cluster = self.cluster.read();
+        let response = match cluster.regions().search(&key) {
+            Some(info) => region_response(info.region.id, Some(info)),
+            None => GetRegionResponse {
+                header: header(Err(new_error(ErrorType::RegionNotFound, format!("no region holds key {:?}", key)))),
+                ..Default::default()
+            },
+        };
+        Ok(tonic::Response::new(response))
+    }
+
+    async fn get_region_by_id(&self, request: tonic::Request<GetRegionByIdRequest>) -> Result<tonic::Response<GetRegionResponse>, tonic::Status> {
+        let region_id = request.into_inner().region_id;
+        let cluster = self.cluster.read();
+        Ok(tonic::Response::new(region_response(region_id, cluster.regions().get(region_id))))
+    }
+
+    async fn scan_regions(&self, request: tonic::Request<ScanRegionsRequest>) -> Result<tonic::Response<ScanRegionsResponse>, tonic::Status> {
+        let request = request.into_inner();
+        let cluster = self.cluster.read();
+        let infos = cluster.regions().scan_range(&request.start_key, &request.end_key, request.limit as usize);
+        Ok(tonic::Response::new(ScanRegionsResponse {
+            header: header(Ok(())),
+            regions: infos.iter().map(|info| info.region.clone()).collect(),
+            leaders: infos.iter().map(|info| info.leader.clone().unwrap_or_default()).collect(),
+        }))
+    }
+
+    async fn get_hot_regions(&self, request: tonic::Request<GetHotRegionsRequest>) -> Result<tonic::Response<GetHotRegionsResponse>, tonic::Status> {
+        let kind = request.into_inner().kind();
+        let regions = self.cluster.read().hot_regions(kind).into_iter()
+            .map(|stat| HotRegion {
+                region_id: stat.region_id,
+                store_id: stat.store_id,
+                bytes_rate: stat.bytes_rate as u64,
+                keys_rate: stat.keys_rate as u64,
+                hot_degree: stat.hot_degree,
+            })
+            .collect();
+        Ok(tonic::Response::new(GetHotRegionsResponse { header: header(Ok(())), regions }))
+    }
+
+    async fn detect(&self, request: tonic::Request<DetectRequest>) -> Result<tonic::Response<DetectResponse>, tonic::Status> {
+        let req = request.into_inner();
+        let mut graph = self.wait_for_graph.lock();
+        let deadlock_key_hash = match req.r#type() {
+            DetectType::Detect => graph.detect(req.txn, req.wait_for_txn, req.key_hash),
+            DetectType::CleanUpWaitFor => {
+                graph.clean_up_wait_for(req.txn, req.wait_for_txn);
+                None
+            },
+            DetectType::CleanUp => {
+                graph.clean_up(req.txn);
+                None
+            },
+        };
+        Ok(tonic::Response::new(DetectResponse {
+            header: header(Ok(())),
+            deadlock: deadlock_key_hash.is_some(),
+            deadlock_key_hash: deadlock_key_hash.unwrap_or_default(),
+        }))
+    }
+
+    async fn get_cluster_config(&self, _request: tonic::Request<GetClusterConfigRequest>) -> Result<tonic::Response<GetClusterConfigResponse>, tonic::Status> {
+        let cluster = self.cluster.read().meta().clone();
+        Ok(tonic::Response::new(GetClusterConfigResponse { header: header(Ok(())), cluster: Some(cluster) }))
+    }
+
+    async fn put_cluster_config(&self, request: tonic::Request<PutClusterConfigRequest>) -> Result<tonic::Response<PutClusterConfigResponse>, tonic::Status> {
+        let result = match request.into_inner().cluster {
+            Some(cluster) => self.cluster.write().put_meta(cluster),
+            None => Err(new_error(ErrorType::Unknown, "missing cluster".to_string())),
+        };
+        Ok(tonic::Response::new(PutClusterConfigResponse { header: header(result) }))
+    }
+}
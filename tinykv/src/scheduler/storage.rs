@@ -0,0 +1,74 @@
+use std::{ops::Bound, sync::Arc};
+
+use prost::Message;
+
+use crate::{kv::{error::TkvResult, ColumnFamily, Storage}, proto::metapb::{Cluster, Region, Store}};
+
+
+const CLUSTER_KEY: &[u8] = b"cluster";
+const STORE_PREFIX: &[u8] = b"stores/";
+const REGION_PREFIX: &[u8] = b"regions/";
+
+fn meta_key(prefix: &[u8], id: u64) -> Vec<u8> {
+    let mut key = prefix.to_vec();
+    key.extend_from_slice(&id.to_be_bytes());
+    key
+}
+
+/// MetaStorage persists the metadata of the cluster: its settings, its
+/// stores and its regions. The stats and the leaders are only kept in
+/// memory, they are reported again by the next heartbeats.
+#[derive(Debug, Clone)]
+pub struct MetaStorage {
+    storage: Arc<dyn Storage>,
+}
+
+impl MetaStorage {
+    pub fn new(storage: Arc<dyn Storage>) -> Self {
+        Self { storage }
+    }
+
+    pub fn load_cluster(&self) -> TkvResult<Option<Cluster>> {
+        match self.storage.get(ColumnFamily::Default, CLUSTER_KEY)? {
+            Some(value) => Ok(Some(Cluster::decode(value.as_slice())?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn save_cluster(&self, cluster: &Cluster) -> TkvResult<()> {
+        self.storage.put(ColumnFamily::Default, CLUSTER_KEY, cluster.encode_to_vec())
+    }
+
+    pub fn load_stores(&self) -> TkvResult<Vec<Store>> {
+        self.load_all(STORE_PREFIX)
+    }
+
+    pub fn save_store(&self, store: &Store) -> TkvResult<()> {
+        self.storage.put(ColumnFamily::Default, &meta_key(STORE_PREFIX, store.id), store.encode_to_vec())
+    }
+
+    pub fn load_regions(&self) -> TkvResult<Vec<Region>> {
+        self.load_all(REGION_PREFIX)
+    }
+
+    pub fn save_region(&self, region: &Region) -> TkvResult<()> {
+        self.storage.put(ColumnFamily::Default, &meta_key(REGION_PREFIX, region.id), region.encode_to_vec())
+    }
+
+    pub fn delete_region(&self, region_id: u64) -> TkvResult<()> {
+        self.storage.delete(ColumnFamily::Default, &meta_key(REGION_PREFIX, region_id))
+    }
+
+    // Decodes every value under prefix, in id order.
+    fn load_all<M: Message + Default>(&self, prefix: &[u8]) -> TkvResult<Vec<M>> {
+        let mut end = prefix.to_vec();
+        *end.last_mut().unwrap() += 1;
+        let scanner = self.storage.scan(ColumnFamily::Default, Bound::Included(prefix.to_vec()), Bound::Excluded(end))?;
+        let mut messages = vec![];
+        for item in scanner.iter() {
+            let (_, value) = item?;
+            messages.push(M::decode(value.as_slice())?);
+        }
+        Ok(messages)
+    }
+}
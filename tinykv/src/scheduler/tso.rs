@@ -0,0 +1,157 @@
+use std::{sync::Arc, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use byteorder::{BigEndian, ByteOrder};
+use parking_lot::Mutex;
+
+use crate::kv::{error::{TkvError, TkvResult}, ColumnFamily, Storage};
+
+
+// A timestamp is physical << LOGICAL_BITS | logical, the physical
+// part being a number of milliseconds since the unix epoch.
+pub const LOGICAL_BITS: u32 = 18;
+pub const MAX_LOGICAL: u64 = 1 << LOGICAL_BITS;
+
+const TIMESTAMP_KEY: &[u8] = b"tso_timestamp";
+
+pub fn compose_ts(physical: u64, logical: u64) -> u64 {
+    (physical << LOGICAL_BITS) | logical
+}
+
+pub fn extract_physical(ts: u64) -> u64 {
+    ts >> LOGICAL_BITS
+}
+
+pub fn extract_logical(ts: u64) -> u64 {
+    ts & (MAX_LOGICAL - 1)
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+#[derive(Debug)]
+struct TsoState {
+    physical: u64,
+    logical: u64,
+    // persisted upper bound of the physical part, no timestamp
+    // at or above it is handed out until it is moved forward.
+    saved_physical: u64,
+}
+
+/// Tso is the timestamp oracle of the cluster, it hands out
+/// strictly increasing timestamps, even across restarts.
+#[derive(Debug)]
+pub struct Tso {
+    storage: Arc<dyn Storage>,
+    save_interval: Duration,
+    state: Mutex<TsoState>,
+}
+
+impl Tso {
+    // Resumes after the high-water mark saved by a previous run,
+    // whatever the clock says.
+    pub fn new(storage: Arc<dyn Storage>, save_interval: Duration) -> TkvResult<Self> {
+        let last = match storage.get(ColumnFamily::Default, TIMESTAMP_KEY)? {
+            Some(value) => BigEndian::read_u64(&value),
+            None => 0,
+        };
+        let physical = now_ms().max(last + 1);
+        let tso = Self {
+            storage,
+            save_interval,
+            state: Mutex::new(TsoState { physical, logical: 0, saved_physical: 0 }),
+        };
+        tso.save(&mut tso.state.lock())?;
+        Ok(tso)
+    }
+
+    // Returns the first of count consecutive timestamps.
+    pub fn get_ts(&self, count: u32) -> TkvResult<u64> {
+        let count = count as u64;
+        if count == 0 || count > MAX_LOGICAL {
+            return Err(TkvError::new(format!("invalid timestamp count {}", count)));
+        }
+        let mut state = self.state.lock();
+        let now = now_ms();
+        if now > state.physical {
+            state.physical = now;
+            state.logical = 0;
+        }
+        // the logical part is exhausted, borrow the next millisecond.
+        if state.logical + count > MAX_LOGICAL {
+            state.physical += 1;
+            state.logical = 0;
+        }
+        if state.physical >= state.saved_physical {
+            self.save(&mut state)?;
+        }
+        let ts = compose_ts(state.physical, state.logical);
+        state.logical += count;
+        Ok(ts)
+    }
+
+    fn save(&self, state: &mut TsoState) -> TkvResult<()> {
+        let saved_physical = state.physical + self.save_interval.as_millis().max(1) as u64;
+        let mut value = vec![0; 8];
+        BigEndian::write_u64(&mut value, saved_physical);
+        self.storage.put(ColumnFamily::Default, TIMESTAMP_KEY, value)?;
+        state.saved_physical = saved_physical;
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::Arc, time::Duration};
+
+    use byteorder::{BigEndian, ByteOrder};
+
+    use crate::kv::error::TkvResult;
+    use crate::kv::storage::memory::MemoryStorage;
+    use crate::kv::{ColumnFamily, Storage};
+
+    use super::{extract_physical, now_ms, Tso, MAX_LOGICAL, TIMESTAMP_KEY};
+
+    #[test]
+    fn increasing_timestamps() -> TkvResult<()> {
+        let tso = Tso::new(Arc::new(MemoryStorage::new()), Duration::from_secs(3))?;
+        let mut last = 0;
+        for count in [1, 10, MAX_LOGICAL as u32, 3, MAX_LOGICAL as u32 - 1, 1] {
+            let ts = tso.get_ts(count)?;
+            assert!(ts > last);
+            last = ts + count as u64 - 1;
+            // a batch never spans two physical timestamps.
+            assert_eq!(extract_physical(ts), extract_physical(last));
+        }
+        assert!(tso.get_ts(0).is_err());
+        assert!(tso.get_ts(MAX_LOGICAL as u32 + 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn never_go_backwards() -> TkvResult<()> {
+        let storage = Arc::new(MemoryStorage::new());
+        let tso = Tso::new(storage.clone(), Duration::from_millis(1))?;
+        // exhaust a few milliseconds ahead of the clock.
+        let mut last = 0;
+        for _ in 0..20 {
+            last = tso.get_ts(MAX_LOGICAL as u32)?;
+        }
+        drop(tso);
+        let tso = Tso::new(storage.clone(), Duration::from_millis(1))?;
+        assert!(tso.get_ts(1)? > last);
+
+        // the clock went back an hour before the restart.
+        let future = now_ms() + 3_600_000;
+        let mut value = vec![0; 8];
+        BigEndian::write_u64(&mut value, future);
+        storage.put(ColumnFamily::Default, TIMESTAMP_KEY, value)?;
+        let tso = Tso::new(storage.clone(), Duration::from_secs(3))?;
+        let ts = tso.get_ts(1)?;
+        assert!(extract_physical(ts) > future);
+        let saved = BigEndian::read_u64(&storage.get(ColumnFamily::Default, TIMESTAMP_KEY)?.unwrap());
+        assert!(saved > extract_physical(ts));
+        Ok(())
+    }
+}